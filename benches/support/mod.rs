@@ -0,0 +1,130 @@
+//! Synthetic Seafile server serving a generated share tree over loopback.
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    thread,
+};
+
+use url::Url;
+
+pub const TOKEN: &str = "0123456789abcdef0123";
+
+/// Shape of the generated share: `dirs` directories with `files` files of
+/// `file_size` bytes each at the root, plus `big.bin` of `big_size` bytes.
+#[derive(Debug, Clone, Copy)]
+pub struct Tree {
+    pub dirs: usize,
+    pub files: usize,
+    pub file_size: u64,
+    pub big_size: u64,
+}
+
+pub struct Server {
+    base: Url,
+}
+
+impl Server {
+    pub fn start(tree: Tree) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("bind loopback");
+        let base = Url::parse(&format!("http://{}/", listener.local_addr().unwrap())).unwrap();
+        let root = base.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let base = root.clone();
+                thread::spawn(move || serve(&base, tree, stream));
+            }
+        });
+        Self { base }
+    }
+
+    pub fn share_url(&self) -> String {
+        format!("{}d/{}/", self.base, TOKEN)
+    }
+}
+
+/// Answers keep-alive requests on one connection until the client hangs up.
+fn serve(base: &Url, tree: Tree, stream: TcpStream) {
+    // without this, delayed ACKs dominate the timings of small responses
+    stream.set_nodelay(true).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut writer = stream;
+    loop {
+        let mut request_line = String::new();
+        if reader.read_line(&mut request_line).unwrap_or(0) == 0 {
+            return;
+        }
+        let mut line = String::new();
+        while reader.read_line(&mut line).is_ok() && line != "\r\n" {
+            line.clear();
+        }
+        let target = request_line.split_whitespace().nth(1).unwrap_or("/");
+        if respond(base, tree, target, &mut writer).is_err() {
+            return;
+        }
+    }
+}
+
+fn respond(base: &Url, tree: Tree, target: &str, writer: &mut TcpStream) -> std::io::Result<()> {
+    let url = base.join(target).unwrap();
+    let query = |key: &str| {
+        url.query_pairs()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.into_owned())
+    };
+    if url.path().ends_with("/dirents/") {
+        let path = query("path").unwrap_or_else(|| "/".to_string());
+        let body = serde_json::json!({ "dirent_list": dirents(tree, &path) }).to_string();
+        let head = format!(
+            "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n",
+            body.len()
+        );
+        writer.write_all((head + &body).as_bytes())
+    } else if query("dl").is_some() {
+        let size = if query("p").as_deref() == Some("/big.bin") {
+            tree.big_size
+        } else {
+            tree.file_size
+        };
+        let head = format!(
+            "HTTP/1.1 200 OK\r\ncontent-type: application/octet-stream\r\n\
+             content-disposition: attachment\r\ncontent-length: {}\r\n\r\n",
+            size
+        );
+        writer.write_all(head.as_bytes())?;
+        std::io::copy(&mut std::io::repeat(0).take(size), writer)?;
+        Ok(())
+    } else {
+        writer.write_all(b"HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\n\r\n")
+    }
+}
+
+fn dirents(tree: Tree, path: &str) -> Vec<serde_json::Value> {
+    const MTIME: &str = "2025-01-01T00:00:00+00:00";
+    let file = |path: String, name: String, size: u64| {
+        serde_json::json!({
+            "is_dir": false, "last_modified": MTIME,
+            "file_path": path, "file_name": name, "size": size,
+        })
+    };
+    let mut entries = Vec::new();
+    if path == "/" {
+        for i in 0..tree.dirs {
+            entries.push(serde_json::json!({
+                "is_dir": true, "last_modified": MTIME,
+                "folder_path": format!("/d{}/", i), "folder_name": format!("d{}", i), "size": 0,
+            }));
+        }
+        entries.push(file("/big.bin".into(), "big.bin".into(), tree.big_size));
+    } else {
+        for j in 0..tree.files {
+            let name = if j % 4 == 0 {
+                format!("f{}.log", j)
+            } else {
+                format!("f{}.dat", j)
+            };
+            entries.push(file(format!("{}{}", path, name), name, tree.file_size));
+        }
+    }
+    entries
+}
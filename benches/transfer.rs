@@ -0,0 +1,98 @@
+//! End-to-end benchmarks running the binary against a synthetic server.
+//!
+//! Each iteration spawns `seaf-share`, so the numbers include process startup
+//! and are meant for spotting regressions rather than absolute figures.
+
+mod support;
+
+use std::process::{Command, Stdio};
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+
+use support::{Server, Tree};
+
+const BIN: &str = env!("CARGO_BIN_EXE_seaf-share");
+
+fn run(args: &[&str]) {
+    let status = Command::new(BIN)
+        .args(args)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .expect("run seaf-share");
+    assert!(status.success(), "seaf-share {:?} failed", args);
+}
+
+fn traversal(c: &mut Criterion) {
+    let server = Server::start(Tree {
+        dirs: 50,
+        files: 200,
+        file_size: 1,
+        big_size: 1,
+    });
+    let url = server.share_url();
+    let mut group = c.benchmark_group("traversal");
+    group.sample_size(20);
+    group.throughput(Throughput::Elements(50 * 200));
+    group.bench_function("dfs", |b| {
+        b.iter(|| run(&["download", "--dry-run", "-r=dfs", &url]))
+    });
+    group.bench_function("bfs", |b| {
+        b.iter(|| run(&["download", "--dry-run", "-r=bfs", &url]))
+    });
+    group.bench_function("spilled", |b| {
+        b.iter(|| run(&["download", "--dry-run", "-r", "--queue-limit", "64", &url]))
+    });
+    group.finish();
+}
+
+fn filtering(c: &mut Criterion) {
+    let server = Server::start(Tree {
+        dirs: 50,
+        files: 200,
+        file_size: 1,
+        big_size: 1,
+    });
+    let url = server.share_url();
+    let mut group = c.benchmark_group("filtering");
+    group.sample_size(20);
+    group.throughput(Throughput::Elements(50 * 200));
+    group.bench_function("exclude", |b| {
+        b.iter(|| {
+            run(&[
+                "download",
+                "--dry-run",
+                "-r",
+                "--exclude",
+                "/d1*",
+                "--exclude",
+                "**/*.log",
+                &url,
+            ])
+        })
+    });
+    group.finish();
+}
+
+fn copy_loop(c: &mut Criterion) {
+    const SIZE: u64 = 256 << 20;
+    let server = Server::start(Tree {
+        dirs: 0,
+        files: 0,
+        file_size: 1,
+        big_size: SIZE,
+    });
+    let url = server.share_url();
+    let output = tempfile::tempdir().expect("create output directory");
+    let output = output.path().to_str().unwrap().to_string();
+    let mut group = c.benchmark_group("copy");
+    group.sample_size(10);
+    group.throughput(Throughput::Bytes(SIZE));
+    group.bench_function("single_file", |b| {
+        b.iter(|| run(&["download", "-y", "-c", "overwrite", "-o", &output, &url]))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, traversal, filtering, copy_loop);
+criterion_main!(benches);
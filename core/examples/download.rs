@@ -0,0 +1,42 @@
+//! Downloads the files of a share link (without subdirectories) into a
+//! local directory, continuing files that are already partly there.
+//!
+//! ```console
+//! $ cargo run -p seaf-share-core --example download -- https://cloud.example/d/6e5297246c/ out/
+//! ```
+
+use anyhow::Context;
+use url::Url;
+
+use seaf_share_core::{seafile::Client, ConflictAction, Downloader, ShareLink};
+
+fn main() -> anyhow::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let (Some(url), Some(output)) = (args.next(), args.next()) else {
+        anyhow::bail!("usage: download URL DIR");
+    };
+    let url = Url::parse(&url)?;
+    let link = ShareLink::from_url(&url).context("not a share link")?;
+    let client = Client::with_agent(ureq::agent(), &url);
+    let downloader = Downloader::with_client(ureq::agent());
+
+    let entries = if link.is_single_file() {
+        vec![client.single_file(&link.page_url(&client))?]
+    } else {
+        client.entries(link.token(), link.path())?
+    };
+    for entry in entries.iter().filter(|e| e.is_file()) {
+        let dest = std::path::Path::new(&output).join(entry.name());
+        let job = downloader.download_entry(entry, dest, ConflictAction::Continue)?;
+        let outcome = job.finish(true, None);
+        let result = outcome.result?;
+        println!(
+            "{} {} ({} bytes in {:.1}s)",
+            result,
+            outcome.path.display(),
+            outcome.received,
+            outcome.elapsed.as_secs_f64()
+        );
+    }
+    Ok(())
+}
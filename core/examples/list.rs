@@ -0,0 +1,23 @@
+//! Lists the entries of a share link.
+//!
+//! ```console
+//! $ cargo run -p seaf-share-core --example list -- https://cloud.example/d/6e5297246c/
+//! ```
+
+use anyhow::Context;
+use url::Url;
+
+use seaf_share_core::{seafile::Client, ShareLink};
+
+fn main() -> anyhow::Result<()> {
+    let url = std::env::args().nth(1).context("usage: list URL")?;
+    let url = Url::parse(&url)?;
+    let link = ShareLink::from_url(&url).context("not a share link")?;
+    let client = Client::with_agent(ureq::agent(), &url);
+
+    for entry in client.entries(link.token(), link.path())? {
+        let size = entry.size().map_or(String::new(), |size| size.to_string());
+        println!("{:>12}  {}", size, entry.path().display());
+    }
+    Ok(())
+}
@@ -0,0 +1,70 @@
+/*
+ * C interface of seaf-share-core, built with
+ *
+ *     cargo rustc -p seaf-share-core --release --features ffi --crate-type cdylib
+ *
+ * Functions returning int return 0 on success and -1 on failure, after which
+ * seaf_share_last_error() describes the error. A client must only be used on
+ * the thread that created it.
+ */
+
+#ifndef SEAF_SHARE_H
+#define SEAF_SHARE_H
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct SeafShareClient SeafShareClient;
+
+/* Entry of a listing, valid for the duration of the callback */
+typedef struct SeafShareEntry {
+    const char *path;
+    bool is_dir;
+    /* Size in bytes, 0 for directories */
+    uint64_t size;
+    /* Modification time in seconds since the Unix epoch, -1 if unknown */
+    int64_t mtime;
+} SeafShareEntry;
+
+typedef void (*SeafShareEntryCallback)(const SeafShareEntry *entry, void *user_data);
+
+/* Called as data is written, with total 0 if the size is unknown */
+typedef void (*SeafShareProgressCallback)(uint64_t received, uint64_t total, void *user_data);
+
+/* Message of the last failed call on this thread, or NULL. The string is
+ * valid until the next call that fails. */
+const char *seaf_share_last_error(void);
+
+/* Opens a share link, unlocking it with password unless that is NULL.
+ * Returns NULL on failure. */
+SeafShareClient *seaf_share_client_new(const char *url, const char *password);
+
+void seaf_share_client_free(SeafShareClient *client);
+
+/* Calls callback for every entry of the linked directory, or of path below it
+ * unless that is NULL. */
+int seaf_share_list(const SeafShareClient *client, const char *path,
+                    SeafShareEntryCallback callback, void *user_data);
+
+/* Download URL of the linked file, or of the file at path below the linked
+ * directory. Returns NULL on failure; free the string with
+ * seaf_share_string_free(). */
+char *seaf_share_download_url(const SeafShareClient *client, const char *path);
+
+void seaf_share_string_free(char *s);
+
+/* Writes the linked file, or the file at path below the linked directory, to
+ * the open file descriptor fd, which is left open. progress may be NULL.
+ * Unix only. */
+int seaf_share_download_fd(const SeafShareClient *client, const char *path, int fd,
+                           SeafShareProgressCallback progress, void *user_data);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,53 @@
+//! API tokens for the authenticated Web API, which lists the libraries of an
+//! account and reads them ([`seafile::ShareToken::library`]) where share
+//! links need none.
+//!
+//! [`TokenAuth`] is [`ureq`] middleware, added to the agent with
+//! [`ureq::config::ConfigBuilder::middleware`]. It sends the token only to
+//! the server it belongs to, not to the hosts that downloads are redirected
+//! to.
+//!
+//! [`seafile::ShareToken::library`]: crate::seafile::ShareToken::library
+
+use ureq::{
+    http::{header, HeaderValue, Request, Response},
+    middleware::{Middleware, MiddlewareNext},
+    Body, SendBody,
+};
+use url::{Origin, Url};
+
+/// Middleware authenticating requests to a server with an API token
+pub struct TokenAuth {
+    origin: Origin,
+    value: HeaderValue,
+}
+
+impl TokenAuth {
+    /// Sends `token` with the requests to the server at `server`; fails on a
+    /// token that cannot be sent in a header.
+    pub fn new(server: &Url, token: &str) -> Result<Self, crate::Error> {
+        let value = HeaderValue::try_from(format!("Token {}", token))
+            .map_err(|_| anyhow::anyhow!("the API token is not valid in a header"))?;
+        Ok(Self {
+            origin: server.origin(),
+            value,
+        })
+    }
+}
+
+impl Middleware for TokenAuth {
+    fn handle(
+        &self,
+        mut request: Request<SendBody>,
+        next: MiddlewareNext,
+    ) -> Result<Response<Body>, ureq::Error> {
+        let same_origin =
+            Url::parse(&request.uri().to_string()).is_ok_and(|url| url.origin() == self.origin);
+        if same_origin && !request.headers().contains_key(header::AUTHORIZATION) {
+            request
+                .headers_mut()
+                .insert(header::AUTHORIZATION, self.value.clone());
+        }
+        next.handle(request)
+    }
+}
@@ -0,0 +1,74 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// Failed probes after which [`CircuitBreaker::wait_until`] gives up, about
+/// 13 minutes into the wait
+const MAX_PROBES: u32 = 10;
+
+/// Time between checks whether a wait should stop
+const STOP_POLL: Duration = Duration::from_millis(100);
+
+/// Trips when too many of the most recent requests failed.
+pub struct CircuitBreaker {
+    outcomes: VecDeque<bool>,
+    window: usize,
+    threshold: f64,
+}
+
+impl CircuitBreaker {
+    /// `threshold` is the percentage of failures within the last `window`
+    /// requests above which the breaker opens.
+    pub fn new(window: usize, threshold: u8) -> Self {
+        Self {
+            outcomes: VecDeque::with_capacity(window),
+            window,
+            threshold: f64::from(threshold) / 100.0,
+        }
+    }
+
+    pub fn record(&mut self, success: bool) {
+        if self.outcomes.len() == self.window {
+            self.outcomes.pop_front();
+        }
+        self.outcomes.push_back(success);
+    }
+
+    pub fn is_open(&self) -> bool {
+        if self.window == 0 || self.outcomes.len() < self.window {
+            return false;
+        }
+        let failures = self.outcomes.iter().filter(|ok| !**ok).count();
+        failures as f64 / self.window as f64 > self.threshold
+    }
+
+    /// Blocks until `probe` succeeds, backing off exponentially between
+    /// attempts, then closes the breaker. Gives up after `MAX_PROBES`
+    /// failed probes, or as soon as `stop` returns true, and returns whether
+    /// the server responded. `probe` reports its own failures, if at all.
+    pub fn wait_until<F>(&mut self, stop: impl Fn() -> bool, mut probe: F) -> bool
+    where
+        F: FnMut() -> anyhow::Result<()>,
+    {
+        const MAX_DELAY: Duration = Duration::from_secs(300);
+        let mut delay = Duration::from_secs(1);
+        for _ in 0..MAX_PROBES {
+            let until = Instant::now() + delay;
+            while let Some(left) = until.checked_duration_since(Instant::now()) {
+                if stop() {
+                    return false;
+                }
+                std::thread::sleep(left.min(STOP_POLL));
+            }
+            match probe() {
+                Ok(()) => {
+                    self.outcomes.clear();
+                    return true;
+                }
+                Err(_) => delay = (delay * 2).min(MAX_DELAY),
+            }
+        }
+        false
+    }
+}
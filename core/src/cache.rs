@@ -0,0 +1,195 @@
+//! Disk cache of API responses, shared by all commands and runs, so that
+//! listing a share again costs a revalidation (or nothing) instead of the
+//! whole listing.
+//!
+//! [`HttpCache`] is [`ureq`] middleware, added to the agent with
+//! [`ureq::config::ConfigBuilder::middleware`]. It follows the caching rules
+//! of RFC 9111 for a private cache: a stored response is used while it is
+//! fresh, and revalidated with its `ETag` or `Last-Modified` afterwards.
+//! Responses without either are only reused when the cache is allowed to
+//! serve stale ones ([`HttpCache::with_max_stale`]), as with a `max-stale`
+//! request directive. Only `GET` requests of the API are cached; file
+//! downloads are not.
+
+use std::{
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use http_cache_semantics::{AfterResponse, BeforeRequest, CacheOptions, CachePolicy};
+use ureq::{
+    http::{header, request, response, Method, Request, Response},
+    middleware::{Middleware, MiddlewareNext},
+    Body, SendBody,
+};
+
+/// Middleware answering API requests from a cache directory where it can
+pub struct HttpCache {
+    dir: PathBuf,
+    max_stale: Option<Duration>,
+}
+
+impl HttpCache {
+    /// Cache in `dir`, which is created when the first response is stored
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            max_stale: None,
+        }
+    }
+
+    /// Serves stored responses for up to `max_stale` after they went stale
+    /// without asking the server.
+    pub fn with_max_stale(mut self, max_stale: Duration) -> Self {
+        self.max_stale = Some(max_stale);
+        self
+    }
+
+    /// Whether the response to `request` may be taken from the cache
+    fn caches(request: &Request<SendBody>) -> bool {
+        request.method() == Method::GET
+            && request.uri().path().contains("/api/")
+            && !request.headers().contains_key(header::RANGE)
+    }
+
+    /// Stored policy and body of the response for `key`; a cache that cannot
+    /// be read is a cache without the response.
+    fn lookup(&self, key: &str) -> Option<(CachePolicy, Vec<u8>)> {
+        let metadata = cacache::metadata_sync(&self.dir, key).ok()??;
+        let policy = serde_json::from_value(metadata.metadata).ok()?;
+        let data = cacache::read_hash_sync(&self.dir, &metadata.integrity).ok()?;
+        Some((policy, data))
+    }
+
+    fn store(dir: &Path, key: &str, policy: &CachePolicy, data: &[u8]) -> anyhow::Result<()> {
+        let mut writer = cacache::WriteOpts::new()
+            .metadata(serde_json::to_value(policy)?)
+            .open_sync(dir, key)?;
+        writer.write_all(data)?;
+        writer.commit()?;
+        Ok(())
+    }
+
+    /// Request parts of `request` for the cache policy, with the
+    /// `max-stale` directive if stale responses may be served
+    fn lookup_parts(&self, request: &Request<SendBody>) -> request::Parts {
+        let mut parts = Request::new(()).into_parts().0;
+        parts.method = request.method().clone();
+        parts.uri = request.uri().clone();
+        parts.headers = request.headers().clone();
+        if let Some(max_stale) = self.max_stale {
+            let directive = format!("max-stale={}", max_stale.as_secs());
+            parts.headers.append(
+                header::CACHE_CONTROL,
+                directive
+                    .parse()
+                    .expect("directive is a valid header value"),
+            );
+        }
+        parts
+    }
+}
+
+impl Middleware for HttpCache {
+    fn handle(
+        &self,
+        mut request: Request<SendBody>,
+        next: MiddlewareNext,
+    ) -> Result<Response<Body>, ureq::Error> {
+        if !Self::caches(&request) {
+            return next.handle(request);
+        }
+        let key = format!("{} {}", request.method(), request.uri());
+        let parts = self.lookup_parts(&request);
+        let cached = self.lookup(&key);
+        if let Some((policy, data)) = &cached {
+            match policy.before_request(&parts, SystemTime::now()) {
+                BeforeRequest::Fresh(res) => return Ok(cached_response(res, data.clone())),
+                BeforeRequest::Stale {
+                    request: revalidation,
+                    ..
+                } => {
+                    // the validators of the stored response
+                    for name in [header::IF_NONE_MATCH, header::IF_MODIFIED_SINCE] {
+                        if let Some(value) = revalidation.headers.get(&name) {
+                            request.headers_mut().insert(name, value.clone());
+                        }
+                    }
+                }
+            }
+        }
+        let res = next.handle(request)?;
+        let (res_parts, body) = res.into_parts();
+        let now = SystemTime::now();
+        let policy = match cached {
+            Some((policy, data)) => match policy.after_response(&parts, &res_parts, now) {
+                AfterResponse::NotModified(policy, res) => {
+                    // a failure to store must not fail the run
+                    let _ = Self::store(&self.dir, &key, &policy, &data);
+                    return Ok(cached_response(res, data));
+                }
+                AfterResponse::Modified(policy, _) => policy,
+            },
+            None => {
+                let options = CacheOptions {
+                    shared: false,
+                    ..CacheOptions::default()
+                };
+                CachePolicy::new_options(&parts, &res_parts, now, options)
+            }
+        };
+        if !policy.is_storable() {
+            return Ok(Response::from_parts(res_parts, body));
+        }
+        // the body is passed on as it is read and stored once complete
+        let mut builder = Body::builder();
+        if let Some(mime_type) = body.mime_type() {
+            builder = builder.mime_type(mime_type);
+        }
+        if let Some(charset) = body.charset() {
+            builder = builder.charset(charset);
+        }
+        let reader = StoringReader {
+            inner: body.into_reader(),
+            data: Vec::new(),
+            entry: Some((self.dir.clone(), key, policy)),
+        };
+        Ok(Response::from_parts(res_parts, builder.reader(reader)))
+    }
+}
+
+/// Response with the stored `data` as its body
+fn cached_response(parts: response::Parts, data: Vec<u8>) -> Response<Body> {
+    let mut body = Body::builder();
+    if let Some(value) = parts.headers.get(header::CONTENT_TYPE) {
+        let value = value.to_str().unwrap_or_default();
+        let mut params = value.split(';').map(str::trim);
+        body = body.mime_type(params.next().unwrap_or_default());
+        if let Some(charset) = params.find_map(|p| p.strip_prefix("charset=")) {
+            body = body.charset(charset.trim_matches('"'));
+        }
+    }
+    Response::from_parts(parts, body.data(data))
+}
+
+/// Response body that stores itself in the cache once read to the end
+struct StoringReader<R> {
+    inner: R,
+    data: Vec<u8>,
+    /// Directory, key and policy, taken when stored
+    entry: Option<(PathBuf, String, CachePolicy)>,
+}
+
+impl<R: Read> Read for StoringReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if n > 0 {
+            self.data.extend_from_slice(&buf[..n]);
+        } else if let Some((dir, key, policy)) = self.entry.take().filter(|_| !buf.is_empty()) {
+            // a failure to store must not fail the run
+            let _ = HttpCache::store(&dir, &key, &policy, &self.data);
+        }
+        Ok(n)
+    }
+}
@@ -0,0 +1,226 @@
+//! Checksums of downloaded files, computed from the data as it is received,
+//! and manifests of them in the format of `sha256sum` and `md5sum`.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use md5::Md5;
+use sha2::{Digest, Sha256};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[non_exhaustive]
+pub enum Algorithm {
+    Sha256,
+    Md5,
+}
+
+impl Algorithm {
+    /// File name of a manifest, as used by `sha256sum -c`
+    pub fn manifest_name(&self) -> &'static str {
+        match self {
+            Self::Sha256 => "SHA256SUMS",
+            Self::Md5 => "MD5SUMS",
+        }
+    }
+
+    /// The algorithm whose checksums are `len` hex digits long
+    fn from_hex_len(len: usize) -> Option<Self> {
+        match len {
+            64 => Some(Self::Sha256),
+            32 => Some(Self::Md5),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum State {
+    Sha256(Sha256),
+    Md5(Md5),
+}
+
+impl State {
+    fn new(algorithm: Algorithm) -> Self {
+        match algorithm {
+            Algorithm::Sha256 => Self::Sha256(Sha256::new()),
+            Algorithm::Md5 => Self::Md5(Md5::new()),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Self::Sha256(state) => state.update(data),
+            Self::Md5(state) => state.update(data),
+        }
+    }
+
+    fn hex(self) -> String {
+        let digest = match self {
+            Self::Sha256(state) => state.finalize().to_vec(),
+            Self::Md5(state) => state.finalize().to_vec(),
+        };
+        digest.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+impl Write for State {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Running checksum of a file. Data received in one go from the start of the
+/// file is hashed as it arrives; where the transfer resumed, or nothing was
+/// transferred, the file is read again once complete.
+#[derive(Debug, Clone)]
+pub struct Hasher {
+    algorithm: Algorithm,
+    state: State,
+    /// Bytes from the start of the file that `state` covers
+    len: u64,
+    /// Whether data arrived out of order since the last restart
+    broken: bool,
+}
+
+impl Hasher {
+    pub fn new(algorithm: Algorithm) -> Self {
+        Self {
+            algorithm,
+            state: State::new(algorithm),
+            len: 0,
+            broken: false,
+        }
+    }
+
+    /// Continues with data for `position` of the file.
+    pub fn seek(&mut self, position: u64) {
+        if position == 0 {
+            self.state = State::new(self.algorithm);
+            self.len = 0;
+            self.broken = false;
+        } else if position != self.len {
+            self.broken = true;
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        if !self.broken {
+            self.state.update(data);
+            self.len += data.len() as u64;
+        }
+    }
+
+    /// Lowercase hex checksum of the data given to [`Hasher::update`]
+    pub fn digest(&self) -> String {
+        self.state.clone().hex()
+    }
+
+    /// Lowercase hex checksum of the complete file at `path`
+    pub fn finish(self, path: &Path) -> io::Result<String> {
+        let mut file = File::open(path)?;
+        let state = if self.broken || self.len != file.metadata()?.len() {
+            let mut state = State::new(self.algorithm);
+            io::copy(&mut file, &mut state)?;
+            state
+        } else {
+            self.state
+        };
+        Ok(state.hex())
+    }
+}
+
+/// Lowercase hex checksum of the file at `path`
+pub fn file_checksum(algorithm: Algorithm, path: &Path) -> io::Result<String> {
+    Hasher::new(algorithm).finish(path)
+}
+
+/// Writes `checksums` of files below `dir` to its manifest there, sorted by
+/// path.
+pub fn write_manifest(
+    dir: &Path,
+    algorithm: Algorithm,
+    mut checksums: Vec<(PathBuf, String)>,
+) -> io::Result<()> {
+    checksums.sort();
+    let mut out = io::BufWriter::new(File::create(dir.join(algorithm.manifest_name()))?);
+    for (path, checksum) in checksums {
+        let path = path.strip_prefix(dir).unwrap_or(&path);
+        writeln!(out, "{}  {}", checksum, path.display())?;
+    }
+    out.flush()
+}
+
+/// Checksums listed in a manifest, by path relative to the directory it
+/// describes
+#[derive(Debug, Clone)]
+pub struct Manifest {
+    algorithm: Algorithm,
+    checksums: HashMap<PathBuf, String>,
+}
+
+impl Manifest {
+    /// Algorithm of the checksums, told by their length
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    /// Listed checksum of `path`, relative to the directory described
+    pub fn get(&self, path: &Path) -> Option<&str> {
+        self.checksums.get(path).map(String::as_str)
+    }
+}
+
+/// Reads a `--checksum-file`, lines of a SHA-256 or MD5 checksum and a path
+/// as written by `sha256sum` and `md5sum`; blank lines and comments (starting
+/// with "#") are skipped.
+pub fn read_manifest(path: &str) -> Result<Manifest, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let mut algorithm = None;
+    let mut checksums = HashMap::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim_end_matches('\r');
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = || {
+            format!(
+                "{}:{}: \"{}\" is not a checksum and a path",
+                path,
+                n + 1,
+                line
+            )
+        };
+        // "*" marks files hashed in binary mode
+        let (checksum, name) = line
+            .split_once("  ")
+            .or_else(|| line.split_once(" *"))
+            .ok_or_else(invalid)?;
+        if !checksum.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        let listed = Algorithm::from_hex_len(checksum.len()).ok_or_else(invalid)?;
+        if *algorithm.get_or_insert(listed) != listed {
+            return Err(format!(
+                "{}:{}: checksums of different lengths",
+                path,
+                n + 1
+            ));
+        }
+        let name = Path::new(name.strip_prefix("./").unwrap_or(name));
+        checksums.insert(name.to_path_buf(), checksum.to_ascii_lowercase());
+    }
+    Ok(Manifest {
+        algorithm: algorithm.ok_or_else(|| format!("{}: no checksums", path))?,
+        checksums,
+    })
+}
@@ -0,0 +1,642 @@
+//! Transfers of remote files to local files, continuing and retrying them
+//! where the server allows.
+
+use std::{
+    cell::Cell,
+    collections::HashSet,
+    fs::OpenOptions,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use url::Url;
+
+use crate::checksum::{Algorithm, Hasher};
+use crate::progress::{Progress, Tracker};
+use crate::retry::Retry;
+use crate::shutdown::Abort;
+use crate::summary::{DownloadOutcome, Verification};
+use crate::{post, seafile, sync, volume, ConflictAction, DirEntry, DownloadResult, Error};
+
+fn conflict_file_options(conflict: ConflictAction) -> OpenOptions {
+    let mut options = OpenOptions::new();
+    match conflict {
+        ConflictAction::Skip | ConflictAction::Update => {
+            options.read(true);
+        }
+        ConflictAction::Check => {
+            options.read(true).write(true);
+        }
+        ConflictAction::Continue => {
+            options.append(true);
+        }
+        ConflictAction::Overwrite => {
+            options.write(true).truncate(true);
+        }
+    }
+    options
+}
+
+/// Size of the per-thread transfer buffer
+const COPY_BUFFER_SIZE: usize = 1 << 20;
+
+thread_local! {
+    static COPY_BUFFER: std::cell::RefCell<Vec<u8>> = const { std::cell::RefCell::new(Vec::new()) };
+}
+
+/// Like [`std::io::copy`], but fills a large buffer that is reused across
+/// transfers on the same thread before each write, so the disk side sees few,
+/// large writes and the loop itself does not allocate.
+fn copy<R, W>(reader: &mut R, writer: &mut W) -> std::io::Result<u64>
+where
+    R: std::io::Read + ?Sized,
+    W: std::io::Write + ?Sized,
+{
+    COPY_BUFFER.with_borrow_mut(|buf| {
+        buf.resize(COPY_BUFFER_SIZE, 0);
+        let mut total = 0;
+        loop {
+            let mut filled = 0;
+            while filled < buf.len() {
+                match reader.read(&mut buf[filled..]) {
+                    Ok(0) => break,
+                    Ok(n) => filled += n,
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                    Err(e) => {
+                        // keep what was received so the transfer can be continued
+                        writer.write_all(&buf[..filled])?;
+                        return Err(e);
+                    }
+                }
+            }
+            if filled == 0 {
+                return Ok(total);
+            }
+            writer.write_all(&buf[..filled])?;
+            total += filled as u64;
+        }
+    })
+}
+
+/// Length of the response body as announced by the server, if the body is
+/// passed on as is
+fn announced_length(res: &ureq::http::Response<ureq::Body>) -> Option<u64> {
+    if res.headers().contains_key("content-encoding") {
+        return None;
+    }
+    res.body().content_length()
+}
+
+fn check_length(len: u64, expected: Option<u64>) -> std::io::Result<()> {
+    match expected {
+        // like a dropped connection, which it usually is
+        Some(expected) if len != expected => Err(std::io::Error::new(
+            std::io::ErrorKind::UnexpectedEof,
+            format!(
+                "incomplete transfer: received {} of {} bytes",
+                len, expected
+            ),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Writer for downloaded file contents
+fn disk_writer(file: &mut std::fs::File) -> Box<dyn std::io::Write + '_> {
+    #[cfg(all(target_os = "linux", feature = "io-uring"))]
+    {
+        if let Ok(ring) = crate::uring::ring() {
+            return Box::new(crate::uring::UringWriter::new(file, ring));
+        }
+    }
+    Box::new(file)
+}
+
+pub struct Downloader {
+    client: ureq::Agent,
+    max_time: Option<Duration>,
+    timeout_per_gib: Option<Duration>,
+    /// Origins that do not honor range requests
+    no_ranges: Mutex<HashSet<String>>,
+    abort: Abort,
+    retry: Retry,
+    progress: Progress,
+    checksums: Option<Algorithm>,
+    file_ids: Option<Arc<sync::FileIds>>,
+    max_file_size: Option<u64>,
+}
+
+impl Downloader {
+    pub fn with_client(client: ureq::Agent) -> Self {
+        Self {
+            client,
+            max_time: None,
+            timeout_per_gib: None,
+            no_ranges: Mutex::default(),
+            abort: Abort::default(),
+            retry: Retry::default(),
+            progress: Progress::default(),
+            checksums: None,
+            file_ids: None,
+            max_file_size: None,
+        }
+    }
+
+    /// Replaces the agent's global timeout for file transfers: `max_time` plus
+    /// `per_gib` for each GiB of the file, or no limit without `per_gib`.
+    pub fn with_timeouts(mut self, max_time: Option<Duration>, per_gib: Option<Duration>) -> Self {
+        self.max_time = max_time;
+        self.timeout_per_gib = per_gib;
+        self
+    }
+
+    /// Makes transfers in progress fail once `abort` is triggered.
+    pub fn with_abort(mut self, abort: Abort) -> Self {
+        self.abort = abort;
+        self
+    }
+
+    /// Retries transfers that fail for transient reasons, continuing from
+    /// the data received so far where the server supports it.
+    pub fn with_retry(mut self, retry: Retry) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Reports transfers, and warnings about them, to `progress`, which also
+    /// paces them to its rate limit.
+    pub fn with_progress(mut self, progress: Progress) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Hashes the files of [`Downloader::download_entry`] with `algorithm`,
+    /// see [`post::Job::checksum`].
+    pub fn with_checksums(mut self, algorithm: Algorithm) -> Self {
+        self.checksums = Some(algorithm);
+        self
+    }
+
+    /// Tells unchanged files by `ids` with [`ConflictAction::Update`], and
+    /// records the IDs of the files it syncs there.
+    pub fn with_file_ids(mut self, ids: Arc<sync::FileIds>) -> Self {
+        self.file_ids = Some(ids);
+        self
+    }
+
+    /// Writes files larger than `max` in parts of `max` bytes, with a
+    /// manifest of their checksums to join them (see [`volume::Parts`]).
+    /// Such files get no checksum of [`Downloader::with_checksums`].
+    pub fn with_max_file_size(mut self, max: u64) -> Self {
+        self.max_file_size = Some(max);
+        self
+    }
+
+    fn get(
+        &self,
+        url: &Url,
+        size: Option<u64>,
+    ) -> ureq::RequestBuilder<ureq::typestate::WithoutBody> {
+        const GIB: f64 = (1u64 << 30) as f64;
+        let timeout = self.timeout_per_gib.map(|per_gib| {
+            let scaled = per_gib.mul_f64(size.unwrap_or(0) as f64 / GIB);
+            self.max_time.unwrap_or_default() + scaled
+        });
+        self.client
+            .get(url.as_str())
+            .config()
+            .timeout_global(timeout)
+            .build()
+    }
+
+    /// Sends the request built by `request`, following redirects here rather
+    /// than in the agent: cookies set by a redirect response (e.g. signed CDN
+    /// cookies) are passed on to the redirect target even if they are scoped
+    /// to another host or path, and the target URL is requested verbatim.
+    /// File links of the authenticated API are exchanged for their download
+    /// link first.
+    fn follow<F>(&self, url: &Url, request: F) -> anyhow::Result<ureq::http::Response<ureq::Body>>
+    where
+        F: Fn(&Url) -> ureq::RequestBuilder<ureq::typestate::WithoutBody>,
+    {
+        const MAX_REDIRECTS: usize = 10;
+        let mut url = seafile::resolve_file_link(&self.client, url)?;
+        for _ in 0..MAX_REDIRECTS {
+            let mut res = request(&url)
+                .config()
+                .max_redirects(0)
+                .max_redirects_will_error(false)
+                .http_status_as_error(false)
+                .build()
+                .call()?;
+            if res.status().as_u16() >= 400 {
+                return Err(seafile::status_error(&mut res).into());
+            }
+            let location = res.headers().get("location").and_then(|v| v.to_str().ok());
+            let Some(location) = location.filter(|_| res.status().is_redirection()) else {
+                return Ok(res);
+            };
+            let target = url.join(location)?;
+            self.carry_cookies(&res, &target)?;
+            url = target;
+        }
+        anyhow::bail!("too many redirects for {}", url)
+    }
+
+    fn carry_cookies(
+        &self,
+        res: &ureq::http::Response<ureq::Body>,
+        target: &Url,
+    ) -> anyhow::Result<()> {
+        let uri: ureq::http::Uri = target.as_str().parse()?;
+        let mut jar = self.client.cookie_jar_lock();
+        for header in res.headers().get_all("set-cookie") {
+            let Some(pair) = header.to_str().ok().and_then(|v| v.split(';').next()) else {
+                continue;
+            };
+            let cookie = ureq::Cookie::parse(format!("{}; Path=/", pair.trim()), &uri)?;
+            jar.insert(cookie, &uri)?;
+        }
+        Ok(())
+    }
+
+    pub fn download<W: std::io::Write + ?Sized>(
+        &self,
+        writer: &mut W,
+        url: &Url,
+        size: Option<u64>,
+    ) -> Result<u64, Error> {
+        let tracker = self.progress.start(Path::new(url.path()), size);
+        self.download_tracked(writer, url, size, &tracker)
+    }
+
+    fn download_tracked<W: std::io::Write + ?Sized>(
+        &self,
+        writer: &mut W,
+        url: &Url,
+        size: Option<u64>,
+        tracker: &Tracker,
+    ) -> Result<u64, Error> {
+        let mut res = self.follow(url, |url| self.get(url, size))?;
+        seafile::check_download_page(&mut res)?;
+        let expected = announced_length(&res);
+        tracker.seek(0);
+        let mut reader = tracker.reader(self.abort.reader(res.body_mut().as_reader()));
+        let len = copy(&mut reader, writer)?;
+        writer.flush()?;
+        check_length(len, expected)?;
+        Ok(len)
+    }
+
+    fn head(&self, url: &Url) -> anyhow::Result<seafile::Metadata> {
+        let res = self.follow(url, |url| self.client.head(url.as_str()))?;
+        Ok(seafile::Metadata::from_headers(res.headers()))
+    }
+
+    fn supports_ranges(&self, url: &Url) -> bool {
+        !self
+            .no_ranges
+            .lock()
+            .unwrap()
+            .contains(&url.origin().ascii_serialization())
+    }
+
+    fn disable_ranges(&self, url: &Url) {
+        let origin = url.origin().ascii_serialization();
+        if self.no_ranges.lock().unwrap().insert(origin.clone()) {
+            self.progress.warn(format!(
+                "{} does not support partial downloads, files will be downloaded again",
+                origin
+            ));
+        }
+    }
+
+    /// Appends `range` of the remote file to `file`, or rewrites the whole file
+    /// if the server answers with the full content instead.
+    fn download_range(
+        &self,
+        file: &mut std::fs::File,
+        url: &Url,
+        range: std::ops::Range<u64>,
+        tracker: &Tracker,
+    ) -> anyhow::Result<DownloadResult> {
+        let mut res = self.follow(url, |url| {
+            self.get(url, Some(range.end - range.start))
+                .header("range", format!("bytes={}-{}", range.start, range.end - 1))
+        })?;
+        seafile::check_download_page(&mut res)?;
+        let result = if res.status() == ureq::http::StatusCode::PARTIAL_CONTENT {
+            tracker.seek(range.start);
+            DownloadResult::Continued
+        } else {
+            self.disable_ranges(url);
+            file.set_len(0)?;
+            tracker.seek(0);
+            DownloadResult::Overwritten
+        };
+        let expected = announced_length(&res);
+        let mut reader = tracker.reader(self.abort.reader(res.body_mut().as_reader()));
+        let writer = &mut *disk_writer(file);
+        let len = copy(&mut reader, writer)?;
+        writer.flush()?;
+        check_length(len, expected)?;
+        Ok(result)
+    }
+
+    /// Continues `file` from its current length.
+    fn resume(
+        &self,
+        file: &mut std::fs::File,
+        url: &Url,
+        entry: &DirEntry,
+        tracker: &Tracker,
+    ) -> anyhow::Result<DownloadResult> {
+        let start = file.metadata()?.len();
+        let head = match self.head(url) {
+            Ok(head) => head,
+            Err(e) => {
+                self.progress.warn(format!(
+                    "cannot continue {}: {}, downloading again",
+                    entry.path().to_string_lossy(),
+                    e,
+                ));
+                file.set_len(0)?;
+                self.download_tracked(&mut *disk_writer(file), url, entry.size(), tracker)?;
+                return Ok(DownloadResult::Overwritten);
+            }
+        };
+        // Sizes scraped from share pages (entries without mtime) may be
+        // stale, so only trust listed sizes when the server does not
+        // report a Content-Length.
+        let end = head
+            .size
+            .or_else(|| entry.last_modified().and(entry.size()));
+        // whether the server sends parts is told by its answer to the range
+        // request, as some leave out Accept-Ranges
+        Ok(match end {
+            Some(end) if start < end && self.supports_ranges(url) => {
+                self.download_range(file, url, start..end, tracker)?
+            }
+            Some(end) if start == end => DownloadResult::Skipped,
+            Some(end) if start < end => {
+                file.set_len(0)?;
+                self.download_tracked(&mut *disk_writer(file), url, entry.size(), tracker)?;
+                DownloadResult::Overwritten
+            }
+            _ => {
+                self.progress.warn(format!(
+                    "cannot continue {}: remote size is unknown or smaller than the local \
+                     file, downloading again",
+                    entry.path().to_string_lossy(),
+                ));
+                file.set_len(0)?;
+                self.download_tracked(&mut *disk_writer(file), url, entry.size(), tracker)?;
+                DownloadResult::Overwritten
+            }
+        })
+    }
+
+    /// Downloads into "<dest>.part" and renames it to `dest` once complete, so
+    /// interrupted transfers never leave a truncated file under the final name.
+    ///
+    /// With `resume`, a part file left by an earlier run is continued, and the
+    /// part file is kept if the transfer fails again.
+    fn download_part(
+        &self,
+        dest: &Path,
+        url: &Url,
+        entry: &DirEntry,
+        resume: bool,
+        tracker: &Tracker,
+        attempts: &Cell<u32>,
+    ) -> anyhow::Result<std::fs::File> {
+        let mut name = dest.file_name().unwrap_or_default().to_os_string();
+        name.push(".part");
+        let part = dest.with_file_name(name);
+
+        let restart = !(resume && std::fs::exists(&part)?);
+        let mut file = OpenOptions::new().create(true).append(true).open(&part)?;
+        // retries continue what the failed attempt received
+        let transferred = self.retry.run(url, |attempt| {
+            attempts.set(attempts.get() + 1);
+            if attempt == 0 && restart {
+                file.set_len(0)?;
+                self.download_tracked(&mut *disk_writer(&mut file), url, entry.size(), tracker)?;
+                Ok(())
+            } else {
+                self.resume(&mut file, url, entry, tracker).map(drop)
+            }
+        });
+        if let Err(e) = transferred {
+            if !resume {
+                let _ = std::fs::remove_file(&part);
+            }
+            return Err(e);
+        }
+        std::fs::rename(&part, dest)?;
+        Ok(file)
+    }
+
+    /// Downloads a file of `size` bytes into parts of `max` bytes with range
+    /// requests, with their manifest. Complete parts are kept unless
+    /// `on_conflict` overwrites them, and the last part is returned.
+    #[allow(clippy::too_many_arguments)]
+    fn download_split(
+        &self,
+        dest: &Path,
+        url: &Url,
+        size: u64,
+        max: u64,
+        on_conflict: ConflictAction,
+        tracker: &Tracker,
+        attempts: &Cell<u32>,
+    ) -> anyhow::Result<(std::fs::File, DownloadResult)> {
+        // a server that answers with the whole file would fill the first
+        // part beyond what the file system takes
+        if !self.head(url)?.accept_ranges {
+            anyhow::bail!("cannot split the file, as the server does not send parts of files");
+        }
+        // skipped if every part is kept, overwritten if any part existed
+        let mut result = DownloadResult::Skipped;
+        let (mut start, mut index) = (0, 0);
+        loop {
+            let end = size.min(start + max);
+            let part = volume::part_path(dest, index);
+            let existing = std::fs::metadata(&part).ok().map(|m| m.len());
+            let file = if existing == Some(end - start) && on_conflict != ConflictAction::Overwrite
+            {
+                OpenOptions::new().read(true).open(&part)?
+            } else {
+                result = match (result, existing) {
+                    (DownloadResult::Overwritten, _) | (_, Some(_)) => DownloadResult::Overwritten,
+                    _ => DownloadResult::Complete,
+                };
+                let mut file = OpenOptions::new().create(true).append(true).open(&part)?;
+                self.retry.run(url, |_| {
+                    attempts.set(attempts.get() + 1);
+                    file.set_len(0)?;
+                    self.download_range(&mut file, url, start..end, tracker)
+                        .map(drop)
+                })?;
+                file
+            };
+            if end == size {
+                let manifest = volume::manifest_path(dest);
+                if result != DownloadResult::Skipped || !manifest.exists() {
+                    let mut parts = volume::Parts::new(dest, size, max);
+                    parts.hash(dest.parent().unwrap_or(Path::new("")))?;
+                    parts.write(dest)?;
+                }
+                return Ok((file, result));
+            }
+            (start, index) = (end, index + 1);
+        }
+    }
+
+    /// Downloads a file entry to `dest`, resolving an existing file there
+    /// according to `on_conflict`.
+    pub fn download_entry(
+        &self,
+        entry: &DirEntry,
+        dest: PathBuf,
+        on_conflict: ConflictAction,
+    ) -> Result<post::Job, Error> {
+        self.download_counted(entry, dest, on_conflict, &Cell::new(0))
+    }
+
+    /// [`Downloader::download_entry`], adding the requests made for the
+    /// file to `attempts`
+    fn download_counted(
+        &self,
+        entry: &DirEntry,
+        dest: PathBuf,
+        on_conflict: ConflictAction,
+        attempts: &Cell<u32>,
+    ) -> Result<post::Job, Error> {
+        let started = Instant::now();
+        if !entry.is_file() {
+            let path = entry.path().to_string_lossy();
+            return Err(anyhow::anyhow!("{} is not a file", path).into());
+        }
+
+        if let Some(parent) = dest.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let url = entry.download_url().unwrap();
+        let mut tracker = self.progress.start(entry.path(), entry.size());
+        if let Some(algorithm) = self.checksums {
+            tracker = tracker.with_hasher(Hasher::new(algorithm));
+        }
+
+        let split = self
+            .max_file_size
+            .zip(entry.size())
+            .filter(|(max, size)| size > max);
+        let (file, result) = if let Some((max, size)) = split {
+            self.download_split(&dest, url, size, max, on_conflict, &tracker, attempts)?
+        } else if std::fs::exists(&dest)? {
+            match on_conflict {
+                ConflictAction::Skip => (
+                    conflict_file_options(on_conflict).open(&dest)?,
+                    DownloadResult::Skipped,
+                ),
+                ConflictAction::Check => {
+                    let path = entry.path().to_string_lossy();
+                    return Err(anyhow::anyhow!(
+                        "cannot check {} against the remote file, which is not supported yet",
+                        path
+                    )
+                    .into());
+                }
+                ConflictAction::Continue => {
+                    let mut file = conflict_file_options(on_conflict).open(&dest)?;
+                    let result = self.retry.run(url, |_| {
+                        attempts.set(attempts.get() + 1);
+                        self.resume(&mut file, url, entry, &tracker)
+                    })?;
+                    (file, result)
+                }
+                ConflictAction::Overwrite => (
+                    self.download_part(&dest, url, entry, false, &tracker, attempts)?,
+                    DownloadResult::Overwritten,
+                ),
+                ConflictAction::Update => {
+                    let file = conflict_file_options(on_conflict).open(&dest)?;
+                    let changes = match &self.file_ids {
+                        Some(ids) => ids.changes(entry, &dest, &file.metadata()?)?,
+                        None => sync::Changes::between(entry, &file.metadata()?)?,
+                    };
+                    if changes.any() {
+                        drop(file);
+                        (
+                            self.download_part(&dest, url, entry, false, &tracker, attempts)?,
+                            DownloadResult::Updated(changes),
+                        )
+                    } else {
+                        (file, DownloadResult::Skipped)
+                    }
+                }
+            }
+        } else {
+            let resume = on_conflict == ConflictAction::Continue;
+            (
+                self.download_part(&dest, url, entry, resume, &tracker, attempts)?,
+                DownloadResult::Complete,
+            )
+        };
+        // a file skipped on request may not be the remote one
+        if let Some(ids) = &self.file_ids {
+            if result != DownloadResult::Skipped || on_conflict == ConflictAction::Update {
+                ids.record(entry, &dest);
+            }
+        }
+        Ok(post::Job {
+            file,
+            path: entry.path().to_path_buf(),
+            dest,
+            mtime: entry.last_modified().copied(),
+            result,
+            received: tracker.received(),
+            attempts: attempts.get(),
+            started,
+            hasher: tracker.take_hasher().filter(|_| split.is_none()),
+            verification: Verification::Unchecked,
+        })
+    }
+}
+
+/// Downloads an entry, renewing an expired password session once. Returns
+/// the job to finish, or the outcome of a file that could not be
+/// transferred.
+pub fn download_with_session(
+    downloader: &Downloader,
+    client: &seafile::Client,
+    entry: &DirEntry,
+    dest: &Path,
+    on_conflict: ConflictAction,
+) -> Result<post::Job, Box<DownloadOutcome>> {
+    let (started, attempts) = (Instant::now(), Cell::new(0));
+    let download =
+        || downloader.download_counted(entry, dest.to_path_buf(), on_conflict, &attempts);
+    let result = match download() {
+        Err(e) if e.is_session_expired() => match client.renew_session() {
+            Ok(true) => download(),
+            Ok(false) => Err(e),
+            Err(renewal) => Err(renewal),
+        },
+        result => result,
+    };
+    result.map_err(|e| {
+        Box::new(DownloadOutcome {
+            path: entry.path().to_path_buf(),
+            dest: dest.to_path_buf(),
+            result: Err(e.into()),
+            received: 0,
+            elapsed: started.elapsed(),
+            attempts: attempts.get(),
+            verification: Verification::Unchecked,
+        })
+    })
+}
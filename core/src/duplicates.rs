@@ -0,0 +1,75 @@
+//! Sets of identical files within a share, found by size and refined by the
+//! file IDs the server reports.
+
+use std::{collections::HashMap, path::PathBuf};
+
+use serde::Serialize;
+
+use crate::{seafile, DirEntry, Error};
+
+/// Files with the same contents, as far as their size and ID tell
+#[derive(Debug, Clone, Serialize)]
+pub struct DuplicateSet {
+    pub size: u64,
+    /// File ID shared by all files, unknown if the server does not report it
+    /// or it was not asked for
+    pub id: Option<String>,
+    pub paths: Vec<PathBuf>,
+}
+
+impl DuplicateSet {
+    /// Bytes that keeping a single copy would save
+    pub fn wasted(&self) -> u64 {
+        self.size * (self.paths.len() as u64 - 1)
+    }
+}
+
+/// Groups `files` by size; with `client`, files of equal size are told apart
+/// by their IDs, which costs a HEAD request each where the listing did not
+/// give them. Empty files and directories are ignored. The largest waste
+/// comes first.
+pub fn find(
+    files: impl IntoIterator<Item = DirEntry>,
+    client: Option<&seafile::Client>,
+) -> Result<Vec<DuplicateSet>, Error> {
+    let mut by_size: HashMap<u64, Vec<DirEntry>> = HashMap::new();
+    for file in files {
+        match file.size() {
+            Some(size) if size > 0 => by_size.entry(size).or_default().push(file),
+            _ => {}
+        }
+    }
+
+    let mut sets = Vec::new();
+    for (size, files) in by_size.into_iter().filter(|(_, files)| files.len() > 1) {
+        let Some(client) = client else {
+            let paths = files.iter().map(|e| e.path().to_path_buf()).collect();
+            sets.push(DuplicateSet {
+                size,
+                id: None,
+                paths,
+            });
+            continue;
+        };
+        let mut by_id: HashMap<Option<String>, Vec<PathBuf>> = HashMap::new();
+        for file in &files {
+            let id = match (file.id(), file.download_url()) {
+                (Some(id), _) => Some(id.to_string()),
+                (None, Some(url)) => client.head(url)?.etag,
+                (None, None) => None,
+            };
+            by_id.entry(id).or_default().push(file.path().to_path_buf());
+        }
+        sets.extend(
+            by_id
+                .into_iter()
+                .filter(|(_, paths)| paths.len() > 1)
+                .map(|(id, paths)| DuplicateSet { size, id, paths }),
+        );
+    }
+    for set in &mut sets {
+        set.paths.sort();
+    }
+    sets.sort_by(|a, b| b.wasted().cmp(&a.wasted()).then(a.paths.cmp(&b.paths)));
+    Ok(sets)
+}
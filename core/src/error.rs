@@ -0,0 +1,132 @@
+use std::io;
+
+use crate::{progress, retry, seafile};
+
+/// Error of the library API
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The share is invalid or its password is missing or wrong
+    Share(seafile::Error),
+    /// A request failed or the server answered with an error status
+    Http(ureq::Error),
+    /// The share cannot be downloaded from until its traffic quota is reset
+    TrafficLimit(seafile::TrafficLimitExceeded),
+    /// Local I/O failed, or a transfer broke off (`UnexpectedEof`)
+    Io(io::Error),
+    /// Anything else, e.g. an unexpected response
+    Other(anyhow::Error),
+}
+
+impl Error {
+    /// Whether the password session of the share is missing or expired, so
+    /// that the request may succeed once it is renewed
+    pub fn is_session_expired(&self) -> bool {
+        matches!(
+            self,
+            Self::Share(seafile::Error::PasswordRequired)
+                | Self::Http(ureq::Error::StatusCode(403))
+        )
+    }
+
+    /// Whether the transfer was skipped through [`crate::progress::Transfer::skip`]
+    pub fn is_skipped(&self) -> bool {
+        match self {
+            Self::Io(e) => e
+                .get_ref()
+                .is_some_and(|inner| inner.is::<progress::Skipped>()),
+            _ => false,
+        }
+    }
+
+    /// Whether the request may succeed if it is sent again
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::Share(_) | Self::TrafficLimit(_) => false,
+            Self::Http(e) => retry::is_transient_ureq(e),
+            Self::Io(e) => retry::is_transient_io(e),
+            Self::Other(e) => retry::is_transient(e),
+        }
+    }
+}
+
+// Wrapped errors are shown as they are, like `#[error(transparent)]`.
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Share(e) => e.fmt(f),
+            Self::Http(e) => e.fmt(f),
+            Self::TrafficLimit(e) => e.fmt(f),
+            Self::Io(e) => e.fmt(f),
+            Self::Other(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Share(e) => e.source(),
+            Self::Http(e) => e.source(),
+            Self::TrafficLimit(e) => e.source(),
+            Self::Io(e) => e.source(),
+            Self::Other(e) => e.source(),
+        }
+    }
+}
+
+impl From<seafile::Error> for Error {
+    fn from(e: seafile::Error) -> Self {
+        Self::Share(e)
+    }
+}
+
+impl From<seafile::TrafficLimitExceeded> for Error {
+    fn from(e: seafile::TrafficLimitExceeded) -> Self {
+        Self::TrafficLimit(e)
+    }
+}
+
+impl From<ureq::Error> for Error {
+    fn from(e: ureq::Error) -> Self {
+        Self::Http(e)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<regex::Error> for Error {
+    fn from(e: regex::Error) -> Self {
+        Self::Other(e.into())
+    }
+}
+
+/// Recovers the typed error from internal code, which uses anyhow.
+impl From<anyhow::Error> for Error {
+    fn from(e: anyhow::Error) -> Self {
+        let e = match e.downcast::<Error>() {
+            Ok(e) => return e,
+            Err(e) => e,
+        };
+        let e = match e.downcast::<seafile::Error>() {
+            Ok(e) => return Self::Share(e),
+            Err(e) => e,
+        };
+        let e = match e.downcast::<seafile::TrafficLimitExceeded>() {
+            Ok(e) => return Self::TrafficLimit(e),
+            Err(e) => e,
+        };
+        let e = match e.downcast::<ureq::Error>() {
+            Ok(e) => return Self::Http(e),
+            Err(e) => e,
+        };
+        match e.downcast::<io::Error>() {
+            Ok(e) => Self::Io(e),
+            Err(e) => Self::Other(e),
+        }
+    }
+}
@@ -0,0 +1,264 @@
+//! C interface to the client, for frontends written in other languages. The
+//! declarations are in `include/seaf_share.h`; build the shared library with
+//!
+//! ```console
+//! $ cargo rustc -p seaf-share-core --release --features ffi --crate-type cdylib
+//! ```
+//!
+//! Functions returning `int` return 0 on success and -1 on failure, after which
+//! [`seaf_share_last_error`] describes the error. A client must only be used
+//! on the thread that created it.
+
+use std::{
+    cell::RefCell,
+    ffi::{c_char, c_int, c_void, CStr, CString},
+    path::Path,
+    ptr,
+};
+
+use anyhow::Context;
+use url::Url;
+
+use crate::{seafile::Client, Downloader, ShareLink};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// A share link and the connection to its server
+pub struct SeafShareClient {
+    client: Client,
+    link: ShareLink,
+    downloader: Downloader,
+}
+
+/// Entry of a listing, valid for the duration of the callback
+#[repr(C)]
+pub struct SeafShareEntry {
+    pub path: *const c_char,
+    pub is_dir: bool,
+    /// Size in bytes, 0 for directories
+    pub size: u64,
+    /// Modification time in seconds since the Unix epoch, -1 if unknown
+    pub mtime: i64,
+}
+
+pub type SeafShareEntryCallback =
+    extern "C" fn(entry: *const SeafShareEntry, user_data: *mut c_void);
+
+/// Called as data is written, with `total` 0 if the size is unknown
+pub type SeafShareProgressCallback =
+    extern "C" fn(received: u64, total: u64, user_data: *mut c_void);
+
+fn set_error(e: impl std::fmt::Display) {
+    let message = CString::new(e.to_string().replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with_borrow_mut(|last| *last = Some(message));
+}
+
+fn status(result: anyhow::Result<()>) -> c_int {
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            set_error(format!("{:#}", e));
+            -1
+        }
+    }
+}
+
+/// # Safety
+///
+/// `s` must be null or a NUL-terminated string.
+unsafe fn str_arg<'a>(s: *const c_char) -> anyhow::Result<Option<&'a str>> {
+    if s.is_null() {
+        return Ok(None);
+    }
+    Ok(Some(CStr::from_ptr(s).to_str()?))
+}
+
+/// Message of the last failed call on this thread, or null. The string is
+/// valid until the next call that fails.
+#[no_mangle]
+pub extern "C" fn seaf_share_last_error() -> *const c_char {
+    LAST_ERROR.with_borrow(|last| last.as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
+
+/// Opens a share link, unlocking it with `password` unless that is null.
+/// Returns null on failure.
+///
+/// # Safety
+///
+/// `url` must be a NUL-terminated string, and `password` null or one.
+#[no_mangle]
+pub unsafe extern "C" fn seaf_share_client_new(
+    url: *const c_char,
+    password: *const c_char,
+) -> *mut SeafShareClient {
+    let client = || -> anyhow::Result<SeafShareClient> {
+        let url = Url::parse(str_arg(url)?.context("no URL given")?)?;
+        let link = ShareLink::from_url(&url)
+            .with_context(|| format!("{} is not a Seafile share link", url))?;
+        let agent = ureq::agent();
+        let mut client = Client::with_agent(agent.clone(), &url);
+        if let Some(password) = str_arg(password)? {
+            client.unlock(&link.page_url(&client), link.token(), password)?;
+        }
+        Ok(SeafShareClient {
+            client,
+            link,
+            downloader: Downloader::with_client(agent),
+        })
+    };
+    match client() {
+        Ok(client) => Box::into_raw(Box::new(client)),
+        Err(e) => {
+            set_error(format!("{:#}", e));
+            ptr::null_mut()
+        }
+    }
+}
+
+/// # Safety
+///
+/// `client` must be null or returned by [`seaf_share_client_new`], and is
+/// invalid afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn seaf_share_client_free(client: *mut SeafShareClient) {
+    if !client.is_null() {
+        drop(Box::from_raw(client));
+    }
+}
+
+/// Calls `callback` for every entry of the linked directory, or of `path`
+/// below it unless that is null.
+///
+/// # Safety
+///
+/// `client` must be valid and `path` null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn seaf_share_list(
+    client: *const SeafShareClient,
+    path: *const c_char,
+    callback: SeafShareEntryCallback,
+    user_data: *mut c_void,
+) -> c_int {
+    let client = &*client;
+    status((|| {
+        let entries = if client.link.is_single_file() {
+            vec![client.client.file(&client.link, None)?]
+        } else {
+            let path = client.link.resolve(str_arg(path)?.map(Path::new));
+            client.client.entries(client.link.token(), path)?
+        };
+        for entry in entries {
+            let path = CString::new(entry.path().to_string_lossy().as_bytes())?;
+            let entry = SeafShareEntry {
+                path: path.as_ptr(),
+                is_dir: entry.is_dir(),
+                size: entry.size().unwrap_or(0),
+                mtime: entry.last_modified().map_or(-1, |t| t.timestamp()),
+            };
+            callback(&entry, user_data);
+        }
+        Ok(())
+    })())
+}
+
+/// Download URL of the linked file, or of the file at `path` below the linked
+/// directory. Returns null on failure; free the string with
+/// [`seaf_share_string_free`].
+///
+/// # Safety
+///
+/// `client` must be valid and `path` null or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn seaf_share_download_url(
+    client: *const SeafShareClient,
+    path: *const c_char,
+) -> *mut c_char {
+    let client = &*client;
+    let url = || -> anyhow::Result<CString> {
+        let path = client.link.resolve(str_arg(path)?.map(Path::new));
+        let entry = client.client.file(&client.link, path.as_deref())?;
+        let url = entry.download_url().expect("files have a download URL");
+        Ok(CString::new(url.as_str())?)
+    };
+    match url() {
+        Ok(url) => url.into_raw(),
+        Err(e) => {
+            set_error(format!("{:#}", e));
+            ptr::null_mut()
+        }
+    }
+}
+
+/// # Safety
+///
+/// `s` must be null or returned by this library, and is invalid afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn seaf_share_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// Writer reporting the bytes written so far to a C callback
+struct ProgressWriter<W> {
+    inner: W,
+    received: u64,
+    total: u64,
+    progress: Option<SeafShareProgressCallback>,
+    user_data: *mut c_void,
+}
+
+impl<W: std::io::Write> std::io::Write for ProgressWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.received += n as u64;
+        if let Some(progress) = self.progress {
+            progress(self.received, self.total, self.user_data);
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writes the linked file, or the file at `path` below the linked directory,
+/// to the open file descriptor `fd`, which is left open. `progress` may be
+/// null.
+///
+/// # Safety
+///
+/// `client` must be valid, `path` null or a NUL-terminated string and `fd` a
+/// file descriptor open for writing.
+#[cfg(unix)]
+#[no_mangle]
+pub unsafe extern "C" fn seaf_share_download_fd(
+    client: *const SeafShareClient,
+    path: *const c_char,
+    fd: c_int,
+    progress: Option<SeafShareProgressCallback>,
+    user_data: *mut c_void,
+) -> c_int {
+    use std::os::fd::FromRawFd;
+
+    let client = &*client;
+    status((|| {
+        let path = client.link.resolve(str_arg(path)?.map(Path::new));
+        let entry = client.client.file(&client.link, path.as_deref())?;
+        let url = entry.download_url().expect("files have a download URL");
+        // borrowed from the caller, who closes it
+        let mut file = std::mem::ManuallyDrop::new(std::fs::File::from_raw_fd(fd));
+        let mut writer = ProgressWriter {
+            inner: &mut *file,
+            received: 0,
+            total: entry.size().unwrap_or(0),
+            progress,
+            user_data,
+        };
+        client.downloader.download(&mut writer, url, entry.size())?;
+        Ok(())
+    })())
+}
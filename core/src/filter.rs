@@ -0,0 +1,218 @@
+//! Ordered include/exclude rules in the style of rsync's `--filter`.
+//!
+//! A rule is `+ PATTERN` (include) or `- PATTERN` (exclude), with a glob
+//! [`Pattern`] matched against the full remote path, in which `*` and `?`
+//! stay within a directory and `**` spans any; a trailing "/" limits the
+//! rule to directories. The first matching rule decides, and an excluded
+//! directory is not searched, so "exclude everything under /raw except CSV
+//! files" reads:
+//!
+//! ```text
+//! + /raw/**/
+//! + /raw/**/*.csv
+//! - /raw/**
+//! ```
+
+use std::{fmt, path::Path, str::FromStr};
+
+use glob::{MatchOptions, Pattern};
+
+/// `*` and `?` do not match "/", like in rsync
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+#[derive(Debug, Clone)]
+pub struct Rule {
+    include: bool,
+    pattern: Pattern,
+    dir_only: bool,
+}
+
+impl Rule {
+    pub fn include(pattern: Pattern) -> Self {
+        Self {
+            include: true,
+            pattern,
+            dir_only: false,
+        }
+    }
+
+    pub fn exclude(pattern: Pattern) -> Self {
+        Self {
+            include: false,
+            pattern,
+            dir_only: false,
+        }
+    }
+
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        (is_dir || !self.dir_only) && self.pattern.matches_path_with(path, MATCH_OPTIONS)
+    }
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (include, pattern) = match s.split_once(' ') {
+            Some(("+", pattern)) => (true, pattern),
+            Some(("-", pattern)) => (false, pattern),
+            _ => {
+                return Err(format!(
+                    "\"{}\" is neither \"+ PATTERN\" nor \"- PATTERN\"",
+                    s
+                ))
+            }
+        };
+        let dir_only = pattern.len() > 1 && pattern.ends_with('/');
+        let pattern = if dir_only {
+            &pattern[..pattern.len() - 1]
+        } else {
+            pattern
+        };
+        Ok(Self {
+            include,
+            pattern: Pattern::new(pattern).map_err(|e| e.to_string())?,
+            dir_only,
+        })
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.include { '+' } else { '-' };
+        let slash = if self.dir_only { "/" } else { "" };
+        write!(f, "{} {}{}", sign, self.pattern, slash)
+    }
+}
+
+/// Rules read from a file, one per line; blank lines and comments (starting
+/// with "#" or ";") are skipped
+#[derive(Debug, Clone)]
+pub struct RuleFile(pub Vec<Rule>);
+
+/// Reads a `--filter-from` file.
+pub fn read_rules(path: &str) -> Result<RuleFile, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    text.lines()
+        .enumerate()
+        .map(|(n, line)| (n, line.trim_end_matches('\r')))
+        .filter(|(_, line)| {
+            !(line.trim().is_empty() || line.starts_with('#') || line.starts_with(';'))
+        })
+        .map(|(n, line)| {
+            line.parse()
+                .map_err(|e| format!("{}:{}: {}", path, n + 1, e))
+        })
+        .collect::<Result<_, _>>()
+        .map(RuleFile)
+}
+
+/// Ordered rules deciding which remote paths are transferred
+#[derive(Debug, Clone)]
+pub struct Filter {
+    rules: Vec<Rule>,
+    /// Whether paths that match no rule are transferred
+    default: bool,
+}
+
+impl Filter {
+    /// Paths matching no rule are transferred if `default` holds; directories
+    /// are searched either way.
+    pub fn new(rules: Vec<Rule>, default: bool) -> Self {
+        Self { rules, default }
+    }
+
+    fn decide(&self, path: &Path, is_dir: bool) -> Option<bool> {
+        // directories are listed with a trailing slash
+        let path = path
+            .to_str()
+            .and_then(|p| p.strip_suffix('/'))
+            .filter(|p| !p.is_empty())
+            .map_or(path, Path::new);
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(path, is_dir))
+            .map(|rule| rule.include)
+    }
+
+    /// Whether `path` is transferred
+    pub fn selects(&self, path: &Path, is_dir: bool) -> bool {
+        self.decide(path, is_dir).unwrap_or(self.default)
+    }
+
+    /// Whether the directory `path` is left out along with its contents
+    pub fn prunes(&self, path: &Path) -> bool {
+        self.decide(path, true) == Some(false)
+    }
+
+    /// Whether the walk yields `path`: directories that are not pruned, so
+    /// selected files below them are found, and selected files
+    pub fn admits(&self, path: &Path, is_dir: bool) -> bool {
+        if is_dir {
+            !self.prunes(path)
+        } else {
+            self.selects(path, false)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter(rules: &[&str]) -> Filter {
+        let rules = rules.iter().map(|rule| rule.parse().unwrap()).collect();
+        Filter::new(rules, true)
+    }
+
+    #[test]
+    fn everything_under_raw_except_csv_files() {
+        let filter = filter(&["+ /raw/**/", "+ /raw/**/*.csv", "- /raw/**"]);
+        for (path, is_dir, selected) in [
+            ("/raw", true, true),
+            ("/raw/2024", true, true),
+            ("/raw/2024/05", true, true),
+            ("/raw/a.csv", false, true),
+            ("/raw/2024/05/b.csv", false, true),
+            ("/raw/a.txt", false, false),
+            ("/raw/2024/05/b.json", false, false),
+            ("/docs/c.txt", false, true),
+        ] {
+            assert_eq!(
+                filter.selects(Path::new(path), is_dir),
+                selected,
+                "{}",
+                path
+            );
+        }
+        // directories are searched, not pruned
+        assert!(!filter.prunes(Path::new("/raw/2024")));
+    }
+
+    #[test]
+    fn wildcards_stay_within_a_directory() {
+        let single = filter(&["- /docs/*.txt"]);
+        assert!(!single.selects(Path::new("/docs/a.txt"), false));
+        assert!(single.selects(Path::new("/docs/a/b.txt"), false));
+        assert!(single.selects(Path::new("/docs/a"), true));
+
+        let any = filter(&["- /docs/**/*.txt"]);
+        assert!(!any.selects(Path::new("/docs/a.txt"), false));
+        assert!(!any.selects(Path::new("/docs/a/b.txt"), false));
+
+        let one = filter(&["- /docs/?"]);
+        assert!(!one.selects(Path::new("/docs/a"), true));
+        assert!(one.selects(Path::new("/docs/a/b"), false));
+    }
+
+    #[test]
+    fn trailing_slashes_match_directories_only() {
+        let filter = filter(&["- /tmp/"]);
+        assert!(filter.prunes(Path::new("/tmp/")));
+        assert!(filter.selects(Path::new("/tmp"), false));
+    }
+}
@@ -0,0 +1,582 @@
+//! Listing and downloading of Seafile share links.
+//!
+//! [`ShareLink::from_url`] parses a link, [`seafile::Client`] lists its
+//! entries ([`DirEntry`]) and [`Downloader`] transfers files. The
+//! `seaf-share` command line tool is built on top of this crate; see the
+//! `examples` directory for smaller programs. Libraries of an account are
+//! read the same way with an API token, see [`auth`].
+//!
+//! # Stability
+//!
+//! The crate follows semantic versioning: until 1.0, releases that break the
+//! public API bump the minor version (0.1 → 0.2), and patch releases only add
+//! to it. Enums marked `#[non_exhaustive]` may gain variants in any release.
+//! Modules and items hidden from the documentation are not covered.
+//!
+//! # Features
+//!
+//! - `client` (default): the server client, downloads and everything else
+//!   that needs the network or the file system. Without it, the crate is left
+//!   with link parsing, entries, filter rules and size accounting, and
+//!   compiles to `wasm32-unknown-unknown`, e.g. for a browser extension.
+//! - `clap`: derives `clap::ValueEnum` for [`ConflictAction`],
+//!   [`Recursive`] and [`seafile::Api`], so that they can be used as command
+//!   line options directly.
+//! - `ffi`: a C interface to the client in [`ffi`], for frontends in other
+//!   languages.
+//! - `python`: a Python extension module in [`python`], for scripts that
+//!   fetch data sets from shares.
+//! - `io-uring`: writes downloaded files through io_uring on Linux.
+//! - `http-cache`: a disk cache of API responses in [`cache`], shared by runs.
+
+#[cfg(feature = "client")]
+pub mod auth;
+pub mod breaker;
+#[cfg(feature = "http-cache")]
+pub mod cache;
+#[cfg(feature = "client")]
+pub mod checksum;
+#[cfg(feature = "client")]
+mod download;
+#[cfg(feature = "client")]
+pub mod duplicates;
+#[cfg(feature = "client")]
+mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod filter;
+#[cfg(feature = "client")]
+mod multipart;
+pub mod ncdu;
+pub mod plan;
+#[cfg(feature = "client")]
+pub mod pool;
+#[cfg(feature = "client")]
+pub mod post;
+#[cfg(feature = "client")]
+pub mod progress;
+#[cfg(feature = "python")]
+pub mod python;
+#[cfg(feature = "client")]
+mod queue;
+pub mod redact;
+#[cfg(feature = "client")]
+pub mod retry;
+pub mod seafile;
+#[cfg(feature = "client")]
+pub mod seed;
+#[cfg(feature = "client")]
+pub mod shutdown;
+#[cfg(feature = "client")]
+pub mod stage;
+#[cfg(feature = "client")]
+pub mod stall;
+#[cfg(feature = "client")]
+pub mod state;
+#[cfg(feature = "client")]
+pub mod summary;
+pub mod sync;
+#[cfg(feature = "client")]
+pub mod tape;
+pub mod tree;
+#[cfg(feature = "client")]
+pub mod upload;
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+mod uring;
+pub mod usage;
+#[cfg(feature = "client")]
+pub mod verify;
+#[cfg(feature = "client")]
+pub mod volume;
+#[cfg(feature = "client")]
+pub mod walker;
+#[cfg(feature = "client")]
+pub mod watch;
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+#[cfg(feature = "client")]
+pub use download::{download_with_session, Downloader};
+#[cfg(feature = "client")]
+pub use error::Error;
+use seafile::ShareToken;
+
+/// What to do with a file that already exists at the destination
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[non_exhaustive]
+pub enum ConflictAction {
+    /// Skip if a file exists
+    #[default]
+    Skip,
+
+    /// Verify by downloading remote chunks in memory, overwrite if the checksum
+    /// is not correct.
+    Check,
+
+    /// Continue the download by sending partial requests ("Range" header).
+    Continue,
+
+    /// always overwrite the destination
+    Overwrite,
+
+    /// Download again if the size or modification time differs from remote
+    Update,
+}
+
+/// Order in which subdirectories are traversed, if at all
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[non_exhaustive]
+pub enum Recursive {
+    /// Do not look into subdirectory entries
+    #[default]
+    None,
+
+    /// Traverse subdirectories by DFS
+    Dfs,
+
+    /// Traverse subdirectories by BFS
+    Bfs,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DownloadResult {
+    Skipped,
+    Overwritten,
+    Continued,
+    Complete,
+    Updated(sync::Changes),
+}
+
+impl std::fmt::Display for DownloadResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Skipped => write!(f, "skipped"),
+            Self::Overwritten => write!(f, "overwritten"),
+            Self::Continued => write!(f, "continued"),
+            Self::Complete => write!(f, "complete"),
+            Self::Updated(_) => write!(f, "updated"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ShareLink {
+    Directory {
+        token: ShareToken,
+        path: Option<PathBuf>,
+        file: bool,
+    },
+    SingleFile {
+        token: ShareToken,
+    },
+    Upload {
+        token: ShareToken,
+    },
+    /// Download URL of a file on the file server, as share pages redirect
+    /// to, valid until its access token expires (an hour by default)
+    FileServer {
+        token: ShareToken,
+        url: Url,
+    },
+}
+
+impl ShareLink {
+    pub fn token(&self) -> &ShareToken {
+        match self {
+            Self::Directory { token, .. } | Self::FileServer { token, .. } => token,
+            Self::SingleFile { token } | Self::Upload { token } => token,
+        }
+    }
+    pub fn is_single_file(&self) -> bool {
+        match self {
+            Self::Directory { .. } | Self::Upload { .. } => false,
+            Self::SingleFile { .. } | Self::FileServer { .. } => true,
+        }
+    }
+    pub fn is_upload(&self) -> bool {
+        matches!(self, Self::Upload { .. })
+    }
+    pub fn is_dir(&self) -> bool {
+        !self.is_file()
+    }
+    pub fn is_file(&self) -> bool {
+        match self {
+            Self::Directory { file, .. } => *file,
+            Self::SingleFile { .. } | Self::FileServer { .. } => true,
+            Self::Upload { .. } => false,
+        }
+    }
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Self::Directory { path, .. } => path.as_ref().map(|p| p.as_ref()),
+            Self::SingleFile { .. } | Self::Upload { .. } | Self::FileServer { .. } => None,
+        }
+    }
+    /// Remote path of `path` relative to the linked directory, or the linked
+    /// path itself without one
+    pub fn resolve(&self, path: Option<&Path>) -> Option<PathBuf> {
+        match path {
+            Some(path) => Some(self.path().unwrap_or(Path::new("/")).join(path)),
+            None => self.path().map(Path::to_path_buf),
+        }
+    }
+    /// URL of the share page, which also hosts the password form
+    #[cfg(feature = "client")]
+    pub fn page_url(&self, client: &seafile::Client) -> Url {
+        match self {
+            Self::Directory { token, .. } => client.dir_url(token, None::<&Path>),
+            Self::SingleFile { token } => client.single_file_url(token),
+            Self::Upload { token } => client.upload_page_url(token),
+            Self::FileServer { url, .. } => url.clone(),
+        }
+    }
+    /// Parses links as copied from the web UI, which may live below a
+    /// subpath and carry a trailing slash, a fragment or UI-only query
+    /// parameters (e.g. "mode=list"); only "p" (the path) is used.
+    ///
+    /// Links to a library of the account, `/library/<repo>/<name>/<path>`
+    /// for directories and `/lib/<repo>/file/<path>` for files, are read
+    /// through the authenticated API (see [`ShareToken::library`]). Smart
+    /// links are not parsed, see [`ShareLink::is_smart_link`].
+    ///
+    /// Direct download URLs of a file are accepted too: those of a share,
+    /// `/d/<token>/files/?p=<path>&dl=1` and `/f/<token>/?dl=1`, and those of
+    /// the file server they redirect to, `/seafhttp/files/<access token>/<name>`.
+    pub fn from_url(url: &Url) -> Option<Self> {
+        let segments: Vec<_> = url.path_segments()?.filter(|s| !s.is_empty()).collect();
+        if let Some(i) = file_server_segment(&segments) {
+            return Some(ShareLink::FileServer {
+                token: ShareToken::access(segments[i + 2]).ok()?,
+                url: url.clone(),
+            });
+        }
+        if let Some(i) = library_segment(&segments) {
+            let (token, rest) = (
+                ShareToken::library(segments[i + 1]).ok()?,
+                &segments[i + 2..],
+            );
+            let (path, file) = match (segments[i], rest) {
+                ("library", [_name, path @ ..]) => (path, false),
+                ("library", []) => (rest, false),
+                ("lib", ["file", path @ ..]) if !path.is_empty() => (path, true),
+                _ => return None,
+            };
+            let path = path
+                .iter()
+                .map(|s| percent_encoding::percent_decode_str(s).decode_utf8_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            return Some(ShareLink::Directory {
+                token,
+                path: remote_path(&path),
+                file,
+            });
+        }
+        let share = match segments.as_slice() {
+            [.., "u", "d", token] => ShareLink::Upload {
+                token: token.parse().ok()?,
+            },
+            [.., "d", token] | [.., "d", token, "files"] => ShareLink::Directory {
+                token: token.parse().ok()?,
+                path: url
+                    .query_pairs()
+                    .find_map(|(k, v)| (k == "p").then_some(v))
+                    .and_then(|p| remote_path(&p)),
+                file: segments.last() == Some(&"files"),
+            },
+            [.., "f", token] => ShareLink::SingleFile {
+                token: token.parse().ok()?,
+            },
+            _ => return None,
+        };
+        Some(share)
+    }
+    /// Whether `url` is a smart link, `/smart-link/<uuid>/`, which Seafile
+    /// makes for files and directories of a library. Such a link only names
+    /// its target to accounts with access to the library:
+    /// [`seafile::resolve_smart_link`] exchanges it for the link to the
+    /// library that [`ShareLink::from_url`] parses.
+    pub fn is_smart_link(url: &Url) -> bool {
+        let segments: Vec<_> = url
+            .path_segments()
+            .map_or(Vec::new(), |s| s.filter(|s| !s.is_empty()).collect());
+        smart_link_segment(&segments).is_some()
+    }
+}
+
+/// Index of the "smart-link" segment of a smart link, which its UUID follows
+pub(crate) fn smart_link_segment(segments: &[&str]) -> Option<usize> {
+    segments
+        .windows(2)
+        .position(|pair| pair[0] == "smart-link" && seafile::is_uuid(pair[1]))
+}
+
+/// Index of the "seafhttp" segment of a download URL of the file server,
+/// which "files", the access token and the name of the file follow
+pub(crate) fn file_server_segment(segments: &[&str]) -> Option<usize> {
+    segments.windows(4).position(|window| {
+        window[..2] == ["seafhttp", "files"] && ShareToken::access(window[2]).is_ok()
+    })
+}
+
+/// Index of the "library" or "lib" segment of a link to a library, which the
+/// ID of the library follows
+pub(crate) fn library_segment(segments: &[&str]) -> Option<usize> {
+    segments.windows(2).position(|pair| {
+        matches!(pair[0], "library" | "lib") && ShareToken::library(pair[1]).is_ok()
+    })
+}
+
+/// Normalizes a remote path from a link: made absolute, without a trailing
+/// slash, and `None` for the root
+fn remote_path(p: &str) -> Option<PathBuf> {
+    let path = Path::new("/").join(p.trim_end_matches('/'));
+    (path != Path::new("/")).then_some(path)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "snake_case",
+    rename_all_fields = "snake_case"
+)]
+pub enum DirEntry {
+    Directory {
+        name: String,
+        path: PathBuf,
+        last_modified: DateTime<Utc>,
+        view_url: Url,
+    },
+    File {
+        name: String,
+        path: PathBuf,
+        size: u64,
+        last_modified: Option<DateTime<Utc>>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        content_type: Option<String>,
+        /// ID of the contents, which changes with them, where the listing
+        /// or a HEAD request told it
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        id: Option<String>,
+        download_url: Url,
+        view_url: Url,
+    },
+}
+
+impl DirEntry {
+    pub fn is_file(&self) -> bool {
+        match self {
+            Self::Directory { .. } => false,
+            Self::File { .. } => true,
+        }
+    }
+    pub fn is_dir(&self) -> bool {
+        match self {
+            Self::Directory { .. } => true,
+            Self::File { .. } => false,
+        }
+    }
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Directory { name, .. } | Self::File { name, .. } => name,
+        }
+    }
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Directory { path, .. } | Self::File { path, .. } => path,
+        }
+    }
+    pub fn size(&self) -> Option<u64> {
+        match self {
+            Self::Directory { .. } => None,
+            Self::File { size, .. } => Some(*size),
+        }
+    }
+    pub fn last_modified(&self) -> Option<&DateTime<Utc>> {
+        match self {
+            Self::Directory { last_modified, .. } => Some(last_modified),
+            Self::File { last_modified, .. } => last_modified.as_ref(),
+        }
+    }
+    pub fn id(&self) -> Option<&str> {
+        match self {
+            Self::Directory { .. } => None,
+            Self::File { id, .. } => id.as_deref(),
+        }
+    }
+    pub fn download_url(&self) -> Option<&Url> {
+        match self {
+            Self::Directory { .. } => None,
+            Self::File { download_url, .. } => Some(download_url),
+        }
+    }
+    /// Merges metadata from a HEAD response: the reported size takes
+    /// precedence, other fields are only filled in when missing.
+    pub fn enrich(&mut self, metadata: seafile::Metadata) {
+        if let Self::File {
+            size,
+            last_modified,
+            content_type,
+            id,
+            ..
+        } = self
+        {
+            if let Some(sz) = metadata.size {
+                *size = sz;
+            }
+            if last_modified.is_none() {
+                *last_modified = metadata.last_modified;
+            }
+            if content_type.is_none() {
+                *content_type = metadata.content_type;
+            }
+            if id.is_none() {
+                *id = metadata.etag;
+            }
+        }
+    }
+    pub fn view_url(&self) -> &Url {
+        match self {
+            Self::Directory { view_url, .. } | Self::File { view_url, .. } => view_url,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REPO: &str = "a1b2c3d4-0000-4000-8000-0123456789ab";
+
+    /// `link` as "<kind> <token> <path>", with "-" for no path
+    fn describe(link: &ShareLink) -> String {
+        let kind = match link {
+            ShareLink::Directory { file: false, .. } => "dir",
+            ShareLink::Directory { file: true, .. } => "file",
+            ShareLink::SingleFile { .. } => "single",
+            ShareLink::Upload { .. } => "upload",
+            ShareLink::FileServer { .. } => "seafhttp",
+        };
+        let library = if link.token().is_library() {
+            "library "
+        } else {
+            ""
+        };
+        let path = link.path().map_or("-".into(), |p| p.to_string_lossy());
+        format!("{} {}{} {}", kind, library, link.token(), path)
+    }
+
+    #[test]
+    fn share_links_from_urls() {
+        let links = [
+            ("https://cloud.example/d/abc123/", "dir abc123 -"),
+            ("https://cloud.example/d/abc123", "dir abc123 -"),
+            ("https://cloud.example/d/abc123//", "dir abc123 -"),
+            ("https://cloud.example/d/abc123/?mode=list", "dir abc123 -"),
+            ("https://cloud.example/d/abc123/?mode=grid", "dir abc123 -"),
+            ("https://cloud.example/d/abc123/#top", "dir abc123 -"),
+            ("https://cloud.example/d/abc123/?p=/", "dir abc123 -"),
+            ("https://cloud.example/d/abc123/?p=/sub/", "dir abc123 /sub"),
+            (
+                "https://cloud.example/d/abc123/?p=%2Fsub%2Fa%20b&mode=list#x",
+                "dir abc123 /sub/a b",
+            ),
+            ("https://cloud.example/d/abc123/?p=sub", "dir abc123 /sub"),
+            (
+                "https://cloud.example/seafile/d/abc123/?p=/sub",
+                "dir abc123 /sub",
+            ),
+            (
+                "https://cloud.example/d/abc123/files/?p=/sub/a.txt&dl=1",
+                "file abc123 /sub/a.txt",
+            ),
+            ("https://cloud.example/f/fff999/", "single fff999 -"),
+            ("https://cloud.example/f/fff999", "single fff999 -"),
+            ("https://cloud.example/f/fff999/?dl=1", "single fff999 -"),
+            ("https://cloud.example/seafile/f/fff999/#x", "single fff999 -"),
+            ("https://cloud.example/u/d/abc123/", "upload abc123 -"),
+            ("https://cloud.example/u/d/abc123", "upload abc123 -"),
+            (
+                "https://cloud.example/seafhttp/files/4b2d9a8e-0000-4000-8000-0123456789ab/report.pdf",
+                "seafhttp 4b2d9a8e-0000-4000-8000-0123456789ab -",
+            ),
+            (
+                "https://cloud.example/seafile/seafhttp/files/4b2d9a8e-0000-4000-8000-0123456789ab/a.pdf?x=1",
+                "seafhttp 4b2d9a8e-0000-4000-8000-0123456789ab -",
+            ),
+        ];
+        for (url, expected) in links {
+            let link = ShareLink::from_url(&url.parse().unwrap());
+            assert_eq!(
+                link.as_ref().map(describe).as_deref(),
+                Some(expected),
+                "{}",
+                url
+            );
+        }
+    }
+
+    #[test]
+    fn library_links_from_urls() {
+        let links = [
+            (format!("https://cloud.example/library/{}/", REPO), "dir"),
+            (
+                format!("https://cloud.example/library/{}/Photos/", REPO),
+                "dir",
+            ),
+            (
+                format!("https://cloud.example/library/{}/Photos/2024/a%20b/", REPO),
+                "dir /2024/a b",
+            ),
+            (
+                format!("https://cloud.example/lib/{}/file/docs/a%20b.txt", REPO),
+                "file /docs/a b.txt",
+            ),
+        ];
+        for (url, expected) in links {
+            let link = ShareLink::from_url(&url.parse().unwrap()).unwrap();
+            let expected = match expected.split_once(' ') {
+                Some((kind, path)) => format!("{} library {} {}", kind, REPO, path),
+                None => format!("{} library {} -", expected, REPO),
+            };
+            assert_eq!(describe(&link), expected, "{}", url);
+        }
+    }
+
+    #[test]
+    fn other_urls_are_not_share_links() {
+        let urls = [
+            "https://cloud.example/".to_string(),
+            "https://cloud.example/about/".to_string(),
+            "https://cloud.example/d/".to_string(),
+            "https://cloud.example/d/ABC123/".to_string(),
+            "https://cloud.example/d/abc-123/".to_string(),
+            format!("https://cloud.example/lib/{}/", REPO),
+            "https://cloud.example/library/not-a-uuid/Photos/".to_string(),
+            "https://cloud.example/seafhttp/files/abc123/a.pdf".to_string(),
+            format!("https://cloud.example/smart-link/{}/", REPO),
+            format!("https://cloud.example/smart-link/{}/?mode=list", REPO),
+        ];
+        for url in urls {
+            let url = url.parse().unwrap();
+            assert!(ShareLink::from_url(&url).is_none(), "{}", url);
+        }
+    }
+
+    #[test]
+    fn smart_links() {
+        let smart = format!("https://cloud.example/seafile/smart-link/{}/", REPO);
+        assert!(ShareLink::is_smart_link(&smart.parse().unwrap()));
+        let share = "https://cloud.example/d/abc123/".parse().unwrap();
+        assert!(!ShareLink::is_smart_link(&share));
+        let short = "https://cloud.example/smart-link/abc123/".parse().unwrap();
+        assert!(!ShareLink::is_smart_link(&short));
+    }
+}
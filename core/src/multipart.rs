@@ -0,0 +1,150 @@
+//! Streamed `multipart/form-data` bodies (RFC 7578), which ureq does not
+//! provide. File parts are read as the body is sent, and the total length is
+//! known up front so servers receive a "Content-Length".
+
+use std::{
+    fs::File,
+    io::{self, Cursor, Read},
+    path::Path,
+};
+
+pub struct Form {
+    boundary: String,
+    parts: Vec<Box<dyn Read + Send>>,
+    len: u64,
+}
+
+impl Form {
+    /// A form whose boundary is made of the number `random`
+    pub fn new(random: u64) -> Self {
+        Self {
+            boundary: format!("----seaf-share-{:016x}", random),
+            parts: Vec::new(),
+            len: 0,
+        }
+    }
+
+    pub fn text(mut self, name: &str, value: &str) -> Self {
+        let header = self.part_header(name, None);
+        self.push_bytes(format!("{}{}\r\n", header, value).into_bytes());
+        self
+    }
+
+    /// Adds the contents of `path` under the given file name.
+    pub fn file(mut self, name: &str, path: &Path, filename: &str) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let header = self.part_header(name, Some(filename));
+        self.push_bytes(header.into_bytes());
+        self.parts.push(Box::new(file.take(len)));
+        self.len += len;
+        self.push_bytes(b"\r\n".to_vec());
+        Ok(self)
+    }
+
+    pub fn content_type(&self) -> String {
+        format!("multipart/form-data; boundary={}", self.boundary)
+    }
+
+    /// Length of the body returned by [`Form::into_reader`]
+    pub fn len(&self) -> u64 {
+        self.len + self.boundary.len() as u64 + 6
+    }
+
+    pub fn into_reader(self) -> impl Read + Send {
+        let end = format!("--{}--\r\n", self.boundary).into_bytes();
+        self.parts
+            .into_iter()
+            .fold(
+                Box::new(io::empty()) as Box<dyn Read + Send>,
+                |body, part| Box::new(body.chain(part)),
+            )
+            .chain(Cursor::new(end))
+    }
+
+    fn part_header(&self, name: &str, filename: Option<&str>) -> String {
+        let mut header = format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"{}\"",
+            self.boundary,
+            escape(name)
+        );
+        if let Some(filename) = filename {
+            header.push_str(&format!(
+                "; filename=\"{}\"\r\nContent-Type: application/octet-stream",
+                escape(filename)
+            ));
+        }
+        header.push_str("\r\n\r\n");
+        header
+    }
+
+    fn push_bytes(&mut self, bytes: Vec<u8>) {
+        self.len += bytes.len() as u64;
+        self.parts.push(Box::new(Cursor::new(bytes)));
+    }
+}
+
+/// Escapes a quoted parameter the way browsers do
+fn escape(s: &str) -> String {
+    s.replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body(form: Form) -> (u64, Vec<u8>) {
+        let len = form.len();
+        let mut body = Vec::new();
+        form.into_reader().read_to_end(&mut body).unwrap();
+        (len, body)
+    }
+
+    #[test]
+    fn len_is_that_of_the_body() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("report.pdf");
+        std::fs::write(&path, b"%PDF-1.7\n\0\xff binary contents\r\n").unwrap();
+        let forms = [
+            Form::new(0),
+            Form::new(1).text("parent_dir", "/"),
+            Form::new(2)
+                .text("parent_dir", "/uploads")
+                .text("relative_path", "naïve \"quoted\"\r\ndir"),
+            Form::new(3).file("file", &path, "report.pdf").unwrap(),
+            Form::new(u64::MAX)
+                .text("parent_dir", "/")
+                .file("file", &path, "résumé \"final\".pdf")
+                .unwrap()
+                .file("file", &path, "copy.pdf")
+                .unwrap(),
+        ];
+        for form in forms {
+            let (len, body) = body(form);
+            assert_eq!(len, body.len() as u64, "{}", String::from_utf8_lossy(&body));
+        }
+    }
+
+    #[test]
+    fn parts_are_delimited() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.txt");
+        std::fs::write(&path, "contents").unwrap();
+        let form = Form::new(0xab)
+            .text("parent_dir", "/")
+            .file("file", &path, "a\".txt")
+            .unwrap();
+        let (_, body) = body(form);
+        assert_eq!(
+            String::from_utf8(body).unwrap(),
+            "------seaf-share-00000000000000ab\r\n\
+             Content-Disposition: form-data; name=\"parent_dir\"\r\n\r\n/\r\n\
+             ------seaf-share-00000000000000ab\r\n\
+             Content-Disposition: form-data; name=\"file\"; filename=\"a%22.txt\"\r\n\
+             Content-Type: application/octet-stream\r\n\r\ncontents\r\n\
+             ------seaf-share-00000000000000ab--\r\n"
+        );
+    }
+}
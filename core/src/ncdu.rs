@@ -0,0 +1,58 @@
+//! Export of remote usage in the JSON format of ncdu (`ncdu -f FILE`), so a
+//! share can be browsed interactively without downloading it.
+//!
+//! See <https://dev.yorhel.nl/ncdu/jsonfmt> for the format.
+
+use std::{
+    io::{self, Write},
+    path::Path,
+};
+
+use serde_json::json;
+
+use crate::tree::Tree;
+
+const MAJOR_VERSION: u32 = 1;
+const MINOR_VERSION: u32 = 2;
+
+/// Writes `tree` as an ncdu export whose root is called `root_name`.
+pub fn export(tree: &Tree, root_name: &str, mut out: impl Write) -> io::Result<()> {
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let meta = json!({
+        "progname": env!("CARGO_PKG_NAME"),
+        "progver": env!("CARGO_PKG_VERSION"),
+        "timestamp": timestamp,
+    });
+    write!(out, "[{},{},{},", MAJOR_VERSION, MINOR_VERSION, meta)?;
+    write_dir(&mut out, tree, tree.base(), json!({ "name": root_name }))?;
+    writeln!(out, "]")?;
+    out.flush()
+}
+
+/// Writes a directory as an array of its info and its children.
+fn write_dir(
+    out: &mut impl Write,
+    tree: &Tree,
+    dir: &Path,
+    info: serde_json::Value,
+) -> io::Result<()> {
+    write!(out, "[{}", info)?;
+    for child in tree.children(dir) {
+        write!(out, ",")?;
+        let mut info = json!({ "name": child.name() });
+        if let Some(mtime) = child.last_modified() {
+            info["mtime"] = json!(mtime.timestamp());
+        }
+        if child.is_dir() {
+            write_dir(out, tree, child.path(), info)?;
+        } else {
+            let size = child.size().unwrap_or(0);
+            info["asize"] = json!(size);
+            info["dsize"] = json!(size);
+            write!(out, "{}", info)?;
+        }
+    }
+    write!(out, "]")
+}
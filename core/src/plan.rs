@@ -0,0 +1,69 @@
+//! What a dry run would do with each file, as printed by `--dry-run --json`.
+
+use std::path::Path;
+
+use serde::Serialize;
+use url::Url;
+
+#[cfg(feature = "client")]
+use crate::{sync, ConflictAction, DirEntry, DownloadResult};
+
+/// What a download would do with a file
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlanAction {
+    /// The file does not exist locally
+    Download,
+    Skip,
+    Check,
+    Continue,
+    Overwrite,
+    /// The local file differs from the remote one
+    Update,
+}
+
+/// A file a dry run found, and what the download would do with it
+#[derive(Debug, Clone, Serialize)]
+pub struct PlanRecord<'a> {
+    /// Remote path of the file
+    pub path: &'a Path,
+    /// Local path it would be written to
+    pub dest: &'a Path,
+    pub size: Option<u64>,
+    pub url: Option<&'a Url>,
+    pub action: PlanAction,
+}
+
+#[cfg(feature = "client")]
+impl<'a> PlanRecord<'a> {
+    /// Plans `entry` for `dest`, resolving an existing file there according
+    /// to `on_conflict` (and `ids`, see [`sync::check`]).
+    pub fn new(
+        entry: &'a DirEntry,
+        dest: &'a Path,
+        on_conflict: ConflictAction,
+        ids: Option<&sync::FileIds>,
+    ) -> anyhow::Result<Self> {
+        let action = if !std::fs::exists(dest)? {
+            PlanAction::Download
+        } else {
+            match on_conflict {
+                ConflictAction::Skip => PlanAction::Skip,
+                ConflictAction::Check => PlanAction::Check,
+                ConflictAction::Continue => PlanAction::Continue,
+                ConflictAction::Overwrite => PlanAction::Overwrite,
+                ConflictAction::Update => match sync::check(entry, dest, ids)? {
+                    DownloadResult::Skipped => PlanAction::Skip,
+                    _ => PlanAction::Update,
+                },
+            }
+        };
+        Ok(Self {
+            path: entry.path(),
+            dest,
+            size: entry.size(),
+            url: entry.download_url(),
+            action,
+        })
+    }
+}
@@ -0,0 +1,194 @@
+//! Workers of a download run, torn down as described in [`crate::shutdown`].
+//!
+//! Items fed to a [`Pool`] are transferred by its workers, and what those
+//! produce is finalized by workers of its own, so that hooks and disk
+//! metadata updates do not hold up the network. The outcome of every item is
+//! reported on the feeding thread.
+
+use std::{
+    cell::RefCell,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver, SyncSender},
+        Mutex,
+    },
+};
+
+use crate::shutdown::Abort;
+
+/// Workers of a run, in two stages: transfers and their finalization
+pub struct Pool<'a> {
+    abort: &'a Abort,
+    jobs: usize,
+    finishers: usize,
+    drain: Option<&'a (dyn Fn() -> bool + Sync)>,
+}
+
+impl<'a> Pool<'a> {
+    /// `jobs` workers transfer items and `finishers` finalize them, until
+    /// `abort` is triggered.
+    pub fn new(abort: &'a Abort, jobs: usize, finishers: usize) -> Self {
+        Self {
+            abort,
+            jobs: jobs.max(1),
+            finishers: finishers.max(1),
+            drain: None,
+        }
+    }
+
+    /// Drains the pool once `drain` returns true, e.g. on the first Ctrl-C:
+    /// no further items are started, while those in progress are completed.
+    pub fn with_drain(mut self, drain: &'a (dyn Fn() -> bool + Sync)) -> Self {
+        self.drain = Some(drain);
+        self
+    }
+
+    /// Whether the pool drains or aborts, so that feeding it should stop
+    pub fn is_stopping(&self) -> bool {
+        self.abort.is_triggered() || self.drain.is_some_and(|drain| drain())
+    }
+
+    /// Runs the pool while `feed` hands it items, and returns what `feed`
+    /// returns once every item handed over is reported.
+    ///
+    /// Each worker transfers items with a function made by `worker` on its
+    /// own thread, which gives either a job for `finish` or the outcome of
+    /// an item that failed, as [`crate::download_with_session`] does.
+    /// `report` gets the outcomes, both while `feed` runs and after it.
+    ///
+    /// A panicking worker aborts the run, and the panic is raised again once
+    /// the other workers are done.
+    pub fn run<T, J, O, W, R>(
+        &self,
+        worker: impl Fn() -> W + Sync,
+        finish: impl Fn(J) -> O + Sync,
+        mut report: impl FnMut(O),
+        feed: impl FnOnce(&Feeder<'_, T, O>) -> R,
+    ) -> R
+    where
+        T: Send,
+        J: Send,
+        O: Send,
+        W: FnMut(T) -> Result<J, Box<O>>,
+    {
+        let (work, queue) = mpsc::sync_channel(self.jobs);
+        let queue = Queue::new(queue, self.jobs);
+        let (to_finish, finishing) = mpsc::sync_channel(self.finishers);
+        let finishing = Queue::new(finishing, self.finishers);
+        let (done, results) = mpsc::channel();
+        std::thread::scope(|scope| {
+            for _ in 0..self.jobs {
+                let (queue, to_finish, done) = (&queue, to_finish.clone(), done.clone());
+                let worker = &worker;
+                scope.spawn(move || {
+                    let _guard = self.abort.on_panic();
+                    let _member = queue.join();
+                    let mut transfer = worker();
+                    while let Some(item) = queue.recv() {
+                        // the rest of the queue is left, without blocking
+                        // the feeding thread on a full queue
+                        if self.is_stopping() {
+                            continue;
+                        }
+                        let sent = match transfer(item) {
+                            Ok(job) => to_finish.send(job).is_ok(),
+                            Err(outcome) => done.send(*outcome).is_ok(),
+                        };
+                        if !sent {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(to_finish);
+            // jobs already transferred are finalized even on abort
+            for _ in 0..self.finishers {
+                let (finishing, done, finish) = (&finishing, done.clone(), &finish);
+                scope.spawn(move || {
+                    let _guard = self.abort.on_panic();
+                    let _member = finishing.join();
+                    while let Some(job) = finishing.recv() {
+                        if done.send(finish(job)).is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+            drop(done);
+
+            let feeder = Feeder {
+                pool: self,
+                work,
+                results: &results,
+                report: RefCell::new(&mut report),
+            };
+            let fed = feed(&feeder);
+            // closes the queue, so that idle workers quit
+            drop(feeder);
+            results.iter().for_each(report);
+            fed
+        })
+    }
+}
+
+/// Feeding end of a running [`Pool`]
+pub struct Feeder<'a, T, O> {
+    pool: &'a Pool<'a>,
+    work: SyncSender<T>,
+    results: &'a Receiver<O>,
+    report: RefCell<&'a mut dyn FnMut(O)>,
+}
+
+impl<T, O> Feeder<'_, T, O> {
+    /// Hands `item` to a worker, waiting while all are busy. Returns false,
+    /// dropping the item, once the pool is stopping or no worker is left.
+    pub fn send(&self, item: T) -> bool {
+        !self.pool.is_stopping() && self.work.send(item).is_ok()
+    }
+
+    /// Reports the items finished so far.
+    pub fn report(&self) {
+        let mut report = self.report.borrow_mut();
+        self.results.try_iter().for_each(&mut **report);
+    }
+}
+
+/// Queue of a stage, shared by its workers
+struct Queue<T> {
+    /// Dropped once no worker of the stage is left, so that senders fail
+    /// instead of waiting for a worker forever
+    receiver: Mutex<Option<Receiver<T>>>,
+    workers: AtomicUsize,
+}
+
+impl<T> Queue<T> {
+    fn new(receiver: Receiver<T>, workers: usize) -> Self {
+        Self {
+            receiver: Mutex::new(Some(receiver)),
+            workers: AtomicUsize::new(workers),
+        }
+    }
+
+    /// Takes the next item, or none once the queue is closed and empty.
+    fn recv(&self) -> Option<T> {
+        // the guard must not outlive this call, or workers would run one at a time
+        let item = self.receiver.lock().unwrap().as_ref()?.recv();
+        item.ok()
+    }
+
+    /// Returns a guard that counts a worker of the stage while it is held.
+    fn join(&self) -> Member<'_, T> {
+        Member(self)
+    }
+}
+
+struct Member<'a, T>(&'a Queue<T>);
+
+impl<T> Drop for Member<'_, T> {
+    fn drop(&mut self) {
+        if self.0.workers.fetch_sub(1, Ordering::AcqRel) == 1 {
+            let mut receiver = self.0.receiver.lock().unwrap_or_else(|e| e.into_inner());
+            *receiver = None;
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! Finalization of downloaded files, run on its own worker pool so that
+//! network workers are never blocked on disk metadata or child processes.
+
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use chrono::{DateTime, Utc};
+
+use crate::{
+    checksum::Hasher,
+    summary::{DownloadOutcome, Verification},
+    DownloadResult,
+};
+
+/// A transferred file waiting to be finalized
+pub struct Job {
+    pub file: File,
+    /// Remote path of the file
+    pub path: PathBuf,
+    pub dest: PathBuf,
+    pub mtime: Option<DateTime<Utc>>,
+    pub result: DownloadResult,
+    /// Bytes received for the file, including those of failed attempts
+    pub received: u64,
+    /// Attempts at transferring the file, more than 1 if it was retried and 0
+    /// if it needed none
+    pub attempts: u32,
+    /// When the transfer started
+    pub started: Instant,
+    /// Checksum of what was received, if asked for
+    pub hasher: Option<Hasher>,
+    /// How the file was checked, set by the caller once it is
+    pub verification: Verification,
+}
+
+impl Job {
+    /// Checksum of the file, if asked for
+    pub fn checksum(&mut self) -> Option<std::io::Result<String>> {
+        let hasher = self.hasher.take()?;
+        Some(hasher.finish(&self.dest))
+    }
+
+    /// Sets the modification time of the file to the remote one with
+    /// `archive`, then runs `exec` with its path unless it was skipped.
+    pub fn finish(self, archive: bool, exec: Option<&Path>) -> DownloadOutcome {
+        let result = (|| {
+            if archive {
+                if let Some(mtime) = self.mtime {
+                    self.file.set_modified(mtime.into())?;
+                }
+            }
+            // hand a closed file to hooks
+            drop(self.file);
+
+            if let Some(program) = exec {
+                if self.result != DownloadResult::Skipped {
+                    let status = std::process::Command::new(program)
+                        .arg(&self.dest)
+                        .status()?;
+                    if !status.success() {
+                        anyhow::bail!("{} failed: {}", program.display(), status);
+                    }
+                }
+            }
+            Ok(self.result)
+        })();
+        DownloadOutcome {
+            path: self.path,
+            dest: self.dest,
+            result,
+            received: self.received,
+            elapsed: self.started.elapsed(),
+            attempts: self.attempts,
+            verification: self.verification,
+        }
+    }
+
+    /// Gives up on the file with `error`, e.g. one that failed its check,
+    /// closing it.
+    pub fn fail(self, error: anyhow::Error) -> DownloadOutcome {
+        DownloadOutcome {
+            path: self.path,
+            dest: self.dest,
+            result: Err(error),
+            received: self.received,
+            elapsed: self.started.elapsed(),
+            attempts: self.attempts,
+            verification: self.verification,
+        }
+    }
+}
@@ -0,0 +1,294 @@
+//! Progress of the transfers in flight (and of large listings), which
+//! [`crate::Downloader`] (and [`crate::seafile::Client`]) update and live
+//! displays read, and controls over them: pausing, skipping files and a
+//! bandwidth cap, all of which take effect at the next chunk read.
+
+use std::{
+    fmt,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::checksum::Hasher;
+
+/// A file being transferred
+#[derive(Debug)]
+pub struct Transfer {
+    path: PathBuf,
+    size: Option<u64>,
+    /// Bytes of the file present locally
+    position: AtomicU64,
+    /// Bytes received by this transfer
+    received: AtomicU64,
+    started: Instant,
+    skipped: AtomicBool,
+}
+
+impl Transfer {
+    /// Remote path of the file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn size(&self) -> Option<u64> {
+        self.size
+    }
+
+    /// Bytes of the file present locally, including continued data
+    pub fn position(&self) -> u64 {
+        self.position.load(Ordering::Relaxed)
+    }
+
+    /// Bytes per second received since the transfer started
+    pub fn speed(&self) -> f64 {
+        rate(
+            self.received.load(Ordering::Relaxed),
+            self.started.elapsed(),
+        )
+    }
+
+    /// Makes the transfer fail with [`Skipped`].
+    pub fn skip(&self) {
+        self.skipped.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Error of a transfer skipped with [`Transfer::skip`]
+#[derive(Debug)]
+pub struct Skipped;
+
+impl fmt::Display for Skipped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "skipped")
+    }
+}
+
+impl std::error::Error for Skipped {}
+
+/// Time between checks whether a paused run goes on
+const PAUSE_POLL: Duration = Duration::from_millis(100);
+
+#[derive(Debug)]
+struct Inner {
+    active: Mutex<Vec<Arc<Transfer>>>,
+    received: AtomicU64,
+    paused: AtomicBool,
+    /// Bytes per second for all transfers together, 0 for no limit
+    rate_limit: AtomicU64,
+    /// When the bytes read so far are within the rate limit
+    next_read: Mutex<Instant>,
+    /// Large directory being listed, and its entries received so far
+    listing: Mutex<Option<(PathBuf, u64)>>,
+    /// Warnings not yet taken by the display
+    warnings: Mutex<Vec<String>>,
+}
+
+impl Default for Inner {
+    fn default() -> Self {
+        Self {
+            active: Mutex::default(),
+            received: AtomicU64::new(0),
+            paused: AtomicBool::new(false),
+            rate_limit: AtomicU64::new(0),
+            next_read: Mutex::new(Instant::now()),
+            listing: Mutex::default(),
+            warnings: Mutex::default(),
+        }
+    }
+}
+
+/// Transfers of a run, shared between workers and a display
+#[derive(Debug, Clone, Default)]
+pub struct Progress(Arc<Inner>);
+
+impl Progress {
+    /// Registers a transfer, which lasts until the tracker is dropped.
+    pub fn start(&self, path: &Path, size: Option<u64>) -> Tracker {
+        let transfer = Arc::new(Transfer {
+            path: path.to_path_buf(),
+            size,
+            position: AtomicU64::new(0),
+            received: AtomicU64::new(0),
+            started: Instant::now(),
+            skipped: AtomicBool::new(false),
+        });
+        self.0.active.lock().unwrap().push(transfer.clone());
+        Tracker {
+            progress: self.clone(),
+            transfer,
+            hasher: None,
+        }
+    }
+
+    /// Transfers in flight, oldest first
+    pub fn active(&self) -> Vec<Arc<Transfer>> {
+        self.0.active.lock().unwrap().clone()
+    }
+
+    /// Bytes received by all transfers so far
+    pub fn received(&self) -> u64 {
+        self.0.received.load(Ordering::Relaxed)
+    }
+
+    /// Holds all transfers until [`Progress::resume`].
+    pub fn pause(&self) {
+        self.0.paused.store(true, Ordering::Relaxed);
+    }
+
+    pub fn resume(&self) {
+        self.0.paused.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.0.paused.load(Ordering::Relaxed)
+    }
+
+    /// Caps the bytes per second of all transfers together.
+    pub fn set_rate_limit(&self, limit: Option<u64>) {
+        let limit = limit.unwrap_or(0);
+        self.0.rate_limit.store(limit, Ordering::Relaxed);
+    }
+
+    pub fn rate_limit(&self) -> Option<u64> {
+        Some(self.0.rate_limit.load(Ordering::Relaxed)).filter(|&limit| limit > 0)
+    }
+
+    /// Notes the entries of `dir` received so far while a listing too large
+    /// for a single batch comes in, or with None that it is complete.
+    pub fn set_listing(&self, listing: Option<(&Path, u64)>) {
+        *self.0.listing.lock().unwrap() = listing.map(|(dir, n)| (dir.to_path_buf(), n));
+    }
+
+    /// Large directory being listed, and its entries received so far
+    pub fn listing(&self) -> Option<(PathBuf, u64)> {
+        self.0.listing.lock().unwrap().clone()
+    }
+
+    /// Notes a warning of a transfer, e.g. that it starts over, for the
+    /// display to show.
+    pub fn warn(&self, warning: String) {
+        self.0.warnings.lock().unwrap().push(warning);
+    }
+
+    /// Warnings noted since the last call, oldest first
+    pub fn take_warnings(&self) -> Vec<String> {
+        std::mem::take(&mut self.0.warnings.lock().unwrap())
+    }
+
+    /// Waits until `n` more bytes fit into the rate limit.
+    fn throttle(&self, n: u64) {
+        let Some(limit) = self.rate_limit() else {
+            return;
+        };
+        let now = Instant::now();
+        let wait = {
+            let mut next = self.0.next_read.lock().unwrap();
+            *next = (*next).max(now) + Duration::from_secs_f64(n as f64 / limit as f64);
+            *next - now
+        };
+        std::thread::sleep(wait);
+    }
+}
+
+/// Handle of a registered transfer
+#[derive(Debug)]
+pub struct Tracker {
+    progress: Progress,
+    transfer: Arc<Transfer>,
+    hasher: Option<Mutex<Hasher>>,
+}
+
+impl Tracker {
+    /// Tracks a transfer that no display sees.
+    pub fn detached(path: &Path, size: Option<u64>) -> Self {
+        Progress::default().start(path, size)
+    }
+
+    /// Hashes what is read along the way.
+    pub fn with_hasher(mut self, hasher: Hasher) -> Self {
+        self.hasher = Some(Mutex::new(hasher));
+        self
+    }
+
+    /// Bytes received by the transfer so far
+    pub fn received(&self) -> u64 {
+        self.transfer.received.load(Ordering::Relaxed)
+    }
+
+    pub fn take_hasher(&mut self) -> Option<Hasher> {
+        self.hasher
+            .take()
+            .map(|hasher| hasher.into_inner().unwrap())
+    }
+
+    /// Continues counting from `position`, e.g. after the local file was
+    /// truncated or when a transfer resumes.
+    pub fn seek(&self, position: u64) {
+        self.transfer.position.store(position, Ordering::Relaxed);
+        if let Some(hasher) = &self.hasher {
+            hasher.lock().unwrap().seek(position);
+        }
+    }
+
+    /// Counts what is read from a response body.
+    pub fn reader<R: Read>(&self, inner: R) -> TrackedReader<'_, R> {
+        TrackedReader {
+            inner,
+            tracker: self,
+        }
+    }
+
+    /// Waits while the run is paused, failing once the transfer is skipped.
+    fn wait(&self) -> io::Result<()> {
+        loop {
+            if self.transfer.skipped.load(Ordering::Relaxed) {
+                return Err(io::Error::other(Skipped));
+            }
+            if !self.progress.is_paused() {
+                return Ok(());
+            }
+            std::thread::sleep(PAUSE_POLL);
+        }
+    }
+
+    fn add(&self, n: u64) {
+        self.transfer.position.fetch_add(n, Ordering::Relaxed);
+        self.transfer.received.fetch_add(n, Ordering::Relaxed);
+        self.progress.0.received.fetch_add(n, Ordering::Relaxed);
+    }
+}
+
+impl Drop for Tracker {
+    fn drop(&mut self) {
+        let mut active = self.progress.0.active.lock().unwrap();
+        active.retain(|t| !Arc::ptr_eq(t, &self.transfer));
+    }
+}
+
+pub struct TrackedReader<'a, R> {
+    inner: R,
+    tracker: &'a Tracker,
+}
+
+impl<R: Read> Read for TrackedReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.tracker.wait()?;
+        let n = self.inner.read(buf)?;
+        if let Some(hasher) = &self.tracker.hasher {
+            hasher.lock().unwrap().update(&buf[..n]);
+        }
+        self.tracker.add(n as u64);
+        self.tracker.progress.throttle(n as u64);
+        Ok(n)
+    }
+}
+
+/// Bytes per second
+pub fn rate(bytes: u64, elapsed: Duration) -> f64 {
+    bytes as f64 / elapsed.as_secs_f64().max(0.001)
+}
@@ -0,0 +1,278 @@
+//! Python bindings, packaged as the `seaf_share` module by `pyproject.toml`:
+//!
+//! ```console
+//! $ pip install ./core
+//! ```
+//!
+//! ```python
+//! import seaf_share
+//!
+//! client = seaf_share.Client("https://cloud.example/d/6e5297246c/")
+//! for entry in client.walk():
+//!     if not entry.is_dir:
+//!         client.download("data" + entry.path, entry.path,
+//!                         progress=lambda received, total: print(received, total))
+//! ```
+//!
+//! Failures raise `seaf_share.Error`. Listing and transfers release the GIL,
+//! and Ctrl-C interrupts a download.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use pyo3::{create_exception, exceptions::PyException, prelude::*};
+use url::Url;
+
+use crate::{
+    progress::Progress, seafile, walker::DirEntryStream, ConflictAction, DirEntry, Downloader,
+    Recursive, ShareLink,
+};
+
+create_exception!(
+    seaf_share,
+    Error,
+    PyException,
+    "Failure to reach a share or transfer a file"
+);
+
+/// Entries listed ahead of the consumer of `Client.walk`
+const WALK_CAPACITY: usize = 1024;
+
+/// Time between calls of the progress callable of `Client.download`
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+fn error(e: impl Into<anyhow::Error>) -> PyErr {
+    Error::new_err(format!("{:#}", e.into()))
+}
+
+/// Entry of a share listing
+#[pyclass(frozen, module = "seaf_share")]
+pub struct Entry {
+    /// Path below the root of the share, e.g. "/raw/a.csv"
+    #[pyo3(get)]
+    path: String,
+    #[pyo3(get)]
+    name: String,
+    #[pyo3(get)]
+    is_dir: bool,
+    /// Size in bytes, None for directories
+    #[pyo3(get)]
+    size: Option<u64>,
+    /// Modification time, None if unknown
+    #[pyo3(get)]
+    mtime: Option<DateTime<Utc>>,
+    /// None for directories
+    #[pyo3(get)]
+    download_url: Option<String>,
+}
+
+impl From<DirEntry> for Entry {
+    fn from(entry: DirEntry) -> Self {
+        Self {
+            path: entry.path().to_string_lossy().into_owned(),
+            name: entry.name().to_string(),
+            is_dir: entry.is_dir(),
+            size: entry.size(),
+            mtime: entry.last_modified().copied(),
+            download_url: entry.download_url().map(Url::to_string),
+        }
+    }
+}
+
+#[pymethods]
+impl Entry {
+    fn __repr__(&self) -> String {
+        format!("Entry({:?})", self.path)
+    }
+}
+
+/// A share link and the connection to its server
+#[pyclass(unsendable, module = "seaf_share")]
+pub struct Client {
+    client: seafile::Client,
+    link: ShareLink,
+    downloader: Downloader,
+    /// Transfers of `downloader`, for the progress of `Client.download`
+    progress: Progress,
+}
+
+impl Client {
+    /// The entries a walk of `path` starts from
+    fn roots(&self, path: Option<&str>) -> anyhow::Result<Vec<DirEntry>> {
+        if self.link.is_dir() {
+            let path = self.link.resolve(path.map(Path::new));
+            Ok(self.client.entries(self.link.token(), path)?)
+        } else {
+            Ok(vec![self.client.file(&self.link, None)?])
+        }
+    }
+}
+
+#[pymethods]
+impl Client {
+    /// Opens a share link, unlocking it with `password` if given.
+    #[new]
+    #[pyo3(signature = (url, password=None))]
+    fn new(url: &str, password: Option<&str>) -> PyResult<Self> {
+        let url = Url::parse(url).map_err(error)?;
+        let link = ShareLink::from_url(&url)
+            .with_context(|| format!("{} is not a Seafile share link", url))
+            .map_err(error)?;
+        let agent = ureq::agent();
+        let mut client = seafile::Client::with_agent(agent.clone(), &url);
+        if let Some(password) = password {
+            client
+                .unlock(&link.page_url(&client), link.token(), password)
+                .map_err(error)?;
+        }
+        let progress = Progress::default();
+        Ok(Self {
+            client,
+            link,
+            downloader: Downloader::with_client(agent).with_progress(progress.clone()),
+            progress,
+        })
+    }
+
+    /// Entries of the linked directory, or of `path` relative to it.
+    #[pyo3(signature = (path=None))]
+    fn list(&self, path: Option<&str>) -> PyResult<Vec<Entry>> {
+        Ok(self
+            .roots(path)
+            .map_err(error)?
+            .into_iter()
+            .map(Entry::from)
+            .collect())
+    }
+
+    /// Iterates over every entry below the linked directory, or below `path`
+    /// relative to it, breadth-first unless `depth_first` is set. Directories
+    /// `max_depth` levels down are yielded but not looked into.
+    #[pyo3(signature = (path=None, max_depth=None, depth_first=false))]
+    fn walk(
+        &self,
+        path: Option<&str>,
+        max_depth: Option<usize>,
+        depth_first: bool,
+    ) -> PyResult<Walk> {
+        let order = if depth_first {
+            Recursive::Dfs
+        } else {
+            Recursive::Bfs
+        };
+        let stream = DirEntryStream::spawn(
+            &self.client,
+            self.link.token(),
+            self.roots(path).map_err(error)?,
+            order,
+            |_| true,
+            WALK_CAPACITY,
+            DirEntryStream::MEMORY_LIMIT,
+            max_depth,
+        );
+        Ok(Walk(Mutex::new(stream)))
+    }
+
+    /// Downloads the linked file, or the file at `path` relative to the linked
+    /// directory, to `dest` and returns the number of bytes written. The file
+    /// is written to "<dest>.part" and renamed once complete. `progress` is
+    /// called with the bytes received so far and the size of the file (None
+    /// if unknown) a few times a second; an exception it raises stops the
+    /// download.
+    #[pyo3(signature = (dest, path=None, progress=None))]
+    fn download(
+        &self,
+        py: Python<'_>,
+        dest: PathBuf,
+        path: Option<&str>,
+        progress: Option<PyObject>,
+    ) -> PyResult<u64> {
+        let path = self.link.resolve(path.map(Path::new));
+        let entry = self
+            .client
+            .file(&self.link, path.as_deref())
+            .map_err(error)?;
+        let (downloader, transfers) = (&self.downloader, &self.progress);
+        let done = AtomicBool::new(false);
+        let (result, raised) = py.allow_threads(|| {
+            std::thread::scope(|scope| {
+                // an exception raised by `progress` or a signal handler skips
+                // the transfer
+                let reporter = scope.spawn(|| {
+                    while !done.load(Ordering::Relaxed) {
+                        std::thread::sleep(PROGRESS_INTERVAL);
+                        let Some(transfer) = transfers.active().into_iter().next() else {
+                            continue;
+                        };
+                        if let Err(e) = report(&progress, transfer.position(), entry.size()) {
+                            transfer.skip();
+                            return Some(e);
+                        }
+                    }
+                    None
+                });
+                let downloaded =
+                    downloader.download_entry(&entry, dest.clone(), ConflictAction::Overwrite);
+                done.store(true, Ordering::Relaxed);
+                let result = downloaded.and_then(|job| Ok(job.finish(false, None).result?));
+                (result, reporter.join().expect("progress reporter panicked"))
+            })
+        });
+        if let Some(e) = raised {
+            return Err(e);
+        }
+        result.map_err(error)?;
+        let written = std::fs::metadata(&dest)?.len();
+        report(&progress, written, entry.size())?;
+        Ok(written)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Client({:?})", self.link.token().to_string())
+    }
+}
+
+/// Iterator over the entries of `Client.walk`
+#[pyclass(module = "seaf_share")]
+pub struct Walk(Mutex<DirEntryStream>);
+
+#[pymethods]
+impl Walk {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&self, py: Python<'_>) -> PyResult<Option<Entry>> {
+        let next = py.allow_threads(|| self.0.lock().unwrap().next());
+        next.transpose().map(|e| e.map(Entry::from)).map_err(error)
+    }
+}
+
+/// Calls `progress` with the bytes received so far and the size of the file,
+/// and raises what a signal handler raises
+fn report(progress: &Option<PyObject>, received: u64, total: Option<u64>) -> PyResult<()> {
+    Python::with_gil(|py| {
+        py.check_signals()?;
+        match progress {
+            Some(progress) => progress.call1(py, (received, total)).map(drop),
+            None => Ok(()),
+        }
+    })
+}
+
+#[pymodule]
+fn seaf_share(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Client>()?;
+    m.add_class::<Entry>()?;
+    m.add_class::<Walk>()?;
+    m.add("Error", m.py().get_type::<Error>())?;
+    Ok(())
+}
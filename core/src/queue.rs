@@ -0,0 +1,122 @@
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Seek, SeekFrom, Write},
+};
+
+use crate::DirEntry;
+
+/// Queue of pending entries that keeps at most `limit` entries in memory and
+/// spills the rest to an anonymous temporary file (one JSON entry per line).
+///
+/// As a stack (`lifo`), the oldest entries are spilled in segments which are
+/// read back once memory runs empty. As a queue, the newest entries are
+/// spilled and every later push is appended to the file until it is drained.
+pub struct PendingQueue {
+    memory: VecDeque<DirEntry>,
+    lifo: bool,
+    limit: usize,
+    spill: Option<File>,
+    /// (offset, count) of spilled stack segments
+    segments: Vec<(u64, usize)>,
+    read_pos: u64,
+    write_pos: u64,
+    spilled: usize,
+}
+
+impl PendingQueue {
+    pub fn new(lifo: bool, limit: usize) -> Self {
+        Self {
+            memory: VecDeque::new(),
+            lifo,
+            limit: limit.max(2),
+            spill: None,
+            segments: Vec::new(),
+            read_pos: 0,
+            write_pos: 0,
+            spilled: 0,
+        }
+    }
+
+    pub fn push(&mut self, entries: impl IntoIterator<Item = DirEntry>) -> anyhow::Result<()> {
+        if !self.lifo && self.spilled > 0 {
+            let entries: Vec<_> = entries.into_iter().collect();
+            self.spilled += entries.len();
+            return self.write(&entries);
+        }
+        self.memory.extend(entries);
+        if self.memory.len() <= self.limit {
+            return Ok(());
+        }
+        let keep = self.limit / 2;
+        let spilled: Vec<_> = if self.lifo {
+            let count = self.memory.len() - keep;
+            self.segments.push((self.write_pos, count));
+            self.memory.drain(..count).collect()
+        } else {
+            self.memory.drain(keep..).collect()
+        };
+        self.spilled += spilled.len();
+        self.write(&spilled)
+    }
+
+    pub fn pop(&mut self) -> anyhow::Result<Option<DirEntry>> {
+        if self.memory.is_empty() && self.spilled > 0 {
+            self.reload()?;
+        }
+        Ok(if self.lifo {
+            self.memory.pop_back()
+        } else {
+            self.memory.pop_front()
+        })
+    }
+
+    fn write(&mut self, entries: &[DirEntry]) -> anyhow::Result<()> {
+        let file = match self.spill.as_mut() {
+            Some(file) => file,
+            None => self.spill.insert(tempfile::tempfile()?),
+        };
+        file.seek(SeekFrom::Start(self.write_pos))?;
+        let mut writer = BufWriter::new(&mut *file);
+        for entry in entries {
+            serde_json::to_writer(&mut writer, entry)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+        drop(writer);
+        self.write_pos = file.stream_position()?;
+        Ok(())
+    }
+
+    fn reload(&mut self) -> anyhow::Result<()> {
+        let Some(file) = self.spill.as_mut() else {
+            return Ok(());
+        };
+        let (offset, count) = if self.lifo {
+            self.segments.pop().unwrap_or_default()
+        } else {
+            (self.read_pos, self.spilled.min(self.limit / 2))
+        };
+        file.seek(SeekFrom::Start(offset))?;
+        let mut reader = BufReader::new(&mut *file);
+        let mut consumed = 0;
+        let mut line = String::new();
+        for _ in 0..count {
+            line.clear();
+            consumed += reader.read_line(&mut line)? as u64;
+            self.memory.push_back(serde_json::from_str(&line)?);
+        }
+        self.spilled -= count;
+        if self.lifo {
+            self.write_pos = offset;
+            file.set_len(offset)?;
+        } else if self.spilled == 0 {
+            self.read_pos = 0;
+            self.write_pos = 0;
+            file.set_len(0)?;
+        } else {
+            self.read_pos = offset + consumed;
+        }
+        Ok(())
+    }
+}
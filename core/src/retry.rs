@@ -0,0 +1,112 @@
+//! Retries of requests that failed for transient reasons (timeouts, dropped
+//! connections, server errors) with exponential backoff and jitter.
+
+use std::{io, time::Duration};
+
+use url::Url;
+
+use crate::redact::Redacted;
+use crate::seed::Seed;
+
+/// Upper bound of the backoff exponent, keeping delays finite
+const MAX_DOUBLINGS: u32 = 10;
+
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Retry {
+    max_retries: u32,
+    delay: Duration,
+    seed: Seed,
+}
+
+impl Retry {
+    /// Retries up to `max_retries` times, waiting `delay` before the first
+    /// retry and twice as long before each following one.
+    pub fn new(max_retries: u32, delay: Duration) -> Self {
+        Self {
+            max_retries,
+            delay,
+            seed: Seed::default(),
+        }
+    }
+
+    /// Derives the random part of delays from `seed`.
+    pub fn with_seed(mut self, seed: Seed) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Runs `f` with the attempt number (0 for the first attempt) until it
+    /// succeeds, fails permanently or runs out of retries.
+    pub fn run<T>(
+        &self,
+        url: &Url,
+        mut f: impl FnMut(u32) -> anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        let mut attempt = 0;
+        loop {
+            match f(attempt) {
+                Err(e) if attempt < self.max_retries && is_transient(&e) => {
+                    let delay = self.backoff(url, attempt);
+                    eprintln!(
+                        "{}: {}, retrying in {:.1}s ({}/{})",
+                        Redacted(url),
+                        Redacted(&e),
+                        delay.as_secs_f64(),
+                        attempt + 1,
+                        self.max_retries,
+                    );
+                    std::thread::sleep(delay);
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Delay before retry `attempt + 1` of `url`, up to half as long again at
+    /// random so that workers failing together do not retry together
+    fn backoff(&self, url: &Url, attempt: u32) -> Duration {
+        let delay = self.delay * 2u32.pow(attempt.min(MAX_DOUBLINGS));
+        let random = self
+            .seed
+            .derive(format!("retry {} {}", attempt, url).as_bytes());
+        delay + delay.mul_f64((random % 1000) as f64 / 2000.0)
+    }
+}
+
+/// Whether a request may succeed if it is sent again
+pub fn is_transient(e: &anyhow::Error) -> bool {
+    e.chain().any(|cause| {
+        if let Some(e) = cause.downcast_ref::<crate::Error>() {
+            e.is_transient()
+        } else if let Some(e) = cause.downcast_ref::<ureq::Error>() {
+            is_transient_ureq(e)
+        } else if let Some(e) = cause.downcast_ref::<io::Error>() {
+            is_transient_io(e)
+        } else {
+            false
+        }
+    })
+}
+
+pub(crate) fn is_transient_ureq(e: &ureq::Error) -> bool {
+    match e {
+        ureq::Error::StatusCode(code) => matches!(code, 408 | 429 | 500..=599),
+        ureq::Error::Io(e) => is_transient_io(e),
+        ureq::Error::Timeout(_) | ureq::Error::ConnectionFailed | ureq::Error::BodyStalled => true,
+        _ => false,
+    }
+}
+
+pub(crate) fn is_transient_io(e: &io::Error) -> bool {
+    use io::ErrorKind::*;
+    match e.kind() {
+        ConnectionReset | ConnectionAborted | ConnectionRefused | BrokenPipe | TimedOut
+        | UnexpectedEof => true,
+        // errors reading a response body wrap the request error
+        _ => e
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<ureq::Error>())
+            .is_some_and(is_transient_ureq),
+    }
+}
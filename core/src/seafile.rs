@@ -0,0 +1,477 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize};
+use serde_json::{Map, Value};
+use url::Url;
+
+use super::DirEntry;
+
+#[cfg(feature = "client")]
+mod client;
+#[cfg(feature = "client")]
+mod legacy;
+#[cfg(feature = "client")]
+mod script;
+#[cfg(feature = "client")]
+pub use client::{
+    check_download_page, check_download_response, resolve_file_link, resolve_smart_link,
+    status_error, Client, ClientBuilder, ClientParts,
+};
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidShare,
+    PasswordRequired,
+    WrongPassword,
+    /// The authenticated API was used without an API token, or with one the
+    /// server does not accept for the library
+    Unauthorized,
+    /// Logging in to the account needs the code of its second factor
+    OtpRequired,
+    /// The username, password or second-factor code was not accepted
+    LoginFailed,
+    /// The pages of the server do not hold their data where it is looked
+    /// for, with the version of Seafile if they tell it
+    UnsupportedServer(Option<String>),
+    /// The owner of the share allows it to be viewed, but not downloaded
+    ViewOnly,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidShare => write!(f, "invalid share"),
+            Self::PasswordRequired => write!(f, "password required"),
+            Self::WrongPassword => write!(f, "wrong password"),
+            Self::Unauthorized => write!(f, "API token missing or not accepted"),
+            Self::OtpRequired => write!(f, "two-factor authentication code required"),
+            Self::LoginFailed => write!(f, "username, password or code not accepted"),
+            Self::UnsupportedServer(version) => {
+                write!(f, "unsupported server version")?;
+                if let Some(version) = version {
+                    write!(f, " (Seafile {})", version)?;
+                }
+                write!(f, ": its pages hold no data this client can read")
+            }
+            Self::ViewOnly => write!(f, "this share is view-only, downloads are not allowed"),
+        }
+    }
+}
+impl std::error::Error for Error {}
+
+/// Downloads of the share are blocked because the traffic quota of its owner
+/// is used up (Seafile Pro)
+#[derive(Debug, Clone)]
+pub struct TrafficLimitExceeded {
+    /// When the quota is reset, e.g. "on 2024-07-01" or "in 3600 seconds",
+    /// if the server says
+    pub reset: Option<String>,
+}
+
+impl std::fmt::Display for TrafficLimitExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "share traffic limit exceeded")?;
+        if let Some(reset) = &self.reset {
+            write!(f, ", resets {}", reset)?;
+        }
+        Ok(())
+    }
+}
+impl std::error::Error for TrafficLimitExceeded {}
+
+/// How directory listings are read from the server
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[non_exhaustive]
+pub enum Api {
+    /// Share link API, or share pages if the server does not have it
+    #[default]
+    Auto,
+
+    /// Share link API (Seafile 6.3 and later)
+    Dirents,
+
+    /// Share pages, from the listing some carry or else from the tables
+    /// older servers list directories in, whose sizes are rounded and file
+    /// times missing
+    Legacy,
+}
+
+/// Token identifying a share link, e.g. the `abc` in `/d/abc/`, or the ID of
+/// a library, which is read through the authenticated API instead
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ShareToken {
+    token: String,
+    library: bool,
+}
+
+impl ShareToken {
+    const MAX_LEN: usize = 64;
+
+    /// Library with the ID `repo_id`, e.g. the
+    /// `a1b2c3d4-0000-4000-8000-0123456789ab` in
+    /// `/library/a1b2c3d4-0000-4000-8000-0123456789ab/Photos/`
+    pub fn library(repo_id: &str) -> Result<Self, Error> {
+        if is_uuid(repo_id) {
+            Ok(Self {
+                token: repo_id.to_string(),
+                library: true,
+            })
+        } else {
+            Err(Error::InvalidShare)
+        }
+    }
+
+    /// Access token of the file server in a download URL, e.g. the
+    /// `4b2d9a8e-...` in `/seafhttp/files/4b2d9a8e-.../report.pdf`
+    pub fn access(token: &str) -> Result<Self, Error> {
+        if is_uuid(token) {
+            Ok(Self {
+                token: token.to_string(),
+                library: false,
+            })
+        } else {
+            Err(Error::InvalidShare)
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.token
+    }
+
+    /// Whether this is the ID of a library rather than a share token
+    pub fn is_library(&self) -> bool {
+        self.library
+    }
+}
+
+/// Whether `s` is a UUID as Seafile writes them, e.g. the IDs of libraries
+pub(crate) fn is_uuid(s: &str) -> bool {
+    s.len() == 36
+        && s.bytes()
+            .all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f' | b'-'))
+}
+
+impl std::str::FromStr for ShareToken {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let valid = (1..=Self::MAX_LEN).contains(&s.len())
+            && s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'));
+        if valid {
+            Ok(Self {
+                token: s.to_string(),
+                library: false,
+            })
+        } else {
+            Err(Error::InvalidShare)
+        }
+    }
+}
+
+impl std::fmt::Display for ShareToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.token)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebFileOptions {
+    #[serde(rename = "repoID")]
+    repo_id: String,
+    #[serde(rename = "filePath")]
+    path: PathBuf,
+    #[serde(rename = "fileName")]
+    name: String,
+    #[serde(rename = "fileSize")]
+    size: u64,
+    raw_path: Url,
+    can_download: bool,
+}
+
+/// Library and link of a share, as far as its share page tells
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ShareInfo {
+    pub repo_id: Option<String>,
+    /// Name of the library, or of the shared folder for a share of a folder
+    /// inside one (share pages do not tell them apart)
+    pub repo_name: Option<String>,
+    /// User who created the link
+    pub owner: Option<String>,
+    /// When the link expires, if the page tells
+    pub expires: Option<DateTime<Utc>>,
+    pub can_download: Option<bool>,
+    pub can_upload: Option<bool>,
+    /// Whether the share page asks for a password. Until it is given, the
+    /// page tells nothing else.
+    pub password_protected: bool,
+}
+
+/// Library of the account of an API token
+#[derive(Debug, Clone, Serialize)]
+pub struct Library {
+    pub id: String,
+    pub name: String,
+    pub size: u64,
+    pub last_modified: Option<DateTime<Utc>>,
+    pub encrypted: bool,
+    /// Web page of the library, which is also a link the library can be
+    /// listed and downloaded with
+    pub url: Url,
+}
+
+/// File metadata reported in HTTP response headers
+#[derive(Debug, Clone, Default)]
+pub struct Metadata {
+    pub size: Option<u64>,
+    pub last_modified: Option<DateTime<Utc>>,
+    pub content_type: Option<String>,
+    /// Whether the server advertises byte range requests
+    pub accept_ranges: bool,
+    /// Entity tag, which the Seafile file server sets to the file ID
+    pub etag: Option<String>,
+}
+
+/// Size of a (sub)tree of a share
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Estimate {
+    pub files: u64,
+    pub dirs: u64,
+    pub bytes: u64,
+    /// Whether the numbers are extrapolated from a sample of directories
+    pub sampled: bool,
+}
+
+impl Estimate {
+    pub fn add(&mut self, entry: &DirEntry) {
+        if entry.is_file() {
+            self.files += 1;
+            self.bytes += entry.size().unwrap_or(0);
+        } else {
+            self.dirs += 1;
+        }
+    }
+}
+
+/// Entry of a share link listing. Which fields it is read from depends on
+/// `is_dir`; see [`DirEnt::from_json_lenient`] for the variations accepted.
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged, rename_all_fields = "snake_case")]
+pub enum DirEnt {
+    Directory {
+        is_dir: bool,
+        last_modified: DateTime<Utc>,
+        #[serde(rename = "folder_path")]
+        path: PathBuf,
+        #[serde(rename = "folder_name")]
+        name: String,
+        size: u64,
+    },
+    File {
+        is_dir: bool,
+        last_modified: DateTime<Utc>,
+        #[serde(rename = "file_path")]
+        path: PathBuf,
+        #[serde(rename = "file_name")]
+        name: String,
+        size: u64,
+        encoded_thumbnail_src: Option<PathBuf>,
+    },
+}
+
+impl DirEnt {
+    pub fn is_file(&self) -> bool {
+        match self {
+            Self::Directory { .. } => false,
+            Self::File { .. } => true,
+        }
+    }
+
+    pub fn is_dir(&self) -> bool {
+        !self.is_file()
+    }
+
+    pub fn size(&self) -> Option<u64> {
+        match self {
+            Self::Directory { .. } => None,
+            Self::File { size, .. } => Some(*size),
+        }
+    }
+
+    pub fn last_modified(&self) -> &DateTime<Utc> {
+        match self {
+            Self::Directory { last_modified, .. } | Self::File { last_modified, .. } => {
+                last_modified
+            }
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Directory { name, .. } | Self::File { name, .. } => name,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Directory { path, .. } | Self::File { path, .. } => path.as_ref(),
+        }
+    }
+
+    /// Reads an entry of a listing that has exactly the fields of the share
+    /// link API.
+    pub fn from_json_strict(value: &Value) -> Result<Self, String> {
+        match Self::from_json_lenient(value)? {
+            (entry, problems) if problems.is_empty() => Ok(entry),
+            (_, problems) => Err(problems.join(", ")),
+        }
+    }
+
+    /// Reads an entry of a listing, making do with what it has: unknown
+    /// fields are ignored, missing ones are inferred or defaulted. Returns the
+    /// entry along with what was unexpected about it, or an error if it has
+    /// no path.
+    ///
+    /// Variations that servers are known to send are not unexpected:
+    /// directories named by `file_name` (or files and directories by `path`
+    /// and `name`), `type` ("dir" or "file") in place of `is_dir`, `mtime` in
+    /// place of `last_modified`, and times in seconds since the epoch.
+    pub fn from_json_lenient(value: &Value) -> Result<(Self, Vec<String>), String> {
+        let object = value.as_object().ok_or("not an object")?;
+        let mut problems = Vec::new();
+        let entry = Self::read(object, &mut problems)?;
+        problems.extend(
+            object
+                .keys()
+                .filter(|key| !KNOWN_FIELDS.contains(&key.as_str()))
+                .map(|key| format!("unknown field `{}`", key)),
+        );
+        Ok((entry, problems))
+    }
+
+    /// Reads the fields of an entry according to `is_dir`, noting in
+    /// `problems` what had to be inferred or defaulted
+    fn read(object: &Map<String, Value>, problems: &mut Vec<String>) -> Result<Self, String> {
+        let is_dir = match (object.get("is_dir"), object.get("type")) {
+            (Some(Value::Bool(is_dir)), _) => *is_dir,
+            (_, Some(Value::String(kind))) if kind == "dir" || kind == "file" => kind == "dir",
+            (is_dir, _) => {
+                problems.push(match is_dir {
+                    Some(_) => "field `is_dir` is not a boolean".to_string(),
+                    None => "missing field `is_dir`".to_string(),
+                });
+                object.contains_key("folder_path") || object.contains_key("folder_name")
+            }
+        };
+        let (path_fields, name_fields): (&[&str], &[&str]) = if is_dir {
+            (
+                &["folder_path", "path", "file_path"],
+                &["folder_name", "name", "file_name"],
+            )
+        } else {
+            (&["file_path", "path"], &["file_name", "name"])
+        };
+        let string = |fields: &[&str]| fields.iter().find_map(|f| object.get(*f)?.as_str());
+        let path = PathBuf::from(
+            string(path_fields).ok_or_else(|| format!("missing field `{}`", path_fields[0]))?,
+        );
+        let name = match string(name_fields) {
+            Some(name) => name.to_string(),
+            None => {
+                problems.push(format!("missing field `{}`", name_fields[0]));
+                path.file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+                    .unwrap_or_default()
+            }
+        };
+        let last_modified = match object.get("last_modified").or(object.get("mtime")) {
+            Some(Value::String(s)) => DateTime::parse_from_rfc3339(s)
+                .ok()
+                .map(|t| t.with_timezone(&Utc)),
+            Some(Value::Number(n)) => n.as_i64().and_then(|t| DateTime::from_timestamp(t, 0)),
+            _ => None,
+        };
+        let last_modified = last_modified.unwrap_or_else(|| {
+            problems.push("missing or invalid field `last_modified`".to_string());
+            DateTime::UNIX_EPOCH
+        });
+        if is_dir {
+            // sent as 0 or "" if at all, and of no use
+            return Ok(Self::Directory {
+                is_dir,
+                last_modified,
+                path,
+                name,
+                size: 0,
+            });
+        }
+        let size = match object.get("size") {
+            Some(Value::Number(n)) => n.as_u64(),
+            Some(Value::String(s)) => {
+                problems.push("field `size` is a string".to_string());
+                s.parse().ok()
+            }
+            _ => None,
+        };
+        let size = size.unwrap_or_else(|| {
+            problems.push("missing or invalid field `size`".to_string());
+            0
+        });
+        let encoded_thumbnail_src = object
+            .get("encoded_thumbnail_src")
+            .and_then(Value::as_str)
+            .map(PathBuf::from);
+        Ok(Self::File {
+            is_dir,
+            last_modified,
+            path,
+            name,
+            size,
+            encoded_thumbnail_src,
+        })
+    }
+}
+
+/// Reads entries the way [`DirEnt::from_json_strict`] does, except that
+/// unknown fields are ignored.
+impl<'de> Deserialize<'de> for DirEnt {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let object = Map::deserialize(deserializer)?;
+        let mut problems = Vec::new();
+        let entry = Self::read(&object, &mut problems).map_err(D::Error::custom)?;
+        if !problems.is_empty() {
+            return Err(D::Error::custom(problems.join(", ")));
+        }
+        Ok(entry)
+    }
+}
+
+/// Fields of listing entries that [`DirEnt`] reads, in any variation
+const KNOWN_FIELDS: [&str; 12] = [
+    "is_dir",
+    "type",
+    "last_modified",
+    "mtime",
+    "folder_path",
+    "folder_name",
+    "file_path",
+    "file_name",
+    "path",
+    "name",
+    "size",
+    "encoded_thumbnail_src",
+];
+
+/// Listing entries with the same unexpected fields, as tolerated by a
+/// `Client` that does not read listings strictly
+#[derive(Debug, Clone)]
+pub struct Deviation {
+    /// What was unexpected, e.g. "unknown field `starred`"
+    pub problem: String,
+    /// Number of entries with the problem
+    pub count: u64,
+    /// The first of them as received
+    pub example: Value,
+}
@@ -0,0 +1,1597 @@
+//! Requests to a Seafile server: share listings, file pages, password
+//! sessions and uploads.
+
+use std::{
+    collections::{btree_map, BTreeMap},
+    fmt,
+    io::{self, BufReader},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use chrono::DateTime;
+use regex::Regex;
+use serde::{
+    de::{self, DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor},
+    Deserialize, Serialize,
+};
+use url::Url;
+
+use super::{
+    legacy, script, Api, Deviation, DirEnt, Error, Estimate, Library, Metadata, ShareInfo,
+    ShareToken, TrafficLimitExceeded, WebFileOptions,
+};
+use crate::multipart::Form;
+use crate::progress::Progress;
+use crate::redact::Redacted;
+use crate::retry::Retry;
+use crate::seed::Seed;
+use crate::state::RunState;
+use crate::walker::Walker;
+use crate::{DirEntry, Recursive, ShareLink};
+
+/// Rejects responses that are the password page instead of file contents,
+/// which is what the server redirects to once a share session expires.
+pub fn check_download_response(res: &ureq::http::Response<ureq::Body>) -> Result<(), Error> {
+    let headers = res.headers();
+    let is_html = headers
+        .get("content-type")
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/html"));
+    if is_html && !headers.contains_key("content-disposition") {
+        Err(Error::PasswordRequired)
+    } else {
+        Ok(())
+    }
+}
+
+/// Like [`check_download_response`], but reads the pages it rejects to tell
+/// a share whose traffic limit is exceeded from an expired session.
+pub fn check_download_page(res: &mut ureq::http::Response<ureq::Body>) -> Result<(), crate::Error> {
+    match check_download_response(res) {
+        Err(e) => Err(traffic_limit(res).map_or(e.into(), crate::Error::from)),
+        Ok(()) => Ok(()),
+    }
+}
+
+/// Error of a response with an error status, reading its body to recognize
+/// an exceeded traffic limit
+pub fn status_error(res: &mut ureq::http::Response<ureq::Body>) -> crate::Error {
+    match traffic_limit(res) {
+        Some(limit) => limit.into(),
+        None => ureq::Error::StatusCode(res.status().as_u16()).into(),
+    }
+}
+
+/// Download link of the file that `url` points to if it is a file link of the
+/// authenticated API (see [`ShareToken::library`]), which answers with the
+/// link rather than the file; other URLs are returned as they are.
+pub fn resolve_file_link(agent: &ureq::Agent, url: &Url) -> Result<Url, crate::Error> {
+    let segments: Vec<&str> = url.path_segments().map_or(Vec::new(), Iterator::collect);
+    if !matches!(segments.as_slice(), [.., "api2", "repos", _, "file", ""]) {
+        return Ok(url.clone());
+    }
+    let mut res = agent
+        .get(url.as_str())
+        .header("accept", "application/json")
+        .call()
+        .map_err(unauthorized)?;
+    let link: String = res.body_mut().read_json()?;
+    Ok(Url::parse(&link).map_err(anyhow::Error::from)?)
+}
+
+/// Link to the library file or directory that `url` points to if it is a
+/// smart link (see [`ShareLink::is_smart_link`]), asking the authenticated
+/// API where it points; other URLs are returned as they are.
+pub fn resolve_smart_link(agent: &ureq::Agent, url: &Url) -> Result<Url, crate::Error> {
+    #[derive(Deserialize)]
+    struct Target {
+        repo_id: String,
+        path: String,
+        is_dir: bool,
+    }
+    let segments: Vec<&str> = url
+        .path_segments()
+        .map_or(Vec::new(), |s| s.filter(|s| !s.is_empty()).collect());
+    let Some(i) = crate::smart_link_segment(&segments) else {
+        return Ok(url.clone());
+    };
+    let root = server_root(url);
+    let mut api = root.clone();
+    api.path_segments_mut()
+        .expect("server URL can be a base")
+        .pop_if_empty()
+        .extend(["api", "v2.1", "smart-links", segments[i + 1], ""]);
+    let mut res = agent
+        .get(api.as_str())
+        .header("accept", "application/json")
+        .call()
+        .map_err(unauthorized)?;
+    let target: Target = res.body_mut().read_json()?;
+    let (kind, name) = if target.is_dir {
+        ("library", "-")
+    } else {
+        ("lib", "file")
+    };
+    // and the path below a directory the link may have been given with
+    let below = segments[i + 2..]
+        .iter()
+        .map(|s| percent_encoding::percent_decode_str(s).decode_utf8_lossy());
+    let mut link = root;
+    {
+        let mut path = link.path_segments_mut().expect("server URL can be a base");
+        path.pop_if_empty()
+            .extend([kind, &target.repo_id, name])
+            .extend(target.path.split('/').filter(|s| !s.is_empty()))
+            .extend(below);
+        if target.is_dir {
+            path.push("");
+        }
+    }
+    Ok(link)
+}
+
+/// Error of a request of the authenticated API, which answers 401 or 403
+/// when the API token is missing or not accepted
+fn unauthorized(e: impl Into<crate::Error>) -> crate::Error {
+    match e.into() {
+        crate::Error::Http(ureq::Error::StatusCode(401 | 403)) => Error::Unauthorized.into(),
+        e => e,
+    }
+}
+
+/// Recognizes the message (in a page or JSON) that Seafile Pro answers
+/// downloads with once the share owner's traffic quota is used up, e.g.
+/// "Unable to access file: share link traffic is used up."
+fn traffic_limit(res: &mut ureq::http::Response<ureq::Body>) -> Option<TrafficLimitExceeded> {
+    const PAGE_LIMIT: u64 = 1 << 20;
+    let message = Regex::new(r"(?i)traffic (?:is used up|ran out|limit|quota)").ok()?;
+    let reset = Regex::new(r#"(?i)\breset(?:s|ted)? ((?:on|at|in) [^<."]+)"#).ok()?;
+    let retry_after = res
+        .headers()
+        .get("retry-after")
+        .and_then(|v| v.to_str().ok())
+        .map(|v| match v.parse::<u64>() {
+            Ok(secs) => format!("in {} seconds", secs),
+            Err(_) => format!("at {}", v),
+        });
+    let body = res
+        .body_mut()
+        .with_config()
+        .limit(PAGE_LIMIT)
+        .read_to_string()
+        .ok()?;
+    if !message.is_match(&body) {
+        return None;
+    }
+    let reset = reset
+        .captures(&body)
+        .map(|reset| reset[1].trim().to_string())
+        .or(retry_after);
+    Some(TrafficLimitExceeded { reset })
+}
+
+#[derive(Debug, Clone)]
+struct Credentials {
+    page: Url,
+    token: ShareToken,
+    password: String,
+}
+
+/// Variables of Seahub pages that their options are set in, and whether
+/// they hold them under "pageOptions": `window.shared` of the share pages,
+/// `window.app.pageOptions` and `window.app` of newer layouts
+const PAGE_OPTIONS: [(&str, bool); 3] = [
+    (r"window\.shared", true),
+    (r"window\.app\.pageOptions", false),
+    (r"window\.app", true),
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct WebPageOptions<T> {
+    #[serde(rename = "pageOptions")]
+    options: T,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SharePageOptions {
+    #[serde(rename = "repoID")]
+    repo_id: Option<String>,
+    repo_name: Option<String>,
+    /// Name of the shared folder, the library's for a share of a library
+    dir_name: Option<String>,
+    shared_by: Option<String>,
+    #[serde(alias = "expire_date")]
+    expire_date: Option<String>,
+    can_download: Option<bool>,
+    can_upload: Option<bool>,
+}
+
+/// Options of a directory share page that carries its listing, in the
+/// entries of the share link API
+#[derive(Debug, Clone, Deserialize)]
+struct DirPageOptions {
+    #[serde(rename = "direntList", alias = "dirent_list")]
+    dirent_list: Vec<serde_json::Value>,
+}
+
+/// Root of the server that the share link `url` points into, with the subpath
+/// it may be installed below and a trailing slash
+fn server_root(url: &Url) -> Url {
+    let segments: Vec<&str> = url
+        .path_segments()
+        .map_or(Vec::new(), |s| s.filter(|s| !s.is_empty()).collect());
+    // the segments before "d/<token>", "f/<token>" or "u/d/<token>", or
+    // before "library/<repo>" or "lib/<repo>" of a library (or
+    // "smart-link/<uuid>" into one), or before "seafhttp" of the file server
+    let share = segments.iter().rposition(|s| *s == "d" || *s == "f");
+    let library = crate::library_segment(&segments)
+        .or(crate::smart_link_segment(&segments))
+        .or(crate::file_server_segment(&segments));
+    let prefix = match (library, share) {
+        (Some(i), _) => i,
+        (None, Some(i)) if i + 1 < segments.len() && i > 0 && segments[i - 1] == "u" => i - 1,
+        (None, Some(i)) if i + 1 < segments.len() => i,
+        _ => segments.len(),
+    };
+    let mut base = url.clone();
+    base.set_path(&format!("/{}", segments[..prefix].join("/")));
+    with_trailing_slash(&mut base);
+    base.set_query(None);
+    base.set_fragment(None);
+    base
+}
+
+fn with_trailing_slash(url: &mut Url) {
+    if !url.path().ends_with('/') {
+        url.set_path(&format!("{}/", url.path()));
+    }
+}
+
+/// Builder of a [`Client`] from its parts, made by [`Client::builder`]
+#[derive(Debug, Clone, Default)]
+pub struct ClientBuilder {
+    base: Option<Url>,
+    agent: Option<ureq::Agent>,
+    password: Option<String>,
+    token: Option<ShareToken>,
+    api: Api,
+}
+
+impl ClientBuilder {
+    /// URL of the server, including the subpath of an installation below
+    /// one, e.g. `https://cloud.example/seafile/`
+    pub fn base_url(mut self, url: Url) -> Self {
+        self.base = Some(url);
+        self
+    }
+
+    /// Agent the requests are made with, a default one otherwise
+    pub fn agent(mut self, agent: ureq::Agent) -> Self {
+        self.agent = Some(agent);
+        self
+    }
+
+    /// Password of the share given with [`token`](Self::token), submitted
+    /// once the server asks for it (see [`Client::unlock`] to submit it
+    /// right away)
+    pub fn password(mut self, password: impl Into<String>) -> Self {
+        self.password = Some(password.into());
+        self
+    }
+
+    /// Token of the password-protected directory share
+    pub fn token(mut self, token: ShareToken) -> Self {
+        self.token = Some(token);
+        self
+    }
+
+    /// How the server lists directories, if known; see [`Api`]
+    pub fn capabilities(mut self, api: Api) -> Self {
+        self.api = api;
+        self
+    }
+
+    /// Fails without a base URL, on one that cannot be a base, or on a
+    /// password without a token.
+    pub fn build(self) -> Result<Client, crate::Error> {
+        let mut base = self
+            .base
+            .ok_or_else(|| anyhow::anyhow!("no base URL for the client"))?;
+        if base.cannot_be_a_base() {
+            return Err(anyhow::anyhow!("{} cannot be the URL of a server", base).into());
+        }
+        with_trailing_slash(&mut base);
+        base.set_query(None);
+        base.set_fragment(None);
+        let agent = self.agent.unwrap_or_else(ureq::agent);
+        let mut client = Client::new(agent, base).with_api(self.api);
+        match (self.password, self.token) {
+            (Some(password), Some(token)) => {
+                client.credentials = Some(Credentials {
+                    page: client.dir_url(&token, None::<&Path>),
+                    token,
+                    password,
+                });
+            }
+            (Some(_), None) => return Err(anyhow::anyhow!("a password needs a share token").into()),
+            (None, _) => {}
+        }
+        Ok(client)
+    }
+}
+
+/// Entries of a listing handed on at a time while the rest is received
+const BATCH: usize = 1000;
+
+/// Results of a search requested at a time
+const SEARCH_PAGE: usize = 100;
+
+/// Error that stops reading a listing once its consumer is gone
+const STOPPED: &str = "listing no longer needed";
+
+/// The `dirent_list` of a listing response, whose entries are handed to the
+/// function one by one as they are read (as JSON, so that a lenient client
+/// can make do with entries that differ) until it returns false
+struct DirEntList<'a>(&'a mut dyn FnMut(serde_json::Value) -> bool);
+
+impl<'de> DeserializeSeed<'de> for DirEntList<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for DirEntList<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a listing with a dirent_list")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let each = self.0;
+        let mut found = false;
+        while let Some(key) = map.next_key::<String>()? {
+            if key == "dirent_list" && !found {
+                found = true;
+                map.next_value_seed(Entries(&mut *each))?;
+            } else {
+                map.next_value::<IgnoredAny>()?;
+            }
+        }
+        if !found {
+            return Err(de::Error::missing_field("dirent_list"));
+        }
+        Ok(())
+    }
+}
+
+/// Elements of a `dirent_list`, see [`DirEntList`]
+struct Entries<'a>(&'a mut dyn FnMut(serde_json::Value) -> bool);
+
+impl<'de> DeserializeSeed<'de> for Entries<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de> Visitor<'de> for Entries<'_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a list of entries")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(value) = seq.next_element()? {
+            if !(self.0)(value) {
+                return Err(de::Error::custom(STOPPED));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Unexpected listing entries seen by a client and its copies, by problem
+type Deviations = Arc<Mutex<BTreeMap<String, Deviation>>>;
+
+pub struct Client {
+    client: ureq::Agent,
+    base: Url,
+    #[cfg(feature = "quickjs")]
+    quickjs: rquickjs::Runtime,
+    credentials: Option<Credentials>,
+    /// Whether the share asked for the password given to [`Client::unlock`]
+    protected: bool,
+    retry: Retry,
+    seed: Seed,
+    state: Option<Arc<RunState>>,
+    strict_json: bool,
+    deviations: Deviations,
+    api: Api,
+    /// Whether [`Api::Auto`] found the server to lack the share link API
+    legacy: Arc<AtomicBool>,
+    progress: Option<Progress>,
+}
+
+/// Connection state of a [`Client`] that can be sent to another thread, where
+/// [`Client::from_parts`] recreates the client around it.
+#[derive(Debug, Clone)]
+pub struct ClientParts {
+    agent: ureq::Agent,
+    base: Url,
+    credentials: Option<Credentials>,
+    /// Whether the share asked for the password given to [`Client::unlock`]
+    protected: bool,
+    retry: Retry,
+    seed: Seed,
+    state: Option<Arc<RunState>>,
+    strict_json: bool,
+    deviations: Deviations,
+    api: Api,
+    /// Whether [`Api::Auto`] found the server to lack the share link API
+    legacy: Arc<AtomicBool>,
+    progress: Option<Progress>,
+}
+
+impl ClientParts {
+    /// See [`Client::deviations`]
+    pub fn deviations(&self) -> Vec<Deviation> {
+        self.deviations.lock().unwrap().values().cloned().collect()
+    }
+}
+
+impl Client {
+    pub fn parts(&self) -> ClientParts {
+        ClientParts {
+            agent: self.client.clone(),
+            base: self.base.clone(),
+            credentials: self.credentials.clone(),
+            protected: self.protected,
+            retry: self.retry,
+            seed: self.seed,
+            state: self.state.clone(),
+            strict_json: self.strict_json,
+            deviations: self.deviations.clone(),
+            api: self.api,
+            legacy: self.legacy.clone(),
+            progress: self.progress.clone(),
+        }
+    }
+
+    pub fn from_parts(parts: ClientParts) -> Self {
+        Self {
+            client: parts.agent,
+            base: parts.base,
+            #[cfg(feature = "quickjs")]
+            quickjs: rquickjs::Runtime::new().unwrap(),
+            credentials: parts.credentials,
+            protected: parts.protected,
+            retry: parts.retry,
+            seed: parts.seed,
+            state: parts.state,
+            strict_json: parts.strict_json,
+            deviations: parts.deviations,
+            api: parts.api,
+            legacy: parts.legacy,
+            progress: parts.progress,
+        }
+    }
+
+    /// Client for the server of the share link `url`, which may live below a
+    /// subpath (e.g. `https://cloud.example/seafile/d/6e5297246c/`). See
+    /// [`Client::builder`] to give the server URL itself.
+    pub fn with_agent(agent: ureq::Agent, url: &Url) -> Self {
+        Self::new(agent, server_root(url))
+    }
+
+    /// Builds a client from the URL of the server rather than a share link.
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::default()
+    }
+
+    fn new(agent: ureq::Agent, base: Url) -> Self {
+        Self {
+            client: agent,
+            base,
+            #[cfg(feature = "quickjs")]
+            quickjs: rquickjs::Runtime::new().unwrap(),
+            credentials: None,
+            protected: false,
+            retry: Retry::default(),
+            seed: Seed::default(),
+            state: None,
+            strict_json: false,
+            deviations: Deviations::default(),
+            api: Api::default(),
+            legacy: Arc::default(),
+            progress: None,
+        }
+    }
+
+    /// Retries API requests that fail for transient reasons.
+    pub fn with_retry(mut self, retry: Retry) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Derives upload boundaries from `seed`; see also [`Retry::with_seed`].
+    pub fn with_seed(mut self, seed: Seed) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Answers listings from `state` where it has them, and records the
+    /// others there.
+    pub fn with_run_state(mut self, state: Arc<RunState>) -> Self {
+        self.state = Some(state);
+        self
+    }
+
+    /// Fails listings with entries that differ from the share link API in any
+    /// way, rather than making do with them and warning.
+    pub fn with_strict_json(mut self, strict: bool) -> Self {
+        self.strict_json = strict;
+        self
+    }
+
+    /// Reads listings through `api`.
+    pub fn with_api(mut self, api: Api) -> Self {
+        self.api = api;
+        self
+    }
+
+    /// Reports the entries received of directories too large to be listed
+    /// in a single batch to `progress`.
+    pub fn with_progress(mut self, progress: Progress) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    /// Listing entries so far that differed from the share link API
+    pub fn deviations(&self) -> Vec<Deviation> {
+        self.deviations.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Submits the password form of a protected share page. The password is
+    /// kept to renew the session once it expires.
+    pub fn unlock(
+        &mut self,
+        page: &Url,
+        token: &ShareToken,
+        password: impl Into<String>,
+    ) -> Result<(), crate::Error> {
+        self.credentials = Some(Credentials {
+            page: page.clone(),
+            token: token.clone(),
+            password: password.into(),
+        });
+        self.protected = self.submit_password()?;
+        Ok(())
+    }
+
+    /// Re-submits the stored password, returning `false` if there is none.
+    pub fn renew_session(&self) -> Result<bool, crate::Error> {
+        if self.credentials.is_none() {
+            return Ok(false);
+        }
+        self.submit_password()?;
+        Ok(true)
+    }
+
+    /// Submits the stored password if the share page asks for it, returning
+    /// whether it did
+    fn submit_password(&self) -> Result<bool, crate::Error> {
+        let Some(credentials) = self.credentials.as_ref() else {
+            return Ok(false);
+        };
+        let csrf_pattern = Regex::new(r#"name="csrfmiddlewaretoken"\s+value="([^"]+)""#)?;
+        let mut res = self.client.get(credentials.page.as_str()).call()?;
+        let page = res.body_mut().read_to_string()?;
+        let Some(csrf) = csrf_pattern.captures(&page).and_then(|c| c.get(1)) else {
+            // no password form: the session is still valid, or the share has
+            // no password
+            return Ok(false);
+        };
+        let mut res = self
+            .client
+            .post(credentials.page.as_str())
+            .header("referer", credentials.page.as_str())
+            .send_form([
+                ("csrfmiddlewaretoken", csrf.as_str()),
+                ("token", credentials.token.as_str()),
+                ("password", credentials.password.as_str()),
+            ])?;
+        let page = res.body_mut().read_to_string()?;
+        if csrf_pattern.is_match(&page) && page.contains(r#"name="password""#) {
+            return Err(Error::WrongPassword.into());
+        }
+        Ok(true)
+    }
+
+    /// Server URL with the given path segments and a trailing slash
+    fn endpoint<'a>(&self, segments: impl IntoIterator<Item = &'a str>) -> Url {
+        let mut url = self.base.clone();
+        url.path_segments_mut()
+            .expect("server URL can be a base")
+            .pop_if_empty()
+            .extend(segments)
+            .push("");
+        url
+    }
+
+    /// Page of a directory share or a library, optionally opened at a
+    /// subdirectory
+    pub fn dir_url(&self, token: &ShareToken, path: Option<impl AsRef<Path>>) -> Url {
+        if token.is_library() {
+            // the web UI takes any name in place of the library's
+            let path = path.as_ref().map_or(Path::new("/"), AsRef::as_ref);
+            return self.endpoint(
+                ["library", token.as_str(), "-"]
+                    .into_iter()
+                    .chain(path.iter().filter_map(|s| s.to_str()).filter(|s| *s != "/")),
+            );
+        }
+        let mut url = self.endpoint(["d", token.as_str()]);
+        if let Some(p) = path.as_ref().and_then(|p| p.as_ref().to_str()) {
+            url.query_pairs_mut().append_pair("p", p);
+        }
+        url
+    }
+
+    /// Page (or download URL with `dl`) of a file inside a directory share or
+    /// a library
+    pub fn file_url(&self, token: &ShareToken, path: impl AsRef<Path>, dl: bool) -> Url {
+        if token.is_library() && dl {
+            // answered with a download link, see resolve_file_link
+            let mut url = self.endpoint(["api2", "repos", token.as_str(), "file"]);
+            if let Some(p) = path.as_ref().to_str() {
+                url.query_pairs_mut()
+                    .append_pair("p", p)
+                    .append_pair("reuse", "1");
+            }
+            return url;
+        }
+        if token.is_library() {
+            let mut url = self.endpoint(["lib", token.as_str(), "file"]);
+            url.path_segments_mut()
+                .expect("server URL can be a base")
+                .pop()
+                .extend(
+                    path.as_ref()
+                        .iter()
+                        .filter_map(|s| s.to_str())
+                        .filter(|s| *s != "/"),
+                );
+            return url;
+        }
+        let mut url = self.endpoint(["d", token.as_str(), "files"]);
+        if let Some(p) = path.as_ref().to_str() {
+            url.query_pairs_mut().append_pair("p", p);
+        }
+        if dl {
+            url.query_pairs_mut().append_pair("dl", "1");
+        }
+        url
+    }
+
+    /// Page of a single file share
+    pub fn single_file_url(&self, token: &ShareToken) -> Url {
+        self.endpoint(["f", token.as_str()])
+    }
+
+    /// Page of an upload link
+    pub fn upload_page_url(&self, token: &ShareToken) -> Url {
+        self.endpoint(["u", "d", token.as_str()])
+    }
+
+    // https://download.seafile.com/published/web-api/v2.1/share-links.md
+    pub fn api_dirents(
+        &self,
+        token: &ShareToken,
+        path: Option<impl AsRef<Path>>,
+    ) -> Result<Vec<DirEnt>, crate::Error> {
+        let mut dirents = Vec::new();
+        self.api_dirent_batches(token, path.as_ref().map(AsRef::as_ref), |batch| {
+            dirents.extend(batch);
+            true
+        })?;
+        Ok(dirents)
+    }
+
+    /// Reads the listing of `path` as it is received, handing its entries to
+    /// `f` in batches until it returns false. A retry after a dropped
+    /// connection skips the entries already handed on.
+    fn api_dirent_batches(
+        &self,
+        token: &ShareToken,
+        path: Option<&Path>,
+        mut f: impl FnMut(Vec<DirEnt>) -> bool,
+    ) -> Result<(), crate::Error> {
+        let mut url = self.endpoint(["api", "v2.1", "share-links", token.as_str(), "dirents"]);
+        if let Some(s) = path.and_then(|p| p.to_str()) {
+            url.query_pairs_mut().append_pair("path", s);
+        }
+        let get = || {
+            self.client
+                .get(url.as_str())
+                .header("accept", "application/json")
+                .call()
+        };
+        // entries of the response handed on, counting those skipped
+        let mut handed = 0;
+        self.retry.run(&url, |_| {
+            let mut res = match get() {
+                Err(ureq::Error::StatusCode(403)) if self.renew_session()? => get()?,
+                res => res?,
+            };
+            let reader = BufReader::new(res.body_mut().as_reader());
+            let mut batch = Vec::new();
+            let (mut read, mut stopped, mut error) = (0, false, None);
+            let mut each = |value: serde_json::Value| {
+                read += 1;
+                if read <= handed {
+                    return true;
+                }
+                match self.dirent(&url, value) {
+                    Ok(Some(entry)) => batch.push(entry),
+                    Ok(None) => {}
+                    Err(e) => {
+                        error = Some(e);
+                        return false;
+                    }
+                }
+                if batch.len() < BATCH {
+                    return true;
+                }
+                handed = read;
+                stopped = !f(std::mem::take(&mut batch));
+                !stopped
+            };
+            let mut deserializer = serde_json::Deserializer::from_reader(reader);
+            let result = DirEntList(&mut each)
+                .deserialize(&mut deserializer)
+                .and_then(|()| deserializer.end())
+                // the I/O error itself, so that a dropped connection is retried
+                .map_err(|e| match e.is_io() {
+                    true => anyhow::Error::from(io::Error::from(e)),
+                    false => e.into(),
+                });
+            if let Some(e) = error {
+                return Err(e);
+            }
+            if stopped {
+                return Ok(());
+            }
+            result?;
+            handed = read;
+            if !batch.is_empty() {
+                f(batch);
+            }
+            Ok(())
+        })?;
+        Ok(())
+    }
+
+    /// Entry of the listing at `url` read from `value`, None if a lenient
+    /// client skips it
+    fn dirent(&self, url: &Url, value: serde_json::Value) -> anyhow::Result<Option<DirEnt>> {
+        if self.strict_json {
+            let entry = DirEnt::from_json_strict(&value).map_err(|problem| {
+                anyhow::anyhow!("unexpected entry in {}: {}: {}", url, problem, value)
+            })?;
+            return Ok(Some(entry));
+        }
+        match DirEnt::from_json_lenient(&value) {
+            Ok((entry, problems)) => {
+                for problem in problems {
+                    self.deviate(url, problem, &value);
+                }
+                Ok(Some(entry))
+            }
+            Err(problem) => {
+                self.deviate(url, format!("{}, skipped", problem), &value);
+                Ok(None)
+            }
+        }
+    }
+
+    /// Notes an unexpected listing entry, warning of each problem once.
+    fn deviate(&self, url: &Url, problem: String, value: &serde_json::Value) {
+        match self.deviations.lock().unwrap().entry(problem) {
+            btree_map::Entry::Occupied(mut deviation) => deviation.get_mut().count += 1,
+            btree_map::Entry::Vacant(deviation) => {
+                eprintln!(
+                    "warning: unexpected entry in {}: {}: {}",
+                    Redacted(url),
+                    deviation.key(),
+                    value
+                );
+                let problem = deviation.key().clone();
+                deviation.insert(Deviation {
+                    problem,
+                    count: 1,
+                    example: value.clone(),
+                });
+            }
+        }
+    }
+
+    // https://download.seafile.com/published/web-api/v2.1/upload-links.md
+    fn api_upload_link(&self, token: &ShareToken) -> Result<Url, crate::Error> {
+        #[derive(Deserialize)]
+        struct UploadLink {
+            upload_link: Url,
+        }
+        let url = self.endpoint(["api", "v2.1", "upload-links", token.as_str(), "upload"]);
+        let get = || {
+            self.client
+                .get(url.as_str())
+                .header("accept", "application/json")
+                .call()
+        };
+        self.retry
+            .run(&url, |_| {
+                let mut res = match get() {
+                    Err(ureq::Error::StatusCode(403)) if self.renew_session()? => get()?,
+                    res => res?,
+                };
+                Ok(res.body_mut().read_json::<UploadLink>()?.upload_link)
+            })
+            .map_err(crate::Error::from)
+    }
+
+    /// Uploads a local file through an upload link, into `relative_path`
+    /// below the shared directory (created as needed). Existing files are
+    /// not replaced; the server picks a new name instead.
+    pub fn upload(
+        &self,
+        token: &ShareToken,
+        file: &Path,
+        relative_path: Option<&Path>,
+    ) -> Result<(), crate::Error> {
+        let name = file
+            .file_name()
+            .and_then(|n| n.to_str())
+            .ok_or_else(|| anyhow::anyhow!("{} has no valid file name", file.display()))?;
+        let random = self
+            .seed
+            .derive(format!("upload {}", file.display()).as_bytes());
+        let mut form = Form::new(random).text("parent_dir", "/");
+        if let Some(p) = relative_path.and_then(|p| p.to_str()) {
+            form = form.text("relative_path", p.trim_start_matches('/'));
+        }
+        let form = form.file("file", file, name)?;
+
+        let mut url = self.api_upload_link(token)?;
+        url.query_pairs_mut().append_pair("ret-json", "1");
+        self.client
+            .post(url.as_str())
+            .config()
+            // a transfer, not an API request
+            .timeout_global(None)
+            .build()
+            .header("content-type", form.content_type())
+            .header("content-length", form.len())
+            .send(ureq::SendBody::from_owned_reader(form.into_reader()))?;
+        Ok(())
+    }
+
+    /// Options of a page, read from the first variable of [`PAGE_OPTIONS`]
+    /// that holds them
+    fn extract_page_options<T: serde::de::DeserializeOwned>(
+        &self,
+        page: impl AsRef<str>,
+    ) -> Result<T, Error> {
+        let page = page.as_ref();
+        for (variable, wrapped) in PAGE_OPTIONS {
+            let Ok(pattern) = Regex::new(&format!(r"{}\s*=\s*\{{", variable)) else {
+                continue;
+            };
+            let Some(object) = pattern
+                .find(page)
+                .and_then(|start| script::object_literal(&page[start.end() - 1..]))
+            else {
+                continue;
+            };
+            let Some(value) = self.evaluate(object) else {
+                continue;
+            };
+            let options = match wrapped {
+                true => serde_json::from_value::<WebPageOptions<T>>(value).map(|o| o.options),
+                false => serde_json::from_value::<T>(value),
+            };
+            if let Ok(options) = options {
+                return Ok(options);
+            }
+        }
+        // a page of Seahub that is rendered by its scripts from the API, or
+        // sets its options in a way not known yet
+        let version =
+            Regex::new(r#"(?:seafileVersion|seafile_version)["']?\s*[:=]\s*["']([^"']+)["']"#)
+                .ok()
+                .and_then(|version| version.captures(page))
+                .map(|version| version[1].to_string());
+        match version.is_some() || page.contains("seahub") {
+            true => Err(Error::UnsupportedServer(version)),
+            false => Err(Error::InvalidShare),
+        }
+    }
+
+    /// Value of the object literal `object` of a page script: evaluated with
+    /// QuickJS where it is built in, or read as JSON5 with the expressions
+    /// in it left out
+    fn evaluate(&self, object: &str) -> Option<serde_json::Value> {
+        #[cfg(feature = "quickjs")]
+        {
+            use rquickjs::{Context, Function, Object, Value};
+            let ctx = Context::full(&self.quickjs).ok()?;
+            let json = ctx.with(|ctx| -> rquickjs::Result<String> {
+                let window = Object::new(ctx.clone())?;
+                window.set("app", Object::new(ctx.clone())?)?;
+                ctx.globals().set("window", window)?;
+                let json: Object = ctx.globals().get("JSON")?;
+                let json_stringify: Function = json.get("stringify")?;
+                ctx.eval::<Value, _>(format!("({})", object))
+                    .and_then(|v| json_stringify.call::<(Value<'_>,), rquickjs::String>((v,)))
+                    .and_then(|s| s.to_string())
+            });
+            // e.g. calls of functions the page defines elsewhere
+            if let Some(value) = json.ok().and_then(|json| serde_json::from_str(&json).ok()) {
+                return Some(value);
+            }
+        }
+        serde_json5::from_str(&script::literals(object)?).ok()
+    }
+
+    pub fn web_file(&self, url: &Url) -> Result<WebFileOptions, crate::Error> {
+        let body = self.retry.run(url, |_| {
+            let mut res = self.client.get(url.as_str()).call()?;
+            Ok(res.body_mut().read_to_string()?)
+        })?;
+        Ok(self.extract_page_options(body)?)
+    }
+
+    /// Counts the files, directories and bytes below `path`, skipping entries
+    /// (and subtrees) rejected by `filter`.
+    ///
+    /// With `max_dirs`, at most that many directories are listed and the
+    /// contents of the remaining ones are extrapolated from their average.
+    pub fn estimate(
+        &self,
+        token: &ShareToken,
+        path: Option<impl AsRef<Path>>,
+        filter: impl Fn(&DirEntry) -> bool,
+        max_dirs: Option<usize>,
+    ) -> Result<Estimate, crate::Error> {
+        let mut walker = Walker::new(self, token, Recursive::Bfs);
+        walker.push_entries(self.entries(token, path)?)?;
+        let mut estimate = Estimate::default();
+        let (mut listed, mut unlisted) = (1, 0);
+        while let Some(entry) = walker.next()? {
+            if !filter(&entry) {
+                continue;
+            }
+            estimate.add(&entry);
+            if entry.is_dir() {
+                if max_dirs.is_some_and(|max| listed >= max) {
+                    unlisted += 1;
+                } else {
+                    walker.descend(&entry)?;
+                    listed += 1;
+                }
+            }
+        }
+        if unlisted > 0 {
+            let ratio = unlisted as f64 / listed as f64;
+            estimate.files += (estimate.files as f64 * ratio) as u64;
+            estimate.bytes += (estimate.bytes as f64 * ratio) as u64;
+            estimate.sampled = true;
+        }
+        Ok(estimate)
+    }
+
+    pub fn head(&self, url: &Url) -> Result<Metadata, crate::Error> {
+        self.retry
+            .run(url, |_| {
+                let url = resolve_file_link(&self.client, url)?;
+                let res = self.client.head(url.as_str()).call()?;
+                Ok(Metadata::from_headers(res.headers()))
+            })
+            .map_err(crate::Error::from)
+    }
+
+    /// Fills in file metadata with a HEAD request to the download URL.
+    pub fn enrich(&self, entry: &mut DirEntry) -> Result<(), crate::Error> {
+        if let Some(url) = entry.download_url() {
+            let metadata = self.head(url)?;
+            entry.enrich(metadata);
+        }
+        Ok(())
+    }
+
+    pub fn entries(
+        &self,
+        token: &ShareToken,
+        path: Option<impl AsRef<Path>>,
+    ) -> Result<Vec<DirEntry>, crate::Error> {
+        let mut entries = Vec::new();
+        self.entry_batches(token, path.as_ref().map(AsRef::as_ref), |batch| {
+            entries.extend(batch);
+            true
+        })?;
+        Ok(entries)
+    }
+
+    /// Entries of `path` in batches as they are received, handed to `f`
+    /// until it returns false, so that a huge directory can be worked on
+    /// before it is listed in full
+    pub fn entry_batches(
+        &self,
+        token: &ShareToken,
+        path: Option<&Path>,
+        mut f: impl FnMut(Vec<DirEntry>) -> bool,
+    ) -> Result<(), crate::Error> {
+        if let Some(entries) = self.state.as_ref().and_then(|s| s.listing(token, path)) {
+            f(entries);
+            return Ok(());
+        }
+        // a listing is recorded once complete
+        let mut recorded = self.state.as_ref().map(|_| Vec::new());
+        let (mut received, mut complete) = (0, true);
+        let dir = path.unwrap_or(Path::new("/"));
+        let mut hand = |batch: Vec<DirEntry>| {
+            received += batch.len() as u64;
+            if let Some(progress) = self.progress.as_ref().filter(|_| batch.len() == BATCH) {
+                progress.set_listing(Some((dir, received)));
+            }
+            if let Some(recorded) = &mut recorded {
+                recorded.extend(batch.iter().cloned());
+            }
+            complete = f(batch);
+            complete
+        };
+        let result = if token.is_library() {
+            self.library_entries(token, path).map(|entries| {
+                hand(entries);
+            })
+        } else if self.api == Api::Legacy || self.legacy.load(Ordering::Relaxed) {
+            self.legacy_entries(token, path).map(|(entries, _)| {
+                hand(entries);
+            })
+        } else {
+            let batches = self.api_dirent_batches(token, path, |dirents| {
+                hand(dirents.iter().map(|e| self.entry(token, e)).collect())
+            });
+            match batches {
+                // servers before 6.3 do not know the API, and some disable or
+                // firewall it
+                Err(crate::Error::Http(ureq::Error::StatusCode(status @ (403 | 404))))
+                    if self.api == Api::Auto =>
+                {
+                    let (entries, exact) = self
+                        .legacy_entries(token, path)
+                        .map_err(|_| crate::Error::Http(ureq::Error::StatusCode(status)))?;
+                    if !self.legacy.swap(true, Ordering::Relaxed) {
+                        eprintln!(
+                            "note: {} does not answer the share link API, reading share pages \
+                             instead{}",
+                            self.base,
+                            match exact {
+                                true => "",
+                                false => " (sizes are rounded, --enrich gets them exactly)",
+                            }
+                        );
+                    }
+                    hand(entries);
+                    Ok(())
+                }
+                result => result,
+            }
+        };
+        if let Some(progress) = self.progress.as_ref().filter(|_| received >= BATCH as u64) {
+            progress.set_listing(None);
+        }
+        result?;
+        if let (Some(state), Some(entries), true) = (&self.state, recorded, complete) {
+            if let Err(e) = state.record_listing(token, path, &entries) {
+                eprintln!("could not save the listing of {}: {}", dir.display(), e);
+            }
+        }
+        Ok(())
+    }
+
+    fn entry(&self, token: &ShareToken, e: &DirEnt) -> DirEntry {
+        if e.is_file() {
+            DirEntry::File {
+                name: e.name().to_string(),
+                path: e.path().to_path_buf(),
+                size: e.size().unwrap(),
+                last_modified: Some(*e.last_modified()),
+                content_type: None,
+                id: None,
+                view_url: self.file_url(token, e.path(), false),
+                download_url: self.file_url(token, e.path(), true),
+            }
+        } else {
+            DirEntry::Directory {
+                name: e.name().to_string(),
+                path: e.path().to_path_buf(),
+                last_modified: *e.last_modified(),
+                view_url: self.dir_url(token, Some(e.path())),
+            }
+        }
+    }
+
+    /// Entries below `path` whose names the search of the server (a Seafile
+    /// Pro feature) finds for `query`, or None if the server cannot search
+    /// the share. The search may find more than names containing `query`,
+    /// and finds only what the server has indexed.
+    pub fn search(
+        &self,
+        token: &ShareToken,
+        path: Option<&Path>,
+        query: &str,
+    ) -> Result<Option<Vec<DirEntry>>, crate::Error> {
+        #[derive(Deserialize)]
+        struct Results {
+            results: Vec<serde_json::Value>,
+            #[serde(default)]
+            has_more: bool,
+        }
+        if token.is_library() || self.api == Api::Legacy || self.legacy.load(Ordering::Relaxed) {
+            return Ok(None);
+        }
+        let dir = path.unwrap_or(Path::new("/"));
+        let mut entries = Vec::new();
+        for page in 1.. {
+            let mut url = self.endpoint(["api", "v2.1", "share-links", token.as_str(), "search"]);
+            url.query_pairs_mut()
+                .append_pair("q", query)
+                .append_pair("path", &dir.to_string_lossy())
+                .append_pair("page", &page.to_string())
+                .append_pair("per_page", &SEARCH_PAGE.to_string());
+            let get = || {
+                self.client
+                    .get(url.as_str())
+                    .header("accept", "application/json")
+                    .call()
+            };
+            let results = self.retry.run(&url, |_| {
+                let mut res = match get() {
+                    Err(ureq::Error::StatusCode(403)) if self.renew_session()? => get()?,
+                    res => res?,
+                };
+                Ok(res.body_mut().read_json::<Results>()?)
+            });
+            let results = match results.map_err(crate::Error::from) {
+                // no search, or none for share links
+                Err(crate::Error::Http(ureq::Error::StatusCode(404 | 405))) => return Ok(None),
+                results => results?,
+            };
+            let more = results.has_more && !results.results.is_empty();
+            for mut value in results.results {
+                // results name the path "fullpath", without the trailing
+                // slash of directories in listings
+                if let Some(object) = value.as_object_mut() {
+                    if let Some(path) = object.remove("fullpath") {
+                        object.entry("path").or_insert(path);
+                    }
+                    let is_dir = object.get("is_dir") == Some(&serde_json::Value::Bool(true));
+                    if let Some(serde_json::Value::String(path)) = object.get_mut("path") {
+                        if is_dir && !path.ends_with('/') {
+                            path.push('/');
+                        }
+                    }
+                }
+                // results have fields of their own, which are no deviation
+                let Ok((entry, _)) = DirEnt::from_json_lenient(&value) else {
+                    continue;
+                };
+                if entry.path().starts_with(dir) && entry.path() != dir {
+                    entries.push(self.entry(token, &entry));
+                }
+            }
+            if !more {
+                break;
+            }
+        }
+        Ok(Some(entries))
+    }
+
+    /// JSON answer of the authenticated API to `url`
+    fn api2_json<T: serde::de::DeserializeOwned>(&self, url: &Url) -> Result<T, crate::Error> {
+        self.retry
+            .run(url, |_| {
+                let mut res = self
+                    .client
+                    .get(url.as_str())
+                    .header("accept", "application/json")
+                    .call()?;
+                Ok(res.body_mut().read_json::<T>()?)
+            })
+            .map_err(unauthorized)
+    }
+
+    /// Listing of a directory of a library, read through the authenticated
+    /// API
+    fn library_entries(
+        &self,
+        token: &ShareToken,
+        path: Option<&Path>,
+    ) -> Result<Vec<DirEntry>, crate::Error> {
+        let dir = path.unwrap_or(Path::new("/"));
+        let mut url = self.endpoint(["api2", "repos", token.as_str(), "dir"]);
+        url.query_pairs_mut()
+            .append_pair("p", &dir.to_string_lossy());
+        let values: Vec<serde_json::Value> = self.api2_json(&url)?;
+        let mut entries = Vec::new();
+        for mut value in values {
+            // entries have a name and a type, but no path
+            if let Some(object) = value.as_object_mut() {
+                let name = object
+                    .get("name")
+                    .and_then(|n| n.as_str())
+                    .unwrap_or_default();
+                let mut path = dir.join(name).to_string_lossy().into_owned();
+                if object.get("type").and_then(|t| t.as_str()) == Some("dir") {
+                    path.push('/');
+                }
+                object.insert("path".to_string(), path.into());
+            }
+            // entries have fields of their own, which are no deviation
+            if let Ok((entry, _)) = DirEnt::from_json_lenient(&value) {
+                let mut entry = self.entry(token, &entry);
+                // the file ID, which share listings do not have
+                if let DirEntry::File { id, .. } = &mut entry {
+                    *id = value.get("id").and_then(|i| i.as_str()).map(str::to_string);
+                }
+                entries.push(entry);
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Libraries the account of the API token owns or has been shared,
+    /// through the authenticated API
+    pub fn libraries(&self) -> Result<Vec<Library>, crate::Error> {
+        #[derive(Deserialize)]
+        struct Repo {
+            id: String,
+            name: String,
+            #[serde(default)]
+            size: u64,
+            mtime: Option<i64>,
+            #[serde(default)]
+            encrypted: bool,
+        }
+        let repos: Vec<Repo> = self.api2_json(&self.endpoint(["api2", "repos"]))?;
+        let mut libraries: Vec<Library> = Vec::new();
+        for repo in repos {
+            // a library shared in several ways is listed once for each
+            if libraries.iter().any(|l| l.id == repo.id) {
+                continue;
+            }
+            libraries.push(Library {
+                url: self.endpoint(["library", &repo.id, &repo.name]),
+                last_modified: repo.mtime.and_then(|t| DateTime::from_timestamp(t, 0)),
+                id: repo.id,
+                name: repo.name,
+                size: repo.size,
+                encrypted: repo.encrypted,
+            });
+        }
+        Ok(libraries)
+    }
+
+    /// Obtains an API token for the account `username`, with the code of its
+    /// second factor (`otp`) where two-factor authentication is on
+    pub fn login(
+        &self,
+        username: &str,
+        password: &str,
+        otp: Option<&str>,
+    ) -> Result<String, crate::Error> {
+        #[derive(Deserialize)]
+        struct Token {
+            token: String,
+        }
+        let url = self.endpoint(["api2", "auth-token"]);
+        let mut request = self
+            .client
+            .post(url.as_str())
+            .config()
+            .http_status_as_error(false)
+            .build();
+        if let Some(otp) = otp {
+            request = request.header("x-seafile-otp", otp);
+        }
+        let mut res = request.send_form([("username", username), ("password", password)])?;
+        if res.status() == ureq::http::StatusCode::BAD_REQUEST {
+            // the server asks for the code in a header of its own
+            let asks_otp = res
+                .headers()
+                .get("x-seafile-otp")
+                .is_some_and(|v| v == "required");
+            return Err(if asks_otp && otp.is_none() {
+                Error::OtpRequired
+            } else {
+                Error::LoginFailed
+            }
+            .into());
+        }
+        if res.status().as_u16() >= 400 {
+            return Err(status_error(&mut res));
+        }
+        Ok(res.body_mut().read_json::<Token>()?.token)
+    }
+
+    /// Email address of the account of the API token
+    pub fn account(&self) -> Result<String, crate::Error> {
+        #[derive(Deserialize)]
+        struct Account {
+            email: String,
+        }
+        let url = self.endpoint(["api2", "account", "info"]);
+        Ok(self.api2_json::<Account>(&url)?.email)
+    }
+
+    /// Listing of a directory read from its share page, for servers without
+    /// the share link API: from the entries in the options of the page, or
+    /// else from its table, with whether the sizes are exact (tables show
+    /// them rounded)
+    fn legacy_entries(
+        &self,
+        token: &ShareToken,
+        path: Option<&Path>,
+    ) -> Result<(Vec<DirEntry>, bool), crate::Error> {
+        let url = self.dir_url(token, path);
+        let get = || self.client.get(url.as_str()).call();
+        let page = self.retry.run(&url, |_| {
+            let mut res = match get() {
+                Err(ureq::Error::StatusCode(403)) if self.renew_session()? => get()?,
+                res => res?,
+            };
+            Ok(res.body_mut().read_to_string()?)
+        })?;
+        if let Ok(options) = self.extract_page_options::<DirPageOptions>(&page) {
+            let mut entries = Vec::new();
+            for value in options.dirent_list {
+                if let Some(e) = self.dirent(&url, value)? {
+                    entries.push(self.entry(token, &e));
+                }
+            }
+            return Ok((entries, true));
+        }
+        let dir = path.unwrap_or(Path::new("/"));
+        let rows = legacy::parse(&page, dir)
+            .ok_or_else(|| anyhow::anyhow!("no directory listing in {}", url))?;
+        let entries = rows
+            .into_iter()
+            .map(|row| {
+                if row.is_dir {
+                    DirEntry::Directory {
+                        view_url: self.dir_url(token, Some(&row.path)),
+                        name: row.name,
+                        path: row.path,
+                        last_modified: row.last_modified.unwrap_or(DateTime::UNIX_EPOCH),
+                    }
+                } else {
+                    // no time, so that the rounded size is not taken as exact
+                    DirEntry::File {
+                        view_url: self.file_url(token, &row.path, false),
+                        download_url: self.file_url(token, &row.path, true),
+                        name: row.name,
+                        path: row.path,
+                        size: row.size,
+                        last_modified: None,
+                        content_type: None,
+                        id: None,
+                    }
+                }
+            })
+            .collect();
+        Ok((entries, false))
+    }
+
+    /// Library and link of the share, read from its share page (or from the
+    /// authenticated API for a library itself)
+    pub fn share_info(&self, link: &ShareLink) -> Result<ShareInfo, crate::Error> {
+        #[derive(Deserialize)]
+        struct Repo {
+            name: String,
+            owner: Option<String>,
+            /// "r" or "rw"
+            permission: Option<String>,
+        }
+        // a download URL has no share page
+        if let ShareLink::FileServer { .. } = link {
+            return Ok(ShareInfo::default());
+        }
+        if link.token().is_library() {
+            let url = self.endpoint(["api2", "repos", link.token().as_str()]);
+            let repo: Repo = self.api2_json(&url)?;
+            return Ok(ShareInfo {
+                repo_id: Some(link.token().to_string()),
+                repo_name: Some(repo.name),
+                owner: repo.owner,
+                can_download: Some(true),
+                can_upload: repo.permission.map(|p| p.contains('w')),
+                ..ShareInfo::default()
+            });
+        }
+        let url = link.page_url(self);
+        let body = self.retry.run(&url, |_| {
+            let mut res = self.client.get(url.as_str()).call()?;
+            Ok(res.body_mut().read_to_string()?)
+        })?;
+        if body.contains("csrfmiddlewaretoken") && body.contains(r#"name="password""#) {
+            return Ok(ShareInfo {
+                password_protected: true,
+                ..ShareInfo::default()
+            });
+        }
+        let page: SharePageOptions = self.extract_page_options(body)?;
+        Ok(ShareInfo {
+            repo_id: page.repo_id,
+            repo_name: page.repo_name.or(page.dir_name),
+            owner: page.shared_by,
+            expires: page
+                .expire_date
+                .and_then(|date| DateTime::parse_from_rfc3339(&date).ok())
+                .map(|date| date.to_utc()),
+            can_download: page.can_download,
+            can_upload: page.can_upload,
+            password_protected: self.protected,
+        })
+    }
+
+    /// Fails with [`Error::ViewOnly`] if the share page says that the share
+    /// cannot be downloaded, which its downloads would otherwise only tell
+    /// one by one with 403. A page that cannot be read (e.g. of a layout
+    /// that is not known, or of a server without pages) tells nothing.
+    pub fn check_downloadable(&self, link: &ShareLink) -> Result<(), crate::Error> {
+        match self.share_info(link) {
+            Ok(info) if info.can_download == Some(false) => Err(Error::ViewOnly.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// File of a single file share, from its share page and the headers of
+    /// its download
+    pub fn single_file(&self, url: &Url) -> Result<DirEntry, crate::Error> {
+        let file = self.web_file(url)?;
+        let mut entry = DirEntry::File {
+            name: file.name.clone(),
+            path: file.path.clone(),
+            size: file.size,
+            last_modified: None,
+            content_type: None,
+            id: None,
+            view_url: url.clone(),
+            download_url: file.raw_path.clone(),
+        };
+        // the page does not tell when the file was modified. Its raw path may
+        // hold a one-time access token, so the download of the share is asked
+        // instead, which redirects to a fresh one; without an answer the
+        // time stays unknown, as the download itself will tell what failed.
+        let mut download = url.clone();
+        download.set_query(Some("dl=1"));
+        if let Ok(metadata) = self.head(&download) {
+            entry.enrich(metadata);
+        }
+        Ok(entry)
+    }
+
+    /// File of a download URL of the file server, as its headers describe it
+    pub fn file_server_file(&self, url: &Url) -> Result<DirEntry, crate::Error> {
+        let name = url
+            .path_segments()
+            .and_then(|mut s| s.rfind(|s| !s.is_empty()))
+            .map(|s| percent_encoding::percent_decode_str(s).decode_utf8_lossy())
+            .ok_or(Error::InvalidShare)?;
+        let mut entry = DirEntry::File {
+            name: name.to_string(),
+            path: Path::new("/").join(name.as_ref()),
+            // told by the headers
+            size: 0,
+            last_modified: None,
+            content_type: None,
+            id: None,
+            view_url: url.clone(),
+            download_url: url.clone(),
+        };
+        self.enrich(&mut entry)?;
+        Ok(entry)
+    }
+
+    /// File at `path` of a directory share or a library, looked up with one
+    /// request rather than in the listing of its directory: the file detail
+    /// of the authenticated API for a library, and the headers of the
+    /// download for a share.
+    pub fn file_detail(&self, token: &ShareToken, path: &Path) -> Result<DirEntry, crate::Error> {
+        #[derive(Deserialize)]
+        struct Detail {
+            id: String,
+            size: u64,
+            mtime: i64,
+        }
+        let not_found = |e: crate::Error| match e {
+            crate::Error::Http(ureq::Error::StatusCode(404)) => {
+                anyhow::anyhow!("{} not found", path.display()).into()
+            }
+            e => e,
+        };
+        let Some(name) = path.file_name() else {
+            return Err(anyhow::anyhow!("{} is a directory", path.display()).into());
+        };
+        let mut entry = DirEntry::File {
+            name: name.to_string_lossy().into_owned(),
+            path: path.to_path_buf(),
+            size: 0,
+            last_modified: None,
+            content_type: None,
+            id: None,
+            view_url: self.file_url(token, path, false),
+            download_url: self.file_url(token, path, true),
+        };
+        if token.is_library() {
+            let mut url = self.endpoint(["api2", "repos", token.as_str(), "file", "detail"]);
+            url.query_pairs_mut()
+                .append_pair("p", &path.to_string_lossy());
+            let detail: Detail = self.api2_json(&url).map_err(not_found)?;
+            if let DirEntry::File {
+                size,
+                last_modified,
+                id,
+                ..
+            } = &mut entry
+            {
+                *size = detail.size;
+                *last_modified = DateTime::from_timestamp(detail.mtime, 0);
+                *id = Some(detail.id);
+            }
+        } else {
+            let url = self.file_url(token, path, true);
+            entry.enrich(self.head(&url).map_err(not_found)?);
+        }
+        Ok(entry)
+    }
+
+    /// The file a link points to, given as a file link or as the path (or
+    /// `path`) of a directory link
+    pub fn file(&self, link: &ShareLink, path: Option<&Path>) -> Result<DirEntry, crate::Error> {
+        if let ShareLink::FileServer { url, .. } = link {
+            return self.file_server_file(url);
+        }
+        if link.is_single_file() {
+            return self.single_file(&link.page_url(self));
+        }
+        let path = path.or(link.path()).unwrap_or(Path::new("/"));
+        self.file_detail(link.token(), path)
+    }
+}
+
+impl Metadata {
+    pub fn from_headers(headers: &ureq::http::HeaderMap) -> Self {
+        let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+        Self {
+            size: header("content-length").and_then(|v| v.parse().ok()),
+            last_modified: header("last-modified")
+                .and_then(|v| DateTime::parse_from_rfc2822(v).ok())
+                .map(|dt| dt.to_utc()),
+            content_type: header("content-type").map(|v| v.to_string()),
+            accept_ranges: header("accept-ranges")
+                .is_some_and(|v| v.split(',').any(|unit| unit.trim() == "bytes")),
+            etag: header("etag").map(|v| v.trim_start_matches("W/").trim_matches('"').to_string()),
+        }
+    }
+}
@@ -0,0 +1,116 @@
+//! Listings of servers older than the share link API (before Seafile 6.3),
+//! read from the table of a directory share page.
+
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use regex::Regex;
+use url::Url;
+
+/// Entry of a share page listing
+#[derive(Debug, Clone)]
+pub(super) struct Row {
+    pub path: PathBuf,
+    pub name: String,
+    pub is_dir: bool,
+    /// Approximate, as pages show sizes rounded to a unit
+    pub size: u64,
+    pub last_modified: Option<DateTime<Utc>>,
+}
+
+/// Entries of `dir` listed in a share page, or None if the page has no
+/// listing
+pub(super) fn parse(page: &str, dir: &Path) -> Option<Vec<Row>> {
+    if !page.contains("repo-file-list") {
+        return None;
+    }
+    let row = Regex::new(r#"(?s)<tr[^>]*class="[^"]*file-item[^"]*"[^>]*>(.*?)</tr>"#).ok()?;
+    let link = Regex::new(r#"(?s)<a([^>]*)href="([^"]*)"[^>]*>\s*([^<]*?)\s*</a>"#).ok()?;
+    let size = Regex::new(
+        r"<td[^>]*>\s*([\d.]+)(?:\s|&nbsp;|\x{a0})*(bytes|B|KB|MB|GB|TB|KiB|MiB|GiB|TiB)\s*</td>",
+    )
+    .ok()?;
+    let time = Regex::new(r"<time[^>]*>").ok()?;
+
+    let rows = row
+        .captures_iter(page)
+        .filter_map(|row| {
+            let row = row.get(1)?.as_str();
+            let link = link.captures(row)?;
+            let attributes = link.get(1)?.as_str();
+            let href = unescape(link.get(2)?.as_str());
+            let name = unescape(link.get(3)?.as_str());
+            // directories link back to the share page with a path ending in
+            // "/", files to a page of their own
+            let is_dir = attributes.contains("dir-link") || {
+                let href = Url::parse("http://localhost/").ok()?.join(&href).ok()?;
+                !href.path().contains("/files/")
+                    && href
+                        .query_pairs()
+                        .any(|(k, v)| k == "p" && v.ends_with('/'))
+            };
+            let size = match size.captures(row) {
+                Some(size) if !is_dir => parse_size(&size[1], &size[2]),
+                _ => 0,
+            };
+            let last_modified = time.find(row).and_then(|time| {
+                let time = time.as_str();
+                attribute(time, "datetime")
+                    .and_then(parse_time)
+                    .or_else(|| {
+                        Some(
+                            DateTime::parse_from_rfc2822(attribute(time, "title")?)
+                                .ok()?
+                                .to_utc(),
+                        )
+                    })
+            });
+            Some(Row {
+                path: dir.join(&name),
+                name,
+                is_dir,
+                size,
+                last_modified,
+            })
+        })
+        .collect();
+    Some(rows)
+}
+
+/// Bytes of a size as shown by Django's `filesizeformat`, e.g. "1.5 MB"
+fn parse_size(number: &str, unit: &str) -> u64 {
+    let number: f64 = number.parse().unwrap_or(0.0);
+    let scale: u64 = match unit {
+        "KB" | "KiB" => 1 << 10,
+        "MB" | "MiB" => 1 << 20,
+        "GB" | "GiB" => 1 << 30,
+        "TB" | "TiB" => 1 << 40,
+        _ => 1,
+    };
+    (number * scale as f64) as u64
+}
+
+/// Times with an offset, or in UTC without one
+fn parse_time(s: &str) -> Option<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Some(time.to_utc());
+    }
+    let time = NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S").ok()?;
+    Some(time.and_utc())
+}
+
+/// Value of the attribute `name` of an HTML `tag`
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let start = tag.find(&format!(" {}=\"", name))? + name.len() + 3;
+    let len = tag[start..].find('"')?;
+    Some(&tag[start..start + len])
+}
+
+fn unescape(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&amp;", "&")
+}
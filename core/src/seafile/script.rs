@@ -0,0 +1,295 @@
+//! Object literals in the scripts of Seahub pages, read without a JavaScript
+//! engine: the literal is cut out by its braces, and values that are
+//! expressions rather than literals (calls like `gettext('…')`, comparisons,
+//! template strings...) are replaced with `null`, which leaves JSON5.
+
+/// Object literal at the start of `source`, up to its closing brace
+pub(super) fn object_literal(source: &str) -> Option<&str> {
+    let mut scanner = Scanner { source, at: 0 };
+    if scanner.peek() != Some(b'{') {
+        return None;
+    }
+    let mut depth = 0;
+    loop {
+        scanner.skip_space();
+        match scanner.peek()? {
+            b'\'' | b'"' | b'`' => scanner.string()?,
+            b'{' | b'[' | b'(' => {
+                depth += 1;
+                scanner.at += 1;
+            }
+            b'}' | b']' | b')' => {
+                depth -= 1;
+                scanner.at += 1;
+                if depth == 0 {
+                    return Some(&source[..scanner.at]);
+                }
+            }
+            _ => scanner.skip_char(),
+        }
+    }
+}
+
+/// `object`, an object literal, as JSON5 with its expressions replaced by
+/// `null`, or None if it has syntax JSON5 cannot hold at all, such as
+/// methods or spread members
+pub(super) fn literals(object: &str) -> Option<String> {
+    let mut scanner = Scanner {
+        source: object,
+        at: 0,
+    };
+    let mut json5 = String::with_capacity(object.len());
+    scanner.value(&mut json5)?;
+    Some(json5)
+}
+
+struct Scanner<'a> {
+    source: &'a str,
+    at: usize,
+}
+
+impl<'a> Scanner<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.source.as_bytes().get(self.at).copied()
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.source[self.at..]
+    }
+
+    fn skip_char(&mut self) {
+        self.at += self.rest().chars().next().map_or(1, char::len_utf8);
+    }
+
+    /// Skips whitespace and comments.
+    fn skip_space(&mut self) {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.at += rest.len() - trimmed.len();
+            if trimmed.starts_with("//") {
+                self.at += trimmed.find('\n').unwrap_or(trimmed.len());
+            } else if let Some(comment) = trimmed.strip_prefix("/*") {
+                self.at += comment.find("*/").map_or(trimmed.len(), |end| end + 4);
+            } else {
+                return;
+            }
+        }
+    }
+
+    /// Skips the string starting at the current quote.
+    fn string(&mut self) -> Option<()> {
+        let quote = self.peek()?;
+        self.at += 1;
+        loop {
+            match self.peek()? {
+                b'\\' => {
+                    self.at += 1;
+                    self.peek()?;
+                    self.skip_char();
+                }
+                c if c == quote => {
+                    self.at += 1;
+                    return Some(());
+                }
+                _ => self.skip_char(),
+            }
+        }
+    }
+
+    /// Skips the characters of an identifier or a number.
+    fn word(&mut self) -> &'a str {
+        let start = self.at;
+        while let Some(c) = self.peek() {
+            let exponent = matches!(c, b'+' | b'-')
+                && self.at > start
+                && matches!(self.source.as_bytes()[self.at - 1], b'e' | b'E')
+                && self.source.as_bytes()[start].is_ascii_digit();
+            if !(c.is_ascii_alphanumeric() || matches!(c, b'_' | b'$' | b'.') || exponent) {
+                break;
+            }
+            self.at += 1;
+        }
+        &self.source[start..self.at]
+    }
+
+    /// Whether the value before ends here, rather than going on as an
+    /// expression
+    fn at_value_end(&mut self) -> bool {
+        self.skip_space();
+        matches!(self.peek(), None | Some(b',' | b'}' | b']'))
+    }
+
+    /// Skips the rest of an expression, up to the "," or closing bracket
+    /// after it.
+    fn expression(&mut self) -> Option<()> {
+        let mut depth = 0;
+        loop {
+            self.skip_space();
+            match self.peek()? {
+                b'\'' | b'"' | b'`' => self.string()?,
+                b'{' | b'[' | b'(' => {
+                    depth += 1;
+                    self.at += 1;
+                }
+                b'}' | b']' | b')' if depth == 0 => return Some(()),
+                b',' if depth == 0 => return Some(()),
+                b'}' | b']' | b')' => {
+                    depth -= 1;
+                    self.at += 1;
+                }
+                _ => self.skip_char(),
+            }
+        }
+    }
+
+    fn value(&mut self, json5: &mut String) -> Option<()> {
+        self.skip_space();
+        let start = self.at;
+        match self.peek()? {
+            b'{' => return self.object(json5),
+            b'[' => return self.array(json5),
+            b'\'' | b'"' => self.string()?,
+            b'-' | b'+' => {
+                self.at += 1;
+                self.word();
+            }
+            _ => {
+                let word = self.word();
+                let literal = word.starts_with(|c: char| c.is_ascii_digit() || c == '.')
+                    || matches!(word, "true" | "false" | "null" | "Infinity" | "NaN");
+                if !literal {
+                    self.expression()?;
+                    json5.push_str("null");
+                    return Some(());
+                }
+            }
+        }
+        let literal = &self.source[start..self.at];
+        if self.at_value_end() && !literal.is_empty() {
+            json5.push_str(literal);
+        } else {
+            self.expression()?;
+            json5.push_str("null");
+        }
+        Some(())
+    }
+
+    fn object(&mut self, json5: &mut String) -> Option<()> {
+        self.at += 1;
+        json5.push('{');
+        loop {
+            self.skip_space();
+            match self.peek()? {
+                b'}' => {
+                    self.at += 1;
+                    json5.push('}');
+                    return Some(());
+                }
+                b'\'' | b'"' => {
+                    let start = self.at;
+                    self.string()?;
+                    json5.push_str(&self.source[start..self.at]);
+                }
+                _ => {
+                    let key = self.word();
+                    if key.is_empty() || key.contains('.') {
+                        return None;
+                    }
+                    // JSON5 takes identifiers as keys, but not numbers
+                    match key.starts_with(|c: char| c.is_ascii_digit()) {
+                        true => json5.push_str(&format!("\"{}\"", key)),
+                        false => json5.push_str(key),
+                    }
+                }
+            }
+            self.skip_space();
+            if self.peek()? != b':' {
+                return None;
+            }
+            self.at += 1;
+            json5.push(':');
+            self.value(json5)?;
+            self.skip_space();
+            match self.peek()? {
+                b',' => {
+                    self.at += 1;
+                    json5.push(',');
+                }
+                b'}' => {}
+                _ => return None,
+            }
+        }
+    }
+
+    fn array(&mut self, json5: &mut String) -> Option<()> {
+        self.at += 1;
+        json5.push('[');
+        loop {
+            self.skip_space();
+            if self.peek()? == b']' {
+                self.at += 1;
+                json5.push(']');
+                return Some(());
+            }
+            self.value(json5)?;
+            self.skip_space();
+            match self.peek()? {
+                b',' => {
+                    self.at += 1;
+                    json5.push(',');
+                }
+                b']' => {}
+                _ => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn object_literal_ends_at_its_closing_brace() {
+        let source = "{a: '}', b: \"\\\"}\", c: `}${x}`, // }\n d: /* } */ f({e: [1]})}; x = {}";
+        assert_eq!(
+            object_literal(source),
+            Some(&source[..source.find(';').unwrap()])
+        );
+        assert_eq!(object_literal(" {}"), None);
+        assert_eq!(object_literal("{a: '}'"), None);
+    }
+
+    #[test]
+    fn expressions_become_null() {
+        let cases = [
+            ("{title: gettext('Share'), size: 3}", "{title:null,size:3}"),
+            ("{a: `x ${y}`, b: 'ok'}", "{a:null,b:'ok'}"),
+            ("{pro: 'True' == 'True', n: 1 + 2}", "{pro:null,n:null}"),
+            ("{/* c */ a: 1, // x\n b: 2 /* y */}", "{a:1,b:2}"),
+            (r#"{a: 'it\'s', "b\"c": "d"}"#, r#"{a:'it\'s',"b\"c":"d"}"#),
+            ("{a: [1, 2,], b: 3,}", "{a:[1,2,],b:3,}"),
+            (
+                "{1: 'a', 0x1: 'b', c2: 'c'}",
+                r#"{"1":'a',"0x1":'b',c2:'c'}"#,
+            ),
+            (
+                "{a: {b: [true, {c: -1.5e+3}], d: x > 1 ? 'y' : 'z'}}",
+                "{a:{b:[true,{c:-1.5e+3}],d:null}}",
+            ),
+        ];
+        for (object, json5) in cases {
+            assert_eq!(literals(object).as_deref(), Some(json5), "{}", object);
+            let parsed = serde_json5::from_str::<serde_json::Value>(json5);
+            assert!(parsed.is_ok(), "{}: {:?}", json5, parsed);
+        }
+    }
+
+    #[test]
+    fn rejects_what_json5_cannot_hold() {
+        for object in ["{f() {}}", "{...a}", "{a.b: 1}", "{a 1}", "{a: 1"] {
+            assert_eq!(literals(object), None, "{}", object);
+        }
+    }
+}
@@ -0,0 +1,47 @@
+//! Source of the pseudo-random numbers used for retry jitter and upload
+//! boundaries. Numbers are derived from a seed and what they are for, rather
+//! than drawn in turn, so a seeded run repeats them regardless of the order
+//! in which parallel workers ask.
+
+/// Seed of all pseudo-random behavior, taken from the clock unless given
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Seed(u64);
+
+impl Seed {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    /// A seed that differs between runs
+    pub fn from_clock() -> Self {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos());
+        Self(mix(nanos as u64, u64::from(std::process::id())))
+    }
+
+    /// A number determined by the seed and `input`
+    pub fn derive(&self, input: &[u8]) -> u64 {
+        input
+            .chunks(8)
+            .fold(mix(self.0, input.len() as u64), |state, chunk| {
+                let mut word = [0; 8];
+                word[..chunk.len()].copy_from_slice(chunk);
+                mix(state, u64::from_le_bytes(word))
+            })
+    }
+}
+
+impl Default for Seed {
+    fn default() -> Self {
+        Self::from_clock()
+    }
+}
+
+/// Combines `value` into `state` with the SplitMix64 finalizer
+fn mix(state: u64, value: u64) -> u64 {
+    let mut z = (state ^ value).wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
@@ -0,0 +1,74 @@
+//! Teardown of a download run.
+//!
+//! A run stops in one of two ways:
+//!
+//! - It drains when it fails (e.g. the listing breaks off) or on the first
+//!   Ctrl-C: no further entries are handed to workers, while transfers
+//!   already started complete, are finalized and reported before the error
+//!   is returned.
+//! - It aborts when a worker panics or on the second Ctrl-C: transfers stop
+//!   at their next chunk and their ".part" files are removed (kept with
+//!   "--conflict continue" so they can be resumed), while continued files
+//!   keep the data appended so far. Transferred files already queued for
+//!   finalization are finalized.
+//!
+//! Either way the listing thread is cancelled and spilled entries are deleted
+//! together with their temporary file. [`crate::pool::Pool`] runs the
+//! workers of a run this way.
+
+use std::{
+    io::{self, Read},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+/// Abort signal shared by all workers of a run
+#[derive(Debug, Clone, Default)]
+pub struct Abort(Arc<AtomicBool>);
+
+impl Abort {
+    pub fn trigger(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Returns a guard that triggers the abort if the current thread panics
+    /// while it is held.
+    pub fn on_panic(&self) -> PanicGuard<'_> {
+        PanicGuard(self)
+    }
+
+    /// Wraps a transfer so that it fails at the next read once aborted.
+    pub fn reader<R: Read>(&self, inner: R) -> AbortableReader<'_, R> {
+        AbortableReader { inner, abort: self }
+    }
+}
+
+pub struct PanicGuard<'a>(&'a Abort);
+
+impl Drop for PanicGuard<'_> {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            self.0.trigger();
+        }
+    }
+}
+
+pub struct AbortableReader<'a, R> {
+    inner: R,
+    abort: &'a Abort,
+}
+
+impl<R: Read> Read for AbortableReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.abort.is_triggered() {
+            return Err(io::Error::other("download aborted"));
+        }
+        self.inner.read(buf)
+    }
+}
@@ -0,0 +1,172 @@
+//! Output destinations published whole with "--stage-then-swap": the tree is
+//! updated in a hidden copy next to the destination and swapped into its
+//! place once every file arrived, so that readers of a mirror never see it
+//! half updated.
+
+use std::{
+    ffi::{OsStr, OsString},
+    fs::File,
+    io,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+
+use crate::{checksum::Algorithm, sync::FILE_IDS};
+
+/// Hidden directory next to `dest` that its tree is updated in, e.g.
+/// `.mirror.staging` for `mirror`. A run that fails keeps it, and the next
+/// one continues it.
+///
+/// The working directory, or a directory holding it, cannot be staged, as
+/// the swap removes the tree it replaces.
+pub fn staging_dir(dest: &Path) -> anyhow::Result<PathBuf> {
+    let dest = std::path::absolute(dest)?;
+    let resolved = dest.canonicalize().unwrap_or_else(|_| dest.clone());
+    if std::env::current_dir()?.starts_with(&resolved) {
+        anyhow::bail!(
+            "--stage-then-swap cannot replace {}, which holds the working directory; \
+             give the destination with --output",
+            resolved.display()
+        );
+    }
+    let dest = resolved;
+    let name = dest
+        .file_name()
+        .with_context(|| format!("{} has no name to stage it next to", dest.display()))?;
+    Ok(dest.with_file_name(hidden(name, "staging")))
+}
+
+fn hidden(name: &std::ffi::OsStr, suffix: &str) -> OsString {
+    let mut hidden = OsString::from(".");
+    hidden.push(name);
+    hidden.push(".");
+    hidden.push(suffix);
+    hidden
+}
+
+/// Fills `staging` with the files of `dest` it does not hold yet, so that
+/// only what changed is downloaded, and files left out of the run are kept
+/// by the swap. Files are hard linked where `link` allows, as downloads
+/// replace files rather than write into them, and copied otherwise, with
+/// their modification time; the records of runs, which are rewritten in
+/// place, are always copied.
+pub fn seed(dest: &Path, staging: &Path, link: bool) -> anyhow::Result<()> {
+    let entries = match std::fs::read_dir(dest) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).with_context(|| format!("cannot read {}", dest.display())),
+    };
+    std::fs::create_dir_all(staging)?;
+    for entry in entries {
+        let entry = entry?;
+        let (from, to) = (entry.path(), staging.join(entry.file_name()));
+        let kind = entry.file_type()?;
+        if kind.is_dir() {
+            seed(&from, &to, link)?;
+            continue;
+        }
+        // staged by a run that failed
+        if std::fs::symlink_metadata(&to).is_ok() {
+            continue;
+        }
+        let context = || format!("cannot stage {}", from.display());
+        if kind.is_symlink() {
+            copy_symlink(&from, &to).with_context(context)?;
+            continue;
+        }
+        // e.g. across file systems
+        let linked =
+            link && !rewritten(&entry.file_name()) && std::fs::hard_link(&from, &to).is_ok();
+        if !linked {
+            copy(&from, &to).with_context(context)?;
+        }
+    }
+    Ok(())
+}
+
+/// Whether files named `name` are records that runs rewrite in place
+fn rewritten(name: &OsStr) -> bool {
+    let manifests = [Algorithm::Sha256, Algorithm::Md5].map(|a| a.manifest_name());
+    name == FILE_IDS
+        || manifests.iter().any(|manifest| name == *manifest)
+        || [".part", ".parts.json"]
+            .iter()
+            .any(|suffix| name.to_string_lossy().ends_with(suffix))
+}
+
+/// Copies the file `from` to `to` with its modification time, by which
+/// syncs tell it unchanged
+fn copy(from: &Path, to: &Path) -> io::Result<()> {
+    std::fs::copy(from, to)?;
+    let modified = std::fs::metadata(from)?.modified()?;
+    File::options().write(true).open(to)?.set_modified(modified)
+}
+
+#[cfg(unix)]
+fn copy_symlink(from: &Path, to: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(std::fs::read_link(from)?, to)
+}
+
+#[cfg(not(unix))]
+fn copy_symlink(from: &Path, to: &Path) -> io::Result<()> {
+    copy(from, to)
+}
+
+/// Puts the tree of `staging` in the place of `dest`, removing the tree it
+/// replaces. Where the system cannot exchange both in one step, `dest` is
+/// moved aside just before the staged tree takes its name.
+pub fn swap(staging: &Path, dest: &Path) -> anyhow::Result<()> {
+    // "." cannot be renamed
+    let dest = &std::path::absolute(dest)?;
+    let context = || format!("cannot swap {} into {}", staging.display(), dest.display());
+    if !std::fs::exists(dest)? {
+        return std::fs::rename(staging, dest).with_context(context);
+    }
+    if exchange(staging, dest).with_context(context)? {
+        // holding the tree it replaced
+        return std::fs::remove_dir_all(staging)
+            .with_context(|| format!("cannot remove {}", staging.display()));
+    }
+    let old = dest.with_file_name(hidden(dest.file_name().unwrap_or_default(), "old"));
+    if std::fs::exists(&old)? {
+        std::fs::remove_dir_all(&old)?;
+    }
+    std::fs::rename(dest, &old).with_context(context)?;
+    if let Err(e) = std::fs::rename(staging, dest) {
+        std::fs::rename(&old, dest)?;
+        return Err(e).with_context(context);
+    }
+    std::fs::remove_dir_all(&old).with_context(|| format!("cannot remove {}", old.display()))
+}
+
+/// Exchanges `a` and `b` atomically, returning false if the file system
+/// cannot
+#[cfg(target_os = "linux")]
+fn exchange(a: &Path, b: &Path) -> io::Result<bool> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+    let a = CString::new(a.as_os_str().as_bytes())?;
+    let b = CString::new(b.as_os_str().as_bytes())?;
+    let exchanged = unsafe {
+        libc::renameat2(
+            libc::AT_FDCWD,
+            a.as_ptr(),
+            libc::AT_FDCWD,
+            b.as_ptr(),
+            libc::RENAME_EXCHANGE,
+        )
+    };
+    if exchanged == 0 {
+        return Ok(true);
+    }
+    let e = io::Error::last_os_error();
+    match e.raw_os_error() {
+        Some(libc::EINVAL | libc::ENOSYS) => Ok(false),
+        _ => Err(e),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn exchange(_: &Path, _: &Path) -> io::Result<bool> {
+    Ok(false)
+}
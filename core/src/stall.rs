@@ -0,0 +1,95 @@
+//! Detection of stalled connections. The timeouts of [`ureq`] cover whole
+//! phases of a request (e.g. receiving the body), which a large download can
+//! legitimately take hours for; [`ReadTimeout`] instead fails a read that
+//! receives nothing for a while.
+
+use std::{io, time::Duration};
+
+use ureq::{
+    config::Config,
+    unversioned::{
+        resolver::DefaultResolver,
+        transport::{
+            Buffers, ConnectionDetails, Connector, DefaultConnector, NextTimeout, Transport,
+        },
+    },
+    Agent, Error,
+};
+
+/// Connector limiting the time a read may wait for data, chained after the
+/// connectors that open the connection
+#[derive(Debug, Clone, Copy)]
+pub struct ReadTimeout(Duration);
+
+impl ReadTimeout {
+    pub fn new(timeout: Duration) -> Self {
+        Self(timeout)
+    }
+}
+
+impl<In: Transport> Connector<In> for ReadTimeout {
+    type Out = StallTransport<In>;
+
+    fn connect(
+        &self,
+        _details: &ConnectionDetails,
+        chained: Option<In>,
+    ) -> Result<Option<Self::Out>, Error> {
+        Ok(chained.map(|inner| StallTransport {
+            inner,
+            timeout: self.0,
+        }))
+    }
+}
+
+/// Transport of a [`ReadTimeout`]
+#[derive(Debug)]
+pub struct StallTransport<T> {
+    inner: T,
+    timeout: Duration,
+}
+
+impl<T: Transport> Transport for StallTransport<T> {
+    fn buffers(&mut self) -> &mut dyn Buffers {
+        self.inner.buffers()
+    }
+
+    fn transmit_output(&mut self, amount: usize, timeout: NextTimeout) -> Result<(), Error> {
+        self.inner.transmit_output(amount, timeout)
+    }
+
+    fn await_input(&mut self, mut timeout: NextTimeout) -> Result<bool, Error> {
+        if timeout.after <= self.timeout.into() {
+            return self.inner.await_input(timeout);
+        }
+        timeout.after = self.timeout.into();
+        self.inner.await_input(timeout).map_err(|e| match e {
+            Error::Timeout(_) => Error::Io(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("no data received for {:?}", self.timeout),
+            )),
+            e => e,
+        })
+    }
+
+    fn is_open(&mut self) -> bool {
+        self.inner.is_open()
+    }
+
+    fn is_tls(&self) -> bool {
+        self.inner.is_tls()
+    }
+}
+
+/// Agent with `config` whose reads fail after waiting `read_timeout` for
+/// data, if any
+pub fn agent(config: Config, read_timeout: Option<Duration>) -> Agent {
+    match read_timeout {
+        Some(timeout) => Agent::with_parts(
+            config,
+            DefaultConnector::new().chain(ReadTimeout::new(timeout)),
+            DefaultResolver::default(),
+        ),
+        None => Agent::new_with_config(config),
+    }
+}
@@ -0,0 +1,227 @@
+//! State of a recursive download kept in a file (`--session`), so that an
+//! interrupted run can be resumed without listing every directory and
+//! checking every file again.
+//!
+//! A [`Client`](crate::seafile::Client) given the state through
+//! [`with_run_state`](crate::seafile::Client::with_run_state) answers listings
+//! from it and records new ones; completed files are recorded by the caller.
+//!
+//! Listings are appended to a journal of their own (the session file with
+//! ".listings" added) as soon as each directory is complete, rather than
+//! saved with the completed files, so that a listing phase that is cut short
+//! (e.g. the estimate before the transfers, or a dry run) continues from the
+//! directories listed so far. The directories still pending are those whose
+//! listings are missing, which a new walk finds without asking the server.
+
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::{seafile::ShareToken, DirEntry};
+
+/// Least time between writes of the file by [`RunState::checkpoint`]
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct State {
+    /// Identifies the run the state belongs to
+    run: String,
+    /// Listings of session files written before the journal
+    #[serde(default, skip_serializing)]
+    listings: HashMap<String, Vec<DirEntry>>,
+    /// Remote paths of files that were downloaded
+    done: HashSet<PathBuf>,
+}
+
+/// First line of the listing journal
+#[derive(Debug, Serialize, Deserialize)]
+struct JournalHeader {
+    run: String,
+}
+
+#[derive(Debug)]
+pub struct RunState {
+    path: PathBuf,
+    state: Mutex<State>,
+    /// Complete listings of directories, by share token and path
+    listings: Mutex<HashMap<String, Vec<DirEntry>>>,
+    journal: Mutex<BufWriter<File>>,
+    saved: Mutex<Instant>,
+}
+
+/// `path` with `suffix` added to its file name
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+fn listing_key(token: &ShareToken, dir: Option<&Path>) -> String {
+    let dir = dir.unwrap_or(Path::new("/"));
+    format!("{}{}", token.as_str(), dir.display())
+}
+
+impl RunState {
+    /// Continues the state in the file at `path`, or starts afresh if there is
+    /// none. `run` identifies what is downloaded where (e.g. the share URL and
+    /// the output directory); a file left by a different run is an error.
+    pub fn open(path: &Path, run: &str) -> anyhow::Result<Self> {
+        let mut state = match std::fs::read(path) {
+            Ok(data) => {
+                let state: State = serde_json::from_slice(&data)
+                    .with_context(|| format!("{} is not a session file", path.display()))?;
+                anyhow::ensure!(
+                    state.run == run,
+                    "{} belongs to another download ({})",
+                    path.display(),
+                    state.run,
+                );
+                state
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => State {
+                run: run.to_string(),
+                ..State::default()
+            },
+            Err(e) => return Err(e).with_context(|| path.display().to_string()),
+        };
+        let journal_path = with_suffix(path, ".listings");
+        let mut listings = std::mem::take(&mut state.listings);
+        listings.extend(read_journal(&journal_path, run)?);
+        let file = File::options()
+            .append(true)
+            .create(true)
+            .open(&journal_path)
+            .with_context(|| journal_path.display().to_string())?;
+        let is_new = file.metadata()?.len() == 0;
+        let mut journal = BufWriter::new(file);
+        if is_new {
+            let header = JournalHeader {
+                run: run.to_string(),
+            };
+            serde_json::to_writer(&mut journal, &header)?;
+            writeln!(journal)?;
+            // listings of an older session file, which are no longer saved there
+            for (dir, entries) in &listings {
+                write_listing(&mut journal, dir, entries)?;
+            }
+        }
+        journal.flush()?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            state: Mutex::new(state),
+            listings: Mutex::new(listings),
+            journal: Mutex::new(journal),
+            saved: Mutex::new(Instant::now()),
+        })
+    }
+
+    /// Whether the state was continued from an earlier run
+    pub fn is_resumed(&self) -> bool {
+        !(self.listings.lock().unwrap().is_empty() && self.state.lock().unwrap().done.is_empty())
+    }
+
+    /// Recorded listing of the directory `dir`
+    pub fn listing(&self, token: &ShareToken, dir: Option<&Path>) -> Option<Vec<DirEntry>> {
+        let listings = self.listings.lock().unwrap();
+        listings.get(&listing_key(token, dir)).cloned()
+    }
+
+    /// Records the complete listing of `dir`, appending it to the journal
+    /// right away.
+    pub fn record_listing(
+        &self,
+        token: &ShareToken,
+        dir: Option<&Path>,
+        entries: &[DirEntry],
+    ) -> io::Result<()> {
+        let key = listing_key(token, dir);
+        let mut journal = self.journal.lock().unwrap();
+        write_listing(&mut *journal, &key, entries)?;
+        journal.flush()?;
+        self.listings.lock().unwrap().insert(key, entries.to_vec());
+        Ok(())
+    }
+
+    /// Whether the file at the remote `path` was downloaded
+    pub fn is_done(&self, path: &Path) -> bool {
+        self.state.lock().unwrap().done.contains(path)
+    }
+
+    pub fn record_done(&self, path: &Path) {
+        self.state.lock().unwrap().done.insert(path.to_path_buf());
+    }
+
+    /// Writes the completed files to the state file, replacing it only once
+    /// complete.
+    pub fn save(&self) -> io::Result<()> {
+        let data = serde_json::to_vec(&*self.state.lock().unwrap())?;
+        let tmp = with_suffix(&self.path, ".tmp");
+        std::fs::write(&tmp, data)?;
+        std::fs::rename(tmp, &self.path)?;
+        *self.saved.lock().unwrap() = Instant::now();
+        Ok(())
+    }
+
+    /// Saves the state unless that was done within the last second.
+    pub fn checkpoint(&self) -> io::Result<()> {
+        if self.saved.lock().unwrap().elapsed() < CHECKPOINT_INTERVAL {
+            return Ok(());
+        }
+        self.save()
+    }
+
+    /// Removes the files once the run is complete.
+    pub fn remove(&self) -> io::Result<()> {
+        for path in [self.path.clone(), with_suffix(&self.path, ".listings")] {
+            match std::fs::remove_file(path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Listings in the journal at `path`, if there is one, which must belong to
+/// `run`. Lines cut short by an interruption are skipped.
+fn read_journal(path: &Path, run: &str) -> anyhow::Result<HashMap<String, Vec<DirEntry>>> {
+    let mut listings = HashMap::new();
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(listings),
+        Err(e) => return Err(e).with_context(|| path.display().to_string()),
+    };
+    let mut lines = BufReader::new(file).lines();
+    let Some(header) = lines.next().transpose()? else {
+        return Ok(listings);
+    };
+    let header: JournalHeader = serde_json::from_str(&header)
+        .with_context(|| format!("{} is not a listing journal", path.display()))?;
+    anyhow::ensure!(
+        header.run == run,
+        "{} belongs to another download ({})",
+        path.display(),
+        header.run,
+    );
+    for line in lines {
+        if let Ok((dir, entries)) = serde_json::from_str(&line?) {
+            listings.insert(dir, entries);
+        }
+    }
+    Ok(listings)
+}
+
+/// Appends the complete listing of `dir` (share token and path) as a line
+/// of `[dir, entries]`.
+fn write_listing(journal: &mut impl Write, dir: &str, entries: &[DirEntry]) -> io::Result<()> {
+    serde_json::to_writer(&mut *journal, &(dir, entries))?;
+    writeln!(journal)
+}
@@ -0,0 +1,194 @@
+//! Outcome of a download run for its output and for scripts: the outcome of
+//! every file, a record of it and the totals. What a dry run would do is in
+//! [`crate::plan`].
+
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::Serialize;
+
+use crate::{progress, redact, DownloadResult};
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Summary {
+    /// Files that did not exist locally
+    pub downloaded: u64,
+    pub updated: u64,
+    pub overwritten: u64,
+    pub continued: u64,
+    /// Files that were already complete, or skipped on request
+    pub skipped: u64,
+    pub failed: u64,
+    /// Bytes received, including those of transfers that were retried
+    pub bytes: u64,
+    pub elapsed_secs: f64,
+    /// Average bytes per second over the run
+    pub throughput: f64,
+    /// Directories that could not be listed, whose files are not counted
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub failed_dirs: Vec<PathBuf>,
+}
+
+impl Summary {
+    /// Counts the result of a file.
+    pub fn add(&mut self, result: &anyhow::Result<DownloadResult>) {
+        let count = match result {
+            Ok(DownloadResult::Complete) => &mut self.downloaded,
+            Ok(DownloadResult::Updated(_)) => &mut self.updated,
+            Ok(DownloadResult::Overwritten) => &mut self.overwritten,
+            Ok(DownloadResult::Continued) => &mut self.continued,
+            Ok(DownloadResult::Skipped) => &mut self.skipped,
+            Err(e) if is_skipped(e) => &mut self.skipped,
+            Err(_) => &mut self.failed,
+        };
+        *count += 1;
+    }
+
+    /// Fills in the transfer totals once the run is over.
+    pub fn finish(&mut self, bytes: u64, elapsed: Duration) {
+        self.bytes = bytes;
+        self.elapsed_secs = elapsed.as_secs_f64();
+        self.throughput = progress::rate(bytes, elapsed);
+    }
+
+    /// Whether no file failed and every directory was listed
+    pub fn is_complete(&self) -> bool {
+        self.failed == 0 && self.failed_dirs.is_empty()
+    }
+
+    /// Files that were processed
+    pub fn files(&self) -> u64 {
+        self.downloaded
+            + self.updated
+            + self.overwritten
+            + self.continued
+            + self.skipped
+            + self.failed
+    }
+}
+
+/// What a download did with a file, made by [`crate::post::Job::finish`] (or
+/// for a failed transfer by [`crate::download_with_session`]) and formatted
+/// by the caller
+#[derive(Debug)]
+pub struct DownloadOutcome {
+    /// Remote path of the file
+    pub path: PathBuf,
+    /// Local path of the file
+    pub dest: PathBuf,
+    pub result: anyhow::Result<DownloadResult>,
+    /// Bytes received for the file, including those of failed attempts; 0
+    /// if the transfer failed
+    pub received: u64,
+    /// Time from the start of the transfer until the file was finished
+    pub elapsed: Duration,
+    /// Attempts at transferring the file, more than 1 if it was retried and 0
+    /// if it needed none
+    pub attempts: u32,
+    pub verification: Verification,
+}
+
+impl DownloadOutcome {
+    /// Whether the file was already complete, or skipped on request
+    pub fn is_skipped(&self) -> bool {
+        match &self.result {
+            Ok(result) => *result == DownloadResult::Skipped,
+            Err(e) => is_skipped(e),
+        }
+    }
+
+    /// Whether the file could not be downloaded
+    pub fn is_failed(&self) -> bool {
+        self.result.as_ref().is_err_and(|e| !is_skipped(e))
+    }
+}
+
+/// Whether a downloaded file was checked against a checksum
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Verification {
+    /// No checksum was computed
+    #[default]
+    Unchecked,
+    /// The checksum of the file, with none to compare it to
+    Computed(String),
+    /// The checksum of the file, which is the expected one
+    Matched(String),
+    /// The file had another checksum than the expected one
+    Mismatched { expected: String, received: String },
+}
+
+impl Verification {
+    /// Checksum of the file, if computed
+    pub fn checksum(&self) -> Option<&str> {
+        match self {
+            Self::Unchecked => None,
+            Self::Computed(sum) | Self::Matched(sum) => Some(sum),
+            Self::Mismatched { received, .. } => Some(received),
+        }
+    }
+
+    /// Whether the checksum was compared to an expected one, and matched
+    pub fn verified(&self) -> Option<bool> {
+        match self {
+            Self::Unchecked | Self::Computed(_) => None,
+            Self::Matched(_) => Some(true),
+            Self::Mismatched { .. } => Some(false),
+        }
+    }
+}
+
+/// What happened to a file
+#[derive(Debug, Clone, Serialize)]
+pub struct FileRecord<'a> {
+    /// Remote path of the file
+    pub path: &'a Path,
+    /// "complete", "updated", "overwritten", "continued", "skipped" or
+    /// "failed"
+    pub action: String,
+    /// Local path of the file
+    pub dest: &'a Path,
+    /// Bytes received for the file
+    pub bytes: u64,
+    pub duration_secs: f64,
+    /// Attempts at transferring the file
+    pub attempts: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<&'a str>,
+    /// Whether the checksum is the expected one, if one was expected
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verified: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl<'a> FileRecord<'a> {
+    pub fn new(outcome: &'a DownloadOutcome) -> Self {
+        let (action, error) = match &outcome.result {
+            Ok(result) => (result.to_string(), None),
+            Err(e) if is_skipped(e) => ("skipped".to_string(), None),
+            Err(e) => (
+                "failed".to_string(),
+                Some(redact::redact(&e.to_string()).into_owned()),
+            ),
+        };
+        Self {
+            path: &outcome.path,
+            action,
+            dest: &outcome.dest,
+            bytes: outcome.received,
+            duration_secs: outcome.elapsed.as_secs_f64(),
+            attempts: outcome.attempts,
+            checksum: outcome.verification.checksum(),
+            verified: outcome.verification.verified(),
+            error,
+        }
+    }
+}
+
+/// Whether `e` is a transfer skipped through [`progress::Transfer::skip`]
+fn is_skipped(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<crate::Error>()
+        .is_some_and(crate::Error::is_skipped)
+}
@@ -0,0 +1,241 @@
+//! Comparison of remote entries with a local mirror, removal of files the
+//! mirror no longer has, and rsync-style itemized output for the sync command.
+
+#[cfg(feature = "client")]
+use std::{
+    collections::{HashMap, HashSet},
+    io::{self, BufRead, BufReader},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+#[cfg(feature = "client")]
+use chrono::{DateTime, Utc};
+
+use crate::DownloadResult;
+#[cfg(feature = "client")]
+use crate::{filter::Filter, DirEntry};
+
+/// Item shown for a created directory
+pub const NEW_DIR: &str = "cd+++++++++";
+
+/// Item shown for a deleted file or directory
+pub const DELETING: &str = "*deleting  ";
+
+/// File in the top directory of a mirror that records the IDs of its files
+pub const FILE_IDS: &str = ".seaf-share-ids";
+
+/// Attributes in which a local file differs from its remote entry
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Changes {
+    pub size: bool,
+    pub time: bool,
+}
+
+impl Changes {
+    /// Compares by size and modification time (in whole seconds). Attributes
+    /// the listing does not provide are not compared, and a file is always
+    /// considered changed if neither is known.
+    #[cfg(feature = "client")]
+    pub fn between(entry: &DirEntry, local: &std::fs::Metadata) -> anyhow::Result<Self> {
+        let mtime = DateTime::<Utc>::from(local.modified()?);
+        Ok(Self {
+            size: entry
+                .size()
+                .map_or(entry.last_modified().is_none(), |size| size != local.len()),
+            time: entry
+                .last_modified()
+                .is_some_and(|remote| remote.timestamp() != mtime.timestamp()),
+        })
+    }
+
+    pub fn any(&self) -> bool {
+        self.size || self.time
+    }
+}
+
+/// IDs of the remote files of a mirror as of their last sync, kept in
+/// [`FILE_IDS`]. Where the listing gives IDs (libraries read with an API
+/// token), a file with the ID it was synced with and the same size is
+/// unchanged whatever its modification time, so neither its contents need to
+/// be compared nor the file downloaded again. Local changes that keep the size
+/// go unnoticed, as with `rsync --size-only`.
+#[cfg(feature = "client")]
+#[derive(Debug)]
+pub struct FileIds {
+    dir: PathBuf,
+    /// IDs by path relative to `dir`
+    ids: Mutex<HashMap<PathBuf, String>>,
+}
+
+#[cfg(feature = "client")]
+impl FileIds {
+    /// IDs recorded in `dir`, none if it has no record yet
+    pub fn load(dir: &Path) -> io::Result<Self> {
+        let mut ids = HashMap::new();
+        match std::fs::File::open(dir.join(FILE_IDS)) {
+            Ok(file) => {
+                for line in BufReader::new(file).lines() {
+                    if let Some((id, path)) = line?.split_once("  ") {
+                        ids.insert(PathBuf::from(path), id.to_string());
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            ids: Mutex::new(ids),
+        })
+    }
+
+    /// Like [`Changes::between`], but without changes for a file at `dest`
+    /// of the size and recorded ID of `entry`
+    pub fn changes(
+        &self,
+        entry: &DirEntry,
+        dest: &Path,
+        local: &std::fs::Metadata,
+    ) -> anyhow::Result<Changes> {
+        let changes = Changes::between(entry, local)?;
+        let key = dest.strip_prefix(&self.dir).unwrap_or(dest);
+        let recorded = entry
+            .id()
+            .is_some_and(|id| self.ids.lock().unwrap().get(key).is_some_and(|r| r == id));
+        Ok(if recorded && !changes.size {
+            Changes::default()
+        } else {
+            changes
+        })
+    }
+
+    /// Records the ID of `entry`, synced to `dest`, or forgets the one
+    /// recorded if the listing had none.
+    pub fn record(&self, entry: &DirEntry, dest: &Path) {
+        let key = dest.strip_prefix(&self.dir).unwrap_or(dest).to_path_buf();
+        let mut ids = self.ids.lock().unwrap();
+        match entry.id() {
+            Some(id) => ids.insert(key, id.to_string()),
+            None => ids.remove(&key),
+        };
+    }
+
+    /// Writes the record, sorted by path, unless there is nothing to record
+    /// and no record to update.
+    pub fn save(&self) -> io::Result<()> {
+        let path = self.dir.join(FILE_IDS);
+        let ids = self.ids.lock().unwrap();
+        if ids.is_empty() && !std::fs::exists(&path)? {
+            return Ok(());
+        }
+        let mut records: Vec<_> = ids.iter().collect();
+        records.sort();
+        let lines: String = records
+            .into_iter()
+            .map(|(path, id)| format!("{}  {}\n", id, path.display()))
+            .collect();
+        std::fs::write(path, lines)
+    }
+}
+
+/// What syncing `entry` to `dest` would do, telling unchanged files by their
+/// `ids` if given
+#[cfg(feature = "client")]
+pub fn check(
+    entry: &DirEntry,
+    dest: &Path,
+    ids: Option<&FileIds>,
+) -> anyhow::Result<DownloadResult> {
+    let local = match std::fs::metadata(dest) {
+        Ok(local) => local,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(DownloadResult::Complete),
+        Err(e) => return Err(e.into()),
+    };
+    let changes = match ids {
+        Some(ids) => ids.changes(entry, dest, &local)?,
+        None => Changes::between(entry, &local)?,
+    };
+    Ok(if changes.any() {
+        DownloadResult::Updated(changes)
+    } else {
+        DownloadResult::Skipped
+    })
+}
+
+/// Itemizes a transferred file like `rsync --itemize-changes`, e.g.
+/// ">f+++++++++" for a new file or ">f.st......" for a file whose size and
+/// time changed. Skipped files are not itemized.
+pub fn itemize(result: DownloadResult) -> Option<String> {
+    let flag = |set: bool, c: char| if set { c } else { '.' };
+    match result {
+        DownloadResult::Skipped => None,
+        DownloadResult::Complete => Some(">f+++++++++".to_string()),
+        DownloadResult::Updated(changes) => Some(format!(
+            ">f.{}{}......",
+            flag(changes.size, 's'),
+            flag(changes.time, 't'),
+        )),
+        DownloadResult::Overwritten | DownloadResult::Continued => Some(">f.........".to_string()),
+    }
+}
+
+/// Deletes local entries of remote directories `dirs` that are not among the
+/// `seen` remote paths, mapping remote paths below `base` into `output`.
+/// Entries not selected by `filter`, and ".part" files of remote files, are
+/// kept. Deletions are printed, itemized with `itemize`, and only printed
+/// with `dry_run`.
+///
+/// `dirs` must only hold directories whose listing completed, as anything
+/// missing from `seen` is deleted.
+#[cfg(feature = "client")]
+pub fn delete_extraneous(
+    dirs: &[PathBuf],
+    seen: &HashSet<PathBuf>,
+    base: &Path,
+    output: &Path,
+    filter: &Filter,
+    itemize: bool,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    for dir in dirs {
+        let local_dir = output.join(dir.strip_prefix(base)?);
+        let children = match std::fs::read_dir(&local_dir) {
+            Ok(children) => children,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        for child in children {
+            let child = child?;
+            let remote = dir.join(child.file_name());
+            let part_of = remote
+                .to_str()
+                .and_then(|p| p.strip_suffix(".part"))
+                .map(Path::new);
+            let is_dir = child.file_type()?.is_dir();
+            if seen.contains(&remote)
+                || part_of.is_some_and(|p| seen.contains(p))
+                || !filter.selects(&remote, is_dir)
+            {
+                continue;
+            }
+            if itemize {
+                println!("{} {}", DELETING, remote.to_string_lossy());
+            } else {
+                println!("deleted {}", child.path().display());
+            }
+            if dry_run {
+                continue;
+            }
+            let removed = if is_dir {
+                std::fs::remove_dir_all(child.path())
+            } else {
+                std::fs::remove_file(child.path())
+            };
+            if let Err(e) = removed {
+                eprintln!("could not delete {}: {}", child.path().display(), e);
+            }
+        }
+    }
+    Ok(())
+}
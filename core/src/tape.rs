@@ -0,0 +1,348 @@
+//! Recording of the HTTP exchanges of a run to a file (a tape), and replay of
+//! them in place of the server, so that a failure seen with one server can be
+//! reproduced offline.
+//!
+//! [`Recorder`] and [`Player`] are [`ureq`] middleware, added to the agent
+//! with [`ureq::config::ConfigBuilder::middleware`]. A tape holds one JSON
+//! object per line: the method, URL and range of a request and the status,
+//! headers and body of its response, or the error it failed with. Cookies and
+//! credentials are left out, and request bodies (which carry passwords) are
+//! not recorded. Of file contents only the length is kept, and they are
+//! replayed as zeros, as a tape is about the responses of the API and pages.
+//!
+//! A tape is not sanitized otherwise: URLs and bodies are kept whole, unlike
+//! errors (see `redact`), as the replay needs them. With the share token and
+//! download URLs, a tape gives access to the share like its link does.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::File,
+    io::{self, Read, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Context;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use ureq::{
+    http::{Request, Response},
+    middleware::{Middleware, MiddlewareNext},
+    Body, SendBody,
+};
+
+/// Headers that are not recorded
+const SENSITIVE_HEADERS: [&str; 4] = [
+    "authorization",
+    "cookie",
+    "proxy-authorization",
+    "set-cookie",
+];
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Exchange {
+    method: String,
+    url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    range: Option<String>,
+    /// None if no response was received
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    status: Option<u16>,
+    /// Status of a response that was turned into an error
+    /// ([`ureq::Error::StatusCode`])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    status_error: Option<u16>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    headers: Vec<(String, String)>,
+    /// Base64 of the body as far as it was read
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    body: String,
+    /// Length of a body that is not recorded, e.g. of a file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body_len: Option<u64>,
+    /// Why the request failed or the body broke off
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Exchange {
+    fn new(request: &Request<SendBody>) -> Self {
+        Self {
+            method: request.method().to_string(),
+            url: request.uri().to_string(),
+            range: range(request),
+            status: None,
+            status_error: None,
+            headers: Vec::new(),
+            body: String::new(),
+            body_len: None,
+            error: None,
+        }
+    }
+
+    /// What the exchange is replayed for
+    fn key(&self) -> String {
+        key(&self.method, &self.url, self.range.as_deref())
+    }
+}
+
+/// Whether `request` asks for file contents: downloads from the file server
+/// and parts of files
+fn is_file_request(request: &Request<SendBody>) -> bool {
+    request.uri().path().contains("/seafhttp/files/") || request.headers().contains_key("range")
+}
+
+/// Whether `response` is a file to save rather than a page or API answer
+fn is_attachment(response: &Response<Body>) -> bool {
+    response
+        .headers()
+        .get("content-disposition")
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            let kind = value.split(';').next().unwrap_or_default().trim();
+            kind.eq_ignore_ascii_case("attachment")
+        })
+}
+
+fn range(request: &Request<SendBody>) -> Option<String> {
+    let range = request.headers().get("range")?;
+    range.to_str().ok().map(str::to_string)
+}
+
+fn key(method: &str, url: &str, range: Option<&str>) -> String {
+    match range {
+        Some(range) => format!("{} {} ({})", method, url, range),
+        None => format!("{} {}", method, url),
+    }
+}
+
+type Tape = Arc<Mutex<File>>;
+
+fn append(tape: &Tape, exchange: &Exchange) {
+    let Ok(mut line) = serde_json::to_vec(exchange) else {
+        return;
+    };
+    line.push(b'\n');
+    // a failure to record must not fail the run
+    let _ = tape.lock().unwrap().write_all(&line);
+}
+
+/// Middleware appending every exchange to a tape
+pub struct Recorder {
+    tape: Tape,
+}
+
+impl Recorder {
+    /// Records to a new tape at `path`, replacing any file there.
+    pub fn create(path: &Path) -> anyhow::Result<Self> {
+        let file = File::create(path).with_context(|| path.display().to_string())?;
+        Ok(Self {
+            tape: Arc::new(Mutex::new(file)),
+        })
+    }
+}
+
+impl Middleware for Recorder {
+    fn handle(
+        &self,
+        request: Request<SendBody>,
+        next: MiddlewareNext,
+    ) -> Result<Response<Body>, ureq::Error> {
+        let mut exchange = Exchange::new(&request);
+        let file_request = is_file_request(&request);
+        let res = match next.handle(request) {
+            Ok(res) => res,
+            Err(e) => {
+                match e {
+                    ureq::Error::StatusCode(status) => exchange.status_error = Some(status),
+                    ref e => exchange.error = Some(e.to_string()),
+                }
+                append(&self.tape, &exchange);
+                return Err(e);
+            }
+        };
+        exchange.status = Some(res.status().as_u16());
+        exchange.headers = res
+            .headers()
+            .iter()
+            .filter(|(name, _)| !SENSITIVE_HEADERS.contains(&name.as_str()))
+            .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+            .collect();
+        // of file contents only the length is kept
+        let recorded = !file_request && !is_attachment(&res);
+        // the body is passed on as it is read and recorded once done with
+        let (parts, body) = res.into_parts();
+        let mut builder = Body::builder();
+        if let Some(mime_type) = body.mime_type() {
+            builder = builder.mime_type(mime_type);
+        }
+        if let Some(charset) = body.charset() {
+            builder = builder.charset(charset);
+        }
+        let reader = RecordingReader {
+            inner: body.into_reader(),
+            recorded,
+            data: Vec::new(),
+            len: 0,
+            exchange: Some(exchange),
+            tape: self.tape.clone(),
+        };
+        Ok(Response::from_parts(parts, builder.reader(reader)))
+    }
+}
+
+/// Response body that records itself to the tape when dropped
+struct RecordingReader<R> {
+    inner: R,
+    /// Whether the body is kept, or only its length
+    recorded: bool,
+    data: Vec<u8>,
+    len: u64,
+    /// Taken when recorded
+    exchange: Option<Exchange>,
+    tape: Tape,
+}
+
+impl<R: Read> Read for RecordingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.inner.read(buf) {
+            Ok(0) if !buf.is_empty() => {
+                self.record(None);
+                Ok(0)
+            }
+            Ok(n) => {
+                if self.recorded {
+                    self.data.extend_from_slice(&buf[..n]);
+                }
+                self.len += n as u64;
+                Ok(n)
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => Err(e),
+            Err(e) => {
+                self.record(Some(e.to_string()));
+                Err(e)
+            }
+        }
+    }
+}
+
+impl<R> RecordingReader<R> {
+    fn record(&mut self, error: Option<String>) {
+        let Some(mut exchange) = self.exchange.take() else {
+            return;
+        };
+        match self.recorded {
+            true => exchange.body = STANDARD.encode(std::mem::take(&mut self.data)),
+            false => exchange.body_len = Some(self.len),
+        }
+        exchange.error = error;
+        append(&self.tape, &exchange);
+    }
+}
+
+impl<R> Drop for RecordingReader<R> {
+    fn drop(&mut self) {
+        // bodies that are not read at all (e.g. of redirects) are complete
+        // for all that matters
+        let error = (self.len > 0).then(|| "body not read to the end".to_string());
+        self.record(error);
+    }
+}
+
+/// Middleware answering requests from a tape instead of the server
+pub struct Player {
+    /// Exchanges in the order they were recorded, by request
+    exchanges: Mutex<HashMap<String, VecDeque<Exchange>>>,
+}
+
+impl Player {
+    pub fn open(path: &Path) -> anyhow::Result<Self> {
+        let data = std::fs::read_to_string(path).with_context(|| path.display().to_string())?;
+        let mut exchanges: HashMap<String, VecDeque<Exchange>> = HashMap::new();
+        for (i, line) in data.lines().enumerate() {
+            let exchange: Exchange = serde_json::from_str(line).with_context(|| {
+                format!("{}:{}: not a recorded exchange", path.display(), i + 1)
+            })?;
+            exchanges
+                .entry(exchange.key())
+                .or_default()
+                .push_back(exchange);
+        }
+        Ok(Self {
+            exchanges: Mutex::new(exchanges),
+        })
+    }
+
+    fn replay(exchange: Exchange) -> Result<Response<Body>, ureq::Error> {
+        if let Some(status) = exchange.status_error {
+            return Err(ureq::Error::StatusCode(status));
+        }
+        let Some(status) = exchange.status else {
+            // replayed as a transient error, so that the retries are too
+            let error = exchange.error.unwrap_or_default();
+            return Err(ureq::Error::Io(io::Error::new(
+                io::ErrorKind::ConnectionReset,
+                error,
+            )));
+        };
+        let mut builder = Response::builder().status(status);
+        let mut body = Body::builder();
+        for (name, value) in &exchange.headers {
+            builder = builder.header(name, value);
+            match name.as_str() {
+                "content-type" => {
+                    let mut params = value.split(';').map(str::trim);
+                    body = body.mime_type(params.next().unwrap_or_default());
+                    if let Some(charset) = params.find_map(|p| p.strip_prefix("charset=")) {
+                        body = body.charset(charset.trim_matches('"'));
+                    }
+                }
+                // the announced length, so that a body that broke off is
+                // seen as incomplete
+                "content-length" if exchange.error.is_some() => {
+                    if let Ok(len) = value.parse() {
+                        body = body.limit(len);
+                    }
+                }
+                _ => {}
+            }
+        }
+        let body = match exchange.body_len {
+            // streamed, as files may be larger than memory
+            Some(len) => body.reader(io::repeat(0).take(len)),
+            None => body.data(
+                STANDARD
+                    .decode(&exchange.body)
+                    .map_err(|e| ureq::Error::Io(io::Error::new(io::ErrorKind::InvalidData, e)))?,
+            ),
+        };
+        Ok(builder.body(body)?)
+    }
+}
+
+impl Middleware for Player {
+    fn handle(
+        &self,
+        request: Request<SendBody>,
+        _next: MiddlewareNext,
+    ) -> Result<Response<Body>, ureq::Error> {
+        let key = key(
+            request.method().as_str(),
+            &request.uri().to_string(),
+            range(&request).as_deref(),
+        );
+        let exchange = self
+            .exchanges
+            .lock()
+            .unwrap()
+            .get_mut(&key)
+            .and_then(VecDeque::pop_front);
+        match exchange {
+            Some(exchange) => Self::replay(exchange),
+            None => Err(ureq::Error::Io(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} is not on the tape", key),
+            ))),
+        }
+    }
+}
@@ -0,0 +1,70 @@
+//! Remote entries arranged by directory, with cumulative sizes.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use crate::{seafile::Estimate, DirEntry};
+
+pub struct Tree {
+    base: PathBuf,
+    children: HashMap<PathBuf, Vec<DirEntry>>,
+    /// Files, directories and bytes below each directory
+    totals: HashMap<PathBuf, Estimate>,
+}
+
+impl Tree {
+    /// Arranges `entries` listed below the remote directory `base`; children
+    /// are sorted by name.
+    pub fn new(base: &Path, entries: impl IntoIterator<Item = DirEntry>) -> Self {
+        let base = normalize(base);
+        let mut children: HashMap<PathBuf, Vec<DirEntry>> = HashMap::new();
+        let mut totals: HashMap<PathBuf, Estimate> = HashMap::new();
+        for entry in entries {
+            let Some(parent) = entry.path().parent() else {
+                continue;
+            };
+            for dir in parent.ancestors() {
+                if !dir.starts_with(&base) {
+                    break;
+                }
+                totals.entry(dir.to_path_buf()).or_default().add(&entry);
+            }
+            children.entry(normalize(parent)).or_default().push(entry);
+        }
+        for entries in children.values_mut() {
+            entries.sort_by(|a, b| a.name().cmp(b.name()));
+        }
+        Self {
+            base,
+            children,
+            totals,
+        }
+    }
+
+    /// The directory the tree starts from
+    pub fn base(&self) -> &Path {
+        &self.base
+    }
+
+    /// Entries directly in `dir`
+    pub fn children(&self, dir: &Path) -> &[DirEntry] {
+        self.children
+            .get(&normalize(dir))
+            .map_or(&[], |entries| entries.as_slice())
+    }
+
+    /// Files, directories and bytes anywhere below `dir`
+    pub fn total(&self, dir: &Path) -> Estimate {
+        self.totals
+            .get(&normalize(dir))
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+/// Drops the trailing slash of directory paths.
+fn normalize(path: &Path) -> PathBuf {
+    path.components().collect()
+}
@@ -0,0 +1,78 @@
+//! Uploads through upload links, where directories are recreated below the
+//! shared directory with the relative path of every file.
+
+use std::path::{Path, PathBuf};
+
+use crate::redact::Redacted;
+use crate::seafile::{Client, ShareToken};
+
+/// Files of an upload run
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Uploaded {
+    pub uploaded: u64,
+    pub failed: u64,
+}
+
+/// Uploads `files` (and the contents of directories among them) into the
+/// remote directory `dest`, reporting each file. Failed files are reported
+/// and skipped, and counted in the result.
+pub fn upload_all(
+    client: &Client,
+    token: &ShareToken,
+    files: &[PathBuf],
+    dest: Option<&Path>,
+) -> anyhow::Result<Uploaded> {
+    anyhow::ensure!(!files.is_empty(), "no files to upload");
+    let mut totals = Uploaded::default();
+    for root in files {
+        let root = std::path::absolute(root)?;
+        let files = if root.is_dir() {
+            let mut files = Vec::new();
+            collect_files(&root, &mut files)?;
+            files
+        } else {
+            vec![root.clone()]
+        };
+        // a directory is uploaded as a whole, e.g. "photos/a.jpg"
+        let base = root.parent().unwrap_or(&root);
+        for file in files {
+            let mut remote_dir = dest.map_or_else(|| PathBuf::from("/"), Path::to_path_buf);
+            if let Some(relative) = file.parent().and_then(|p| p.strip_prefix(base).ok()) {
+                if !relative.as_os_str().is_empty() {
+                    remote_dir.push(relative);
+                }
+            }
+            let remote_dir = (remote_dir != Path::new("/")).then_some(remote_dir);
+            match client.upload(token, &file, remote_dir.as_deref()) {
+                Ok(()) => {
+                    println!("uploaded {}", file.display());
+                    totals.uploaded += 1;
+                }
+                Err(e) => {
+                    eprintln!("could not upload {}: {}", file.display(), Redacted(e));
+                    totals.failed += 1;
+                }
+            }
+        }
+    }
+    Ok(totals)
+}
+
+/// Lists the files below `dir` in name order. Links to directories are
+/// skipped, as they may lead back into the tree.
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> anyhow::Result<()> {
+    let mut children = std::fs::read_dir(dir)?
+        .map(|child| child.and_then(|c| Ok((c.path(), c.file_type()?))))
+        .collect::<Result<Vec<_>, _>>()?;
+    children.sort_by(|a, b| a.0.cmp(&b.0));
+    for (child, file_type) in children {
+        if file_type.is_dir() {
+            collect_files(&child, files)?;
+        } else if file_type.is_symlink() && child.is_dir() {
+            eprintln!("skipped {}: a link to a directory", child.display());
+        } else {
+            files.push(child);
+        }
+    }
+    Ok(())
+}
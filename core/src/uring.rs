@@ -0,0 +1,107 @@
+//! File writes through io_uring (Linux, `io-uring` feature).
+//!
+//! One write is kept in flight while the next chunk is read from the network,
+//! so disk and network transfers overlap.
+
+use std::{
+    fs::File,
+    io::{self, Write},
+    os::fd::AsRawFd,
+};
+
+use io_uring::{opcode, types, IoUring};
+
+pub struct UringWriter<'a> {
+    file: &'a mut File,
+    ring: IoUring,
+    /// Buffer owned by the kernel while a write is in flight
+    inflight: Option<Vec<u8>>,
+    spare: Vec<u8>,
+    /// Set when the kernel does not support the write opcode
+    fallback: bool,
+}
+
+/// Sets up a ring for one [`UringWriter`]; this fails on old kernels or under
+/// seccomp filters.
+pub fn ring() -> io::Result<IoUring> {
+    IoUring::new(2)
+}
+
+impl<'a> UringWriter<'a> {
+    pub fn new(file: &'a mut File, ring: IoUring) -> Self {
+        Self {
+            file,
+            ring,
+            inflight: None,
+            spare: Vec::new(),
+            fallback: false,
+        }
+    }
+
+    fn submit(&mut self, buf: Vec<u8>) -> io::Result<()> {
+        // offset -1 writes at (and advances) the file position, like write(2)
+        let entry = opcode::Write::new(
+            types::Fd(self.file.as_raw_fd()),
+            buf.as_ptr(),
+            buf.len() as u32,
+        )
+        .offset(u64::MAX)
+        .build();
+        // SAFETY: the buffer is kept in `inflight` until the completion is
+        // reaped in `wait`, which also runs on drop.
+        unsafe { self.ring.submission().push(&entry) }
+            .map_err(|_| io::Error::other("io_uring submission queue is full"))?;
+        self.inflight = Some(buf);
+        self.ring.submit()?;
+        Ok(())
+    }
+
+    fn wait(&mut self) -> io::Result<()> {
+        let Some(buf) = self.inflight.take() else {
+            return Ok(());
+        };
+        let result = loop {
+            self.ring.submit_and_wait(1)?;
+            if let Some(cqe) = self.ring.completion().next() {
+                break cqe.result();
+            }
+        };
+        let written = match result {
+            n if n >= 0 => n as usize,
+            n if -n == libc::EINVAL || -n == libc::EOPNOTSUPP => {
+                self.fallback = true;
+                0
+            }
+            n => return Err(io::Error::from_raw_os_error(-n)),
+        };
+        // short writes and unsupported kernels finish synchronously
+        self.file.write_all(&buf[written..])?;
+        self.spare = buf;
+        Ok(())
+    }
+}
+
+impl Write for UringWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.wait()?;
+        if self.fallback {
+            return self.file.write(buf);
+        }
+        let mut next = std::mem::take(&mut self.spare);
+        next.clear();
+        next.extend_from_slice(buf);
+        self.submit(next)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.wait()?;
+        self.file.flush()
+    }
+}
+
+impl Drop for UringWriter<'_> {
+    fn drop(&mut self) {
+        let _ = self.wait();
+    }
+}
@@ -0,0 +1,118 @@
+//! Space used by a share, broken down by directory and file extension, in
+//! the spirit of `du` and ncdu.
+
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use crate::{seafile::Estimate, DirEntry};
+
+/// Files and bytes of one file extension
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+pub struct ExtensionUsage {
+    pub files: u64,
+    pub bytes: u64,
+}
+
+/// Sizes collected from the entries below a remote directory
+#[derive(Debug, Clone)]
+pub struct Usage {
+    base: PathBuf,
+    total: Estimate,
+    /// Bytes of each directory below `base`, including subdirectories
+    dirs: HashMap<PathBuf, u64>,
+    /// The `top` largest files so far, smallest first out
+    largest: BinaryHeap<Reverse<(u64, PathBuf)>>,
+    top: usize,
+    extensions: HashMap<String, ExtensionUsage>,
+}
+
+impl Usage {
+    /// Collects sizes below `base`, keeping track of the `top` largest files.
+    pub fn new(base: &Path, top: usize) -> Self {
+        Self {
+            base: base.to_path_buf(),
+            total: Estimate::default(),
+            dirs: HashMap::new(),
+            largest: BinaryHeap::new(),
+            top,
+            extensions: HashMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, entry: &DirEntry) {
+        self.total.add(entry);
+        let Some(size) = entry.size() else {
+            // empty directories are listed too
+            if entry.path() != self.base && entry.path().starts_with(&self.base) {
+                self.dirs.entry(entry.path().to_path_buf()).or_default();
+            }
+            return;
+        };
+        for dir in entry.path().ancestors().skip(1) {
+            if dir == self.base || !dir.starts_with(&self.base) {
+                break;
+            }
+            *self.dirs.entry(dir.to_path_buf()).or_default() += size;
+        }
+        if self.top > 0 {
+            self.largest
+                .push(Reverse((size, entry.path().to_path_buf())));
+            if self.largest.len() > self.top {
+                self.largest.pop();
+            }
+        }
+        let extension = entry
+            .path()
+            .extension()
+            .map_or(String::new(), |e| e.to_string_lossy().to_lowercase());
+        let usage = self.extensions.entry(extension).or_default();
+        usage.files += 1;
+        usage.bytes += size;
+    }
+
+    /// Files, directories and bytes below the base directory
+    pub fn total(&self) -> Estimate {
+        self.total
+    }
+
+    /// The largest files, largest first
+    pub fn largest_files(&self) -> Vec<(PathBuf, u64)> {
+        let mut files: Vec<_> = self
+            .largest
+            .iter()
+            .map(|Reverse((size, path))| (path.clone(), *size))
+            .collect();
+        files.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        files
+    }
+
+    /// All directories below the base directory, largest first
+    pub fn dirs(&self) -> Vec<(PathBuf, u64)> {
+        let mut dirs: Vec<_> = self.dirs.iter().map(|(p, s)| (p.clone(), *s)).collect();
+        dirs.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        dirs
+    }
+
+    /// The `n` largest directories, largest first
+    pub fn largest_dirs(&self, n: usize) -> Vec<(PathBuf, u64)> {
+        let mut dirs = self.dirs();
+        dirs.truncate(n);
+        dirs
+    }
+
+    /// Usage per lowercase file extension ("" for none), most bytes first
+    pub fn extensions(&self) -> Vec<(String, ExtensionUsage)> {
+        let mut extensions: Vec<_> = self
+            .extensions
+            .iter()
+            .map(|(e, u)| (e.clone(), *u))
+            .collect();
+        extensions.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes).then(a.0.cmp(&b.0)));
+        extensions
+    }
+}
@@ -0,0 +1,133 @@
+//! Comparison of a local directory with the remote tree it mirrors, e.g. to
+//! check a backup.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use crate::{
+    sync::{self, Changes, FileIds},
+    DirEntry,
+};
+
+/// How a local file differs from the remote tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    /// The remote file does not exist locally
+    Missing,
+    /// The local file does not exist remotely
+    Extra,
+    /// The sizes differ
+    Size,
+    /// The modification times differ
+    Mtime,
+}
+
+impl std::fmt::Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(match self {
+            Self::Missing => "missing",
+            Self::Extra => "extra",
+            Self::Size => "size",
+            Self::Mtime => "mtime",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Difference {
+    pub status: Status,
+    /// Remote path of the file, or where it would be for extra files
+    pub path: PathBuf,
+}
+
+/// Compares remote entries below `base` with their counterparts below a
+/// local directory
+pub struct Verifier {
+    base: PathBuf,
+    local: PathBuf,
+    size_only: bool,
+    file_ids: Option<FileIds>,
+    seen: HashSet<PathBuf>,
+}
+
+impl Verifier {
+    /// With `size_only`, modification times are not compared.
+    pub fn new(base: &Path, local: &Path, size_only: bool) -> Self {
+        Self {
+            base: base.to_path_buf(),
+            local: local.to_path_buf(),
+            size_only,
+            file_ids: None,
+            seen: HashSet::new(),
+        }
+    }
+
+    /// Takes files with the `ids` recorded by a sync, and the same size, as
+    /// unchanged, see [`FileIds`].
+    pub fn with_file_ids(mut self, ids: FileIds) -> Self {
+        self.file_ids = Some(ids);
+        self
+    }
+
+    /// Differences of the local counterpart of a remote entry
+    pub fn check(&mut self, entry: &DirEntry) -> anyhow::Result<Vec<Difference>> {
+        self.seen.insert(entry.path().to_path_buf());
+        if entry.is_dir() {
+            return Ok(Vec::new());
+        }
+        let difference = |status| Difference {
+            status,
+            path: entry.path().to_path_buf(),
+        };
+        let local = self.local.join(entry.path().strip_prefix(&self.base)?);
+        let metadata = match std::fs::metadata(&local) {
+            Ok(local) if local.is_file() => local,
+            Ok(_) => return Ok(vec![difference(Status::Missing)]),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(vec![difference(Status::Missing)]);
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let changes = match &self.file_ids {
+            Some(ids) => ids.changes(entry, &local, &metadata)?,
+            None => Changes::between(entry, &metadata)?,
+        };
+        let mut differences = Vec::new();
+        if changes.size {
+            differences.push(difference(Status::Size));
+        }
+        if changes.time && !self.size_only {
+            differences.push(difference(Status::Mtime));
+        }
+        Ok(differences)
+    }
+
+    /// Local files that none of the checked entries correspond to
+    pub fn extra(&self) -> anyhow::Result<Vec<Difference>> {
+        let mut differences = Vec::new();
+        let mut dirs = vec![self.local.clone()];
+        while let Some(dir) = dirs.pop() {
+            for child in std::fs::read_dir(&dir)? {
+                let child = child?;
+                let path = child.path();
+                if child.file_type()?.is_dir() {
+                    dirs.push(path);
+                    continue;
+                }
+                let remote = self.base.join(path.strip_prefix(&self.local)?);
+                if !self.seen.contains(&remote) && path != self.local.join(sync::FILE_IDS) {
+                    differences.push(Difference {
+                        status: Status::Extra,
+                        path: remote,
+                    });
+                }
+            }
+        }
+        Ok(differences)
+    }
+}
@@ -0,0 +1,587 @@
+//! Limits of the file system that files are downloaded to, for removable
+//! drives formatted with FAT32 or exFAT: names must not hold some characters
+//! that Seafile allows, and files on FAT32 must be smaller than 4 GiB.
+//! Downloads that would fail halfway with an OS error are found up front, and
+//! renamed, split into parts, or skipped instead. As both file systems ignore
+//! case, and mapped names can be equal, [`Names`] finds entries that would
+//! take the place of another.
+//!
+//! Files written in parts come with a manifest of the parts and their
+//! checksums, which [`join`] reads to put the file together again.
+
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    fs::{File, OpenOptions},
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    checksum::{self, Algorithm, Hasher},
+    DownloadResult,
+};
+
+/// File system of an output destination
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[non_exhaustive]
+pub enum FileSystem {
+    Fat32,
+    Exfat,
+    /// Any file system without these limits
+    Other,
+}
+
+/// What to do with files the file system cannot hold as they are
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "clap", derive(clap::ValueEnum))]
+#[non_exhaustive]
+pub enum Unsupported {
+    /// Replace the characters that names cannot hold with "_", give names
+    /// that are taken already a suffix (see [`Names`]), and split files that
+    /// are too large into parts (see [`part_path`])
+    #[default]
+    Map,
+    /// Skip such files, and everything in such directories
+    Skip,
+}
+
+impl FileSystem {
+    /// File system of `dir`, or of its closest ancestor that exists, where
+    /// the platform tells (Linux and macOS)
+    pub fn detect(dir: &Path) -> Option<Self> {
+        let dir = dir.ancestors().find_map(|dir| dir.canonicalize().ok())?;
+        Self::from_name(&fs_type(&dir)?)
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "vfat" | "msdos" | "fat" => Some(Self::Fat32),
+            "exfat" => Some(Self::Exfat),
+            // e.g. exFAT through FUSE, which cannot be told apart from NTFS
+            "fuseblk" => None,
+            _ => Some(Self::Other),
+        }
+    }
+
+    /// Largest size of a file, if the file system limits it
+    pub fn max_file_size(&self) -> Option<u64> {
+        match self {
+            Self::Fat32 => Some(u64::from(u32::MAX)),
+            Self::Exfat | Self::Other => None,
+        }
+    }
+
+    /// `name` with the characters the file system does not allow replaced
+    /// with "_", as well as trailing dots and spaces, which it drops
+    pub fn map_name<'a>(&self, name: &'a str) -> Cow<'a, str> {
+        if *self == Self::Other {
+            return Cow::Borrowed(name);
+        }
+        let invalid = |c: char| c.is_control() || "\"*/:<>?\\|".contains(c);
+        let kept = name.trim_end_matches(['.', ' ']).len();
+        if !name.contains(invalid) && kept == name.len() {
+            return Cow::Borrowed(name);
+        }
+        let mapped: String = name
+            .char_indices()
+            .map(|(i, c)| if invalid(c) || i >= kept { '_' } else { c })
+            .collect();
+        Cow::Owned(mapped)
+    }
+
+    /// Relative `path` with every name mapped by [`FileSystem::map_name`]
+    pub fn map_path(&self, path: &Path) -> PathBuf {
+        path.components()
+            .map(|component| match component {
+                Component::Normal(name) => match name.to_str() {
+                    Some(name) => PathBuf::from(self.map_name(name).as_ref()),
+                    None => PathBuf::from(name),
+                },
+                other => PathBuf::from(other.as_os_str()),
+            })
+            .collect()
+    }
+}
+
+/// Local names of the entries of a walk, so that no two entries get the same
+/// one once mapped, e.g. "a:b" and "a_b", or "A.txt" and "a.txt"
+#[derive(Debug)]
+pub struct Names {
+    fs: FileSystem,
+    unsupported: Unsupported,
+    /// Local paths taken, as the file system compares them, and the remote
+    /// paths they were taken by
+    taken: HashMap<String, PathBuf>,
+    /// Local paths of the directories that were renamed, by remote path
+    renamed: HashMap<PathBuf, PathBuf>,
+    /// Remote paths of the directories that were skipped
+    skipped: HashSet<PathBuf>,
+}
+
+/// Local path of an entry, see [`Names::claim`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Named {
+    /// The mapped path
+    Mapped(PathBuf),
+    /// A path with a suffix, as the mapped one is taken by the remote path
+    /// `taken_by`
+    Renamed { local: PathBuf, taken_by: PathBuf },
+    /// None, as the mapped path is taken by the remote path `taken_by`, or
+    /// the entry is in a skipped directory
+    Skipped { taken_by: Option<PathBuf> },
+}
+
+impl Names {
+    pub fn new(fs: FileSystem, unsupported: Unsupported) -> Self {
+        Self {
+            fs,
+            unsupported,
+            taken: HashMap::new(),
+            renamed: HashMap::new(),
+            skipped: HashSet::new(),
+        }
+    }
+
+    /// Local path for the entry at the relative `remote` path, mapped by
+    /// [`FileSystem::map_path`] below the local paths of its directories. If
+    /// another entry has the path already, the entry is renamed to the
+    /// first free "NAME (2).EXT", "NAME (3).EXT"... or skipped, depending on
+    /// what to do with unsupported files.
+    pub fn claim(&mut self, remote: &Path, is_dir: bool) -> Named {
+        if remote.ancestors().any(|dir| self.skipped.contains(dir)) {
+            return Named::Skipped { taken_by: None };
+        }
+        let local = self.local(remote);
+        let taken_by = match self.taken.get(&self.fold(&local)) {
+            Some(taken_by) if taken_by != remote => taken_by.clone(),
+            _ => {
+                self.taken.insert(self.fold(&local), remote.to_path_buf());
+                return Named::Mapped(local);
+            }
+        };
+        if self.unsupported == Unsupported::Skip {
+            if is_dir {
+                self.skipped.insert(remote.to_path_buf());
+            }
+            return Named::Skipped {
+                taken_by: Some(taken_by),
+            };
+        }
+        let name = local.file_name().unwrap_or_default().to_string_lossy();
+        let (stem, extension) = match name.rsplit_once('.') {
+            Some((stem, extension)) if !is_dir && !stem.is_empty() => {
+                (stem.to_string(), format!(".{}", extension))
+            }
+            _ => (name.into_owned(), String::new()),
+        };
+        let free = (2..)
+            .map(|n| local.with_file_name(format!("{} ({}){}", stem, n, extension)))
+            .find(|candidate| !self.taken.contains_key(&self.fold(candidate)))
+            .expect("a suffix is free");
+        self.taken.insert(self.fold(&free), remote.to_path_buf());
+        if is_dir {
+            self.renamed.insert(remote.to_path_buf(), free.clone());
+        }
+        Named::Renamed {
+            local: free,
+            taken_by,
+        }
+    }
+
+    /// Local path of the relative `remote` path, below the local paths of
+    /// the directories claimed so far
+    pub fn local(&self, remote: &Path) -> PathBuf {
+        for dir in remote.ancestors() {
+            if let Some(local) = self.renamed.get(dir) {
+                let rest = remote.strip_prefix(dir).expect("an ancestor");
+                return local.join(self.fs.map_path(rest));
+            }
+        }
+        self.fs.map_path(remote)
+    }
+
+    /// `path` as the file system compares names
+    fn fold(&self, path: &Path) -> String {
+        let path = path.to_string_lossy();
+        match self.fs {
+            FileSystem::Fat32 | FileSystem::Exfat => path.to_lowercase(),
+            FileSystem::Other => path.into_owned(),
+        }
+    }
+}
+
+impl std::fmt::Display for FileSystem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Fat32 => "FAT32",
+            Self::Exfat => "exFAT",
+            Self::Other => "another file system",
+        })
+    }
+}
+
+/// Path of part `index` of a file written to `dest` in parts, e.g.
+/// "video.mp4.000", which `cat video.mp4.[0-9]* > video.mp4` joins again
+pub fn part_path(dest: &Path, index: usize) -> PathBuf {
+    let mut name = dest.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{:03}", index));
+    dest.with_file_name(name)
+}
+
+/// Path of the manifest of a file written to `dest` in parts, e.g.
+/// "video.mp4.parts.json"
+pub fn manifest_path(dest: &Path) -> PathBuf {
+    let mut name = dest.file_name().unwrap_or_default().to_os_string();
+    name.push(".parts.json");
+    dest.with_file_name(name)
+}
+
+/// Manifest of a file written in parts, next to them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Parts {
+    /// Name of the file
+    pub name: String,
+    pub size: u64,
+    /// SHA-256 checksum of the file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// The parts, in order
+    pub parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Part {
+    pub name: String,
+    pub size: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+impl Parts {
+    /// Parts of `max` bytes of a file of `size` bytes written to `dest`
+    pub fn new(dest: &Path, size: u64, max: u64) -> Self {
+        let parts = (0..size.div_ceil(max))
+            .map(|index| {
+                let start = index * max;
+                Part {
+                    name: file_name(&part_path(dest, index as usize)),
+                    size: max.min(size - start),
+                    sha256: None,
+                }
+            })
+            .collect();
+        Self {
+            name: file_name(dest),
+            size,
+            sha256: None,
+            parts,
+        }
+    }
+
+    /// Computes the checksums of the parts in `dir`, and of the file.
+    pub fn hash(&mut self, dir: &Path) -> anyhow::Result<()> {
+        let mut total = Hasher::new(Algorithm::Sha256);
+        for part in &mut self.parts {
+            let mut hasher = Hasher::new(Algorithm::Sha256);
+            let mut file = BufReader::new(File::open(dir.join(&part.name))?);
+            copy_hashed(&mut file, None, part.size, [&mut hasher, &mut total])?;
+            part.sha256 = Some(hasher.digest());
+        }
+        self.sha256 = Some(total.digest());
+        Ok(())
+    }
+
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        let file = File::open(path).with_context(|| format!("cannot read {}", path.display()))?;
+        serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("invalid manifest {}", path.display()))
+    }
+
+    /// Writes the manifest of a file written to `dest`.
+    pub fn write(&self, dest: &Path) -> anyhow::Result<()> {
+        let mut out = BufWriter::new(File::create(manifest_path(dest))?);
+        serde_json::to_writer_pretty(&mut out, self)?;
+        writeln!(out)?;
+        Ok(out.flush()?)
+    }
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Copies `len` bytes of `from` to `to`, if given, hashing them with
+/// `hashers` on the way.
+fn copy_hashed(
+    from: &mut impl Read,
+    mut to: Option<&mut File>,
+    len: u64,
+    mut hashers: [&mut Hasher; 2],
+) -> io::Result<()> {
+    let mut buf = vec![0; 256 * 1024];
+    let mut left = len;
+    while left > 0 {
+        let chunk = left.min(buf.len() as u64) as usize;
+        let n = from.read(&mut buf[..chunk])?;
+        if n == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        for hasher in &mut hashers {
+            hasher.update(&buf[..n]);
+        }
+        if let Some(to) = &mut to {
+            to.write_all(&buf[..n])?;
+        }
+        left -= n as u64;
+    }
+    Ok(())
+}
+
+/// Whether `sum` is the checksum the manifest expects, if it has one
+fn expected(expected: &Option<String>, sum: &str) -> bool {
+    expected.as_deref().is_none_or(|expected| expected == sum)
+}
+
+/// Joins the parts of the manifest at `manifest` into their file, written
+/// to `dir` (next to the parts by default), checking every part and the
+/// file against the checksums of the manifest.
+///
+/// A file joined before is kept ([`DownloadResult::Skipped`]), and a join
+/// that was cut short continues after the parts it completed
+/// ([`DownloadResult::Continued`]). Returns the path of the file.
+pub fn join(manifest: &Path, dir: Option<&Path>) -> anyhow::Result<(PathBuf, DownloadResult)> {
+    let parts = Parts::read(manifest)?;
+    let from = manifest.parent().unwrap_or(Path::new(""));
+    // a name with a directory in it would be read or written elsewhere
+    let names = std::iter::once(&parts.name).chain(parts.parts.iter().map(|p| &p.name));
+    for name in names {
+        if Path::new(name).file_name() != Some(std::ffi::OsStr::new(name)) {
+            anyhow::bail!("invalid file name \"{}\" in {}", name, manifest.display());
+        }
+    }
+    let dest = dir.unwrap_or(from).join(&parts.name);
+    if std::fs::metadata(&dest).is_ok_and(|m| m.len() == parts.size) {
+        let sum = checksum::file_checksum(Algorithm::Sha256, &dest)?;
+        if expected(&parts.sha256, &sum) {
+            return Ok((dest, DownloadResult::Skipped));
+        }
+    }
+    for part in &parts.parts {
+        let path = from.join(&part.name);
+        let len = std::fs::metadata(&path)
+            .with_context(|| format!("missing part {}", path.display()))?
+            .len();
+        if len != part.size {
+            anyhow::bail!(
+                "part {} has {} bytes, expected {}",
+                path.display(),
+                len,
+                part.size
+            );
+        }
+    }
+
+    // renamed into place once complete, like downloads
+    let mut temp = dest.clone().into_os_string();
+    temp.push(".part");
+    let temp = PathBuf::from(temp);
+    let mut out = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .read(true)
+        .write(true)
+        .open(&temp)?;
+    let mut kept = out.metadata()?.len();
+    let mut total = Hasher::new(Algorithm::Sha256);
+    let (mut start, mut resumed) = (0, false);
+    for part in &parts.parts {
+        let end = start + part.size;
+        // joined by an earlier run, if it is still intact
+        if kept >= end {
+            let (mut hasher, before) = (Hasher::new(Algorithm::Sha256), total.clone());
+            out.seek(SeekFrom::Start(start))?;
+            copy_hashed(&mut out, None, part.size, [&mut hasher, &mut total])?;
+            if expected(&part.sha256, &hasher.digest()) {
+                (start, resumed) = (end, true);
+                continue;
+            }
+            total = before;
+        }
+        out.set_len(start)?;
+        out.seek(SeekFrom::Start(start))?;
+        let path = from.join(&part.name);
+        let mut hasher = Hasher::new(Algorithm::Sha256);
+        let mut file = BufReader::new(File::open(&path)?);
+        copy_hashed(
+            &mut file,
+            Some(&mut out),
+            part.size,
+            [&mut hasher, &mut total],
+        )?;
+        let sum = hasher.digest();
+        if !expected(&part.sha256, &sum) {
+            out.set_len(start)?;
+            anyhow::bail!(
+                "part {} is damaged: its SHA-256 checksum is {}, expected {}",
+                path.display(),
+                sum,
+                part.sha256.as_deref().unwrap_or_default()
+            );
+        }
+        (start, kept) = (end, end);
+    }
+    out.set_len(parts.size)?;
+    out.sync_all()?;
+    drop(out);
+    let sum = total.digest();
+    if !expected(&parts.sha256, &sum) {
+        std::fs::remove_file(&temp)?;
+        anyhow::bail!(
+            "joined file has the SHA-256 checksum {}, expected {}",
+            sum,
+            parts.sha256.as_deref().unwrap_or_default()
+        );
+    }
+    std::fs::rename(&temp, &dest)?;
+    let result = match resumed {
+        true => DownloadResult::Continued,
+        false => DownloadResult::Complete,
+    };
+    Ok((dest, result))
+}
+
+/// Deletes the parts of the manifest at `manifest`, and the manifest.
+pub fn remove_parts(manifest: &Path) -> anyhow::Result<()> {
+    let parts = Parts::read(manifest)?;
+    let from = manifest.parent().unwrap_or(Path::new(""));
+    for part in &parts.parts {
+        std::fs::remove_file(from.join(&part.name))?;
+    }
+    Ok(std::fs::remove_file(manifest)?)
+}
+
+/// Name of the file system type of `dir`
+#[cfg(target_os = "linux")]
+fn fs_type(dir: &Path) -> Option<String> {
+    // the mount point that is the longest prefix of `dir`
+    let mounts = std::fs::read_to_string("/proc/self/mounts").ok()?;
+    mounts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split(' ');
+            let point = fields.nth(1)?.replace("\\040", " ");
+            let fs_type = fields.next()?;
+            dir.starts_with(&point)
+                .then(|| (point.len(), fs_type.to_string()))
+        })
+        .max_by_key(|(len, _)| *len)
+        .map(|(_, fs_type)| fs_type)
+}
+
+/// Name of the file system type of `dir`
+#[cfg(target_os = "macos")]
+fn fs_type(dir: &Path) -> Option<String> {
+    use std::{ffi::CStr, os::unix::ffi::OsStrExt};
+
+    let path = std::ffi::CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let mut stat = std::mem::MaybeUninit::<libc::statfs>::uninit();
+    // SAFETY: `path` is NUL-terminated and `stat` is written on success
+    if unsafe { libc::statfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return None;
+    }
+    let stat = unsafe { stat.assume_init() };
+    let name = unsafe { CStr::from_ptr(stat.f_fstypename.as_ptr()) };
+    Some(name.to_string_lossy().into_owned())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn fs_type(_dir: &Path) -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claim(names: &mut Names, remote: &str, is_dir: bool) -> Named {
+        names.claim(Path::new(remote), is_dir)
+    }
+
+    fn mapped(local: &str) -> Named {
+        Named::Mapped(PathBuf::from(local))
+    }
+
+    fn renamed(local: &str, taken_by: &str) -> Named {
+        Named::Renamed {
+            local: PathBuf::from(local),
+            taken_by: PathBuf::from(taken_by),
+        }
+    }
+
+    #[test]
+    fn names_that_map_to_the_same_one_are_renamed() {
+        let mut names = Names::new(FileSystem::Exfat, Unsupported::Map);
+        assert_eq!(claim(&mut names, "a_b.txt", false), mapped("a_b.txt"));
+        assert_eq!(
+            claim(&mut names, "a:b.txt", false),
+            renamed("a_b (2).txt", "a_b.txt")
+        );
+        assert_eq!(
+            claim(&mut names, "A_B.txt", false),
+            renamed("A_B (3).txt", "a_b.txt")
+        );
+        // claimed again, e.g. by a file listed twice
+        assert_eq!(claim(&mut names, "a_b.txt", false), mapped("a_b.txt"));
+    }
+
+    #[test]
+    fn entries_of_renamed_directories_follow_them() {
+        let mut names = Names::new(FileSystem::Fat32, Unsupported::Map);
+        assert_eq!(claim(&mut names, "Photos", true), mapped("Photos"));
+        assert_eq!(
+            claim(&mut names, "Photos/a.jpg", false),
+            mapped("Photos/a.jpg")
+        );
+        assert_eq!(
+            claim(&mut names, "photos", true),
+            renamed("photos (2)", "Photos")
+        );
+        assert_eq!(
+            claim(&mut names, "photos/a.jpg", false),
+            mapped("photos (2)/a.jpg")
+        );
+        assert_eq!(
+            claim(&mut names, "photos/x?/b.jpg", false),
+            mapped("photos (2)/x_/b.jpg")
+        );
+        assert_eq!(
+            names.local(Path::new("photos/c")),
+            PathBuf::from("photos (2)/c")
+        );
+    }
+
+    #[test]
+    fn names_that_map_to_the_same_one_are_skipped() {
+        let mut names = Names::new(FileSystem::Exfat, Unsupported::Skip);
+        assert_eq!(claim(&mut names, "Docs", true), mapped("Docs"));
+        assert_eq!(
+            claim(&mut names, "DOCS", true),
+            Named::Skipped {
+                taken_by: Some(PathBuf::from("Docs"))
+            }
+        );
+        assert_eq!(
+            claim(&mut names, "DOCS/a.txt", false),
+            Named::Skipped { taken_by: None }
+        );
+        assert_eq!(claim(&mut names, "Docs/a.txt", false), mapped("Docs/a.txt"));
+    }
+}
@@ -0,0 +1,405 @@
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+};
+
+use crate::queue::PendingQueue;
+use crate::seafile::{self, ShareToken};
+use crate::DirEntry;
+use crate::Recursive;
+
+/// Traverses remote entries in the order given by [`Recursive`].
+///
+/// Pending entries beyond a memory limit are spilled to disk, so shares with
+/// millions of entries can be traversed with bounded memory.
+///
+/// Directories are not expanded automatically; call [`Walker::descend`] for
+/// each directory that should be looked into, so callers can prune subtrees.
+///
+/// Directories are listed on a thread of their own and walked as their
+/// entries arrive, so that the first entries of a huge directory are walked
+/// while the rest is received. Breadth-first, a directory is only listed
+/// once the entries before its contents are walked; depth-first, the rest of
+/// a directory is received before one of its subdirectories is listed.
+pub(crate) struct Walker<'a> {
+    client: &'a seafile::Client,
+    token: ShareToken,
+    order: Recursive,
+    queue: PendingQueue,
+    /// Breadth-first, the directories to list
+    deferred: PendingQueue,
+    lister: Option<Lister>,
+    /// Received entries of the directory being listed, which come before
+    /// those queued depth-first and after them breadth-first
+    listing: VecDeque<DirEntry>,
+    /// Whether more entries of the directory being listed are to come
+    open: bool,
+    /// Listing error met while receiving the rest of a directory
+    failed: Option<anyhow::Error>,
+}
+
+/// What the listing thread of a [`Walker`] sends
+enum Listed {
+    Batch(Vec<DirEntry>),
+    Done,
+    Failed(PathBuf, crate::Error),
+}
+
+/// Context of the error of a directory that could not be listed, which a
+/// walk goes on without
+#[derive(Debug, Clone)]
+pub struct ListingFailed {
+    pub dir: PathBuf,
+}
+
+impl std::fmt::Display for ListingFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "cannot list {}", self.dir.display())
+    }
+}
+
+/// Thread listing the directories a [`Walker`] asks for, one at a time
+struct Lister {
+    requests: Sender<Option<PathBuf>>,
+    received: Receiver<Listed>,
+}
+
+impl Lister {
+    fn spawn(client: &seafile::Client, token: &ShareToken) -> Self {
+        let (requests, dirs) = mpsc::channel::<Option<PathBuf>>();
+        // unbounded, so that responses are read at full speed however slowly
+        // the walk goes on
+        let (sender, received) = mpsc::channel();
+        let parts = client.parts();
+        let token = token.clone();
+        std::thread::spawn(move || {
+            let client = seafile::Client::from_parts(parts);
+            for dir in dirs {
+                let result = client.entry_batches(&token, dir.as_deref(), |batch| {
+                    sender.send(Listed::Batch(batch)).is_ok()
+                });
+                let end = match result {
+                    Ok(()) => Listed::Done,
+                    Err(e) => Listed::Failed(dir.unwrap_or_else(|| PathBuf::from("/")), e),
+                };
+                if sender.send(end).is_err() {
+                    break;
+                }
+            }
+        });
+        Self { requests, received }
+    }
+}
+
+impl<'a> Walker<'a> {
+    /// Default number of pending entries kept in memory
+    pub const MEMORY_LIMIT: usize = 100_000;
+
+    pub fn new(client: &'a seafile::Client, token: &ShareToken, order: Recursive) -> Self {
+        Self {
+            client,
+            token: token.clone(),
+            order,
+            queue: PendingQueue::new(order == Recursive::Dfs, Self::MEMORY_LIMIT),
+            deferred: PendingQueue::new(false, Self::MEMORY_LIMIT),
+            lister: None,
+            listing: VecDeque::new(),
+            open: false,
+            failed: None,
+        }
+    }
+
+    /// Keeps at most `limit` pending entries in memory and spills the rest to
+    /// a temporary file. Must be called before entries are pushed.
+    pub fn with_memory_limit(mut self, limit: usize) -> Self {
+        self.queue = PendingQueue::new(self.order == Recursive::Dfs, limit);
+        self.deferred = PendingQueue::new(false, limit);
+        self
+    }
+
+    pub fn push_entries(&mut self, entries: Vec<DirEntry>) -> anyhow::Result<()> {
+        if self.order == Recursive::Dfs {
+            self.queue.push(entries.into_iter().rev())
+        } else {
+            self.queue.push(entries)
+        }
+    }
+
+    /// Walks the contents of `dir` (the share's if None) next.
+    pub fn list(&mut self, dir: Option<&Path>) -> anyhow::Result<()> {
+        self.settle()?;
+        self.open(dir)
+    }
+
+    /// Walks the contents of `dir` in turn. Listing errors are returned by
+    /// [`Walker::next`] as [`crate::Error`]s with the context
+    /// [`ListingFailed`], after which the walk goes on.
+    pub fn descend(&mut self, dir: &DirEntry) -> anyhow::Result<()> {
+        if self.order == Recursive::Dfs {
+            self.settle()?;
+            self.open(Some(dir.path()))
+        } else {
+            self.deferred.push([dir.clone()])
+        }
+    }
+
+    pub fn next(&mut self) -> anyhow::Result<Option<DirEntry>> {
+        if let Some(e) = self.failed.take() {
+            return Err(e);
+        }
+        if self.order == Recursive::Dfs {
+            return match self.next_listed()? {
+                Some(entry) => Ok(Some(entry)),
+                None => self.queue.pop(),
+            };
+        }
+        loop {
+            if let Some(entry) = self.queue.pop()? {
+                return Ok(Some(entry));
+            }
+            if let Some(entry) = self.next_listed()? {
+                return Ok(Some(entry));
+            }
+            match self.deferred.pop()? {
+                Some(dir) => self.open(Some(dir.path()))?,
+                None => return Ok(None),
+            }
+        }
+    }
+
+    fn open(&mut self, dir: Option<&Path>) -> anyhow::Result<()> {
+        let lister = match &self.lister {
+            Some(lister) => lister,
+            None => self.lister.insert(Lister::spawn(self.client, &self.token)),
+        };
+        lister.requests.send(dir.map(Path::to_path_buf))?;
+        self.open = true;
+        Ok(())
+    }
+
+    /// Next received entry of the directory being listed, waiting for it
+    fn next_listed(&mut self) -> anyhow::Result<Option<DirEntry>> {
+        while self.listing.is_empty() && self.open {
+            self.receive()?;
+        }
+        Ok(self.listing.pop_front())
+    }
+
+    /// Waits for more of the directory being listed.
+    fn receive(&mut self) -> anyhow::Result<()> {
+        let Some(lister) = self.lister.as_ref().filter(|_| self.open) else {
+            return Ok(());
+        };
+        let Ok(listed) = lister.received.recv() else {
+            anyhow::bail!("the listing thread stopped");
+        };
+        match listed {
+            Listed::Batch(entries) => self.listing.extend(entries),
+            Listed::Done => self.open = false,
+            Listed::Failed(dir, e) => {
+                self.open = false;
+                return Err(anyhow::Error::from(e).context(ListingFailed { dir }));
+            }
+        }
+        Ok(())
+    }
+
+    /// Receives the rest of the directory being listed into the queue.
+    fn settle(&mut self) -> anyhow::Result<()> {
+        while self.open {
+            if let Err(e) = self.receive() {
+                if !e.is::<crate::Error>() {
+                    return Err(e);
+                }
+                self.failed = Some(e);
+            }
+        }
+        let rest: Vec<_> = self.listing.drain(..).collect();
+        self.push_entries(rest)
+    }
+}
+
+/// Where a [`DirEntryStream`] starts
+enum Start {
+    Roots(Vec<DirEntry>),
+    Dir(Option<PathBuf>),
+}
+
+/// Entries produced by a `Walker` running on a background thread.
+///
+/// The channel is bounded, so listing pauses while the consumer falls behind.
+/// Dropping the stream cancels the traversal.
+pub struct DirEntryStream {
+    receiver: Receiver<anyhow::Result<DirEntry>>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl DirEntryStream {
+    /// Default number of pending entries kept in memory
+    pub const MEMORY_LIMIT: usize = Walker::MEMORY_LIMIT;
+
+    /// Walks from `roots`, descending into directories unless `order` is
+    /// [`Recursive::None`]. Entries rejected by `filter` are neither yielded
+    /// nor descended into; listing errors are yielded, with the context
+    /// [`ListingFailed`], and skip the subtree.
+    ///
+    /// With `max_depth`, directories that many levels down (`roots` being the
+    /// first level) are yielded but not descended into.
+    #[allow(clippy::too_many_arguments)]
+    pub fn spawn<F>(
+        client: &seafile::Client,
+        token: &ShareToken,
+        roots: Vec<DirEntry>,
+        order: Recursive,
+        filter: F,
+        capacity: usize,
+        memory_limit: usize,
+        max_depth: Option<usize>,
+    ) -> Self
+    where
+        F: Fn(&DirEntry) -> bool + Send + 'static,
+    {
+        let levels_above = roots
+            .first()
+            .map_or(0, |root| root.path().components().count() - 1);
+        Self::start(
+            client,
+            token,
+            Start::Roots(roots),
+            levels_above,
+            order,
+            filter,
+            capacity,
+            memory_limit,
+            max_depth,
+        )
+    }
+
+    /// Walks like [`DirEntryStream::spawn`] from the contents of `dir` (the
+    /// share's if None), which are listed on the walking thread: the first
+    /// entries of a huge directory are yielded while the rest is received.
+    #[allow(clippy::too_many_arguments)]
+    pub fn spawn_dir<F>(
+        client: &seafile::Client,
+        token: &ShareToken,
+        dir: Option<&Path>,
+        order: Recursive,
+        filter: F,
+        capacity: usize,
+        memory_limit: usize,
+        max_depth: Option<usize>,
+    ) -> Self
+    where
+        F: Fn(&DirEntry) -> bool + Send + 'static,
+    {
+        let levels_above = dir.unwrap_or(Path::new("/")).components().count();
+        Self::start(
+            client,
+            token,
+            Start::Dir(dir.map(Path::to_path_buf)),
+            levels_above,
+            order,
+            filter,
+            capacity,
+            memory_limit,
+            max_depth,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn start<F>(
+        client: &seafile::Client,
+        token: &ShareToken,
+        start: Start,
+        levels_above: usize,
+        order: Recursive,
+        filter: F,
+        capacity: usize,
+        memory_limit: usize,
+        max_depth: Option<usize>,
+    ) -> Self
+    where
+        F: Fn(&DirEntry) -> bool + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let parts = client.parts();
+        let token = token.clone();
+        let flag = cancelled.clone();
+        let within_depth = move |dir: &DirEntry| {
+            max_depth.is_none_or(|max| dir.path().components().count() - levels_above < max)
+        };
+        std::thread::spawn(move || {
+            let client = seafile::Client::from_parts(parts);
+            let mut walker = Walker::new(&client, &token, order).with_memory_limit(memory_limit);
+            let started = match start {
+                Start::Roots(roots) => walker.push_entries(roots),
+                Start::Dir(dir) => walker.list(dir.as_deref()),
+            };
+            if let Err(e) = started {
+                let _ = sender.send(Err(e));
+                return;
+            }
+            loop {
+                let entry = match walker.next() {
+                    Ok(Some(entry)) => entry,
+                    Ok(None) => break,
+                    // a directory that could not be listed is skipped
+                    Err(e) if e.is::<crate::Error>() => {
+                        if sender.send(Err(e)).is_err() {
+                            break;
+                        }
+                        continue;
+                    }
+                    Err(e) => {
+                        let _ = sender.send(Err(e));
+                        break;
+                    }
+                };
+                if flag.load(Ordering::Relaxed) {
+                    break;
+                }
+                if !filter(&entry) {
+                    continue;
+                }
+                if entry.is_dir() && order != Recursive::None && within_depth(&entry) {
+                    if let Err(e) = walker.descend(&entry) {
+                        if sender.send(Err(e)).is_err() {
+                            break;
+                        }
+                    }
+                }
+                if sender.send(Ok(entry)).is_err() {
+                    break;
+                }
+            }
+        });
+        Self {
+            receiver,
+            cancelled,
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Iterator for DirEntryStream {
+    type Item = anyhow::Result<DirEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl Drop for DirEntryStream {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
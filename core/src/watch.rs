@@ -0,0 +1,175 @@
+//! Files found changed by `watch`, kept until they are downloaded, also
+//! while the network or the server is down, and the events of its log.
+
+use std::{
+    collections::{HashSet, VecDeque},
+    path::{Path, PathBuf},
+};
+
+use serde::Serialize;
+
+use crate::DirEntry;
+
+/// Files waiting to be downloaded to their destination, oldest first, at
+/// most `limit` of them and each file once.
+pub struct WorkQueue {
+    items: VecDeque<(DirEntry, PathBuf)>,
+    paths: HashSet<PathBuf>,
+    limit: usize,
+}
+
+impl WorkQueue {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            items: VecDeque::new(),
+            paths: HashSet::new(),
+            limit: limit.max(1),
+        }
+    }
+
+    /// Queues `entry` for `dest`, in place of a queued item of the same
+    /// file, and returns the oldest item if it was dropped to keep within
+    /// the limit.
+    pub fn push(&mut self, entry: DirEntry, dest: PathBuf) -> Option<(DirEntry, PathBuf)> {
+        if self.paths.contains(entry.path()) {
+            let item = self
+                .items
+                .iter_mut()
+                .find(|(e, _)| e.path() == entry.path());
+            *item.expect("queued path has an item") = (entry, dest);
+            return None;
+        }
+        self.paths.insert(entry.path().to_path_buf());
+        self.items.push_back((entry, dest));
+        if self.items.len() <= self.limit {
+            return None;
+        }
+        let dropped = self.items.pop_front()?;
+        self.paths.remove(dropped.0.path());
+        Some(dropped)
+    }
+
+    /// Puts an item back in front, e.g. one that could not be downloaded
+    /// while offline, unless a newer item of the file was queued since.
+    pub fn retry(&mut self, entry: DirEntry, dest: PathBuf) {
+        if self.paths.insert(entry.path().to_path_buf()) {
+            self.items.push_front((entry, dest));
+        }
+    }
+
+    pub fn pop(&mut self) -> Option<(DirEntry, PathBuf)> {
+        let item = self.items.pop_front()?;
+        self.paths.remove(item.0.path());
+        Some(item)
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+}
+
+/// Event of the log of `watch`, one JSON object per line
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    /// The share could not be reached; the queued files wait for it
+    Offline { queued: usize, error: String },
+    /// The share is reachable again, and the queued files are downloaded
+    Online { queued: usize },
+    /// A file left the full queue; it is queued again by the next check
+    /// that finds it changed
+    Dropped { path: &'a Path, queued: usize },
+    /// A check of the share, and the downloads of its queued files, ended
+    Checked {
+        changed: usize,
+        downloaded: usize,
+        failed: usize,
+        queued: usize,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, size: u64) -> DirEntry {
+        let url = url::Url::parse("https://example.com/d/abc/files/").unwrap();
+        DirEntry::File {
+            name: path.rsplit('/').next().unwrap().to_string(),
+            path: path.into(),
+            size,
+            last_modified: None,
+            content_type: None,
+            id: None,
+            download_url: url.clone(),
+            view_url: url,
+        }
+    }
+
+    fn queued(queue: &mut WorkQueue) -> Vec<(String, Option<u64>)> {
+        std::iter::from_fn(|| queue.pop())
+            .map(|(e, _)| (e.path().display().to_string(), e.size()))
+            .collect()
+    }
+
+    #[test]
+    fn keeps_each_file_once_with_its_latest_entry() {
+        let mut queue = WorkQueue::new(10);
+        assert!(queue.push(file("/a", 1), "out/a".into()).is_none());
+        assert!(queue.push(file("/b", 1), "out/b".into()).is_none());
+        assert!(queue.push(file("/a", 2), "out/a".into()).is_none());
+        assert_eq!(queue.len(), 2);
+        assert_eq!(
+            queued(&mut queue),
+            [("/a".into(), Some(2)), ("/b".into(), Some(1))]
+        );
+    }
+
+    #[test]
+    fn drops_the_oldest_beyond_the_limit() {
+        let mut queue = WorkQueue::new(2);
+        queue.push(file("/a", 1), "out/a".into());
+        queue.push(file("/b", 1), "out/b".into());
+        let (dropped, _) = queue.push(file("/c", 1), "out/c".into()).unwrap();
+        assert_eq!(dropped.path(), Path::new("/a"));
+        // a dropped file is queued anew when it is found again
+        queue.push(file("/a", 1), "out/a".into());
+        assert_eq!(
+            queued(&mut queue),
+            [("/c".into(), Some(1)), ("/a".into(), Some(1)),]
+        );
+    }
+
+    #[test]
+    fn retried_items_come_first_unless_queued_again() {
+        let mut queue = WorkQueue::new(10);
+        queue.push(file("/a", 1), "out/a".into());
+        queue.push(file("/b", 1), "out/b".into());
+        let (a, dest) = queue.pop().unwrap();
+        queue.retry(a, dest);
+        let (a, dest) = queue.pop().unwrap();
+        assert_eq!(a.path(), Path::new("/a"));
+        queue.push(file("/a", 2), "out/a".into());
+        queue.retry(a, dest);
+        assert_eq!(
+            queued(&mut queue),
+            [("/b".into(), Some(1)), ("/a".into(), Some(2))]
+        );
+    }
+
+    #[test]
+    fn events_are_tagged() {
+        let event = Event::Dropped {
+            path: Path::new("/a"),
+            queued: 2,
+        };
+        assert_eq!(
+            serde_json::to_string(&event).unwrap(),
+            r#"{"event":"dropped","path":"/a","queued":2}"#
+        );
+    }
+}
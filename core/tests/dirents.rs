@@ -0,0 +1,159 @@
+//! Listing entries as servers send them, read by `seafile::DirEnt`.
+
+use std::path::Path;
+
+use seaf_share_core::seafile::DirEnt;
+use serde::Deserialize;
+use serde_json::Value;
+
+#[derive(Deserialize)]
+struct Listing {
+    dirent_list: Vec<DirEnt>,
+}
+
+fn fixture(name: &str) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/dirents")
+        .join(name);
+    std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+}
+
+/// The raw entries of a fixture
+fn entries(name: &str) -> Vec<Value> {
+    let listing: Value = serde_json::from_str(&fixture(name)).unwrap();
+    listing["dirent_list"].as_array().unwrap().clone()
+}
+
+/// `(is_dir, path, name, size)` of an entry
+fn describe(entry: &DirEnt) -> (bool, &str, &str, Option<u64>) {
+    let path = entry.path().to_str().unwrap();
+    (entry.is_dir(), path, entry.name(), entry.size())
+}
+
+#[test]
+fn responses_of_seafile_8_to_11() {
+    let expected = [
+        (
+            "seafile-8.json",
+            [
+                (true, "/Photos/", "Photos", None),
+                (false, "/notes.txt", "notes.txt", Some(1024)),
+            ],
+        ),
+        (
+            "seafile-9.json",
+            [
+                (true, "/Photos/", "Photos", None),
+                (false, "/notes.txt", "notes.txt", Some(1024)),
+            ],
+        ),
+        (
+            "seafile-10.json",
+            [
+                (true, "/Photos/", "Photos", None),
+                (false, "/Photos/beach.jpg", "beach.jpg", Some(2097152)),
+            ],
+        ),
+        (
+            "seafile-11.json",
+            [
+                (true, "/Data sets/2024/", "2024", None),
+                (
+                    false,
+                    "/Data sets/2024/results.csv",
+                    "results.csv",
+                    Some(52428800),
+                ),
+            ],
+        ),
+    ];
+    for (name, expected) in expected {
+        let strict = entries(name)
+            .iter()
+            .map(DirEnt::from_json_strict)
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap_or_else(|e| panic!("{}: {}", name, e));
+        assert_eq!(strict.iter().map(describe).collect::<Vec<_>>(), expected);
+
+        let listing: Listing = serde_json::from_str(&fixture(name)).unwrap();
+        assert_eq!(
+            listing.dirent_list.iter().map(describe).collect::<Vec<_>>(),
+            expected,
+            "{}",
+            name
+        );
+    }
+}
+
+#[test]
+fn times_of_entries() {
+    let listing: Listing = serde_json::from_str(&fixture("seafile-8.json")).unwrap();
+    assert_eq!(
+        listing.dirent_list[0].last_modified().to_rfc3339(),
+        "2021-03-02T02:15:00+00:00"
+    );
+}
+
+#[test]
+fn known_variations_are_read_strictly() {
+    let strict = entries("variations.json")
+        .iter()
+        .map(DirEnt::from_json_strict)
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(
+        strict.iter().map(describe).collect::<Vec<_>>(),
+        [
+            (true, "/Archive/", "Archive", None),
+            (false, "/Archive/old.zip", "old.zip", Some(4096)),
+        ]
+    );
+    assert_eq!(strict[1].last_modified().timestamp(), 1700000100);
+}
+
+#[test]
+fn deviations_fail_strictly_and_are_read_leniently() {
+    let expected = [
+        (
+            (false, "/starred.txt", "starred.txt", Some(10)),
+            "unknown field `starred`",
+        ),
+        (
+            (false, "/quoted.txt", "quoted.txt", Some(20)),
+            "field `size` is a string",
+        ),
+        (
+            (true, "/untyped/", "untyped", None),
+            "missing field `is_dir`",
+        ),
+        (
+            (false, "/undated.txt", "undated.txt", Some(30)),
+            "missing field `file_name`, missing or invalid field `last_modified`",
+        ),
+    ];
+    for (value, (entry, problems)) in entries("deviations.json").iter().zip(expected) {
+        assert_eq!(DirEnt::from_json_strict(value).unwrap_err(), problems);
+        let (lenient, found) = DirEnt::from_json_lenient(value).unwrap();
+        assert_eq!(describe(&lenient), entry);
+        assert_eq!(found.join(", "), problems);
+    }
+}
+
+#[test]
+fn deserializing_ignores_only_unknown_fields() {
+    let entries = entries("deviations.json");
+    let starred: DirEnt = serde_json::from_value(entries[0].clone()).unwrap();
+    assert_eq!(starred.name(), "starred.txt");
+    for value in &entries[1..] {
+        assert!(serde_json::from_value::<DirEnt>(value.clone()).is_err());
+    }
+}
+
+#[test]
+fn entries_without_a_path_are_not_read() {
+    let value = serde_json::json!({ "is_dir": false, "file_name": "lost.txt", "size": 1 });
+    assert_eq!(
+        DirEnt::from_json_lenient(&value).unwrap_err(),
+        "missing field `file_path`"
+    );
+}
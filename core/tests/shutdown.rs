@@ -0,0 +1,238 @@
+//! Teardown of a run as the library promises it, see `shutdown`.
+
+#![cfg(feature = "client")]
+
+use std::{
+    io::{Read, Write},
+    net::TcpListener,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc,
+    },
+    thread,
+    time::Duration,
+};
+
+use seaf_share_core::{
+    pool::Pool, progress::Progress, shutdown::Abort, ConflictAction, DirEntry, Downloader,
+};
+
+/// Time after which a run is taken to hang
+const HANG: Duration = Duration::from_secs(10);
+
+/// Runs `f` on its own thread, failing the test if it does not return in
+/// time. Returns whether it panicked.
+fn panics_in_time(f: impl FnOnce() + Send + 'static) -> bool {
+    let (done, finished) = mpsc::channel();
+    thread::spawn(move || {
+        let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)).is_err();
+        let _ = done.send(panicked);
+    });
+    finished.recv_timeout(HANG).expect("the run hangs")
+}
+
+#[test]
+fn drain_completes_the_items_in_progress() {
+    let abort = Abort::default();
+    let draining = AtomicBool::new(false);
+    let drain = || draining.load(Ordering::SeqCst);
+    let pool = Pool::new(&abort, 2, 1).with_drain(&drain);
+    let transferred = AtomicUsize::new(0);
+    let mut reported = Vec::new();
+    let taken = pool.run(
+        || {
+            |item: usize| {
+                thread::sleep(Duration::from_millis(10));
+                transferred.fetch_add(1, Ordering::SeqCst);
+                Ok::<_, Box<usize>>(item)
+            }
+        },
+        |item| item,
+        |item| reported.push(item),
+        |feeder| {
+            let mut taken = 0;
+            for item in 0..100 {
+                if item == 10 {
+                    draining.store(true, Ordering::SeqCst);
+                }
+                if !feeder.send(item) {
+                    break;
+                }
+                taken += 1;
+                feeder.report();
+            }
+            taken
+        },
+    );
+    assert_eq!(taken, 10);
+    // started items are reported, queued ones are left
+    assert_eq!(reported.len(), transferred.load(Ordering::SeqCst));
+    assert!(reported.iter().all(|&item| item < 10));
+    assert!(!abort.is_triggered());
+}
+
+#[test]
+fn abort_finalizes_the_transferred_items() {
+    let abort = Abort::default();
+    let pool = Pool::new(&abort, 4, 1);
+    let transferred = AtomicUsize::new(0);
+    let finalized = AtomicUsize::new(0);
+    let mut reported = 0;
+    pool.run(
+        || {
+            |item: usize| {
+                if item == 20 {
+                    abort.trigger();
+                }
+                transferred.fetch_add(1, Ordering::SeqCst);
+                Ok::<_, Box<usize>>(item)
+            }
+        },
+        |item| {
+            // finalization is slower than transfers, so jobs are queued
+            thread::sleep(Duration::from_millis(5));
+            finalized.fetch_add(1, Ordering::SeqCst);
+            item
+        },
+        |_| reported += 1,
+        |feeder| (0..100).take_while(|&item| feeder.send(item)).count(),
+    );
+    assert!(abort.is_triggered());
+    assert!(transferred.load(Ordering::SeqCst) < 100);
+    assert_eq!(
+        finalized.load(Ordering::SeqCst),
+        transferred.load(Ordering::SeqCst)
+    );
+    assert_eq!(reported, finalized.load(Ordering::SeqCst));
+}
+
+#[test]
+fn failed_items_are_reported() {
+    let abort = Abort::default();
+    let pool = Pool::new(&abort, 2, 2);
+    let mut reported = Vec::new();
+    pool.run(
+        || {
+            |item: i32| match item % 2 {
+                0 => Ok(item),
+                _ => Err(Box::new(-item)),
+            }
+        },
+        |item| item,
+        |item| reported.push(item),
+        |feeder| (0..10).for_each(|item| assert!(feeder.send(item))),
+    );
+    reported.sort();
+    assert_eq!(reported, [-9, -7, -5, -3, -1, 0, 2, 4, 6, 8]);
+}
+
+#[test]
+fn panic_of_every_worker_does_not_hang_the_feeder() {
+    let abort = Abort::default();
+    let aborted = abort.clone();
+    let panicked = panics_in_time(move || {
+        let pool = Pool::new(&abort, 2, 1);
+        pool.run(
+            || |_: usize| -> Result<usize, Box<usize>> { panic!("worker") },
+            |item| item,
+            |_| (),
+            |feeder| (0..1000).take_while(|&item| feeder.send(item)).count(),
+        );
+    });
+    assert!(panicked);
+    assert!(aborted.is_triggered());
+}
+
+#[test]
+fn panic_of_every_finisher_does_not_hang_the_workers() {
+    let abort = Abort::default();
+    let aborted = abort.clone();
+    let panicked = panics_in_time(move || {
+        let pool = Pool::new(&abort, 2, 1);
+        pool.run(
+            || |item: usize| Ok::<_, Box<usize>>(item),
+            |_: usize| -> usize { panic!("finisher") },
+            |_| (),
+            |feeder| (0..1000).take_while(|&item| feeder.send(item)).count(),
+        );
+    });
+    assert!(panicked);
+    assert!(aborted.is_triggered());
+}
+
+/// Serves a file of `len` bytes once, sending the first `sent` bytes, then
+/// the rest once `next` receives.
+fn serve_slowly(len: usize, sent: usize, next: mpsc::Receiver<()>) -> url::Url {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/file", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut head = Vec::new();
+        let mut byte = [0];
+        while !head.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap() == 1 {
+            head.push(byte[0]);
+        }
+        let header = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", len);
+        stream.write_all(header.as_bytes()).unwrap();
+        stream.write_all(&vec![b'x'; sent]).unwrap();
+        stream.flush().unwrap();
+        if next.recv().is_ok() {
+            let _ = stream.write_all(&vec![b'x'; len - sent]);
+        }
+    });
+    url.parse().unwrap()
+}
+
+fn file_entry(url: url::Url) -> DirEntry {
+    DirEntry::File {
+        name: "file".to_string(),
+        path: PathBuf::from("/file"),
+        size: 1 << 20,
+        last_modified: None,
+        content_type: None,
+        id: None,
+        download_url: url.clone(),
+        view_url: url,
+    }
+}
+
+/// Aborts a download of a file to `dest` once it is under way.
+fn abort_download(dest: &Path, on_conflict: ConflictAction) {
+    let (next, sending) = mpsc::channel();
+    let url = serve_slowly(1 << 20, 1 << 16, sending);
+    let abort = Abort::default();
+    let progress = Progress::default();
+    let downloader = Downloader::with_client(ureq::Agent::new_with_defaults())
+        .with_abort(abort.clone())
+        .with_progress(progress.clone());
+    let entry = file_entry(url);
+    thread::scope(|scope| {
+        let download =
+            scope.spawn(|| downloader.download_entry(&entry, dest.to_path_buf(), on_conflict));
+        while progress.received() == 0 {
+            thread::sleep(Duration::from_millis(10));
+        }
+        abort.trigger();
+        next.send(()).unwrap();
+        assert!(download.join().unwrap().is_err());
+    });
+    assert!(!dest.exists());
+}
+
+#[test]
+fn abort_removes_part_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let dest = dir.path().join("file");
+    abort_download(&dest, ConflictAction::Overwrite);
+    assert!(!dest.with_file_name("file.part").exists());
+}
+
+#[test]
+fn abort_keeps_part_files_to_continue() {
+    let dir = tempfile::tempdir().unwrap();
+    let dest = dir.path().join("file");
+    abort_download(&dest, ConflictAction::Continue);
+    let part = std::fs::metadata(dest.with_file_name("file.part")).unwrap();
+    assert!(part.len() > 0);
+}
@@ -0,0 +1,134 @@
+//! Staging of output destinations for "--stage-then-swap", see `stage`.
+
+#![cfg(feature = "client")]
+
+use std::{
+    fs,
+    path::Path,
+    time::{Duration, SystemTime},
+};
+
+use seaf_share_core::{stage, sync::FILE_IDS};
+
+fn write(path: &Path, contents: &str) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, contents).unwrap();
+}
+
+fn read(path: &Path) -> String {
+    fs::read_to_string(path).unwrap()
+}
+
+#[cfg(unix)]
+fn same_file(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    let (a, b) = (fs::metadata(a).unwrap(), fs::metadata(b).unwrap());
+    (a.dev(), a.ino()) == (b.dev(), b.ino())
+}
+
+#[test]
+fn stages_next_to_the_destination() {
+    let dir = tempfile::tempdir().unwrap();
+    let staging = stage::staging_dir(&dir.path().join("mirror")).unwrap();
+    assert_eq!(
+        staging,
+        dir.path().canonicalize().unwrap().join(".mirror.staging")
+    );
+}
+
+#[test]
+fn refuses_the_working_directory() {
+    let cwd = std::env::current_dir().unwrap();
+    for dest in [Path::new("./"), Path::new("."), &cwd, cwd.parent().unwrap()] {
+        let e = stage::staging_dir(dest).unwrap_err();
+        assert!(
+            e.to_string().contains("holds the working directory"),
+            "{}: {}",
+            dest.display(),
+            e
+        );
+    }
+}
+
+#[test]
+fn seeds_with_the_files_of_the_destination() {
+    let dir = tempfile::tempdir().unwrap();
+    let (dest, staging) = (
+        dir.path().join("mirror"),
+        dir.path().join(".mirror.staging"),
+    );
+    write(&dest.join("a.txt"), "a");
+    write(&dest.join("sub/b.txt"), "b");
+    write(&dest.join(FILE_IDS), "id  a.txt\n");
+    let old = SystemTime::now() - Duration::from_secs(3600);
+    fs::File::options()
+        .write(true)
+        .open(dest.join("sub/b.txt"))
+        .unwrap()
+        .set_modified(old)
+        .unwrap();
+    // left by a run that failed
+    write(&staging.join("a.txt"), "new a");
+
+    stage::seed(&dest, &staging, true).unwrap();
+    assert_eq!(read(&staging.join("a.txt")), "new a");
+    assert_eq!(read(&staging.join("sub/b.txt")), "b");
+    let modified = |path: &Path| fs::metadata(path).unwrap().modified().unwrap();
+    assert_eq!(modified(&staging.join("sub/b.txt")), old);
+    #[cfg(unix)]
+    {
+        assert!(same_file(
+            &dest.join("sub/b.txt"),
+            &staging.join("sub/b.txt")
+        ));
+        // rewritten in place, so not shared with the destination
+        assert!(!same_file(&dest.join(FILE_IDS), &staging.join(FILE_IDS)));
+    }
+    write(&staging.join(FILE_IDS), "");
+    assert_eq!(read(&dest.join(FILE_IDS)), "id  a.txt\n");
+}
+
+#[test]
+fn seeds_copies_unless_linking() {
+    let dir = tempfile::tempdir().unwrap();
+    let (dest, staging) = (
+        dir.path().join("mirror"),
+        dir.path().join(".mirror.staging"),
+    );
+    write(&dest.join("a.txt"), "a");
+    stage::seed(&dest, &staging, false).unwrap();
+    fs::OpenOptions::new()
+        .append(true)
+        .open(staging.join("a.txt"))
+        .unwrap()
+        .set_len(0)
+        .unwrap();
+    assert_eq!(read(&dest.join("a.txt")), "a");
+}
+
+#[test]
+fn seeds_nothing_for_a_new_destination() {
+    let dir = tempfile::tempdir().unwrap();
+    let staging = dir.path().join(".mirror.staging");
+    stage::seed(&dir.path().join("mirror"), &staging, true).unwrap();
+    assert!(!staging.exists());
+}
+
+#[test]
+fn swap_keeps_the_files_left_out_of_the_run() {
+    let dir = tempfile::tempdir().unwrap();
+    let dest = dir.path().join("mirror");
+    write(&dest.join("a.txt"), "a");
+    write(&dest.join("excluded.log"), "kept");
+    let staging = stage::staging_dir(&dest).unwrap();
+    stage::seed(&dest, &staging, true).unwrap();
+    // a download replaces the file it updates
+    write(&staging.join("a.txt.part"), "new a");
+    fs::rename(staging.join("a.txt.part"), staging.join("a.txt")).unwrap();
+    assert_eq!(read(&dest.join("a.txt")), "a");
+
+    stage::swap(&staging, &dest).unwrap();
+    assert_eq!(read(&dest.join("a.txt")), "new a");
+    assert_eq!(read(&dest.join("excluded.log")), "kept");
+    assert!(!staging.exists());
+}
@@ -1,32 +1,188 @@
-use std::path::{Path, PathBuf};
+use std::{
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-use clap::{Args, Parser, Subcommand, ValueEnum};
+use clap::{
+    builder::{BoolishValueParser, PossibleValuesParser},
+    ArgGroup, Args, CommandFactory, FromArgMatches, Parser, Subcommand,
+};
+use ureq::http::{HeaderName, HeaderValue};
 use url::Url;
 
+use seaf_share_core::{
+    checksum,
+    filter::{self, Filter, Rule, RuleFile},
+    seafile,
+    volume::{self, FileSystem, Unsupported},
+    DirEntry,
+};
+pub use seaf_share_core::{ConflictAction, Recursive};
+
+use crate::{config::Config, examples, handler, printf::Template};
+
 #[derive(Debug, Clone, Parser)]
 #[clap(version)]
 pub struct Cli {
     #[clap(subcommand)]
     command: Command,
+
+    /// Refuse to run anything that writes files (downloads, uploads, "join",
+    /// "--record"...) and do not use the HTTP cache, e.g. to audit what a
+    /// share contains before approving a transfer
+    #[clap(
+        long,
+        global = true,
+        env = "SEAF_SHARE_NO_WRITE",
+        value_parser = BoolishValueParser::new()
+    )]
+    no_write: bool,
+
+    /// Show share tokens, passwords and signed download URLs in errors and
+    /// log lines rather than "***", e.g. to debug a share
+    #[clap(long, global = true)]
+    no_redact: bool,
 }
 
 impl Cli {
+    /// Parses the command line like [`Parser::parse`], with the defaults of
+    /// `config`.
+    pub fn parse_with(config: &Config) -> Self {
+        let matches = Self::command_with(config).get_matches();
+        Self::from_arg_matches(&matches).unwrap_or_else(|e| e.exit())
+    }
+
+    /// Command line with the defaults of `config` and the examples of each
+    /// command at the end of its help
+    pub fn command_with(config: &Config) -> clap::Command {
+        let mut cli = config.apply(<Self as CommandFactory>::command());
+        let names: Vec<String> = cli
+            .get_subcommands()
+            .map(|c| c.get_name().to_string())
+            .collect();
+        for name in names {
+            if let Some(help) = examples::help(&name) {
+                cli = cli.mut_subcommand(name, |c| c.after_help(help));
+            }
+        }
+        #[cfg(debug_assertions)]
+        examples::check(&cli);
+        cli
+    }
+
     pub fn command(&self) -> &Command {
         &self.command
     }
+    pub fn no_write(&self) -> bool {
+        self.no_write
+    }
+    pub fn no_redact(&self) -> bool {
+        self.no_redact
+    }
 }
 
 #[derive(Debug, Clone, Subcommand)]
 pub enum Command {
     List(ListOptions),
     Download(DownloadOptions),
+    /// Download new and changed files only, like rsync
+    Sync(SyncOptions),
+    /// Sync a share every few minutes, keeping the changed files queued while
+    /// the network or the server is down
+    Watch(WatchOptions),
+    /// Search a share recursively
+    Find(FindOptions),
+    /// Show how much space a share uses
+    Du(DuOptions),
+    /// Show the directory structure of a share with file counts and sizes
+    Tree(TreeOptions),
+    /// Show what the share page tells of a share: its library, owner,
+    /// expiration date and permissions, and whether it has a password
+    Info(InfoOptions),
+    /// Write a file to stdout
+    Cat(CatOptions),
+    /// Compare a local copy with the share, failing on any difference
+    Verify(VerifyOptions),
+    /// Upload files and directories through an upload link
+    Upload(UploadOptions),
+    /// Join files written in parts with "--split-size" (or onto FAT32)
+    Join(JoinOptions),
+    /// List the libraries of the account of "--api-token" on the server at
+    /// URL, with links to list and download them
+    Libraries(LibrariesOptions),
+    /// Log in to a server and keep its API token in the keyring of the
+    /// system, for the libraries of the account
+    Auth(AuthOptions),
+    /// Open "seaf-share://" and "seafile://" links from the browser with this
+    /// tool, which downloads them recursively
+    InstallHandler(HandlerOptions),
+    /// Show examples of the commands
+    Examples(ExamplesOptions),
+    /// Show the configuration file and the defaults it sets
+    Config,
 }
 
 impl Command {
-    pub fn common(&self) -> &CommonOptions {
+    /// Options of commands that access a share
+    pub fn common(&self) -> Option<&CommonOptions> {
         match self {
-            Self::List(options) => options.common(),
-            Self::Download(options) => options.common(),
+            Self::List(options) => Some(options.common()),
+            Self::Download(options) => Some(options.common()),
+            Self::Sync(options) => Some(options.common()),
+            Self::Watch(options) => Some(options.common()),
+            Self::Find(options) => Some(options.common()),
+            Self::Du(options) => Some(options.common()),
+            Self::Tree(options) => Some(options.common()),
+            Self::Info(options) => Some(options.common()),
+            Self::Cat(options) => Some(options.common()),
+            Self::Verify(options) => Some(options.common()),
+            Self::Upload(options) => Some(options.common()),
+            Self::Libraries(options) => Some(options.common()),
+            Self::Auth(options) => Some(options.common()),
+            Self::Join(_) | Self::InstallHandler(_) | Self::Examples(_) | Self::Config => None,
+        }
+    }
+    /// What the command writes, if anything, which "--no-write" forbids
+    pub fn writes(&self) -> Option<&'static str> {
+        let what = match self {
+            Self::Download(_) | Self::Sync(_) | Self::Watch(_) => "downloaded files",
+            Self::Upload(_) => "uploaded files to the share",
+            Self::Join(_) => "joined files",
+            Self::InstallHandler(options) if !options.dry_run() => "the link handler",
+            Self::Auth(options) if !matches!(options.command(), AuthCommand::Status(_)) => {
+                "the keyring"
+            }
+            Self::Du(options) if options.export_ncdu().is_some_and(|f| f != Path::new("-")) => {
+                "the ncdu export"
+            }
+            command if command.common().is_some_and(|c| c.record().is_some()) => {
+                "the recorded exchanges"
+            }
+            _ => return None,
+        };
+        Some(what)
+    }
+    /// Download options for commands that download files
+    pub fn download_options(&self) -> Option<DownloadOptions> {
+        match self {
+            Self::List(_)
+            | Self::Find(_)
+            | Self::Du(_)
+            | Self::Tree(_)
+            | Self::Info(_)
+            | Self::Cat(_)
+            | Self::Verify(_)
+            | Self::Upload(_)
+            | Self::Libraries(_)
+            | Self::Auth(_)
+            | Self::Join(_)
+            | Self::InstallHandler(_)
+            | Self::Examples(_)
+            | Self::Config => None,
+            Self::Download(options) => Some(options.clone()),
+            Self::Sync(options) => Some(options.download_options()),
+            Self::Watch(options) => Some(options.download_options()),
         }
     }
 }
@@ -38,6 +194,31 @@ pub struct ListOptions {
     /// JSON output
     #[clap(long)]
     json: bool,
+    /// Show a QR code of the download URL (or the page of a directory) of
+    /// every entry, e.g. to open it on a phone
+    #[clap(long, conflicts_with = "json")]
+    qr: bool,
+    /// Print every entry with a template, e.g. '%p\t%s\t%m\n'
+    ///
+    /// Directives: %n name, %p path, %y type (f or d), %s size in bytes,
+    /// %S human-readable size, %m modification time (RFC 3339), %t
+    /// modification time (Unix seconds), %c content type, %u download URL, %v
+    /// page URL, %% a literal "%". Escapes: \n, \t, \r, \0 and \\. Fields
+    /// without a value, like the size of a directory, are left empty.
+    #[clap(long, value_name = "FORMAT", conflicts_with_all = ["json", "qr"])]
+    printf: Option<Template>,
+
+    /// List subdirectories recursively (DFS by default)
+    #[clap(
+        short, long,
+        require_equals = true, num_args = 0..=1, default_missing_value = "dfs",
+        default_value_t, value_enum,
+    )]
+    recursive: Recursive,
+
+    /// Descend at most N levels of subdirectories (implies "--recursive")
+    #[clap(long, value_name = "N")]
+    max_depth: Option<NonZeroUsize>,
 }
 
 impl ListOptions {
@@ -47,6 +228,21 @@ impl ListOptions {
     pub fn json(&self) -> bool {
         self.json
     }
+    pub fn qr(&self) -> bool {
+        self.qr
+    }
+    pub fn printf(&self) -> Option<&Template> {
+        self.printf.as_ref()
+    }
+    pub fn recursive(&self) -> Recursive {
+        match self.recursive {
+            Recursive::None if self.max_depth.is_some() => Recursive::Dfs,
+            order => order,
+        }
+    }
+    pub fn max_depth(&self) -> Option<usize> {
+        self.max_depth.map(NonZeroUsize::get)
+    }
 }
 
 #[derive(Debug, Clone, Args)]
@@ -58,51 +254,307 @@ pub struct CommonOptions {
     /// https://cloud.example/f/abc
     /// https://cloud.example/d/6e5297246c/?p=%2Fpath&mode=list
     /// https://cloud.example/d/6e5297246c/files/?p=%2Fpath%2Ffile.jpg
-    #[clap(verbatim_doc_comment)]
-    url: Url,
+    /// seaf-share://cloud.example/d/abc
+    #[clap(
+        verbatim_doc_comment, value_parser = handler::parse_url,
+        required_unless_present = "from_clipboard",
+    )]
+    url: Option<Url>,
+
+    /// Take the share URL from the clipboard
+    #[clap(long, conflicts_with = "url")]
+    from_clipboard: bool,
 
     /// Remote path to fetch, which can be absolute or relative to the share URL
     #[clap(short, long)]
     path: Option<PathBuf>,
+
+    /// Fill in file size, mtime and content type with HEAD requests
+    #[clap(long)]
+    enrich: bool,
+
+    /// Password of a password-protected share
+    #[clap(long, env = "SEAF_SHARE_PASSWORD", hide_env_values = true)]
+    password: Option<String>,
+
+    /// API token of an account, for links to its libraries ("/library/..."
+    /// and "/lib/.../file/...") and "seaf-share libraries" (by default, the
+    /// one "seaf-share auth login" stored for the server)
+    #[clap(long, env = "SEAF_SHARE_API_TOKEN", hide_env_values = true)]
+    api_token: Option<String>,
+
+    /// Proxy for all requests, e.g. http://proxy.example:3128 or
+    /// socks5://localhost:1080 (by default, the proxy environment variables
+    /// are used)
+    #[clap(long, value_name = "URL", overrides_with = "no_proxy")]
+    proxy: Option<String>,
+
+    /// Connect directly, ignoring the proxy environment variables and a
+    /// configured proxy
+    #[clap(long, overrides_with = "proxy")]
+    no_proxy: bool,
+
+    /// Trust the certificates in FILE (PEM) instead of the built-in roots,
+    /// e.g. for a server with a certificate of an internal CA
+    #[clap(long, visible_alias = "cacert", value_name = "FILE")]
+    ca_cert: Option<PathBuf>,
+
+    /// Do not verify the certificate of the server
+    #[clap(long)]
+    insecure: bool,
+
+    /// Authenticate with the client certificate (chain) in FILE (PEM), for
+    /// servers that require one
+    #[clap(long, value_name = "FILE")]
+    client_cert: Option<PathBuf>,
+
+    /// Private key of the client certificate (PEM), if not in the file of
+    /// "--client-cert"
+    #[clap(long, value_name = "FILE")]
+    client_key: Option<PathBuf>,
+
+    /// Add a header to the requests to the server, e.g. "X-Auth: secret"
+    /// for a proxy in front of it (can be repeated)
+    #[clap(long = "header", short = 'H', value_name = "NAME: VALUE")]
+    headers: Vec<Header>,
+
+    /// User-Agent header of every request, none if empty
+    #[clap(long, value_name = "AGENT")]
+    user_agent: Option<String>,
+
+    /// Maximum time in seconds for each API request (file transfers are
+    /// governed by "--timeout-per-gib" instead)
+    #[clap(long, value_name = "SECS")]
+    max_time: Option<u64>,
+
+    /// Maximum time in seconds for opening a connection, 0 for no limit
+    #[clap(long, value_name = "SECS", default_value_t = 30)]
+    connect_timeout: u64,
+
+    /// Maximum time in seconds to wait for data from the server before the
+    /// connection is taken as stalled (and the request retried), 0 for no
+    /// limit
+    #[clap(long, value_name = "SECS", default_value_t = 60)]
+    read_timeout: u64,
+
+    /// Number of times a request is retried after a timeout, a dropped
+    /// connection or a server error (interrupted transfers are continued)
+    #[clap(long, value_name = "N", default_value_t = 2)]
+    max_retries: u32,
+
+    /// Delay in seconds before the first retry, doubled for each further one
+    #[clap(long, value_name = "SECS", default_value_t = 1)]
+    retry_delay: u64,
+
+    /// Seed of the random parts of retry delays and uploads, so that a run
+    /// can be repeated exactly (with "--jobs 1")
+    #[clap(long, value_name = "N")]
+    seed: Option<u64>,
+
+    /// Record all HTTP exchanges to FILE, without cookies, credentials,
+    /// request bodies and file contents, but with the share token and
+    /// download URLs, which are not redacted
+    #[clap(long, value_name = "FILE")]
+    record: Option<PathBuf>,
+
+    /// Run against the exchanges recorded with "--record" instead of the
+    /// server
+    #[clap(long, value_name = "FILE", conflicts_with = "record")]
+    replay: Option<PathBuf>,
+
+    /// Keep API responses in ~/.cache/seaf-share/http and reuse them while
+    /// the server allows, revalidating them otherwise
+    #[clap(long, overrides_with = "no_http_cache")]
+    http_cache: bool,
+
+    /// Do not use the HTTP cache, e.g. when the configuration file turns it on
+    #[clap(long, overrides_with = "http_cache")]
+    no_http_cache: bool,
+
+    /// With "--http-cache", reuse cached API responses up to SECS after they
+    /// went stale, without asking the server
+    #[clap(long, value_name = "SECS")]
+    max_stale: Option<u64>,
+
+    /// Fail on listing entries that differ from the share link API in any
+    /// way, instead of warning and making do with them
+    #[clap(long)]
+    strict_json: bool,
+
+    /// How directories are listed: "legacy" reads the share pages of servers
+    /// older than Seafile 6.3, which "auto" falls back to when the API is
+    /// missing or blocked
+    #[clap(long, default_value_t, value_enum)]
+    api: seafile::Api,
 }
 
 impl CommonOptions {
-    pub fn url(&self) -> &Url {
-        &self.url
+    /// Turns the HTTP cache off, e.g. for "--no-write"
+    pub fn without_http_cache(mut self) -> Self {
+        self.http_cache = false;
+        self
+    }
+    /// Uses `password` and `api_token` where none is given, e.g. those
+    /// configured for the server
+    pub fn with_credentials(mut self, password: Option<&str>, api_token: Option<&str>) -> Self {
+        self.password = self.password.or(password.map(str::to_string));
+        self.api_token = self.api_token.or(api_token.map(str::to_string));
+        self
+    }
+    /// Share URL given on the command line, none with "--from-clipboard"
+    pub fn url(&self) -> Option<&Url> {
+        self.url.as_ref()
     }
     pub fn path(&self) -> Option<&Path> {
         self.path.as_ref().map(|p| p.as_ref())
     }
+    pub fn enrich(&self) -> bool {
+        self.enrich
+    }
+    pub fn password(&self) -> Option<&str> {
+        self.password.as_deref()
+    }
+    pub fn api_token(&self) -> Option<&str> {
+        self.api_token.as_deref()
+    }
+    /// Proxy given with "--proxy" (or configured), none with "--no-proxy"
+    pub fn proxy(&self) -> Option<&str> {
+        self.proxy.as_deref().filter(|_| !self.no_proxy)
+    }
+    pub fn no_proxy(&self) -> bool {
+        self.no_proxy
+    }
+    pub fn ca_cert(&self) -> Option<&Path> {
+        self.ca_cert.as_deref()
+    }
+    pub fn insecure(&self) -> bool {
+        self.insecure
+    }
+    pub fn client_cert(&self) -> Option<&Path> {
+        self.client_cert.as_deref()
+    }
+    pub fn client_key(&self) -> Option<&Path> {
+        self.client_key.as_deref()
+    }
+    pub fn headers(&self) -> &[Header] {
+        &self.headers
+    }
+    pub fn user_agent(&self) -> Option<&str> {
+        self.user_agent.as_deref()
+    }
+    pub fn max_time(&self) -> Option<Duration> {
+        self.max_time.map(Duration::from_secs)
+    }
+    pub fn connect_timeout(&self) -> Option<Duration> {
+        (self.connect_timeout > 0).then(|| Duration::from_secs(self.connect_timeout))
+    }
+    pub fn read_timeout(&self) -> Option<Duration> {
+        (self.read_timeout > 0).then(|| Duration::from_secs(self.read_timeout))
+    }
+    pub fn max_retries(&self) -> u32 {
+        self.max_retries
+    }
+    pub fn retry_delay(&self) -> Duration {
+        Duration::from_secs(self.retry_delay)
+    }
+    pub fn seed(&self) -> Option<u64> {
+        self.seed
+    }
+    pub fn record(&self) -> Option<&Path> {
+        self.record.as_deref()
+    }
+    pub fn replay(&self) -> Option<&Path> {
+        self.replay.as_deref()
+    }
+    pub fn http_cache(&self) -> bool {
+        self.http_cache && !self.no_http_cache
+    }
+    pub fn max_stale(&self) -> Option<Duration> {
+        self.max_stale.map(Duration::from_secs)
+    }
+    pub fn strict_json(&self) -> bool {
+        self.strict_json
+    }
+    pub fn api(&self) -> seafile::Api {
+        self.api
+    }
 }
 
 #[derive(Debug, Clone, Args)]
-pub struct DownloadOptions {
+pub struct TransferOptions {
     #[clap(flatten)]
     common: CommonOptions,
 
-    /// Dry run (output download link only)
+    /// Dry run (output download link only, or with "--json" what would be
+    /// done with each file)
     #[clap(long)]
     dry_run: bool,
 
-    /// Output destination
+    /// Output destination, in which "{token}", "{repo_id}", "{repo_name}"
+    /// and "{date}" (today's, as YYYY-MM-DD) are replaced with those of the
+    /// share, e.g. "~/shares/{repo_name}-{date}"
     #[clap(short, long, default_value = "./")]
     output: PathBuf,
 
-    /// Archive mode, which sets "mtime" (modification time) shown in remote
-    #[clap(short, long)]
-    archive: bool,
+    /// Remote directory whose contents are placed directly in the output
+    /// destination (the downloaded directory by default)
+    #[clap(long, value_name = "REMOTE_DIR", conflicts_with = "preserve_full_path")]
+    relative_to: Option<PathBuf>,
 
-    /// Action to be taken if a file already exists
-    #[clap(short, long, default_value_t, value_enum)]
-    conflict: ConflictAction,
+    /// Recreate the full remote path of every entry in the output destination
+    #[clap(long)]
+    preserve_full_path: bool,
+
+    /// Delete local files in downloaded directories that no longer exist in
+    /// the share (files excluded from the transfer are kept)
+    #[clap(long)]
+    delete: bool,
+
+    /// Update a copy of the output destination in a hidden directory next to
+    /// it, and swap it into place only once every file arrived, so that
+    /// readers never see the destination half updated (not for the working
+    /// directory)
+    #[clap(long, conflicts_with = "route")]
+    stage_then_swap: bool,
+
+    /// File system of the output destination, which limits names and file
+    /// sizes (detected on Linux and macOS)
+    #[clap(long, value_name = "TYPE", value_enum)]
+    filesystem: Option<FileSystem>,
+
+    /// What to do with files the file system of the output destination cannot
+    /// hold: "map" replaces the characters it does not allow in names with
+    /// "_", adds a suffix like " (2)" to names it does not tell apart from
+    /// another and splits files over 4 GiB on FAT32 into parts like
+    /// "--split-size", "skip" leaves them out
+    #[clap(long, value_name = "ACTION", value_enum, default_value_t)]
+    unsupported: Unsupported,
+
+    /// Write files larger than SIZE (e.g. 700M, or 4000M for FAT32, which
+    /// holds files up to 4 GiB less a byte) as parts NAME.000, NAME.001... of
+    /// that size, with a manifest NAME.parts.json that "seaf-share join"
+    /// reads to put them together again
+    #[clap(long, value_name = "SIZE", value_parser = parse_split_size)]
+    split_size: Option<ByteSize>,
+
+    /// Write the files a condition selects below DIR instead of the output
+    /// destination: ">SIZE" or "<SIZE" for files larger or smaller than SIZE,
+    /// or a GLOB pattern of remote paths like "*.jpg". The first matching
+    /// route is taken, e.g. to put large files on another disk.
+    #[clap(long, value_name = "CONDITION=DIR")]
+    route: Vec<Route>,
 
     /// Include remote paths only (GLOB patterns, see examples with "--help")
     ///
+    /// Files must match one of the patterns to be downloaded; directories are
+    /// searched for matching files either way.
+    ///
     /// Examples:
     /// /xyz/*
     /// /ab?/**
     ///
-    /// Check https://docs.rs/glob/latest/glob/struct.Pattern.html for details.
+    /// "*" and "?" stay within a directory, "**" spans any. Check
+    /// https://docs.rs/glob/latest/glob/struct.Pattern.html for details.
     #[clap(long)]
     include: Vec<glob::Pattern>,
 
@@ -110,6 +562,22 @@ pub struct DownloadOptions {
     #[clap(long)]
     exclude: Vec<glob::Pattern>,
 
+    /// Include ("+ GLOB") or exclude ("- GLOB") remote paths by the first
+    /// matching rule, see examples with "--help"
+    ///
+    /// Rules apply in order: "--filter" rules, "--filter-from" files, then
+    /// "--exclude" and "--include". A trailing "/" makes a rule match
+    /// directories only, and excluded directories are not searched.
+    ///
+    /// Examples (everything under /raw except CSV files):
+    /// --filter '+ /raw/**/' --filter '+ /raw/**/*.csv' --filter '- /raw/**'
+    #[clap(long, value_name = "RULE", allow_hyphen_values = true)]
+    filter: Vec<Rule>,
+
+    /// Read "--filter" rules from a file, one per line ("#" starts a comment)
+    #[clap(long, value_name = "FILE", value_parser = filter::read_rules)]
+    filter_from: Vec<RuleFile>,
+
     /// Recursive download (DFS by default)
     #[clap(
         short, long,
@@ -117,9 +585,110 @@ pub struct DownloadOptions {
         default_value_t, value_enum,
     )]
     recursive: Recursive,
+
+    /// Time limit for file transfers in seconds per GiB, added to "--max-time"
+    /// (transfers are not time-limited by default)
+    #[clap(long, value_name = "SECS")]
+    timeout_per_gib: Option<u64>,
+
+    /// Maximum number of pending remote entries kept in memory, the rest is
+    /// spilled to a temporary file
+    #[clap(long, value_name = "N", default_value_t = 100_000)]
+    queue_limit: usize,
+
+    /// List at most this many directories for the confirmation estimate (and
+    /// that of "--stats-interval") and extrapolate the rest
+    #[clap(long, value_name = "N")]
+    prescan_dirs: Option<usize>,
+
+    /// Pause downloading when more than this percentage of recent transfers
+    /// failed, until the server responds again (the run stops if it does not
+    /// within about 13 minutes)
+    #[clap(long, default_value_t = 50, value_parser = clap::value_parser!(u8).range(0..=100))]
+    failure_threshold: u8,
+
+    /// Number of recent transfers considered by "--failure-threshold"
+    /// (0 disables pausing)
+    #[clap(long, default_value_t = 20)]
+    failure_window: usize,
+
+    /// Number of files downloaded concurrently
+    #[clap(short, long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    jobs: u16,
+
+    /// Limit the bandwidth of all transfers together to SIZE per second
+    /// (e.g. 500K, 2M)
+    #[clap(long, value_name = "SIZE")]
+    limit_rate: Option<ByteSize>,
+
+    /// Print a line per file instead of the live progress display, which is
+    /// shown when stderr is a terminal and can be controlled with keys
+    /// (p: pause/resume, 1-9: skip a file, -/+: lower/raise the bandwidth
+    /// limit, u: remove the limit)
+    #[clap(long)]
+    no_progress: bool,
+
+    /// Print a status line (files done, bytes, rate and time left) to stderr
+    /// every SECS seconds while the live progress display is not shown, e.g.
+    /// for the log of a cron job; the total is estimated beforehand (see
+    /// "--prescan-dirs")
+    #[clap(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+    stats_interval: Option<u64>,
+
+    /// Do not ask for confirmation before large transfers
+    #[clap(short, long)]
+    yes: bool,
+
+    /// Ask for confirmation if the transfer exceeds this size (e.g. 500M, 10G)
+    #[clap(long, default_value = "10G")]
+    confirm_above: ByteSize,
+
+    /// Run PROGRAM with the path of every downloaded file as its argument
+    #[clap(long, value_name = "PROGRAM")]
+    exec: Option<PathBuf>,
+
+    /// Write a manifest of file checksums to the output directory
+    #[clap(long, value_name = "ALGORITHM", value_enum)]
+    write_checksums: Option<checksum::Algorithm>,
+
+    /// Check files against a manifest written by sha256sum or md5sum, removing
+    /// those that do not match
+    #[clap(long, value_name = "FILE", value_parser = checksum::read_manifest)]
+    checksum_file: Option<checksum::Manifest>,
+
+    /// Keep completed files in FILE and listings in FILE.listings, so that an
+    /// interrupted run started again with it continues where it stopped
+    /// (listings are kept as each directory completes, also by a dry run)
+    #[clap(long, value_name = "FILE")]
+    session: Option<PathBuf>,
+
+    /// Also write the totals printed at the end of the run to FILE ("-" for
+    /// stdout) as JSON
+    #[clap(long, value_name = "FILE")]
+    summary_json: Option<PathBuf>,
+
+    /// Ping URL/start when the run starts, then URL if it succeeds or
+    /// URL/fail if it does not, with its totals as JSON, for a monitor like
+    /// healthchecks.io
+    #[clap(long, value_name = "URL")]
+    healthcheck_url: Option<Url>,
+
+    /// Stop at the first file that fails, aborting the transfers in progress
+    #[clap(long, overrides_with = "keep_going")]
+    fail_fast: bool,
+
+    /// Go on with the other files when one fails (the default)
+    #[clap(long, overrides_with = "fail_fast")]
+    keep_going: bool,
+
+    /// Print a JSON object per file (path, action, bytes, duration_secs and
+    /// error) instead of a line of text; with "--dry-run", the plan for it
+    /// (path, dest, size, url and action)
+    #[clap(long)]
+    json: bool,
 }
 
-impl DownloadOptions {
+impl TransferOptions {
     pub fn common(&self) -> &CommonOptions {
         &self.common
     }
@@ -129,49 +698,686 @@ impl DownloadOptions {
     pub fn output(&self) -> &Path {
         self.output.as_ref()
     }
+    pub fn relative_to(&self) -> Option<&Path> {
+        self.relative_to.as_deref()
+    }
+    pub fn preserve_full_path(&self) -> bool {
+        self.preserve_full_path
+    }
+    pub fn delete(&self) -> bool {
+        self.delete
+    }
+    pub fn stage_then_swap(&self) -> bool {
+        self.stage_then_swap && !self.dry_run
+    }
+    pub fn filesystem(&self) -> Option<FileSystem> {
+        self.filesystem
+    }
+    pub fn unsupported(&self) -> Unsupported {
+        self.unsupported
+    }
+    pub fn split_size(&self) -> Option<u64> {
+        self.split_size.map(|size| size.bytes())
+    }
+    /// Directory `entry` is written below: that of the first "--route" that
+    /// selects it, or the output destination
+    pub fn root(&self, entry: &DirEntry) -> &Path {
+        self.route
+            .iter()
+            .find(|route| route.selects(entry))
+            .map_or(self.output(), |route| route.dir.as_path())
+    }
+    /// Rules of "--filter", "--filter-from", "--exclude" and "--include",
+    /// in this order
+    pub fn filter(&self) -> Filter {
+        let rules = self
+            .filter
+            .iter()
+            .chain(self.filter_from.iter().flat_map(|file| &file.0))
+            .cloned()
+            .chain(self.exclude.iter().cloned().map(Rule::exclude))
+            .chain(self.include.iter().cloned().map(Rule::include))
+            .collect();
+        Filter::new(rules, self.include.is_empty())
+    }
+    pub fn recursive(&self) -> Recursive {
+        self.recursive
+    }
+    pub fn timeout_per_gib(&self) -> Option<Duration> {
+        self.timeout_per_gib.map(Duration::from_secs)
+    }
+    pub fn queue_limit(&self) -> usize {
+        self.queue_limit
+    }
+    pub fn prescan_dirs(&self) -> Option<usize> {
+        self.prescan_dirs
+    }
+    pub fn failure_threshold(&self) -> u8 {
+        self.failure_threshold
+    }
+    pub fn failure_window(&self) -> usize {
+        self.failure_window
+    }
+    pub fn jobs(&self) -> usize {
+        self.jobs.into()
+    }
+    pub fn limit_rate(&self) -> Option<u64> {
+        self.limit_rate.map(|size| size.bytes())
+    }
+    pub fn no_progress(&self) -> bool {
+        self.no_progress
+    }
+    pub fn stats_interval(&self) -> Option<Duration> {
+        self.stats_interval.map(Duration::from_secs)
+    }
+    pub fn yes(&self) -> bool {
+        self.yes
+    }
+    pub fn confirm_above(&self) -> u64 {
+        self.confirm_above.bytes()
+    }
+    pub fn exec(&self) -> Option<&Path> {
+        self.exec.as_deref()
+    }
+    pub fn write_checksums(&self) -> Option<checksum::Algorithm> {
+        self.write_checksums
+    }
+    pub fn checksum_file(&self) -> Option<&checksum::Manifest> {
+        self.checksum_file.as_ref()
+    }
+    pub fn session(&self) -> Option<&Path> {
+        self.session.as_deref()
+    }
+    pub fn summary_json(&self) -> Option<&Path> {
+        self.summary_json.as_deref()
+    }
+    pub fn healthcheck_url(&self) -> Option<&Url> {
+        self.healthcheck_url.as_ref()
+    }
+    pub fn fail_fast(&self) -> bool {
+        self.fail_fast && !self.keep_going
+    }
+    pub fn json(&self) -> bool {
+        self.json
+    }
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct DownloadOptions {
+    #[clap(flatten)]
+    transfer: TransferOptions,
+
+    /// Archive mode, which sets "mtime" (modification time) shown in remote
+    #[clap(short, long, overrides_with = "no_archive")]
+    archive: bool,
+
+    /// Turn off archive mode, e.g. when the configuration file turns it on
+    #[clap(long, overrides_with = "archive")]
+    no_archive: bool,
+
+    /// Action to be taken if a file already exists
+    #[clap(short, long, default_value_t, value_enum)]
+    conflict: ConflictAction,
+
+    /// Print an rsync-style itemized list of changes
+    #[clap(skip)]
+    itemize: bool,
+}
+
+impl DownloadOptions {
     pub fn archive(&self) -> bool {
-        self.archive
+        self.archive && !self.no_archive
     }
     pub fn on_conflict(&self) -> ConflictAction {
         self.conflict
     }
-    pub fn includes(&self) -> &[glob::Pattern] {
-        self.include.as_slice()
+    pub fn itemize(&self) -> bool {
+        self.itemize
     }
-    pub fn excludes(&self) -> &[glob::Pattern] {
-        self.exclude.as_slice()
+    /// Replaces the output destination, e.g. with its tokens resolved
+    pub fn with_output(mut self, output: PathBuf) -> Self {
+        self.transfer.output = output;
+        self
     }
-    pub fn recursive(&self) -> Recursive {
-        self.recursive
+}
+
+impl std::ops::Deref for DownloadOptions {
+    type Target = TransferOptions;
+
+    fn deref(&self) -> &TransferOptions {
+        &self.transfer
+    }
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct SyncOptions {
+    #[clap(flatten)]
+    transfer: TransferOptions,
+}
+
+impl SyncOptions {
+    pub fn common(&self) -> &CommonOptions {
+        self.transfer.common()
+    }
+    /// A sync is a download that replaces changed files, keeps remote
+    /// modification times (so the next sync can compare them) and itemizes
+    /// its changes.
+    pub fn download_options(&self) -> DownloadOptions {
+        DownloadOptions {
+            transfer: self.transfer.clone(),
+            archive: true,
+            no_archive: false,
+            conflict: ConflictAction::Update,
+            itemize: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct WatchOptions {
+    #[clap(flatten)]
+    sync: SyncOptions,
+
+    /// Check the share for changes every SECS seconds
+    #[clap(
+        long, value_name = "SECS", default_value_t = 300,
+        value_parser = clap::value_parser!(u64).range(1..),
+    )]
+    interval: u64,
+
+    /// Keep at most N changed files queued while the network or the server
+    /// is down; beyond that the oldest are dropped, to be found again by the
+    /// first check after the outage
+    #[clap(long, value_name = "N", default_value_t = 10_000)]
+    max_queued: usize,
+
+    /// Write events (offline, online, dropped and checked) to FILE ("-" for
+    /// stdout) as JSON lines
+    #[clap(long, value_name = "FILE")]
+    events: Option<PathBuf>,
+}
+
+impl WatchOptions {
+    pub fn common(&self) -> &CommonOptions {
+        self.sync.common()
+    }
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.interval)
+    }
+    pub fn max_queued(&self) -> usize {
+        self.max_queued
+    }
+    pub fn events(&self) -> Option<&Path> {
+        self.events.as_deref()
+    }
+    /// Replaces the output destination, e.g. with its tokens resolved
+    pub fn with_output(mut self, output: PathBuf) -> Self {
+        self.sync.transfer.output = output;
+        self
+    }
+    /// Every check is a sync
+    pub fn download_options(&self) -> DownloadOptions {
+        self.sync.download_options()
+    }
+}
+
+#[derive(Debug, Clone, Args)]
+#[clap(group(ArgGroup::new("query").args(["name", "duplicates"]).multiple(true).required(true)))]
+pub struct FindOptions {
+    #[clap(flatten)]
+    common: CommonOptions,
+
+    /// Find files and directories whose name matches GLOB, with the search of
+    /// the server where it has one (Seafile Pro)
+    #[clap(long, value_name = "GLOB")]
+    name: Option<glob::Pattern>,
+
+    /// Find names by listing every directory, even where the server could
+    /// search the share
+    #[clap(long, requires = "name")]
+    no_server_search: bool,
+
+    /// Report sets of duplicate files and the bytes they waste (among the
+    /// files found by "--name", if given)
+    #[clap(long)]
+    duplicates: bool,
+
+    /// Compare sizes only, without a HEAD request per candidate to compare
+    /// file IDs
+    #[clap(long)]
+    size_only: bool,
+
+    /// JSON output
+    #[clap(long)]
+    json: bool,
+}
+
+impl FindOptions {
+    pub fn common(&self) -> &CommonOptions {
+        &self.common
+    }
+    pub fn name(&self) -> Option<&glob::Pattern> {
+        self.name.as_ref()
+    }
+    pub fn server_search(&self) -> bool {
+        !self.no_server_search
+    }
+    pub fn duplicates(&self) -> bool {
+        self.duplicates
+    }
+    pub fn size_only(&self) -> bool {
+        self.size_only
+    }
+    pub fn json(&self) -> bool {
+        self.json
+    }
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct DuOptions {
+    #[clap(flatten)]
+    common: CommonOptions,
+
+    /// Show only the N largest files and directories
+    #[clap(long, value_name = "N")]
+    top: Option<usize>,
+
+    /// Break the usage down by file extension
+    #[clap(long)]
+    extensions: bool,
+
+    /// Print the report as JSON
+    #[clap(long)]
+    json: bool,
+
+    /// Write the scan in ncdu's export format to FILE ("-" for stdout) for
+    /// browsing with "ncdu -f FILE"
+    #[clap(long, value_name = "FILE", conflicts_with_all = ["top", "extensions", "json"])]
+    export_ncdu: Option<PathBuf>,
+}
+
+impl DuOptions {
+    pub fn common(&self) -> &CommonOptions {
+        &self.common
+    }
+    pub fn top(&self) -> Option<usize> {
+        self.top
+    }
+    pub fn extensions(&self) -> bool {
+        self.extensions
+    }
+    pub fn json(&self) -> bool {
+        self.json
+    }
+    pub fn export_ncdu(&self) -> Option<&Path> {
+        self.export_ncdu.as_deref()
+    }
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct TreeOptions {
+    #[clap(flatten)]
+    common: CommonOptions,
+
+    /// Show at most N levels (sizes still count everything below)
+    #[clap(short = 'L', long, value_name = "N")]
+    level: Option<usize>,
+}
+
+impl TreeOptions {
+    pub fn common(&self) -> &CommonOptions {
+        &self.common
+    }
+    pub fn level(&self) -> Option<usize> {
+        self.level
+    }
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct InfoOptions {
+    #[clap(flatten)]
+    common: CommonOptions,
+    /// JSON output
+    #[clap(long)]
+    json: bool,
+}
+
+impl InfoOptions {
+    pub fn common(&self) -> &CommonOptions {
+        &self.common
+    }
+    pub fn json(&self) -> bool {
+        self.json
+    }
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct CatOptions {
+    #[clap(flatten)]
+    common: CommonOptions,
+
+    /// Limit the bandwidth to SIZE per second (e.g. 500K, 2M)
+    #[clap(long, value_name = "SIZE")]
+    limit_rate: Option<ByteSize>,
+}
+
+impl CatOptions {
+    pub fn common(&self) -> &CommonOptions {
+        &self.common
+    }
+    pub fn limit_rate(&self) -> Option<u64> {
+        self.limit_rate.map(|size| size.bytes())
+    }
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct VerifyOptions {
+    #[clap(flatten)]
+    common: CommonOptions,
+
+    /// Local copy to compare, with the tokens of "download --output"
+    #[clap(short, long, default_value = "./")]
+    output: PathBuf,
+
+    /// Compare sizes only, e.g. for copies downloaded without "--archive"
+    #[clap(long)]
+    size_only: bool,
+
+    /// Print the differences as JSON
+    #[clap(long)]
+    json: bool,
+}
+
+impl VerifyOptions {
+    pub fn common(&self) -> &CommonOptions {
+        &self.common
+    }
+    pub fn output(&self) -> &Path {
+        self.output.as_ref()
+    }
+    pub fn size_only(&self) -> bool {
+        self.size_only
+    }
+    pub fn json(&self) -> bool {
+        self.json
+    }
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct UploadOptions {
+    #[clap(flatten)]
+    common: CommonOptions,
+
+    /// Local files and directories to upload ("--path" selects the remote
+    /// directory they are uploaded to)
+    files: Vec<PathBuf>,
+}
+
+impl UploadOptions {
+    pub fn common(&self) -> &CommonOptions {
+        &self.common
+    }
+    pub fn files(&self) -> &[PathBuf] {
+        self.files.as_slice()
     }
 }
 
-#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, ValueEnum)]
-pub enum ConflictAction {
-    /// Skip if a file exists
-    #[default]
-    Skip,
+#[derive(Debug, Clone, Args)]
+pub struct JoinOptions {
+    /// Files written in parts, by their name (e.g. "video.mp4") or the name of
+    /// their manifest ("video.mp4.parts.json")
+    #[clap(required = true, value_name = "FILE")]
+    files: Vec<PathBuf>,
 
-    /// Verify by downloading remote chunks in memory, overwrite if the checksum
-    /// is not correct.
-    Check,
+    /// Directory to write the joined files to (that of their parts by
+    /// default)
+    #[clap(short = 'o', long = "output", value_name = "DIR")]
+    into: Option<PathBuf>,
 
-    /// Continue the download by sending partial requests ("Range" header).
-    Continue,
+    /// Delete the parts and their manifest once their file is joined
+    #[clap(long)]
+    remove_parts: bool,
+}
 
-    /// always overwrite the destination
-    Overwrite,
+impl JoinOptions {
+    /// Manifests of the files to join
+    pub fn manifests(&self) -> impl Iterator<Item = PathBuf> + '_ {
+        self.files.iter().map(|file| {
+            if file.to_string_lossy().ends_with(".parts.json") {
+                file.clone()
+            } else {
+                volume::manifest_path(file)
+            }
+        })
+    }
+    pub fn into(&self) -> Option<&Path> {
+        self.into.as_deref()
+    }
+    pub fn remove_parts(&self) -> bool {
+        self.remove_parts
+    }
 }
 
-#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, ValueEnum)]
-pub enum Recursive {
-    /// Do not look into subdirectory entries
-    #[default]
-    None,
+#[derive(Debug, Clone, Args)]
+pub struct LibrariesOptions {
+    #[clap(flatten)]
+    common: CommonOptions,
+    /// JSON output
+    #[clap(long)]
+    json: bool,
+}
+
+impl LibrariesOptions {
+    pub fn common(&self) -> &CommonOptions {
+        &self.common
+    }
+    pub fn json(&self) -> bool {
+        self.json
+    }
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct AuthOptions {
+    #[clap(subcommand)]
+    command: AuthCommand,
+}
+
+impl AuthOptions {
+    pub fn common(&self) -> &CommonOptions {
+        match &self.command {
+            AuthCommand::Login(options) => &options.common,
+            AuthCommand::Logout(common) | AuthCommand::Status(common) => common,
+        }
+    }
+    pub fn command(&self) -> &AuthCommand {
+        &self.command
+    }
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum AuthCommand {
+    /// Obtain an API token with the username and password of the account
+    /// and store it
+    Login(LoginOptions),
+    /// Remove the stored API token of the server
+    Logout(CommonOptions),
+    /// Show the account of the stored API token, failing if there is none or
+    /// the server does not accept it
+    Status(CommonOptions),
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct LoginOptions {
+    #[clap(flatten)]
+    common: CommonOptions,
 
-    /// Traverse subdirectories by DFS
-    Dfs,
+    /// Username (usually the email address) of the account, asked for if
+    /// not given; the password is always asked for
+    #[clap(short, long, env = "SEAF_SHARE_USERNAME")]
+    username: Option<String>,
 
-    /// Traverse subdirectories by BFS
-    Bfs,
+    /// Code of the second factor, for accounts with two-factor
+    /// authentication (asked for when needed in a terminal)
+    #[clap(long, value_name = "CODE")]
+    otp: Option<String>,
+}
+
+impl LoginOptions {
+    pub fn username(&self) -> Option<&str> {
+        self.username.as_deref()
+    }
+    pub fn otp(&self) -> Option<&str> {
+        self.otp.as_deref()
+    }
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct HandlerOptions {
+    /// Output destination of opened links
+    #[clap(short, long, default_value = "./")]
+    output: PathBuf,
+
+    /// Print the registration instead of installing it
+    #[clap(long)]
+    dry_run: bool,
+}
+
+impl HandlerOptions {
+    pub fn output(&self) -> &Path {
+        self.output.as_ref()
+    }
+    pub fn dry_run(&self) -> bool {
+        self.dry_run
+    }
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct ExamplesOptions {
+    /// Command to show examples of (all by default)
+    #[clap(value_parser = PossibleValuesParser::new(examples::topics()))]
+    command: Option<String>,
+}
+
+impl ExamplesOptions {
+    pub fn command(&self) -> Option<&str> {
+        self.command.as_deref()
+    }
+}
+
+/// Byte size with optional binary unit suffix, e.g. `512K`, `1.5G` or `10GiB`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ByteSize(u64);
+
+impl ByteSize {
+    pub fn bytes(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Header given as "Name: value"
+#[derive(Debug, Clone)]
+pub struct Header {
+    pub name: HeaderName,
+    pub value: HeaderValue,
+}
+
+impl std::str::FromStr for Header {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = s
+            .split_once(':')
+            .ok_or_else(|| format!("expected \"Name: value\", got \"{}\"", s))?;
+        Ok(Self {
+            name: name
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid header name \"{}\"", name.trim()))?,
+            value: value
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid value of header {}", name.trim()))?,
+        })
+    }
+}
+
+/// Directory of "--route" for the files a condition selects
+#[derive(Debug, Clone)]
+pub struct Route {
+    condition: RouteCondition,
+    dir: PathBuf,
+}
+
+#[derive(Debug, Clone)]
+enum RouteCondition {
+    Larger(u64),
+    Smaller(u64),
+    Pattern(glob::Pattern),
+}
+
+impl Route {
+    /// Whether `entry` is a file the route takes
+    fn selects(&self, entry: &DirEntry) -> bool {
+        entry.is_file()
+            && match &self.condition {
+                RouteCondition::Larger(size) => entry.size().is_some_and(|s| s > *size),
+                RouteCondition::Smaller(size) => entry.size().is_some_and(|s| s < *size),
+                RouteCondition::Pattern(pattern) => pattern.matches_path(entry.path()),
+            }
+    }
+}
+
+impl std::str::FromStr for Route {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (condition, dir) = s
+            .split_once('=')
+            .filter(|(condition, dir)| !condition.is_empty() && !dir.is_empty())
+            .ok_or_else(|| format!("expected \"CONDITION=DIR\", got \"{}\"", s))?;
+        let condition = if let Some(size) = condition.strip_prefix('>') {
+            RouteCondition::Larger(size.parse::<ByteSize>()?.bytes())
+        } else if let Some(size) = condition.strip_prefix('<') {
+            RouteCondition::Smaller(size.parse::<ByteSize>()?.bytes())
+        } else {
+            RouteCondition::Pattern(glob::Pattern::new(condition).map_err(|e| e.to_string())?)
+        };
+        Ok(Self {
+            condition,
+            dir: PathBuf::from(dir),
+        })
+    }
+}
+
+/// Size of the parts of "--split-size", which must have a byte
+fn parse_split_size(s: &str) -> Result<ByteSize, String> {
+    let size: ByteSize = s.parse()?;
+    if size.bytes() == 0 {
+        return Err("parts must not be empty".to_string());
+    }
+    Ok(size)
+}
+
+impl std::str::FromStr for ByteSize {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let split = s
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(s.len());
+        let (number, unit) = s.split_at(split);
+        let number: f64 = number
+            .parse()
+            .map_err(|_| format!("invalid size \"{}\"", s))?;
+        let shift = match unit.trim().to_ascii_uppercase().as_str() {
+            "" | "B" => 0,
+            "K" | "KB" | "KIB" => 10,
+            "M" | "MB" | "MIB" => 20,
+            "G" | "GB" | "GIB" => 30,
+            "T" | "TB" | "TIB" => 40,
+            _ => return Err(format!("unknown size unit \"{}\"", unit)),
+        };
+        Ok(Self((number * (1u64 << shift) as f64) as u64))
+    }
 }
@@ -0,0 +1,51 @@
+//! Reading share links from the system clipboard through the platform's
+//! clipboard tools.
+
+use std::process::Command;
+
+use anyhow::Context;
+use url::Url;
+
+use crate::handler;
+
+/// Commands printing the clipboard contents, tried in order
+#[cfg(target_os = "macos")]
+const PASTE: &[&[&str]] = &[&["pbpaste"]];
+#[cfg(windows)]
+const PASTE: &[&[&str]] = &[&["powershell", "-NoProfile", "-Command", "Get-Clipboard"]];
+#[cfg(not(any(target_os = "macos", windows)))]
+const PASTE: &[&[&str]] = &[
+    &["wl-paste", "--no-newline"],
+    &["xclip", "-selection", "clipboard", "-o"],
+    &["xsel", "--clipboard", "--output"],
+];
+
+pub fn read() -> anyhow::Result<String> {
+    for command in PASTE {
+        let output = match Command::new(command[0]).args(&command[1..]).output() {
+            Ok(output) => output,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e).with_context(|| format!("cannot run {}", command[0])),
+        };
+        // e.g. wl-paste without a Wayland session, fall back to X11 tools
+        if !output.status.success() {
+            continue;
+        }
+        return String::from_utf8(output.stdout).with_context(|| "clipboard does not hold text");
+    }
+    anyhow::bail!(
+        "cannot read the clipboard (tried {})",
+        PASTE.iter().map(|c| c[0]).collect::<Vec<_>>().join(", ")
+    );
+}
+
+/// Finds the first URL in the clipboard, which may be a whole chat message
+/// with the link somewhere in it.
+pub fn share_url() -> anyhow::Result<Url> {
+    let text = read()?;
+    text.split_whitespace()
+        .map(|word| word.trim_matches(|c| matches!(c, '<' | '>' | '"' | '\'' | '(' | ')')))
+        .filter_map(|word| handler::parse_url(word).ok())
+        .find(|url| matches!(url.scheme(), "http" | "https"))
+        .with_context(|| "clipboard does not hold a URL")
+}
@@ -0,0 +1,46 @@
+//! Command handlers. They take the client, the downloader and the output to
+//! write to, rather than reading the command line themselves.
+
+use std::path::Path;
+
+use seaf_share_core::{redact::Redacted, seafile, DirEntry, ShareLink};
+
+mod auth;
+mod download;
+mod info;
+mod libraries;
+mod list;
+mod watch;
+
+pub use auth::{login, logout, status};
+pub use download::download;
+pub use info::info;
+pub use libraries::libraries;
+pub use list::list;
+pub use watch::watch;
+
+/// Number of listed entries buffered ahead of the downloads
+pub const STREAM_CAPACITY: usize = 1024;
+
+/// Entries a download starts from: the linked file itself, or the contents of
+/// the linked directory.
+pub fn download_roots(
+    client: &seafile::Client,
+    link: &ShareLink,
+    path: Option<&Path>,
+) -> anyhow::Result<Vec<DirEntry>> {
+    if link.is_dir() {
+        return Ok(client.entries(link.token(), path)?);
+    }
+    Ok(vec![client.file(link, None)?])
+}
+
+pub fn enrich(client: &seafile::Client, entry: &mut DirEntry) {
+    if let Err(e) = client.enrich(entry) {
+        eprintln!(
+            "could not fetch metadata of {}: {}",
+            entry.path().to_string_lossy(),
+            Redacted(e)
+        );
+    }
+}
@@ -0,0 +1,64 @@
+//! The auth commands, which keep the API token of a server in the keyring
+
+use std::io::{IsTerminal, Write};
+
+use anyhow::Context;
+use url::Url;
+
+use seaf_share_core::{redact, seafile};
+
+use crate::{cli::LoginOptions, credentials};
+
+fn server(url: &Url) -> String {
+    url.origin().ascii_serialization()
+}
+
+/// Logs in to the server of `url` with the username and password asked for,
+/// and stores the API token obtained.
+pub fn login(client: &seafile::Client, url: &Url, options: &LoginOptions) -> anyhow::Result<()> {
+    let username = match options.username() {
+        Some(username) => username.to_string(),
+        None => credentials::prompt("Username: ", false)?,
+    };
+    let password = credentials::prompt("Password: ", true)?;
+    redact::secret(&password);
+    let token = match client.login(&username, &password, options.otp()) {
+        Err(seaf_share_core::Error::Share(seafile::Error::OtpRequired))
+            if std::io::stdin().is_terminal() =>
+        {
+            let otp = credentials::prompt("Two-factor authentication code: ", false)?;
+            client.login(&username, &password, Some(&otp))?
+        }
+        result => result?,
+    };
+    credentials::store(url, &token)?;
+    eprintln!("Logged in to {} as {}", server(url), username);
+    Ok(())
+}
+
+/// Removes the stored API token of the server of `url`.
+pub fn logout(url: &Url) -> anyhow::Result<()> {
+    if credentials::remove(url)? {
+        eprintln!("Logged out of {}", server(url));
+    } else {
+        eprintln!("Not logged in to {}", server(url));
+    }
+    Ok(())
+}
+
+/// Writes the account of the API token stored for the server of `url` to
+/// `out`, asking the client that `connect` makes with the token.
+pub fn status(
+    url: &Url,
+    connect: impl FnOnce(&str) -> anyhow::Result<seafile::Client>,
+    out: &mut impl Write,
+) -> anyhow::Result<()> {
+    let Some(token) = credentials::token(url)? else {
+        anyhow::bail!("not logged in to {}", server(url));
+    };
+    let account = connect(&token)?
+        .account()
+        .with_context(|| format!("the token stored for {} does not work", server(url)))?;
+    writeln!(out, "Logged in to {} as {}", server(url), account)?;
+    Ok(())
+}
@@ -0,0 +1,728 @@
+//! The download and sync commands
+
+use std::{
+    collections::HashSet,
+    io::{IsTerminal, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
+};
+
+use anyhow::Context;
+use human_bytes::human_bytes;
+use url::Url;
+
+use seaf_share_core::{
+    breaker::CircuitBreaker,
+    checksum, download_with_session,
+    plan::PlanRecord,
+    pool::{Feeder, Pool},
+    post,
+    progress::Progress,
+    seafile,
+    shutdown::Abort,
+    state::RunState,
+    summary::{DownloadOutcome, FileRecord, Summary, Verification},
+    sync,
+    volume::{self, FileSystem, Named, Names, Unsupported},
+    walker::{DirEntryStream, ListingFailed},
+    ConflictAction, DirEntry, Downloader, Recursive, ShareLink,
+};
+
+use super::{download_roots, enrich, STREAM_CAPACITY};
+use crate::{cli::DownloadOptions, dashboard::Dashboard, interrupt};
+
+/// What a download run did
+#[derive(Debug, Default)]
+pub struct Report {
+    pub summary: Summary,
+    /// Signal that interrupted the run
+    pub signal: Option<i32>,
+}
+
+/// Estimates what a download would transfer, honoring the traversal and
+/// filter options.
+fn prescan(
+    client: &seafile::Client,
+    link: &ShareLink,
+    path: Option<&Path>,
+    roots: Option<Vec<DirEntry>>,
+    options: &DownloadOptions,
+) -> anyhow::Result<seafile::Estimate> {
+    let filter = options.filter();
+    let selected = |e: &DirEntry| filter.admits(e.path(), e.is_dir());
+    if link.is_dir() && options.recursive() != Recursive::None {
+        return Ok(client.estimate(link.token(), path, selected, options.prescan_dirs())?);
+    }
+    let roots = match roots {
+        Some(roots) => roots,
+        None => download_roots(client, link, path)?,
+    };
+    let mut estimate = seafile::Estimate::default();
+    for entry in roots.iter().filter(|e| e.is_file() && selected(e)) {
+        estimate.add(entry);
+    }
+    Ok(estimate)
+}
+
+/// Checks a downloaded file against `--checksum-file`, noting the result in
+/// its job, and returns the checksum for `--write-checksums`.
+fn check_file(job: &mut post::Job, options: &DownloadOptions) -> anyhow::Result<Option<String>> {
+    let Some(sum) = job.checksum().transpose()? else {
+        return Ok(None);
+    };
+    let listed = match options.checksum_file() {
+        // files of "--route" are listed by their full path
+        Some(manifest) => {
+            manifest.get(job.dest.strip_prefix(options.output()).unwrap_or(&job.dest))
+        }
+        None => None,
+    };
+    job.verification = match listed {
+        Some(expected) if expected != sum => {
+            job.verification = Verification::Mismatched {
+                expected: expected.to_string(),
+                received: sum.clone(),
+            };
+            anyhow::bail!("checksum mismatch: expected {}, received {}", expected, sum);
+        }
+        Some(_) => Verification::Matched(sum.clone()),
+        None => Verification::Computed(sum.clone()),
+    };
+    let sum = match options.write_checksums() {
+        Some(algorithm) if Some(algorithm) != options.checksum_file().map(|m| m.algorithm()) => {
+            Some(checksum::file_checksum(algorithm, &job.dest)?)
+        }
+        Some(_) => Some(sum),
+        None => None,
+    };
+    Ok(sum)
+}
+
+/// Finalizes a downloaded file, or removes it if it fails its check
+fn finish_file(mut job: post::Job, options: &DownloadOptions) -> (DownloadOutcome, Option<String>) {
+    match check_file(&mut job, options) {
+        Ok(sum) => (job.finish(options.archive(), options.exec()), sum),
+        Err(e) => {
+            let mismatched = matches!(job.verification, Verification::Mismatched { .. });
+            // closed first, or it cannot be removed on Windows
+            let mut outcome = job.fail(e);
+            if mismatched {
+                if let Err(e) = std::fs::remove_file(&outcome.dest) {
+                    outcome.result = Err(e.into());
+                }
+            }
+            (outcome, None)
+        }
+    }
+}
+
+/// Remote directory that corresponds to the output destination
+pub(super) fn output_base(
+    link: &ShareLink,
+    path: Option<&Path>,
+    roots: &[DirEntry],
+    options: &DownloadOptions,
+) -> anyhow::Result<PathBuf> {
+    let root = Path::new("/");
+    let downloaded = if link.is_dir() {
+        path.unwrap_or(root)
+    } else {
+        roots
+            .first()
+            .and_then(|e| e.path().parent())
+            .unwrap_or(root)
+    };
+    if options.preserve_full_path() {
+        return Ok(root.to_path_buf());
+    }
+    let Some(dir) = options.relative_to() else {
+        return Ok(downloaded.to_path_buf());
+    };
+    let base = link.path().unwrap_or(root).join(dir);
+    anyhow::ensure!(
+        downloaded.starts_with(&base),
+        "--relative-to {} does not contain the downloaded path {}",
+        base.display(),
+        downloaded.display(),
+    );
+    Ok(base)
+}
+
+/// Prints the totals of a download run, and writes them as JSON to `json`
+/// (`out` for "-").
+fn print_summary(
+    summary: &Summary,
+    json: Option<&Path>,
+    out: &mut (dyn Write + Send),
+) -> anyhow::Result<()> {
+    eprintln!(
+        "{} files: {} downloaded, {} updated, {} overwritten, {} continued, {} skipped, {} failed",
+        summary.files(),
+        summary.downloaded,
+        summary.updated,
+        summary.overwritten,
+        summary.continued,
+        summary.skipped,
+        summary.failed,
+    );
+    if !summary.failed_dirs.is_empty() {
+        eprintln!(
+            "{} directories could not be listed, and their files are missing",
+            summary.failed_dirs.len()
+        );
+    }
+    eprintln!(
+        "{} received in {:.1}s ({}/s)",
+        human_bytes(summary.bytes as f64),
+        summary.elapsed_secs,
+        human_bytes(summary.throughput),
+    );
+    match json {
+        Some(file) if file == Path::new("-") => {
+            writeln!(out, "{}", serde_json::to_string(summary)?)?
+        }
+        Some(file) => std::fs::write(file, serde_json::to_string(summary)? + "\n")
+            .with_context(|| file.display().to_string())?,
+        None => {}
+    }
+    Ok(())
+}
+
+fn confirm(prompt: &str) -> anyhow::Result<bool> {
+    eprint!("{}", prompt);
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(
+        answer.trim().to_ascii_lowercase().as_str(),
+        "y" | "yes"
+    ))
+}
+
+/// Downloads the link (or `path` in it), which `url` was given for, and
+/// reports every file to `out` (or stderr).
+pub fn download(
+    client: seafile::Client,
+    downloader: Downloader,
+    link: &ShareLink,
+    url: &Url,
+    path: Option<&Path>,
+    options: &DownloadOptions,
+    out: &mut (dyn Write + Send),
+) -> anyhow::Result<Report> {
+    client.check_downloadable(link)?;
+    // listings and completed files of an interrupted run; a dry run only
+    // keeps its listings, for the run it plans
+    let state = match options.session() {
+        Some(file) => {
+            let run = format!(
+                "{} {} -> {}",
+                url,
+                path.unwrap_or(Path::new("/")).display(),
+                options.output().display(),
+            );
+            Some(Arc::new(RunState::open(file, &run)?))
+        }
+        None => None,
+    };
+    let abort = Abort::default();
+    let progress = Progress::default();
+    let client = match &state {
+        Some(state) => client.with_run_state(state.clone()),
+        None => client,
+    };
+    let client = client.with_progress(progress.clone());
+    let mut downloader = downloader
+        .with_timeouts(options.common().max_time(), options.timeout_per_gib())
+        .with_abort(abort.clone())
+        .with_progress(progress.clone());
+    // files are hashed as they arrive for one algorithm only
+    let hashed = options
+        .checksum_file()
+        .map(checksum::Manifest::algorithm)
+        .or(options.write_checksums());
+    if let Some(algorithm) = hashed {
+        downloader = downloader.with_checksums(algorithm);
+    }
+    // unchanged files are told by the IDs they were synced with
+    let file_ids = match options.on_conflict() {
+        ConflictAction::Update => Some(Arc::new(sync::FileIds::load(options.output())?)),
+        _ => None,
+    };
+    if let Some(ids) = &file_ids {
+        downloader = downloader.with_file_ids(ids.clone());
+    }
+    // names and sizes a removable drive cannot hold are dealt with up front
+    let filesystem = options
+        .filesystem()
+        .or_else(|| FileSystem::detect(options.output()))
+        .filter(|fs| *fs != FileSystem::Other);
+    let max_file_size = filesystem.and_then(|fs| fs.max_file_size());
+    // files written in parts, to fit the file system or the chosen size
+    let split_size = max_file_size
+        .filter(|_| options.unsupported() == Unsupported::Map)
+        .into_iter()
+        .chain(options.split_size())
+        .min();
+    if let Some(max) = split_size {
+        downloader = downloader.with_max_file_size(max);
+    }
+    // the contents of a linked directory are listed as they are walked, so
+    // that downloads start before a huge directory is received in full
+    let mut roots = if link.is_dir() {
+        None
+    } else {
+        Some(download_roots(&client, link, path)?)
+    };
+    // files of the roots are enriched once, for the estimate as well as the
+    // transfers
+    let mut enriched = HashSet::new();
+    if options.common().enrich() {
+        let filter = options.filter();
+        for entry in roots.iter_mut().flatten() {
+            if entry.is_file() && filter.admits(entry.path(), false) {
+                enrich(&client, entry);
+                enriched.insert(entry.path().to_path_buf());
+            }
+        }
+    }
+    let base = output_base(link, path, roots.as_deref().unwrap_or_default(), options)?;
+
+    let mut expected = None;
+    let confirms = !options.yes() && std::io::stdin().is_terminal();
+    let stats = options.stats_interval().filter(|_| !options.dry_run());
+    if !options.dry_run() && (confirms || stats.is_some()) {
+        let estimate = prescan(&client, link, path, roots.clone(), options)?;
+        expected = Some(estimate);
+        if confirms && estimate.bytes > options.confirm_above() {
+            let prompt = format!(
+                "{}{} files, {} will be downloaded to {} — continue? [y/N] ",
+                if estimate.sampled { "about " } else { "" },
+                estimate.files,
+                human_bytes(estimate.bytes as f64),
+                options.output().display(),
+            );
+            if !confirm(&prompt)? {
+                return Ok(Report::default());
+            }
+        }
+    }
+
+    interrupt::install(&abort);
+    let started = Instant::now();
+    let live = !options.no_progress() && !options.dry_run() && std::io::stderr().is_terminal();
+    progress.set_rate_limit(options.limit_rate());
+    let keys = std::io::stdin().is_terminal();
+    let dashboard =
+        Dashboard::new(progress.clone(), expected, live, keys, out).with_stats_interval(stats);
+    let stop_dashboard = AtomicBool::new(false);
+    let mut breaker = CircuitBreaker::new(options.failure_window(), options.failure_threshold());
+    // directories are walked unless pruned, as they may hold
+    // selected files
+    let filter = options.filter();
+    let admitted = filter.clone();
+    let admits = move |e: &DirEntry| admitted.admits(e.path(), e.is_dir());
+    let (order, limit) = (options.recursive(), options.queue_limit());
+    let entries = match roots {
+        Some(roots) => DirEntryStream::spawn(
+            &client,
+            link.token(),
+            roots,
+            order,
+            admits,
+            STREAM_CAPACITY,
+            limit,
+            None,
+        ),
+        None => DirEntryStream::spawn_dir(
+            &client,
+            link.token(),
+            path,
+            order,
+            admits,
+            STREAM_CAPACITY,
+            limit,
+            None,
+        ),
+    };
+    let post_jobs = std::thread::available_parallelism()
+        .map_or(1, usize::from)
+        .max(options.jobs());
+    let pool = Pool::new(&abort, options.jobs(), post_jobs).with_drain(&interrupt::is_requested);
+    let checksums = Mutex::new(Vec::new());
+    // remote directories listed in full, and all remote paths
+    // listed, for "--delete"
+    let mut listed = Vec::new();
+    let mut seen = HashSet::new();
+    // files handed to workers, and reported done or failed
+    let (mut queued, mut reported, mut failed) = (0, 0, 0);
+    let mut summary = Summary::default();
+    // files completed by an earlier run of the session
+    let mut resumed = 0;
+    // files the file system of the output destination cannot hold
+    let mut unsupported = 0;
+    let mut names = filesystem.map(|fs| Names::new(fs, options.unsupported()));
+    // directories that could not be listed
+    let mut failed_dirs = Vec::new();
+    if options.delete() && link.is_dir() {
+        listed.push(path.unwrap_or(Path::new("/")).to_path_buf());
+    }
+    // see `shutdown` for how the run is torn down
+    let (parts, downloader, enriched) = (client.parts(), &downloader, &enriched);
+    let worker = || {
+        let client = seafile::Client::from_parts(parts.clone());
+        move |(mut entry, dest): (DirEntry, PathBuf)| {
+            if options.common().enrich() && !enriched.contains(entry.path()) {
+                enrich(&client, &mut entry);
+            }
+            download_with_session(downloader, &client, &entry, &dest, options.on_conflict())
+        }
+    };
+    let finish = |job| {
+        let (outcome, sum) = finish_file(job, options);
+        if let Some(sum) = sum {
+            checksums.lock().unwrap().push((outcome.dest.clone(), sum));
+        }
+        if let (Ok(_), Some(state)) = (&outcome.result, &state) {
+            state.record_done(&outcome.path);
+        }
+        outcome
+    };
+    std::thread::scope(|scope| -> anyhow::Result<()> {
+        scope.spawn(|| dashboard.run(&stop_dashboard));
+        let mut checked_view_only = false;
+        let report = |outcome: DownloadOutcome| {
+            for warning in progress.take_warnings() {
+                dashboard.eprintln(warning);
+            }
+            let result = &outcome.result;
+            reported += 1;
+            failed += usize::from(result.is_err());
+            summary.add(result);
+            if let Some(Err(e)) = state.as_ref().map(|s| s.checkpoint()) {
+                dashboard.eprintln(format_args!("could not save the session: {}", e));
+            }
+            // skipped on request, which says nothing about the server
+            let skipped = result.is_err() && outcome.is_skipped();
+            if !skipped {
+                breaker.record(result.is_ok());
+            }
+            let error = result.as_ref().err().filter(|_| !skipped);
+            dashboard.finished(error.map(|e| e.to_string()));
+            let fail = error.is_some() && options.fail_fast();
+            // no other file can be downloaded until the quota is reset
+            let traffic_limit = result.as_ref().is_err_and(|e| {
+                matches!(
+                    e.downcast_ref::<seaf_share_core::Error>(),
+                    Some(seaf_share_core::Error::TrafficLimit(_))
+                )
+            });
+            // a share made view-only while it is downloaded answers every
+            // file with 403; its page is asked once whether that is why
+            let forbidden = result.as_ref().is_err_and(|e| {
+                matches!(
+                    e.downcast_ref::<seaf_share_core::Error>(),
+                    Some(seaf_share_core::Error::Http(ureq::Error::StatusCode(403)))
+                )
+            });
+            let view_only = forbidden
+                && !std::mem::replace(&mut checked_view_only, true)
+                && matches!(
+                    client.check_downloadable(link),
+                    Err(seaf_share_core::Error::Share(seafile::Error::ViewOnly))
+                );
+            let file = outcome.path.to_string_lossy();
+            if options.json() {
+                let record = FileRecord::new(&outcome);
+                match serde_json::to_string(&record) {
+                    Ok(line) => dashboard.println(line),
+                    Err(e) => dashboard.eprintln(e),
+                }
+            } else {
+                match result {
+                    Err(_) if skipped => dashboard.eprintln(format_args!("skipped {}", file)),
+                    Err(e) => {
+                        dashboard.eprintln(format_args!("could not download {}: {}", file, e))
+                    }
+                    Ok(result) if options.itemize() => {
+                        if let Some(item) = sync::itemize(*result) {
+                            dashboard.println(format_args!("{} {}", item, file))
+                        }
+                    }
+                    Ok(result) => {
+                        dashboard.println(format_args!("downloaded {}: {}", file, result))
+                    }
+                }
+            }
+            if traffic_limit && !abort.is_triggered() {
+                dashboard.eprintln("stopping, as the share allows no more downloads");
+                abort.trigger();
+            }
+            if view_only && !abort.is_triggered() {
+                dashboard.eprintln(format_args!("stopping, as {}", seafile::Error::ViewOnly));
+                abort.trigger();
+            }
+            if fail && !abort.is_triggered() {
+                dashboard.eprintln("stopping at the first failure (--fail-fast)");
+                abort.trigger();
+            }
+            if breaker.is_open() && !abort.is_triggered() {
+                dashboard.eprintln("too many recent failures, pausing until the server responds");
+                let stopping = || pool.is_stopping();
+                let probe = || {
+                    download_roots(&client, link, path)
+                        .map(drop)
+                        .inspect_err(|e| {
+                            dashboard.eprintln(format_args!("server still unavailable: {}", e))
+                        })
+                };
+                let responded = breaker.wait_until(stopping, probe);
+                if !responded && !stopping() {
+                    dashboard.eprintln("stopping, as the server did not respond");
+                    abort.trigger();
+                }
+            }
+        };
+
+        let feed = |feeder: &Feeder<_, _>| {
+            let fed = entries.take_while(|_| !pool.is_stopping()).try_for_each(
+                |entry| -> anyhow::Result<()> {
+                    let mut entry = match entry {
+                        Ok(entry) => entry,
+                        // the other directories are downloaded still, unless
+                        // the failed one is the downloaded directory itself
+                        Err(e) => {
+                            let Some(failed) = e
+                                .downcast_ref::<ListingFailed>()
+                                .filter(|f| f.dir != path.unwrap_or(Path::new("/")))
+                            else {
+                                return Err(e);
+                            };
+                            dashboard.eprintln(format_args!("{:#}; its files are skipped", e));
+                            // its files are not known, so none are deleted
+                            let mut local = failed.dir.strip_prefix(&base)?.to_path_buf();
+                            if let Some(names) = &names {
+                                local = names.local(&local);
+                            }
+                            listed.retain(|dir| *dir != base.join(&local));
+                            failed_dirs.push(failed.dir.clone());
+                            return Ok(());
+                        }
+                    };
+                    let mut local = entry.path().strip_prefix(&base)?.to_path_buf();
+                    let too_large = entry
+                        .size()
+                        .zip(max_file_size)
+                        .is_some_and(|(size, max)| size > max);
+                    if let Some((fs, names)) = filesystem.zip(names.as_mut()) {
+                        let mapped = fs.map_path(&local);
+                        let renamed = fs.map_name(entry.name()) != entry.name();
+                        if options.unsupported() == Unsupported::Skip
+                            && (mapped != local || too_large)
+                        {
+                            if renamed || too_large {
+                                dashboard.eprintln(format_args!(
+                                    "skipped {}: {} on {}",
+                                    entry.path().display(),
+                                    if too_large {
+                                        "too large"
+                                    } else {
+                                        "invalid name"
+                                    },
+                                    fs
+                                ));
+                            }
+                            unsupported += u64::from(entry.is_file());
+                            return Ok(());
+                        }
+                        if renamed {
+                            dashboard.eprintln(format_args!(
+                                "renamed {} to {}, as {} does not allow its name",
+                                entry.path().display(),
+                                mapped.file_name().unwrap_or_default().to_string_lossy(),
+                                fs
+                            ));
+                        }
+                        // names that the file system does not tell apart
+                        local = match names.claim(&local, entry.is_dir()) {
+                            Named::Mapped(local) => local,
+                            Named::Renamed { local, taken_by } => {
+                                dashboard.eprintln(format_args!(
+                                    "renamed {} to {}, as {} has the same name on {}",
+                                    entry.path().display(),
+                                    local.file_name().unwrap_or_default().to_string_lossy(),
+                                    base.join(taken_by).display(),
+                                    fs
+                                ));
+                                local
+                            }
+                            Named::Skipped { taken_by } => {
+                                if let Some(taken_by) = taken_by {
+                                    dashboard.eprintln(format_args!(
+                                        "skipped {}: {} has the same name on {}",
+                                        entry.path().display(),
+                                        base.join(taken_by).display(),
+                                        fs
+                                    ));
+                                }
+                                unsupported += u64::from(entry.is_file());
+                                return Ok(());
+                            }
+                        };
+                    }
+                    let parts = entry
+                        .size()
+                        .zip(split_size)
+                        .filter(|(size, max)| size > max)
+                        .map(|(size, max)| (size.div_ceil(max), max));
+                    if let Some((count, max)) = parts {
+                        dashboard.eprintln(format_args!(
+                            "splitting {} into {} parts of {}",
+                            entry.path().display(),
+                            count,
+                            human_bytes(max as f64)
+                        ));
+                    }
+                    let dest = options.root(&entry).join(&local);
+                    if options.delete() {
+                        seen.insert(base.join(&local));
+                        if let Some((count, _)) = parts {
+                            for index in 0..count as usize {
+                                seen.insert(base.join(volume::part_path(&local, index)));
+                            }
+                            seen.insert(base.join(volume::manifest_path(&local)));
+                        }
+                    }
+
+                    // left by an interrupted run, and not removed since
+                    let finished =
+                        state.as_ref().is_some_and(|s| s.is_done(entry.path())) && dest.exists();
+                    if entry.is_file() && finished {
+                        resumed += 1;
+                        if let Some(algorithm) = options.write_checksums() {
+                            let sum = checksum::file_checksum(algorithm, &dest)?;
+                            checksums.lock().unwrap().push((dest, sum));
+                        }
+                    } else if entry.is_file() {
+                        if options.dry_run() {
+                            if options.common().enrich() && !enriched.contains(entry.path()) {
+                                enrich(&client, &mut entry);
+                            }
+                            if options.json() {
+                                let plan = PlanRecord::new(
+                                    &entry,
+                                    &dest,
+                                    options.on_conflict(),
+                                    file_ids.as_deref(),
+                                )?;
+                                dashboard.println(serde_json::to_string(&plan)?);
+                            } else if options.itemize() {
+                                let result = sync::check(&entry, &dest, file_ids.as_deref())?;
+                                if let Some(item) = sync::itemize(result) {
+                                    dashboard.println(format_args!(
+                                        "{} {}",
+                                        item,
+                                        entry.path().to_string_lossy()
+                                    ));
+                                }
+                            } else {
+                                eprintln!("{}", entry.download_url().unwrap());
+                            }
+                        } else if feeder.send((entry, dest)) {
+                            queued += 1;
+                        }
+                    } else if options.recursive() != Recursive::None {
+                        if options.delete() {
+                            listed.push(base.join(&local));
+                        }
+                        // directories of included files are created
+                        // along with them
+                        if filter.selects(entry.path(), true) {
+                            if options.itemize() && !dest.exists() {
+                                dashboard.println(format_args!(
+                                    "{} {}",
+                                    sync::NEW_DIR,
+                                    entry.path().to_string_lossy()
+                                ));
+                            }
+                            if !options.dry_run() {
+                                std::fs::create_dir_all(dest)?;
+                            }
+                        }
+                    }
+                    feeder.report();
+                    Ok(())
+                },
+            );
+            if interrupt::is_requested() && !abort.is_triggered() {
+                dashboard.eprintln(
+                    "stopping after the transfers in progress, \
+                     press Ctrl-C again to abort them",
+                );
+            }
+            fed
+        };
+        let fed = pool.run(worker, finish, report, feed);
+        for warning in progress.take_warnings() {
+            dashboard.eprintln(warning);
+        }
+        stop_dashboard.store(true, Ordering::Relaxed);
+        fed
+    })?;
+    summary.skipped += resumed + unsupported;
+    summary.failed_dirs = failed_dirs;
+    summary.finish(progress.received(), started.elapsed());
+    if let Some(state) = state.as_ref().filter(|_| !options.dry_run()) {
+        // kept to retry failed files, or to continue after Ctrl-C
+        if failed > 0 || abort.is_triggered() || interrupt::is_requested() {
+            state.save()?;
+        } else {
+            state.remove()?;
+        }
+    }
+    if let Some(algorithm) = options.write_checksums() {
+        if !options.dry_run() {
+            checksum::write_manifest(options.output(), algorithm, checksums.into_inner().unwrap())?;
+        }
+        // kept by "--delete" like a file of the share
+        seen.insert(base.join(algorithm.manifest_name()));
+    }
+    if let Some(ids) = &file_ids {
+        if !options.dry_run() {
+            ids.save()?;
+        }
+        seen.insert(base.join(sync::FILE_IDS));
+    }
+    // a partial listing would delete files that still exist
+    if options.delete() && !abort.is_triggered() && !interrupt::is_requested() {
+        sync::delete_extraneous(
+            &listed,
+            &seen,
+            &base,
+            options.output(),
+            &options.filter(),
+            options.itemize(),
+            options.dry_run(),
+        )?;
+    }
+    if !options.dry_run() {
+        print_summary(&summary, options.summary_json(), dashboard.into_output())?;
+    }
+    let signal = interrupt::signal();
+    if signal.is_some() {
+        eprintln!(
+            "interrupted: {} files downloaded, {} failed, {} listed but not started",
+            reported - failed,
+            failed,
+            queued - reported,
+        );
+        if let Some(file) = options.session() {
+            eprintln!("run again with --session {} to continue", file.display());
+        }
+    }
+    Ok(Report { summary, signal })
+}
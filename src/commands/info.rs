@@ -0,0 +1,63 @@
+//! The info command
+
+use std::io::Write;
+
+use chrono::Utc;
+
+use seaf_share_core::{seafile, ShareLink};
+
+use crate::cli::InfoOptions;
+
+/// Writes what the share page of `link` tells to `out`.
+pub fn info(
+    client: &seafile::Client,
+    link: &ShareLink,
+    options: &InfoOptions,
+    out: &mut impl Write,
+) -> anyhow::Result<()> {
+    let info = client.share_info(link)?;
+    if options.json() {
+        writeln!(out, "{}", serde_json::to_string(&info)?)?;
+        return Ok(());
+    }
+    // a locked share page tells nothing but that it is locked
+    let locked = info.password_protected && options.common().password().is_none();
+    let unknown = || "unknown".to_string();
+    let allowed = |allowed: Option<bool>| match allowed {
+        Some(true) => "yes",
+        Some(false) => "no",
+        None => "unknown",
+    };
+    let expires = match info.expires {
+        Some(date) if date < Utc::now() => format!("{} (expired)", date.to_rfc3339()),
+        Some(date) => {
+            let left = match (date - Utc::now()).num_days() {
+                0 => "today".to_string(),
+                1 => "in 1 day".to_string(),
+                days => format!("in {} days", days),
+            };
+            format!("{} ({})", date.to_rfc3339(), left)
+        }
+        None if locked => unknown(),
+        None => "not shown on the share page".to_string(),
+    };
+    writeln!(
+        out,
+        "Library:    {}",
+        info.repo_name.unwrap_or_else(unknown)
+    )?;
+    writeln!(out, "Library ID: {}", info.repo_id.unwrap_or_else(unknown))?;
+    writeln!(out, "Owner:      {}", info.owner.unwrap_or_else(unknown))?;
+    writeln!(out, "Expires:    {}", expires)?;
+    writeln!(out, "Download:   {}", allowed(info.can_download))?;
+    writeln!(out, "Upload:     {}", allowed(info.can_upload))?;
+    writeln!(
+        out,
+        "Password:   {}",
+        if info.password_protected { "yes" } else { "no" }
+    )?;
+    if locked {
+        writeln!(out, "\nGive the password with --password to read the rest.")?;
+    }
+    Ok(())
+}
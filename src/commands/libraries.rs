@@ -0,0 +1,40 @@
+//! The libraries command
+
+use std::io::Write;
+
+use human_bytes::human_bytes;
+
+use seaf_share_core::seafile;
+
+use crate::cli::LibrariesOptions;
+
+/// Writes the libraries of the account of the client's API token to `out`,
+/// one per line: ID, size, name and URL.
+pub fn libraries(
+    client: &seafile::Client,
+    options: &LibrariesOptions,
+    out: &mut impl Write,
+) -> anyhow::Result<()> {
+    let libraries = client.libraries()?;
+    if options.json() {
+        writeln!(out, "{}", serde_json::to_string(&libraries)?)?;
+        return Ok(());
+    }
+    for library in &libraries {
+        let lock = if library.encrypted {
+            " (encrypted)"
+        } else {
+            ""
+        };
+        writeln!(
+            out,
+            "{}\t{}\t{}{}\t{}",
+            library.id,
+            human_bytes(library.size as f64),
+            library.name,
+            lock,
+            library.url
+        )?;
+    }
+    Ok(())
+}
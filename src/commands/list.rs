@@ -0,0 +1,113 @@
+//! The list command
+
+use std::{io::Write, path::Path};
+
+use anyhow::Context;
+use cli_table::{Cell, Table};
+use human_bytes::human_bytes;
+use url::Url;
+
+use seaf_share_core::{seafile, walker::DirEntryStream, DirEntry, Recursive, ShareLink};
+
+use super::{enrich, STREAM_CAPACITY};
+use crate::cli::ListOptions;
+
+/// Renders a URL as a QR code of half-height blocks, light on dark so that
+/// it scans on terminals with a dark background.
+fn qr_code(url: &Url) -> anyhow::Result<String> {
+    use qrcode::render::unicode::Dense1x2;
+    let code = qrcode::QrCode::new(url.as_str())?;
+    Ok(code
+        .render::<Dense1x2>()
+        .dark_color(Dense1x2::Light)
+        .light_color(Dense1x2::Dark)
+        .build())
+}
+
+/// Lists the link (or `path` in it) to `out` and returns the entries listed.
+pub fn list(
+    client: &seafile::Client,
+    link: &ShareLink,
+    path: Option<&Path>,
+    options: &ListOptions,
+    out: &mut impl Write,
+) -> anyhow::Result<Vec<DirEntry>> {
+    // rather than list download URLs that all fail
+    client.check_downloadable(link)?;
+    let mut result = Vec::new();
+    if link.is_single_file() {
+        let file = client
+            .file(link, None)
+            .with_context(|| "cannot fetch single file info")?;
+        result.push(file);
+    } else if link.is_file() {
+        result.push(client.file(link, None)?);
+    } else if options.recursive() != Recursive::None {
+        let roots = client.entries(link.token(), path)?;
+        let entries = DirEntryStream::spawn(
+            client,
+            link.token(),
+            roots,
+            options.recursive(),
+            |_| true,
+            STREAM_CAPACITY,
+            DirEntryStream::MEMORY_LIMIT,
+            options.max_depth(),
+        );
+        for entry in entries {
+            result.push(entry?);
+        }
+    } else {
+        let entries = client.entries(link.token(), path)?;
+        result.extend(entries);
+    }
+    if options.common().enrich() {
+        result.iter_mut().for_each(|e| enrich(client, e));
+    }
+    if options.json() {
+        writeln!(out, "{}", serde_json::to_string(&result)?)?;
+    } else if let Some(template) = options.printf() {
+        for e in &result {
+            out.write_all(template.render(e).as_bytes())?;
+        }
+    } else if options.qr() {
+        for e in &result {
+            let url = e.download_url().unwrap_or(e.view_url());
+            writeln!(out, "{}\n{}", url, qr_code(url)?)?;
+        }
+    } else {
+        let table = result
+            .iter()
+            .map(|e| {
+                let name = if options.recursive() != Recursive::None {
+                    // names alone are ambiguous across directories
+                    let path = e.path().strip_prefix(path.unwrap_or(Path::new("/")));
+                    path.unwrap_or(e.path()).to_string_lossy().into_owned()
+                } else {
+                    e.name().to_string()
+                };
+                let name = if e.is_dir() && !name.ends_with('/') {
+                    format!("{}/", name)
+                } else {
+                    name
+                };
+                let na = "N/A".to_string();
+                [
+                    name.cell(),
+                    e.size()
+                        .map(|sz| human_bytes(sz as f64))
+                        .unwrap_or(na.clone())
+                        .cell(),
+                    e.last_modified()
+                        .map(|dt| dt.to_rfc3339())
+                        .unwrap_or(na.clone())
+                        .cell(),
+                ]
+            })
+            .table()
+            .title(["Name", "Size", "Last Modified"])
+            .display()?;
+        writeln!(out, "{}", table)?;
+    }
+    Ok(result)
+}
@@ -0,0 +1,294 @@
+//! The watch command: a sync of the share every `--interval`, whose changed
+//! files stay queued while the network or the server is down
+
+use std::{
+    fs::File,
+    io::Write,
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use seaf_share_core::{
+    download_with_session,
+    progress::Progress,
+    redact::Redacted,
+    retry, seafile,
+    shutdown::Abort,
+    sync,
+    walker::DirEntryStream,
+    watch::{Event, WorkQueue},
+    ConflictAction, DirEntry, DownloadResult, Downloader, ShareLink,
+};
+
+use super::{download::output_base, download_roots, STREAM_CAPACITY};
+use crate::{
+    cli::{DownloadOptions, WatchOptions},
+    interrupt,
+};
+
+/// Time between checks whether the watch should stop
+const STOP_POLL: Duration = Duration::from_millis(200);
+
+/// Event as written to the log, with the time it happened
+#[derive(Serialize)]
+struct Logged<'a> {
+    time: DateTime<Utc>,
+    #[serde(flatten)]
+    event: &'a Event<'a>,
+}
+
+/// The log of `--events`, if any
+struct Log(Option<Box<dyn Write>>);
+
+impl Log {
+    fn open(file: Option<&Path>) -> anyhow::Result<Self> {
+        let out: Box<dyn Write> = match file {
+            None => return Ok(Self(None)),
+            Some(file) if file == Path::new("-") => Box::new(std::io::stdout()),
+            Some(file) => Box::new(
+                File::options()
+                    .create(true)
+                    .append(true)
+                    .open(file)
+                    .with_context(|| file.display().to_string())?,
+            ),
+        };
+        Ok(Self(Some(out)))
+    }
+
+    fn write(&mut self, event: Event) -> anyhow::Result<()> {
+        let Some(out) = &mut self.0 else {
+            return Ok(());
+        };
+        let logged = Logged {
+            time: Utc::now(),
+            event: &event,
+        };
+        writeln!(out, "{}", serde_json::to_string(&logged)?)?;
+        Ok(out.flush()?)
+    }
+}
+
+/// Files downloaded and failed by a check
+#[derive(Default)]
+struct Counts {
+    downloaded: usize,
+    failed: usize,
+}
+
+struct Watch<'a> {
+    client: &'a seafile::Client,
+    downloader: &'a Downloader,
+    progress: Progress,
+    ids: &'a sync::FileIds,
+    link: &'a ShareLink,
+    path: Option<&'a Path>,
+    sync: DownloadOptions,
+    queue: WorkQueue,
+    log: Log,
+    out: &'a mut (dyn Write + Send),
+}
+
+impl Watch<'_> {
+    /// Downloads the queued files, then those found changed since the last
+    /// check. What cannot be downloaded while the share cannot be reached
+    /// stays queued.
+    fn check(&mut self, offline: &mut bool) -> anyhow::Result<()> {
+        let roots = download_roots(self.client, self.link, self.path)?;
+        if std::mem::take(offline) {
+            eprintln!(
+                "the share can be reached again, downloading {} queued files",
+                self.queue.len()
+            );
+            self.log.write(Event::Online {
+                queued: self.queue.len(),
+            })?;
+        }
+        let mut counts = Counts::default();
+        self.flush(&mut counts)?;
+        let changed = self.queue_changed(roots)?;
+        self.flush(&mut counts)?;
+        self.log.write(Event::Checked {
+            changed,
+            downloaded: counts.downloaded,
+            failed: counts.failed,
+            queued: self.queue.len(),
+        })
+    }
+
+    /// Queues the files of the share that are new or differ from the local
+    /// mirror as they are listed, and returns their number. Directories are
+    /// created on the way.
+    fn queue_changed(&mut self, roots: Vec<DirEntry>) -> anyhow::Result<usize> {
+        let base = output_base(self.link, self.path, &roots, &self.sync)?;
+        let filter = self.sync.filter();
+        let admitted = filter.clone();
+        let admits = move |e: &DirEntry| admitted.admits(e.path(), e.is_dir());
+        let entries = DirEntryStream::spawn(
+            self.client,
+            self.link.token(),
+            roots,
+            self.sync.recursive(),
+            admits,
+            STREAM_CAPACITY,
+            self.sync.queue_limit(),
+            None,
+        );
+        let mut changed = 0;
+        for entry in entries {
+            let entry = entry?;
+            let dest = self
+                .sync
+                .root(&entry)
+                .join(entry.path().strip_prefix(&base)?);
+            if entry.is_dir() {
+                if filter.selects(entry.path(), true) {
+                    std::fs::create_dir_all(dest)?;
+                }
+                continue;
+            }
+            if sync::check(&entry, &dest, Some(self.ids))? == DownloadResult::Skipped {
+                continue;
+            }
+            changed += 1;
+            if let Some((dropped, _)) = self.queue.push(entry, dest) {
+                self.log.write(Event::Dropped {
+                    path: dropped.path(),
+                    queued: self.queue.len(),
+                })?;
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Downloads the queued files until the queue is empty, or a file
+    /// cannot be downloaded for want of the network or the server, which is
+    /// queued again.
+    fn flush(&mut self, counts: &mut Counts) -> anyhow::Result<()> {
+        while let Some((entry, dest)) = self.queue.pop() {
+            if interrupt::is_requested() {
+                self.queue.retry(entry, dest);
+                break;
+            }
+            let downloaded = download_with_session(
+                self.downloader,
+                self.client,
+                &entry,
+                &dest,
+                ConflictAction::Update,
+            );
+            let outcome = match downloaded {
+                Ok(job) => job.finish(self.sync.archive(), self.sync.exec()),
+                Err(outcome) => *outcome,
+            };
+            for warning in self.progress.take_warnings() {
+                eprintln!("{}", Redacted(warning));
+            }
+            let file = entry.path().to_string_lossy().into_owned();
+            match outcome.result {
+                Ok(result) => {
+                    counts.downloaded += 1;
+                    if let Some(item) = sync::itemize(result) {
+                        writeln!(self.out, "{} {}", item, file)?;
+                    }
+                }
+                Err(e) if retry::is_transient(&e) => {
+                    self.queue.retry(entry, dest);
+                    return Err(e);
+                }
+                Err(e) => {
+                    counts.failed += 1;
+                    eprintln!("could not download {}: {}", file, Redacted(&e));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Sleeps for `duration`, or until the watch is asked to stop
+fn sleep(duration: Duration) {
+    let until = Instant::now() + duration;
+    while let Some(left) = until.checked_duration_since(Instant::now()) {
+        if interrupt::is_requested() {
+            return;
+        }
+        std::thread::sleep(left.min(STOP_POLL));
+    }
+}
+
+/// Syncs the link (or `path` in it) every `--interval` until Ctrl-C, writing
+/// the changes to `out`. Changed files that cannot be downloaded while the
+/// network or the server is down are kept queued, up to `--max-queued`, and
+/// downloaded first once the share can be reached again.
+pub fn watch(
+    client: seafile::Client,
+    downloader: Downloader,
+    link: &ShareLink,
+    path: Option<&Path>,
+    options: &WatchOptions,
+    out: &mut (dyn Write + Send),
+) -> anyhow::Result<()> {
+    let sync = options.download_options();
+    let unsupported = [
+        (sync.dry_run(), "--dry-run"),
+        (sync.delete(), "--delete"),
+        (sync.session().is_some(), "--session"),
+        (sync.stage_then_swap(), "--stage-then-swap"),
+    ];
+    if let Some((_, flag)) = unsupported.iter().find(|(set, _)| *set) {
+        anyhow::bail!("{} cannot be used with watch", flag);
+    }
+    client.check_downloadable(link)?;
+    let abort = Abort::default();
+    interrupt::install(&abort);
+    // unchanged files are told by the IDs they were synced with
+    let ids = Arc::new(sync::FileIds::load(sync.output())?);
+    let progress = Progress::default();
+    let downloader = downloader
+        .with_timeouts(sync.common().max_time(), sync.timeout_per_gib())
+        .with_abort(abort)
+        .with_progress(progress.clone())
+        .with_file_ids(ids.clone());
+    let mut watch = Watch {
+        client: &client,
+        downloader: &downloader,
+        progress,
+        ids: &ids,
+        link,
+        path,
+        queue: WorkQueue::new(options.max_queued()),
+        log: Log::open(options.events())?,
+        sync,
+        out,
+    };
+    let mut offline = false;
+    while !interrupt::is_requested() {
+        match watch.check(&mut offline) {
+            Ok(()) => {}
+            Err(e) if retry::is_transient(&e) => {
+                if !offline {
+                    eprintln!(
+                        "the share cannot be reached, {} changed files wait for it: {}",
+                        watch.queue.len(),
+                        Redacted(&e)
+                    );
+                    watch.log.write(Event::Offline {
+                        queued: watch.queue.len(),
+                        error: Redacted(&e).to_string(),
+                    })?;
+                }
+                offline = true;
+            }
+            Err(e) => return Err(e),
+        }
+        ids.save()?;
+        sleep(options.interval());
+    }
+    Ok(())
+}
@@ -0,0 +1,506 @@
+//! Defaults of the options from a configuration file,
+//! `~/.config/seaf-share/config.toml` (or in `$XDG_CONFIG_HOME`), e.g.
+//!
+//! ```toml
+//! output = "~/shares/{repo_name}"
+//! jobs = 4
+//! proxy = "socks5://localhost:1080"
+//! conflict = "continue"
+//! archive = true
+//! http_cache = true
+//! exclude = ["/**/.DS_Store", "/**/Thumbs.db"]
+//! ```
+//!
+//! Connection settings for several servers can be kept in profiles, selected
+//! with "--profile work":
+//!
+//! ```toml
+//! [profiles.work]
+//! url = "https://cloud.work.example/"
+//! password = "..."
+//! api_token = "..."
+//! ca_cert = "~/.config/seaf-share/work-ca.pem"
+//! output = "~/work"
+//! ```
+//!
+//! Share links may then be given relative to the URL of the profile, e.g.
+//! "d/6e5297246c". The settings of a profile (url, password, api_token, proxy,
+//! ca_cert, insecure, client_cert, client_key and output) can also be given
+//! outside of one, for all servers. The password and API token go with the
+//! url, though: they are only used for links to the server of that url.
+//!
+//! The configured values become the default values of the options, so that
+//! the flags of the command line override them; "--exclude" given there
+//! replaces the configured patterns.
+
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    num::NonZeroU16,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use clap::{Arg, ValueEnum};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use seaf_share_core::ConflictAction;
+
+use crate::handler;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// File the configuration was read from, if it exists
+    #[serde(skip)]
+    path: Option<PathBuf>,
+    /// Name of the profile in use
+    #[serde(skip)]
+    profile: Option<String>,
+    url: Option<Url>,
+    password: Option<String>,
+    api_token: Option<String>,
+    proxy: Option<String>,
+    ca_cert: Option<PathBuf>,
+    insecure: Option<bool>,
+    client_cert: Option<PathBuf>,
+    client_key: Option<PathBuf>,
+    output: Option<PathBuf>,
+    jobs: Option<NonZeroU16>,
+    conflict: Option<String>,
+    archive: Option<bool>,
+    http_cache: Option<bool>,
+    exclude: Vec<String>,
+    profiles: BTreeMap<String, Profile>,
+}
+
+/// Settings of a server
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Profile {
+    /// Base URL of share links given without one
+    url: Option<Url>,
+    password: Option<String>,
+    api_token: Option<String>,
+    proxy: Option<String>,
+    ca_cert: Option<PathBuf>,
+    insecure: Option<bool>,
+    client_cert: Option<PathBuf>,
+    client_key: Option<PathBuf>,
+    output: Option<PathBuf>,
+}
+
+impl Config {
+    /// Reads the configuration file, if there is one, with the settings of
+    /// `profile` in place of the general ones.
+    pub fn load(profile: Option<&str>) -> anyhow::Result<Self> {
+        let mut config = Self::read()?;
+        if let Some(name) = profile {
+            config.select(name)?;
+        }
+        config.output = config.output.as_deref().map(expand_home);
+        config.ca_cert = config.ca_cert.as_deref().map(expand_home);
+        config.client_cert = config.client_cert.as_deref().map(expand_home);
+        config.client_key = config.client_key.as_deref().map(expand_home);
+        Ok(config)
+    }
+
+    fn read() -> anyhow::Result<Self> {
+        let Some(path) = path() else {
+            return Ok(Self::default());
+        };
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e).with_context(|| format!("cannot read {}", path.display())),
+        };
+        let mut config: Self =
+            toml::from_str(&text).with_context(|| format!("invalid {}", path.display()))?;
+        config
+            .check()
+            .with_context(|| format!("invalid {}", path.display()))?;
+        config.path = Some(path);
+        Ok(config)
+    }
+
+    /// Uses the settings of the profile `name`.
+    fn select(&mut self, name: &str) -> anyhow::Result<()> {
+        let Some(profile) = self.profiles.get(name).cloned() else {
+            let known: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+            match &self.path {
+                Some(path) if known.is_empty() => {
+                    anyhow::bail!("no profile \"{}\", {} has none", name, path.display())
+                }
+                Some(_) => anyhow::bail!(
+                    "no profile \"{}\", expected one of {}",
+                    name,
+                    known.join(", ")
+                ),
+                None => anyhow::bail!("no profile \"{}\" without a configuration file", name),
+            }
+        };
+        self.profile = Some(name.to_string());
+        // the credentials go with the server of the profile
+        if profile.url.is_some() {
+            self.password = profile.password;
+            self.api_token = profile.api_token;
+        } else {
+            self.password = profile.password.or(self.password.take());
+            self.api_token = profile.api_token.or(self.api_token.take());
+        }
+        self.url = profile.url.or(self.url.take());
+        self.proxy = profile.proxy.or(self.proxy.take());
+        self.ca_cert = profile.ca_cert.or(self.ca_cert.take());
+        self.insecure = profile.insecure.or(self.insecure);
+        // the key goes with the certificate of the profile
+        if profile.client_cert.is_some() {
+            self.client_cert = profile.client_cert;
+            self.client_key = profile.client_key;
+        }
+        self.output = profile.output.or(self.output.take());
+        Ok(())
+    }
+
+    /// Fails on values the options would not accept, which clap expects of
+    /// default values.
+    fn check(&self) -> anyhow::Result<()> {
+        if let Some(conflict) = &self.conflict {
+            ConflictAction::from_str(conflict, false)
+                .map_err(|_| anyhow::anyhow!("unknown conflict action \"{}\"", conflict))?;
+        }
+        let proxies = self.profiles.values().map(|profile| &profile.proxy);
+        for proxy in std::iter::once(&self.proxy).chain(proxies).flatten() {
+            ureq::Proxy::new(proxy).with_context(|| format!("invalid proxy \"{}\"", proxy))?;
+        }
+        for pattern in &self.exclude {
+            glob::Pattern::new(pattern)
+                .with_context(|| format!("invalid exclude pattern \"{}\"", pattern))?;
+        }
+        let has_credentials = |password: &Option<String>, api_token: &Option<String>| {
+            password.is_some() || api_token.is_some()
+        };
+        if self.url.is_none() && has_credentials(&self.password, &self.api_token) {
+            anyhow::bail!("password and api_token need the url of their server");
+        }
+        for (name, profile) in &self.profiles {
+            let url = profile.url.as_ref().or(self.url.as_ref());
+            if url.is_none() && has_credentials(&profile.password, &profile.api_token) {
+                anyhow::bail!(
+                    "password and api_token of profile \"{}\" need the url of their server",
+                    name
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Configured password for the share at `url`, if it is on the server
+    /// the password is configured for
+    pub fn password_for(&self, url: &Url) -> Option<&str> {
+        self.password.as_deref().filter(|_| self.is_server(url))
+    }
+
+    /// Configured API token for `url`, if it is on the server the token is
+    /// configured for
+    pub fn api_token_for(&self, url: &Url) -> Option<&str> {
+        self.api_token.as_deref().filter(|_| self.is_server(url))
+    }
+
+    /// Whether `url` is on the configured server
+    fn is_server(&self, url: &Url) -> bool {
+        self.url
+            .as_ref()
+            .is_some_and(|server| server.origin() == url.origin())
+    }
+
+    /// Sets the default values of the options of `cli` and its commands to
+    /// the configured ones.
+    pub fn apply(&self, cli: clap::Command) -> clap::Command {
+        // known before the command line is parsed, see [`profile_arg`]
+        let mut profile = Arg::new("profile")
+            .long("profile")
+            .value_name("NAME")
+            .global(true)
+            .env("SEAF_SHARE_PROFILE")
+            .help("Use the settings of a profile of the configuration file");
+        if let Some(name) = &self.profile {
+            profile = profile.default_value(name.clone());
+        }
+        let mut cli = cli.arg(profile);
+        let names: Vec<String> = cli
+            .get_subcommands()
+            .map(|c| c.get_name().to_string())
+            .collect();
+        for name in names {
+            cli = cli.mut_subcommand(name, |c| self.apply_to(c));
+        }
+        cli
+    }
+
+    fn apply_to(&self, mut command: clap::Command) -> clap::Command {
+        // e.g. "auth login"
+        let names: Vec<String> = command
+            .get_subcommands()
+            .map(|c| c.get_name().to_string())
+            .collect();
+        for name in names {
+            command = command.mut_subcommand(name, |c| self.apply_to(c));
+        }
+        // in place, as positional arguments are numbered in order
+        command.mut_args(|arg| {
+            let value: Option<OsString> = match arg.get_id().as_str() {
+                "url" => match self.url.clone() {
+                    Some(base) => return arg.value_parser(move |s: &str| parse_url_in(&base, s)),
+                    None => None,
+                },
+                "exclude" if !self.exclude.is_empty() => {
+                    return arg.default_values(self.exclude.clone())
+                }
+                "proxy" => self.proxy.clone().map(Into::into),
+                "ca_cert" => self.ca_cert.clone().map(Into::into),
+                "insecure" => self.insecure.map(|insecure| insecure.to_string().into()),
+                "client_cert" => self.client_cert.clone().map(Into::into),
+                "client_key" => self.client_key.clone().map(Into::into),
+                "output" => self.output.clone().map(Into::into),
+                "jobs" => self.jobs.map(|jobs| jobs.to_string().into()),
+                "conflict" => self.conflict.clone().map(Into::into),
+                "archive" => self.archive.map(|archive| archive.to_string().into()),
+                "http_cache" => self.http_cache.map(|cache| cache.to_string().into()),
+                _ => None,
+            };
+            match value {
+                Some(value) => arg.default_value(value),
+                None => arg,
+            }
+        })
+    }
+
+    /// Prints where the configuration is read from and the defaults that
+    /// result for the options of `cli`, as configuration file.
+    pub fn print(&self, cli: &clap::Command) -> anyhow::Result<()> {
+        match (&self.path, path()) {
+            (Some(path), _) => println!("# {}", path.display()),
+            (None, Some(path)) => println!("# {} (not found)", path.display()),
+            (None, None) => println!("# no configuration file (no home directory)"),
+        }
+        if !self.profiles.is_empty() {
+            let names: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+            println!("# profiles: {}", names.join(", "));
+        }
+        if let Some(name) = &self.profile {
+            println!("# using profile {}", name);
+        }
+        let download = cli
+            .find_subcommand("download")
+            .expect("download is a command");
+        let defaults = |id: &str| -> Vec<String> {
+            download
+                .get_arguments()
+                .find(|arg| arg.get_id() == id)
+                .map_or(Vec::new(), |arg| {
+                    arg.get_default_values()
+                        .iter()
+                        .map(|value| value.to_string_lossy().into_owned())
+                        .collect()
+                })
+        };
+        let first = |id: &str| defaults(id).into_iter().next().unwrap_or_default();
+        let effective = Effective {
+            url: self.url.as_ref().map(Url::to_string),
+            output: first("output"),
+            jobs: first("jobs").parse()?,
+            proxy: self.proxy.clone(),
+            ca_cert: self.ca_cert.clone(),
+            insecure: self.insecure.unwrap_or(false),
+            client_cert: self.client_cert.clone(),
+            client_key: self.client_key.clone(),
+            conflict: first("conflict"),
+            // flags have no default value until the command line is parsed
+            archive: first("archive") == "true",
+            http_cache: self.http_cache.unwrap_or(false),
+            exclude: defaults("exclude"),
+        };
+        print!("{}", toml::to_string(&effective)?);
+        // only for links to the server of the url, see [`Config::password_for`]
+        if self.password.is_some() {
+            println!("# password: (hidden)");
+        }
+        if self.api_token.is_some() {
+            println!("# api_token: (hidden)");
+        }
+        if self.proxy.is_none() {
+            match ureq::Proxy::try_from_env() {
+                Some(proxy) => println!(
+                    "# proxy from the environment: {}:{}",
+                    proxy.host(),
+                    proxy.port()
+                ),
+                None => println!("# no proxy"),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Configuration in effect, for printing
+#[derive(Serialize)]
+struct Effective {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    output: String,
+    jobs: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    proxy: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ca_cert: Option<PathBuf>,
+    insecure: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_cert: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    client_key: Option<PathBuf>,
+    conflict: String,
+    archive: bool,
+    http_cache: bool,
+    exclude: Vec<String>,
+}
+
+/// Profile selected with "--profile" (or `$SEAF_SHARE_PROFILE`), which is
+/// looked for before the command line is parsed, as it sets the defaults
+pub fn profile_arg() -> Option<String> {
+    let mut args = std::env::args_os().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        } else if arg == "--profile" {
+            return args.next().map(|name| name.to_string_lossy().into_owned());
+        } else if let Some(name) = arg.to_str().and_then(|a| a.strip_prefix("--profile=")) {
+            return Some(name.to_string());
+        }
+    }
+    std::env::var("SEAF_SHARE_PROFILE")
+        .ok()
+        .filter(|name| !name.is_empty())
+}
+
+/// Parses a share URL like [`handler::parse_url`], resolving one without a
+/// scheme (e.g. "d/6e5297246c") against `base`
+fn parse_url_in(base: &Url, s: &str) -> Result<Url, String> {
+    match Url::parse(s) {
+        Err(url::ParseError::RelativeUrlWithoutBase) => {
+            let mut base = base.clone();
+            if !base.path().ends_with('/') {
+                base.set_path(&format!("{}/", base.path()));
+            }
+            base.join(s).map_err(|e| e.to_string())
+        }
+        _ => handler::parse_url(s),
+    }
+}
+
+/// Path of the configuration file, none without a home directory
+fn path() -> Option<PathBuf> {
+    let config_home = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(config_home.join("seaf-share/config.toml"))
+}
+
+/// `path` with a leading "~" replaced by the home directory, which the shell
+/// does for the command line but nothing does for the configuration file
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), std::env::var_os("HOME")) {
+        (Ok(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r#"
+        url = "https://cloud.example/"
+        password = "general"
+        api_token = "general-token"
+
+        [profiles.same]
+        output = "/srv/same"
+
+        [profiles.other]
+        url = "https://files.example.org/"
+        api_token = "other-token"
+
+        [profiles.bare]
+        url = "https://bare.example/"
+    "#;
+
+    fn profile(name: &str) -> Config {
+        let mut config: Config = toml::from_str(CONFIG).unwrap();
+        config.check().unwrap();
+        config.select(name).unwrap();
+        config
+    }
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    #[test]
+    fn profiles_without_a_url_inherit_the_credentials() {
+        let config = profile("same");
+        let share = url("https://cloud.example/d/6e5297246c/");
+        assert_eq!(config.password_for(&share), Some("general"));
+        assert_eq!(config.api_token_for(&share), Some("general-token"));
+    }
+
+    #[test]
+    fn profiles_with_a_url_drop_the_general_credentials() {
+        let config = profile("other");
+        let share = url("https://files.example.org/d/6e5297246c/");
+        assert_eq!(config.password_for(&share), None);
+        assert_eq!(config.api_token_for(&share), Some("other-token"));
+        // nor are its own given to the general server
+        let general = url("https://cloud.example/d/6e5297246c/");
+        assert_eq!(config.password_for(&general), None);
+        assert_eq!(config.api_token_for(&general), None);
+
+        let config = profile("bare");
+        let share = url("https://bare.example/d/6e5297246c/");
+        assert_eq!(config.password_for(&share), None);
+        assert_eq!(config.api_token_for(&share), None);
+    }
+
+    #[test]
+    fn credentials_are_not_given_to_other_origins() {
+        let general: Config = toml::from_str(CONFIG).unwrap();
+        for config in [general, profile("same"), profile("other")] {
+            for other in [
+                "https://evil.example/d/6e5297246c/",
+                "http://cloud.example/d/6e5297246c/",
+                "https://cloud.example:8443/d/6e5297246c/",
+                "https://files.example.org.evil.example/d/6e5297246c/",
+                "https://sub.cloud.example/d/6e5297246c/",
+            ] {
+                let other = url(other);
+                assert_eq!(config.password_for(&other), None, "{}", other);
+                assert_eq!(config.api_token_for(&other), None, "{}", other);
+            }
+        }
+    }
+
+    #[test]
+    fn credentials_need_the_url_of_their_server() {
+        let config: Config = toml::from_str(r#"password = "secret""#).unwrap();
+        assert!(config.check().is_err());
+        let config: Config = toml::from_str(
+            r#"
+            [profiles.work]
+            api_token = "token"
+            "#,
+        )
+        .unwrap();
+        assert!(config.check().is_err());
+    }
+}
@@ -0,0 +1,55 @@
+//! API tokens of "auth login", kept in the keyring of the system (the
+//! Keychain on macOS, the Credential Manager on Windows and the kernel
+//! keyring on Linux) under the origin of their server.
+
+use std::io::{BufRead, IsTerminal};
+
+use anyhow::Context;
+use url::Url;
+
+/// Service the tokens are stored under
+const SERVICE: &str = "seaf-share";
+
+fn entry(server: &Url) -> anyhow::Result<keyring::Entry> {
+    keyring::Entry::new(SERVICE, &server.origin().ascii_serialization())
+        .context("cannot open the keyring")
+}
+
+/// Token stored for the server of `server`
+pub fn token(server: &Url) -> anyhow::Result<Option<String>> {
+    match entry(server)?.get_password() {
+        Ok(token) => Ok(Some(token)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(e).context("cannot read the keyring"),
+    }
+}
+
+pub fn store(server: &Url, token: &str) -> anyhow::Result<()> {
+    entry(server)?
+        .set_password(token)
+        .context("cannot write to the keyring")
+}
+
+/// Removes the token of the server of `server`, returning whether there was
+/// one.
+pub fn remove(server: &Url) -> anyhow::Result<bool> {
+    match entry(server)?.delete_credential() {
+        Ok(()) => Ok(true),
+        Err(keyring::Error::NoEntry) => Ok(false),
+        Err(e) => Err(e).context("cannot write to the keyring"),
+    }
+}
+
+/// Line read from stdin after showing `label`, without echoing it in a
+/// terminal if `hidden`
+pub fn prompt(label: &str, hidden: bool) -> anyhow::Result<String> {
+    if hidden && std::io::stdin().is_terminal() {
+        return Ok(rpassword::prompt_password(label)?);
+    }
+    eprint!("{}", label);
+    let mut line = String::new();
+    if std::io::stdin().lock().read_line(&mut line)? == 0 {
+        anyhow::bail!("no input for {}", label.trim_end_matches([':', ' ']));
+    }
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
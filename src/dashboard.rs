@@ -0,0 +1,340 @@
+//! Live display of a download run on stderr: a line per transfer in flight,
+//! the listing of a large directory, an overall line and the most recent
+//! error. Lines logged through it (to stderr, or to the output of the run)
+//! scroll by above the display. Keys pause the run, skip files and adjust the
+//! bandwidth limit. Without the display, a status line can be logged at an
+//! interval instead.
+
+use std::{
+    fmt::Display,
+    io::Write,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use human_bytes::human_bytes;
+
+use seaf_share_core::{
+    progress::{self, Progress},
+    redact::{self, Redacted},
+    seafile::Estimate,
+};
+
+use crate::keys;
+
+/// Time between redraws
+const REFRESH: Duration = Duration::from_millis(250);
+/// Width of the overall progress bar
+const BAR_WIDTH: usize = 20;
+/// Lowest bandwidth limit the "-" key sets
+const MIN_RATE_LIMIT: u64 = 1024;
+
+#[derive(Default)]
+struct State {
+    /// Lines currently drawn
+    lines: usize,
+    done: u64,
+    failed: u64,
+    last_error: Option<String>,
+}
+
+pub struct Dashboard<'a> {
+    progress: Progress,
+    /// Files and bytes expected, if estimated
+    total: Option<Estimate>,
+    started: Instant,
+    /// Whether the display is drawn, otherwise only lines are logged
+    live: bool,
+    /// Time between status lines while the display is not drawn
+    stats_interval: Option<Duration>,
+    /// Whether keys are read
+    keys: bool,
+    state: Mutex<State>,
+    out: Mutex<&'a mut (dyn Write + Send)>,
+}
+
+impl<'a> Dashboard<'a> {
+    pub fn new(
+        progress: Progress,
+        total: Option<Estimate>,
+        live: bool,
+        keys: bool,
+        out: &'a mut (dyn Write + Send),
+    ) -> Self {
+        Self {
+            progress,
+            total,
+            started: Instant::now(),
+            live,
+            stats_interval: None,
+            keys: live && keys,
+            state: Mutex::default(),
+            out: Mutex::new(out),
+        }
+    }
+
+    /// Logs a status line to stderr every `interval` while the display is
+    /// not drawn.
+    pub fn with_stats_interval(mut self, interval: Option<Duration>) -> Self {
+        self.stats_interval = interval;
+        self
+    }
+
+    /// Output of the run, once the display is done with
+    pub fn into_output(self) -> &'a mut (dyn Write + Send) {
+        self.out.into_inner().unwrap()
+    }
+
+    /// Logs a line to the output of the run.
+    pub fn println(&self, line: impl Display) {
+        self.log(|| {
+            // like println!, but a closed output is not worth a panic
+            let _ = writeln!(self.out.lock().unwrap(), "{}", line);
+        });
+    }
+
+    /// Logs a line to stderr.
+    pub fn eprintln(&self, line: impl Display) {
+        self.log(|| eprintln!("{}", Redacted(line)));
+    }
+
+    /// Counts a finished file, keeping the error of a failed one on display.
+    pub fn finished(&self, error: Option<String>) {
+        let mut state = self.state.lock().unwrap();
+        match error {
+            Some(e) => {
+                state.failed += 1;
+                state.last_error = Some(redact::redact(&e).into_owned());
+            }
+            None => state.done += 1,
+        }
+    }
+
+    /// Redraws the display until `stop` is set, then removes it. Keys are
+    /// read meanwhile if enabled. Without the display, logs status lines if
+    /// enabled.
+    pub fn run(&self, stop: &AtomicBool) {
+        if !self.live {
+            if let Some(interval) = self.stats_interval {
+                self.report(stop, interval);
+            }
+            return;
+        }
+        if self.keys {
+            std::thread::scope(|scope| {
+                scope.spawn(|| keys::listen(stop, |key| self.key(key)));
+                self.redraw(stop);
+            });
+        } else {
+            self.redraw(stop);
+        }
+    }
+
+    fn redraw(&self, stop: &AtomicBool) {
+        while !stop.load(Ordering::Relaxed) {
+            std::thread::sleep(REFRESH);
+            let mut state = self.state.lock().unwrap();
+            self.clear(&mut state);
+            self.draw(&mut state);
+        }
+        self.clear(&mut self.state.lock().unwrap());
+    }
+
+    fn report(&self, stop: &AtomicBool, interval: Duration) {
+        let mut next = self.started + interval;
+        while !stop.load(Ordering::Relaxed) {
+            std::thread::sleep(REFRESH);
+            if Instant::now() < next {
+                continue;
+            }
+            next += interval;
+            let line = self.status(&self.state.lock().unwrap());
+            self.eprintln(line);
+        }
+    }
+
+    /// Status line for logs, e.g. "[0:01:30] 12/56 files done, 1 failed,
+    /// 3.2 MiB of 10 MiB at 400 KiB/s, 0:00:17 left"
+    fn status(&self, state: &State) -> String {
+        let elapsed = self.started.elapsed();
+        let received = self.progress.received();
+        let rate = progress::rate(received, elapsed);
+        let about = match self.total {
+            Some(total) if total.sampled => "~",
+            _ => "",
+        };
+        let mut line = format!("[{}] ", clock(elapsed));
+        match self.total {
+            Some(total) => line.push_str(&format!(
+                "{}/{}{} files done, {} failed, {} of {}{}",
+                state.done,
+                about,
+                total.files,
+                state.failed,
+                human_bytes(received as f64),
+                about,
+                human_bytes(total.bytes as f64),
+            )),
+            None => line.push_str(&format!(
+                "{} files done, {} failed, {}",
+                state.done,
+                state.failed,
+                human_bytes(received as f64),
+            )),
+        }
+        line.push_str(&format!(" at {}/s", human_bytes(rate)));
+        if let Some(total) = self.total.filter(|_| rate > 0.0) {
+            let left = total.bytes.saturating_sub(received) as f64 / rate;
+            line.push_str(&format!(
+                ", {}{} left",
+                about,
+                clock(Duration::from_secs_f64(left))
+            ));
+        }
+        if self.progress.is_paused() {
+            line.push_str(" — paused");
+        }
+        line
+    }
+
+    fn key(&self, key: u8) {
+        match key {
+            b'p' if self.progress.is_paused() => self.progress.resume(),
+            b'p' => self.progress.pause(),
+            b'1'..=b'9' => {
+                let active = self.progress.active();
+                if let Some(transfer) = active.get(usize::from(key - b'1')) {
+                    transfer.skip();
+                }
+            }
+            b'-' => {
+                let current = self.progress.rate_limit().unwrap_or_else(|| {
+                    progress::rate(self.progress.received(), self.started.elapsed()) as u64
+                });
+                let limit = (current / 4 * 3).max(MIN_RATE_LIMIT);
+                self.progress.set_rate_limit(Some(limit));
+            }
+            b'+' => {
+                if let Some(limit) = self.progress.rate_limit() {
+                    self.progress.set_rate_limit(Some(limit / 3 * 4));
+                }
+            }
+            b'u' => self.progress.set_rate_limit(None),
+            _ => {}
+        }
+    }
+
+    fn log(&self, print: impl FnOnce()) {
+        let mut state = self.state.lock().unwrap();
+        self.clear(&mut state);
+        print();
+        if self.live {
+            self.draw(&mut state);
+        }
+    }
+
+    fn clear(&self, state: &mut State) {
+        if state.lines > 0 {
+            // move to the first line drawn and clear everything below
+            eprint!("\x1b[{}A\x1b[J", state.lines);
+            state.lines = 0;
+        }
+    }
+
+    fn draw(&self, state: &mut State) {
+        let width = terminal_width();
+        let mut lines = Vec::new();
+        for (i, transfer) in self.progress.active().iter().enumerate() {
+            let percent = match transfer.size() {
+                Some(size) if size > 0 => format!("{:>3}%", transfer.position() * 100 / size),
+                _ => "   ?".to_string(),
+            };
+            let number = match i {
+                0..9 if self.keys => format!("{} ", i + 1),
+                _ if self.keys => "  ".to_string(),
+                _ => String::new(),
+            };
+            lines.push(format!(
+                "{}{} {:>10} {:>10}/s  {}",
+                number,
+                percent,
+                human_bytes(transfer.position() as f64),
+                human_bytes(transfer.speed()),
+                transfer.path().display(),
+            ));
+        }
+
+        if let Some((dir, entries)) = self.progress.listing() {
+            lines.push(format!(
+                "listing {}: {} entries so far",
+                dir.display(),
+                entries
+            ));
+        }
+
+        let received = self.progress.received();
+        let finished = state.done + state.failed;
+        let bar = match self.total.map(|total| total.files) {
+            Some(total) if total > 0 => {
+                let finished = finished.min(total);
+                let filled = (finished * BAR_WIDTH as u64 / total) as usize;
+                format!(
+                    "[{}{}] {}/{} files, ",
+                    "#".repeat(filled),
+                    "-".repeat(BAR_WIDTH - filled),
+                    finished,
+                    total,
+                )
+            }
+            _ => String::new(),
+        };
+        lines.push(format!(
+            "{}{} done, {} failed, {} at {}/s",
+            bar,
+            state.done,
+            state.failed,
+            human_bytes(received as f64),
+            human_bytes(progress::rate(received, self.started.elapsed())),
+        ));
+        if let Some(limit) = self.progress.rate_limit() {
+            let last = lines.last_mut().unwrap();
+            last.push_str(&format!(" (limit {}/s)", human_bytes(limit as f64)));
+        }
+        if self.progress.is_paused() {
+            lines.last_mut().unwrap().push_str(" — paused");
+        }
+        if let Some(e) = &state.last_error {
+            lines.push(format!("last error: {}", e));
+        }
+        if self.keys {
+            lines.push(
+                "p: pause/resume, 1-9: skip file, -/+: lower/raise limit, u: no limit".to_string(),
+            );
+        }
+
+        let mut out = String::new();
+        for line in &lines {
+            // wrapped lines would throw off clearing
+            out.extend(line.chars().take(width.saturating_sub(1)));
+            out.push('\n');
+        }
+        eprint!("{}", out);
+        state.lines = lines.len();
+    }
+}
+
+/// `duration` as hours, minutes and seconds, e.g. "1:02:03"
+fn clock(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+fn terminal_width() -> usize {
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|c| c.parse().ok())
+        .unwrap_or(80)
+}
@@ -0,0 +1,253 @@
+//! Examples of the commands, shown at the end of their "--help" and by the
+//! examples command. In debug builds, every example is parsed when the
+//! command line is built, so that one that no longer fits the flags fails
+//! right away.
+
+use clap::builder::PossibleValue;
+
+pub struct Example {
+    /// Command that the example is shown for
+    pub command: &'static str,
+    pub description: &'static str,
+    /// Arguments after the command name
+    pub args: &'static [&'static str],
+}
+
+const SHARE: &str = "https://cloud.example/d/6e5297246c/";
+
+pub const EXAMPLES: &[Example] = &[
+    Example {
+        command: "list",
+        description: "List a shared directory",
+        args: &[SHARE],
+    },
+    Example {
+        command: "list",
+        description: "List a subdirectory, given the URL of its page",
+        args: &["https://cloud.example/d/6e5297246c/?p=%2Fphotos&mode=list"],
+    },
+    Example {
+        command: "list",
+        description: "List everything below a subdirectory as JSON",
+        args: &["-r", "--json", "-p", "/photos", SHARE],
+    },
+    Example {
+        command: "list",
+        description: "Print the size and path of every file, for scripts",
+        args: &["-r", "--printf", r"%s\t%p\n", SHARE],
+    },
+    Example {
+        command: "download",
+        description: "Download a directory recursively into ./data",
+        args: &["-r", "-o", "data", SHARE],
+    },
+    Example {
+        command: "download",
+        description: "Download a single file share",
+        args: &["https://cloud.example/f/9c4e2a7b1d/"],
+    },
+    Example {
+        command: "download",
+        description: "Download only CSV files, 4 at a time",
+        args: &["-r", "-j", "4", "--include", "/**/*.csv", SHARE],
+    },
+    Example {
+        command: "download",
+        description: "Keep the progress of a large download, to continue it after an interruption",
+        args: &["-r", "--session", "data.session", "-o", "data", SHARE],
+    },
+    Example {
+        command: "download",
+        description: "Download a copy per day, named after the shared library",
+        args: &["-r", "-o", "shares/{repo_name}-{date}", SHARE],
+    },
+    Example {
+        command: "download",
+        description: "Archive a share onto a FAT32 drive, in parts of files over 4000 MiB",
+        args: &["-r", "--split-size", "4000M", "-o", "/media/usb", SHARE],
+    },
+    Example {
+        command: "download",
+        description: "Put files over 1 GiB on a disk pool and photos on another",
+        args: &[
+            "-r",
+            "--route",
+            ">1G=/mnt/bulk",
+            "--route",
+            "*.jpg=/mnt/photos",
+            "-o",
+            "/srv/data",
+            SHARE,
+        ],
+    },
+    Example {
+        command: "sync",
+        description: "Mirror a share nightly (e.g. from cron), deleting files removed from it",
+        args: &["-r", "--delete", "-o", "/srv/mirror", SHARE],
+    },
+    Example {
+        command: "sync",
+        description: "Publish a new copy of a share only once it is complete",
+        args: &["-r", "--stage-then-swap", "-o", "/srv/mirror", SHARE],
+    },
+    Example {
+        command: "watch",
+        description: "Keep a laptop's copy of a share current, logging outages",
+        args: &[
+            "-r",
+            "--interval",
+            "600",
+            "--events",
+            "watch.ndjson",
+            "-o",
+            "shared",
+            SHARE,
+        ],
+    },
+    Example {
+        command: "sync",
+        description: "Report each run of a cron job to healthchecks.io",
+        args: &[
+            "-r",
+            "--healthcheck-url",
+            "https://hc-ping.com/your-uuid",
+            "-o",
+            "/srv/mirror",
+            SHARE,
+        ],
+    },
+    Example {
+        command: "find",
+        description: "Find PDF files by name, with the search of the server where it has one",
+        args: &["--name", "*.pdf", SHARE],
+    },
+    Example {
+        command: "find",
+        description: "Find files stored more than once",
+        args: &["--duplicates", SHARE],
+    },
+    Example {
+        command: "du",
+        description: "Show the 10 largest files and directories",
+        args: &["--top", "10", SHARE],
+    },
+    Example {
+        command: "tree",
+        description: "Show the first two levels of a share",
+        args: &["-L", "2", SHARE],
+    },
+    Example {
+        command: "info",
+        description: "Check when a link expires, before scheduling a large mirror",
+        args: &[SHARE],
+    },
+    Example {
+        command: "cat",
+        description: "Write a file to stdout",
+        args: &["-p", "/notes.txt", SHARE],
+    },
+    Example {
+        command: "verify",
+        description: "Check a downloaded copy, comparing sizes only",
+        args: &["--size-only", "-o", "data", SHARE],
+    },
+    Example {
+        command: "upload",
+        description: "Upload a file and a directory through an upload link",
+        args: &[
+            "https://cloud.example/u/d/3f8a1c9e5b/",
+            "report.pdf",
+            "figures/",
+        ],
+    },
+    Example {
+        command: "join",
+        description: "Put a file written in parts together again, deleting the parts",
+        args: &["--remove-parts", "video.mp4"],
+    },
+    Example {
+        command: "libraries",
+        description: "List the libraries of the account whose API token is in \
+                      SEAF_SHARE_API_TOKEN",
+        args: &["https://cloud.example/"],
+    },
+    Example {
+        command: "auth",
+        description: "Log in once, so that the libraries of the account are read without \
+                      --api-token",
+        args: &[
+            "login",
+            "--username",
+            "me@example.org",
+            "https://cloud.example/",
+        ],
+    },
+];
+
+/// Commands with examples, for the examples command
+pub fn topics() -> Vec<PossibleValue> {
+    let mut topics = Vec::new();
+    for example in EXAMPLES {
+        if !topics.contains(&example.command) {
+            topics.push(example.command);
+        }
+    }
+    topics.into_iter().map(PossibleValue::new).collect()
+}
+
+/// Examples of `command` as a help section, if it has any
+pub fn help(command: &str) -> Option<String> {
+    let mut examples = EXAMPLES.iter().filter(|e| e.command == command).peekable();
+    examples.peek()?;
+    let mut help = "Examples:\n".to_string();
+    for example in examples {
+        help.push_str(&format!("  # {}\n  {}\n", example.description, example));
+    }
+    Some(help)
+}
+
+/// Prints the examples of `command`, or of all commands
+pub fn print(command: Option<&str>) {
+    let mut last = None;
+    for example in EXAMPLES {
+        if command.is_some_and(|command| command != example.command) {
+            continue;
+        }
+        if last.is_some_and(|last| last != example.command) {
+            println!();
+        }
+        println!("# {}\n{}", example.description, example);
+        last = Some(example.command);
+    }
+}
+
+/// Parses every example, panicking at the first one that does not fit the
+/// command line `cli`
+#[cfg(debug_assertions)]
+pub fn check(cli: &clap::Command) {
+    for example in EXAMPLES {
+        let args = [cli.get_name(), example.command]
+            .into_iter()
+            .chain(example.args.iter().copied());
+        if let Err(e) = cli.clone().try_get_matches_from(args) {
+            panic!("example \"{}\" is invalid: {}", example, e);
+        }
+    }
+}
+
+impl std::fmt::Display for Example {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "seaf-share {}", self.command)?;
+        for arg in self.args {
+            let plain = arg
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_./:=%,".contains(c));
+            if plain {
+                write!(f, " {}", arg)?;
+            } else {
+                write!(f, " '{}'", arg)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,39 @@
+//! Exit statuses, so that scripts can tell failures apart. Usage errors exit
+//! with 2 (from clap), and Ctrl-C and SIGTERM with 128 plus the signal number.
+
+use std::process::ExitCode;
+
+use seaf_share_core::{seafile, summary::Summary};
+
+/// Any other error
+const ERROR: u8 = 1;
+/// The share link is invalid or gone, its password is missing or wrong, it
+/// is view-only, or the API token or the login is not accepted
+const INVALID_SHARE: u8 = 3;
+/// Some files could not be downloaded, or some directories not listed
+const PARTIAL_FAILURE: u8 = 4;
+/// None of the files could be downloaded
+const ALL_FAILED: u8 = 5;
+
+/// Status of a run that ended with `e`
+pub fn of_error(e: &anyhow::Error) -> ExitCode {
+    let invalid_share = e.chain().any(|cause| {
+        let http = match cause.downcast_ref::<seaf_share_core::Error>() {
+            Some(seaf_share_core::Error::Share(_)) => return true,
+            Some(seaf_share_core::Error::Http(e)) => Some(e),
+            _ => cause.downcast_ref::<ureq::Error>(),
+        };
+        // the share link was deleted or has expired
+        cause.is::<seafile::Error>() || matches!(http, Some(ureq::Error::StatusCode(404)))
+    });
+    ExitCode::from(if invalid_share { INVALID_SHARE } else { ERROR })
+}
+
+/// Status of a download run with the totals `summary`
+pub fn of_summary(summary: &Summary) -> ExitCode {
+    match summary.failed {
+        _ if summary.is_complete() => ExitCode::SUCCESS,
+        failed if failed > 0 && failed == summary.files() => ExitCode::from(ALL_FAILED),
+        _ => ExitCode::from(PARTIAL_FAILURE),
+    }
+}
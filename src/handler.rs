@@ -0,0 +1,196 @@
+//! `seaf-share://` and `seafile://` links, which let a browser hand a share
+//! URL to this tool once it is registered as their scheme handler.
+//!
+//! The payload is the share URL without its scheme (`seaf-share://cloud.example/d/abc`
+//! stands for `https://cloud.example/d/abc`), or the full URL for shares not
+//! served over HTTPS (`seaf-share:http://cloud.example/d/abc`).
+
+use std::process::Command;
+
+use anyhow::Context;
+use url::Url;
+
+use crate::cli::HandlerOptions;
+
+/// Schemes handled by this tool
+pub const SCHEMES: [&str; 2] = ["seaf-share", "seafile"];
+
+/// Parses a share URL given on the command line, unwrapping scheme handler
+/// payloads.
+pub fn parse_url(s: &str) -> Result<Url, String> {
+    let url = match SCHEMES.iter().find_map(|scheme| strip_scheme(s, scheme)) {
+        Some(payload) => {
+            let payload = payload.strip_prefix("//").unwrap_or(payload);
+            if payload.starts_with("http://") || payload.starts_with("https://") {
+                Url::parse(payload)
+            } else {
+                Url::parse(&format!("https://{}", payload))
+            }
+        }
+        None => Url::parse(s),
+    };
+    url.map_err(|e| e.to_string())
+}
+
+fn strip_scheme<'a>(s: &'a str, scheme: &str) -> Option<&'a str> {
+    let (prefix, rest) = s.split_once(':')?;
+    prefix.eq_ignore_ascii_case(scheme).then_some(rest)
+}
+
+/// Registers the running executable as the handler of [`SCHEMES`] for the
+/// current user. Opened links are downloaded recursively to the output
+/// directory in a terminal, so that prompts and progress stay visible.
+pub fn install(options: &HandlerOptions) -> anyhow::Result<()> {
+    let exe = std::env::current_exe().with_context(|| "cannot locate executable")?;
+    let output = std::path::absolute(options.output())?;
+    platform::install(&exe, &output, options.dry_run())
+}
+
+#[cfg_attr(target_os = "macos", allow(dead_code))]
+fn run(command: &mut Command, dry_run: bool) -> anyhow::Result<()> {
+    if dry_run {
+        println!("{:?}", command);
+        return Ok(());
+    }
+    let status = command
+        .status()
+        .with_context(|| format!("cannot run {:?}", command.get_program()))?;
+    if !status.success() {
+        anyhow::bail!("{:?} failed: {}", command.get_program(), status);
+    }
+    Ok(())
+}
+
+#[cfg(all(unix, not(target_os = "macos")))]
+mod platform {
+    use std::path::{Path, PathBuf};
+
+    use super::{run, Command, SCHEMES};
+
+    const DESKTOP_FILE: &str = "seaf-share.desktop";
+
+    /// Writes a desktop entry (freedesktop.org) and makes it the default
+    /// application of the schemes.
+    pub fn install(exe: &Path, output: &Path, dry_run: bool) -> anyhow::Result<()> {
+        let exec = [
+            exe,
+            Path::new("download"),
+            Path::new("-r"),
+            Path::new("-o"),
+            output,
+        ]
+        .iter()
+        .map(|arg| quote(&arg.to_string_lossy()))
+        .collect::<Vec<_>>()
+        .join(" ");
+        let mime_types: String = SCHEMES
+            .iter()
+            .map(|scheme| format!("x-scheme-handler/{};", scheme))
+            .collect();
+        let entry = format!(
+            "[Desktop Entry]\n\
+             Type=Application\n\
+             Name=seaf-share\n\
+             Comment=Download Seafile shares\n\
+             Exec={} %u\n\
+             Terminal=true\n\
+             NoDisplay=true\n\
+             MimeType={}\n",
+            exec, mime_types,
+        );
+
+        let path = applications_dir()?.join(DESKTOP_FILE);
+        if dry_run {
+            println!("{}:\n{}", path.display(), entry);
+        } else {
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            std::fs::write(&path, entry)?;
+            eprintln!("Wrote {}", path.display());
+        }
+        for scheme in SCHEMES {
+            run(
+                Command::new("xdg-mime")
+                    .args(["default", DESKTOP_FILE])
+                    .arg(format!("x-scheme-handler/{}", scheme)),
+                dry_run,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn applications_dir() -> anyhow::Result<PathBuf> {
+        let data_home = match std::env::var_os("XDG_DATA_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => match std::env::var_os("HOME") {
+                Some(home) => PathBuf::from(home).join(".local/share"),
+                None => anyhow::bail!("cannot locate home directory"),
+            },
+        };
+        Ok(data_home.join("applications"))
+    }
+
+    /// Quotes an argument of the "Exec" key, which is itself escaped as a
+    /// string value.
+    fn quote(arg: &str) -> String {
+        let mut quoted = String::from("\"");
+        for c in arg.chars() {
+            match c {
+                '"' | '`' | '$' => quoted.push_str(&format!("\\\\{}", c)),
+                '\\' => quoted.push_str("\\\\\\\\"),
+                '%' => quoted.push_str("%%"),
+                _ => quoted.push(c),
+            }
+        }
+        quoted.push('"');
+        quoted
+    }
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::path::Path;
+
+    use super::{run, Command, SCHEMES};
+
+    /// Registers the schemes under "HKEY_CURRENT_USER\Software\Classes".
+    pub fn install(exe: &Path, output: &Path, dry_run: bool) -> anyhow::Result<()> {
+        let command = format!(
+            "\"{}\" download -r -o \"{}\" \"%1\"",
+            exe.display(),
+            output.display(),
+        );
+        for scheme in SCHEMES {
+            let key = format!(r"HKCU\Software\Classes\{}", scheme);
+            let url_protocol = format!("URL:{}", scheme);
+            reg_add(&key, None, &url_protocol, dry_run)?;
+            reg_add(&key, Some("URL Protocol"), "", dry_run)?;
+            reg_add(
+                &format!(r"{}\shell\open\command", key),
+                None,
+                &command,
+                dry_run,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn reg_add(key: &str, value: Option<&str>, data: &str, dry_run: bool) -> anyhow::Result<()> {
+        let mut command = Command::new("reg");
+        command.args(["add", key]);
+        match value {
+            Some(value) => command.args(["/v", value]),
+            None => command.arg("/ve"),
+        };
+        run(command.args(["/d", data, "/f"]), dry_run)
+    }
+}
+
+#[cfg(not(any(windows, all(unix, not(target_os = "macos")))))]
+mod platform {
+    use std::path::Path;
+
+    pub fn install(_exe: &Path, _output: &Path, _dry_run: bool) -> anyhow::Result<()> {
+        // macOS only routes URL schemes to application bundles
+        anyhow::bail!("installing a scheme handler is not supported on this platform");
+    }
+}
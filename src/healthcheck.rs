@@ -0,0 +1,80 @@
+//! Pings of a monitor like healthchecks.io for "--healthcheck-url": `URL/start`
+//! when a run starts, then `URL` if it succeeded or `URL/fail` if it did not,
+//! with the totals of the run as JSON (or the error that ended it) as body,
+//! so that a cron job that fails or stops running is noticed.
+
+use std::{
+    sync::{Mutex, OnceLock},
+    time::Duration,
+};
+
+use url::Url;
+
+use seaf_share_core::{
+    redact::{self, Redacted},
+    summary::Summary,
+};
+
+/// Time a ping may take, so that a monitor that hangs does not hold up the run
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+struct Monitor {
+    url: Url,
+    agent: ureq::Agent,
+    /// Body of the ping at the end of the run
+    body: Mutex<String>,
+}
+
+static MONITOR: OnceLock<Monitor> = OnceLock::new();
+
+/// Pings `url` for the start of the run, whose end is pinged by [`finish`].
+pub fn start(url: &Url) {
+    let agent = ureq::Agent::config_builder()
+        .timeout_global(Some(TIMEOUT))
+        .build()
+        .into();
+    // the URL of a check is enough to ping it
+    redact::secret(url.as_str().trim_end_matches('/'));
+    let monitor = MONITOR.get_or_init(|| Monitor {
+        url: url.clone(),
+        agent,
+        body: Mutex::default(),
+    });
+    monitor.ping(Some("start"), String::new());
+}
+
+/// Notes the totals of the run, which the ping of its end carries.
+pub fn summary(summary: &Summary) {
+    if let (Some(monitor), Ok(json)) = (MONITOR.get(), serde_json::to_string(summary)) {
+        *monitor.body.lock().unwrap() = json;
+    }
+}
+
+/// Pings the end of the run, if it was started with [`start`].
+pub fn finish(success: bool) {
+    if let Some(monitor) = MONITOR.get() {
+        let body = std::mem::take(&mut *monitor.body.lock().unwrap());
+        monitor.ping((!success).then_some("fail"), body);
+    }
+}
+
+/// Pings the failure of the run with the error that ended it.
+pub fn fail(e: &anyhow::Error) {
+    if let Some(monitor) = MONITOR.get() {
+        *monitor.body.lock().unwrap() = format!("{:#}", Redacted(e));
+    }
+    finish(false);
+}
+
+impl Monitor {
+    fn ping(&self, kind: Option<&str>, body: String) {
+        let mut url = self.url.clone();
+        if let (Some(kind), Ok(mut segments)) = (kind, url.path_segments_mut()) {
+            segments.pop_if_empty().push(kind);
+        }
+        // a monitor that is down must not fail the run it watches
+        if let Err(e) = self.agent.post(url.as_str()).send(body) {
+            eprintln!("could not ping {}: {}", Redacted(&url), Redacted(e));
+        }
+    }
+}
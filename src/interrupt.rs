@@ -0,0 +1,62 @@
+//! Ctrl-C and SIGTERM during a download. The first signal stops the run from
+//! starting further files while the transfers in progress complete, the
+//! second aborts those (see `shutdown` in the core crate) and the third ends
+//! the process at once.
+
+use std::sync::{
+    atomic::{AtomicI32, AtomicUsize, Ordering},
+    OnceLock,
+};
+
+use seaf_share_core::shutdown::Abort;
+
+/// Signals received so far
+static COUNT: AtomicUsize = AtomicUsize::new(0);
+/// Number of the last signal received
+static SIGNAL: AtomicI32 = AtomicI32::new(0);
+/// Triggered by the second signal
+static ABORT: OnceLock<Abort> = OnceLock::new();
+
+/// Handles SIGINT and SIGTERM for the rest of the process, triggering `abort`
+/// on the second one.
+#[cfg(unix)]
+pub fn install(abort: &Abort) {
+    let _ = ABORT.set(abort.clone());
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        unsafe { libc::signal(signal, on_signal as *const () as libc::sighandler_t) };
+    }
+}
+
+/// Ctrl-C ends the process right away where signals are not handled.
+#[cfg(not(unix))]
+pub fn install(_abort: &Abort) {}
+
+/// Whether the handler is installed, so that others leave the signals to it
+pub fn is_installed() -> bool {
+    ABORT.get().is_some()
+}
+
+/// Whether the run was asked to stop
+pub fn is_requested() -> bool {
+    COUNT.load(Ordering::Relaxed) > 0
+}
+
+/// The signal that stopped the run, if any
+pub fn signal() -> Option<i32> {
+    is_requested().then(|| SIGNAL.load(Ordering::Relaxed))
+}
+
+#[cfg(unix)]
+extern "C" fn on_signal(signal: libc::c_int) {
+    // only atomics are touched here, which is async-signal-safe
+    SIGNAL.store(signal, Ordering::Relaxed);
+    match COUNT.fetch_add(1, Ordering::Relaxed) {
+        0 => {}
+        1 => {
+            if let Some(abort) = ABORT.get() {
+                abort.trigger();
+            }
+        }
+        _ => crate::keys::restore_and_raise(signal),
+    }
+}
@@ -0,0 +1,98 @@
+//! Single key presses read from a terminal while a download runs.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Calls `on_key` with every key pressed until `stop` is set. Keys are read
+/// without echo or waiting for Enter; Ctrl-C still interrupts.
+#[cfg(unix)]
+pub fn listen(stop: &AtomicBool, mut on_key: impl FnMut(u8)) {
+    let Some(_raw) = RawMode::enable() else {
+        return;
+    };
+    while !stop.load(Ordering::Relaxed) {
+        let mut poll = libc::pollfd {
+            fd: libc::STDIN_FILENO,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // wake up regularly to notice `stop`
+        if unsafe { libc::poll(&mut poll, 1, 100) } <= 0 {
+            continue;
+        }
+        let mut key = 0u8;
+        match unsafe { libc::read(libc::STDIN_FILENO, (&mut key as *mut u8).cast(), 1) } {
+            1 => on_key(key),
+            // end of input
+            0 => return,
+            _ => continue,
+        }
+    }
+}
+
+#[cfg(not(unix))]
+pub fn listen(_stop: &AtomicBool, _on_key: impl FnMut(u8)) {}
+
+/// Terminal settings before raw mode, restored on drop or when a signal
+/// ends the process
+#[cfg(unix)]
+static SAVED: std::sync::OnceLock<libc::termios> = std::sync::OnceLock::new();
+
+#[cfg(unix)]
+struct RawMode;
+
+#[cfg(unix)]
+impl RawMode {
+    fn enable() -> Option<Self> {
+        let mut termios = std::mem::MaybeUninit::<libc::termios>::uninit();
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, termios.as_mut_ptr()) } != 0 {
+            return None;
+        }
+        let saved = *SAVED.get_or_init(|| unsafe { termios.assume_init() });
+        let mut raw = saved;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        // a graceful stop keeps raw mode until the display is removed
+        let signals: &[_] = if crate::interrupt::is_installed() {
+            &[libc::SIGHUP]
+        } else {
+            &[libc::SIGINT, libc::SIGTERM, libc::SIGHUP]
+        };
+        unsafe {
+            for &signal in signals {
+                libc::signal(signal, on_signal as *const () as libc::sighandler_t);
+            }
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+                return None;
+            }
+        }
+        Some(Self)
+    }
+}
+
+#[cfg(unix)]
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        if let Some(saved) = SAVED.get() {
+            unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, saved) };
+        }
+    }
+}
+
+#[cfg(unix)]
+extern "C" fn on_signal(signal: libc::c_int) {
+    restore_and_raise(signal);
+}
+
+/// Restores the terminal and lets the signal take its default course.
+#[cfg(unix)]
+pub fn restore_and_raise(signal: libc::c_int) {
+    // tcsetattr, signal and raise are async-signal-safe
+    unsafe {
+        if let Some(saved) = SAVED.get() {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, saved);
+        }
+        libc::signal(signal, libc::SIG_DFL);
+        libc::raise(signal);
+    }
+}
@@ -1,459 +1,750 @@
 mod cli;
-mod seafile;
+mod clipboard;
+mod commands;
+mod config;
+mod credentials;
+mod dashboard;
+mod examples;
+mod exit;
+mod handler;
+mod healthcheck;
+mod interrupt;
+mod keys;
+mod output;
+mod printf;
 
 use std::{
-    collections::VecDeque,
     path::{Path, PathBuf},
-    str::FromStr,
+    process::ExitCode,
 };
 
 use anyhow::Context;
-use chrono::{DateTime, Utc};
-use clap::Parser;
-use cli_table::{Cell, Table};
 use human_bytes::human_bytes;
-use regex::{Regex, RegexSet};
-use serde::{Deserialize, Serialize};
-use url::Url;
 
-use cli::{Cli, Command, ConflictAction, DownloadOptions, Recursive};
+use seaf_share_core::{
+    auth, cache, duplicates, ncdu,
+    progress::Progress,
+    redact::{self, Redacted},
+    retry::Retry,
+    seafile,
+    seed::Seed,
+    stage, stall,
+    summary::Summary,
+    sync, tape,
+    tree::Tree,
+    upload,
+    usage::Usage,
+    verify::Verifier,
+    volume,
+    walker::DirEntryStream,
+    ConflictAction, DirEntry, Downloader, Recursive, ShareLink,
+};
+
+use cli::{AuthCommand, Cli, Command};
+use commands::{download_roots, STREAM_CAPACITY};
+use config::Config;
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
-enum DownloadResult {
-    Skipped,
-    Overwritten,
-    Continued,
-    Complete,
+/// Every entry below the link (or `path`), listed breadth-first
+fn walk(
+    client: &seafile::Client,
+    link: &ShareLink,
+    path: Option<&Path>,
+) -> anyhow::Result<DirEntryStream> {
+    if link.is_dir() {
+        return Ok(DirEntryStream::spawn_dir(
+            client,
+            link.token(),
+            path,
+            Recursive::Bfs,
+            |_| true,
+            STREAM_CAPACITY,
+            DirEntryStream::MEMORY_LIMIT,
+            None,
+        ));
+    }
+    let roots = download_roots(client, link, path)?;
+    Ok(DirEntryStream::spawn(
+        client,
+        link.token(),
+        roots,
+        Recursive::Bfs,
+        |_| true,
+        STREAM_CAPACITY,
+        DirEntryStream::MEMORY_LIMIT,
+        None,
+    ))
 }
 
-impl std::fmt::Display for DownloadResult {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Self::Skipped => write!(f, "skipped"),
-            Self::Overwritten => write!(f, "overwritten"),
-            Self::Continued => write!(f, "continued"),
-            Self::Complete => write!(f, "complete"),
+/// Entries below the link (or `path`) whose names match `pattern`, found by
+/// the search of the server where it has one (and `server_search`), else by
+/// listing every directory
+fn find_named(
+    client: &seafile::Client,
+    link: &ShareLink,
+    path: Option<&Path>,
+    pattern: &glob::Pattern,
+    server_search: bool,
+) -> anyhow::Result<Vec<DirEntry>> {
+    let matches = |entry: &DirEntry| pattern.matches(entry.name());
+    let query = search_query(pattern.as_str()).filter(|_| server_search && link.is_dir());
+    if let Some(query) = query {
+        let base = walk_base(link, path);
+        if let Some(entries) = client.search(link.token(), Some(base), query)? {
+            return Ok(entries.into_iter().filter(matches).collect());
         }
+        eprintln!("note: the server cannot search the share, listing every directory instead");
     }
+    walk(client, link, path)?
+        .filter(|e| e.as_ref().map_or(true, matches))
+        .collect()
 }
 
-use std::fs::OpenOptions;
-fn conflict_file_options(conflict: ConflictAction) -> OpenOptions {
-    let mut options = OpenOptions::new();
-    match conflict {
-        ConflictAction::Skip => {
-            options.read(true);
-        }
-        ConflictAction::Check => {
-            options.read(true).write(true);
-        }
-        ConflictAction::Continue => {
-            options.append(true);
-        }
-        ConflictAction::Overwrite => {
-            options.write(true).truncate(true);
+/// Longest part of a glob pattern without wildcards, which the search of the
+/// server is asked for, none if it is all wildcards
+fn search_query(pattern: &str) -> Option<&str> {
+    let mut longest = "";
+    let (mut start, mut class) = (0, false);
+    for (i, c) in pattern.char_indices() {
+        match c {
+            '*' | '?' | '[' if !class => {
+                if i - start > longest.len() {
+                    longest = &pattern[start..i];
+                }
+                class = c == '[';
+                start = i + 1;
+            }
+            ']' if class => {
+                class = false;
+                start = i + 1;
+            }
+            _ => {}
         }
     }
-    options
+    if !class && pattern.len() - start > longest.len() {
+        longest = &pattern[start..];
+    }
+    Some(longest).filter(|query| !query.is_empty())
 }
 
-struct Downloader {
-    client: ureq::Agent,
+/// Remote directory a walk from the link (or `path`) starts in
+fn walk_base<'a>(link: &ShareLink, path: Option<&'a Path>) -> &'a Path {
+    let root = Path::new("/");
+    match path {
+        Some(path) if !link.is_dir() => path.parent().unwrap_or(root),
+        path => path.unwrap_or(root),
+    }
 }
 
-impl Downloader {
-    fn with_client(client: ureq::Agent) -> Self {
-        Self { client }
+/// Prints the entries of `dir` below `prefix` like the Unix `tree` tool,
+/// down to `level` directories deep.
+fn print_tree(tree: &Tree, dir: &Path, prefix: &str, level: Option<usize>) {
+    if level == Some(0) {
+        return;
     }
-    fn download<W: ?Sized>(&self, writer: &mut W, url: &Url) -> anyhow::Result<u64>
-    where
-        W: std::io::Write,
-    {
-        let mut res = self.client.get(url.as_str()).call()?;
-        let mut reader = res.body_mut().as_reader();
-        Ok(std::io::copy(&mut reader, writer)?)
-    }
-
-    fn download_range<W: ?Sized>(
-        &self,
-        writer: &mut W,
-        url: &Url,
-        range: std::ops::Range<u64>,
-    ) -> anyhow::Result<u64>
-    where
-        W: std::io::Write,
-    {
-        let mut res = self
-            .client
-            .get(url.as_str())
-            .header("range", format!("bytes={}-{}", range.start, range.end - 1))
-            .call()?;
-        if res.status() == ureq::http::StatusCode::PARTIAL_CONTENT {
-            let mut reader = res.body_mut().as_reader();
-            Ok(std::io::copy(&mut reader, writer)?)
+    let children = tree.children(dir);
+    for (i, child) in children.iter().enumerate() {
+        let last = i + 1 == children.len();
+        let (branch, indent) = if last {
+            ("└── ", "    ")
+        } else {
+            ("├── ", "│   ")
+        };
+        if child.is_dir() {
+            let total = tree.total(child.path());
+            println!(
+                "{}{}{}/ ({} files, {})",
+                prefix,
+                branch,
+                child.name(),
+                total.files,
+                human_bytes(total.bytes as f64),
+            );
+            let prefix = format!("{}{}", prefix, indent);
+            print_tree(tree, child.path(), &prefix, level.map(|l| l - 1));
         } else {
-            todo!()
+            let size = child
+                .size()
+                .map_or(String::new(), |s| human_bytes(s as f64));
+            println!("{}{}{} ({})", prefix, branch, child.name(), size);
         }
     }
+}
 
-    pub fn download_entry(
-        &self,
-        entry: &DirEntry,
-        options: &DownloadOptions,
-    ) -> anyhow::Result<DownloadResult> {
-        if entry.is_dir() {
-            return Ok(DownloadResult::Skipped);
-        }
-
-        let mut dest = options.output().to_path_buf();
-        dest.push(entry.path().strip_prefix("/")?);
-
-        if let Some(parent) = dest.parent() {
-            std::fs::create_dir_all(parent)?;
-        }
+/// Client certificate chain in `cert`, with the private key in `key` (or in
+/// `cert` as well)
+fn read_client_cert(cert: &Path, key: Option<&Path>) -> anyhow::Result<ureq::tls::ClientCert> {
+    let chain = read_certs(cert)?;
+    let path = key.unwrap_or(cert);
+    let pem = std::fs::read(path).with_context(|| format!("cannot read {}", path.display()))?;
+    let key = ureq::tls::parse_pem(&pem)
+        .find_map(|item| match item {
+            Ok(ureq::tls::PemItem::PrivateKey(key)) => Some(Ok(key)),
+            Ok(_) => None,
+            Err(e) => Some(Err(e)),
+        })
+        .transpose()
+        .with_context(|| format!("invalid key file {}", path.display()))?
+        .ok_or_else(|| anyhow::anyhow!("no private key in {}", path.display()))?;
+    Ok(ureq::tls::ClientCert::new_with_certs(&chain, key))
+}
 
-        let url = entry.download_url().unwrap();
+/// Directory of the HTTP cache, in `$XDG_CACHE_HOME` or `~/.cache`
+fn cache_dir() -> anyhow::Result<PathBuf> {
+    let cache_home = match std::env::var_os("XDG_CACHE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => match std::env::var_os("HOME") {
+            Some(home) => PathBuf::from(home).join(".cache"),
+            None => anyhow::bail!("cannot locate home directory for the HTTP cache"),
+        },
+    };
+    Ok(cache_home.join("seaf-share/http"))
+}
 
-        let (file, result) = if std::fs::exists(&dest)? {
-            let action = options.on_conflict();
-            let mut file = conflict_file_options(action).open(dest)?;
-            let result = match action {
-                ConflictAction::Skip => DownloadResult::Skipped,
-                ConflictAction::Check => {
-                    todo!()
-                }
-                ConflictAction::Continue => {
-                    let start = file.metadata()?.len();
-                    let end = entry.size().unwrap();
-                    if start < end {
-                        self.download_range(&mut file, url, start..end)?;
-                        DownloadResult::Continued
-                    } else {
-                        DownloadResult::Skipped
-                    }
-                }
-                ConflictAction::Overwrite => {
-                    self.download(&mut file, url)?;
-                    DownloadResult::Overwritten
-                }
-            };
-            (file, result)
-        } else {
-            let mut file = std::fs::File::create(dest)?;
-            self.download(&mut file, url)?;
-            (file, DownloadResult::Complete)
-        };
-        if options.archive() {
-            if let Some(mtime) = entry.last_modified() {
-                file.set_modified(mtime.clone().into())?;
-            }
-        }
-        Ok(result)
+/// Certificates of the PEM file at `path`, for "--ca-cert"
+fn read_certs(path: &Path) -> anyhow::Result<Vec<ureq::tls::Certificate<'static>>> {
+    let pem = std::fs::read(path).with_context(|| format!("cannot read {}", path.display()))?;
+    let certs = ureq::tls::parse_pem(&pem)
+        .filter_map(|item| match item {
+            Ok(ureq::tls::PemItem::Certificate(cert)) => Some(Ok(cert)),
+            Ok(_) => None,
+            Err(e) => Some(Err(e)),
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("invalid certificate file {}", path.display()))?;
+    if certs.is_empty() {
+        anyhow::bail!("no certificates in {}", path.display());
     }
+    Ok(certs)
 }
 
-#[derive(Debug, Clone)]
-enum ShareLink {
-    Directory {
-        token: String,
-        path: Option<PathBuf>,
-        file: bool,
-    },
-    SingleFile {
-        token: String,
-    },
+/// API token for the server of `url`: the one given, or else the one stored
+/// by "auth login" where it may be needed (not for share links)
+fn api_token(common: &cli::CommonOptions, url: &url::Url) -> Option<String> {
+    if let Some(token) = common.api_token() {
+        return Some(token.to_string());
+    }
+    if ShareLink::from_url(url).is_some_and(|link| !link.token().is_library()) {
+        return None;
+    }
+    credentials::token(url).unwrap_or_else(|e| {
+        eprintln!("{:#}", Redacted(e));
+        None
+    })
 }
 
-impl ShareLink {
-    pub fn token(&self) -> &str {
-        match self {
-            Self::Directory { token, .. } => token,
-            Self::SingleFile { token } => token,
+/// Agent for the requests of a run against the server of `url`, set up with
+/// the connection options of `common` and sending `token` to the server
+fn agent(
+    common: &cli::CommonOptions,
+    url: &url::Url,
+    token: Option<&str>,
+) -> anyhow::Result<ureq::Agent> {
+    let proxy = match common.proxy() {
+        Some(proxy) => {
+            Some(ureq::Proxy::new(proxy).with_context(|| format!("invalid proxy {}", proxy))?)
         }
+        None if common.no_proxy() => None,
+        None => ureq::Proxy::try_from_env(),
+    };
+    if proxy.as_ref().is_some_and(ureq::Proxy::is_from_env) {
+        eprintln!("Proxy environment variables are used.");
     }
-    pub fn is_single_file(&self) -> bool {
-        match self {
-            Self::Directory { .. } => false,
-            Self::SingleFile { .. } => true,
-        }
+    if common.insecure() {
+        eprintln!("The certificate of the server is not verified.");
     }
-    pub fn is_dir(&self) -> bool {
-        !self.is_file()
+    let mut tls = ureq::tls::TlsConfig::builder().disable_verification(common.insecure());
+    if let Some(path) = common.ca_cert() {
+        tls = tls.root_certs(ureq::tls::RootCerts::new_with_certs(&read_certs(path)?));
     }
-    pub fn is_file(&self) -> bool {
-        match self {
-            Self::Directory { file, .. } => *file,
-            Self::SingleFile { .. } => true,
-        }
+    match (common.client_cert(), common.client_key()) {
+        (Some(cert), key) => tls = tls.client_cert(Some(read_client_cert(cert, key)?)),
+        (None, Some(_)) => anyhow::bail!("--client-key requires --client-cert"),
+        (None, None) => {}
     }
-    pub fn path(&self) -> Option<&Path> {
-        match self {
-            Self::Directory { path, .. } => path.as_ref().map(|p| p.as_ref()),
-            Self::SingleFile { .. } => None,
-        }
+    let mut config = ureq::config::Config::builder()
+        .proxy(proxy)
+        .tls_config(tls.build())
+        .timeout_global(common.max_time())
+        .timeout_connect(common.connect_timeout());
+    if let Some(agent) = common.user_agent() {
+        config = config.user_agent(agent);
     }
-    fn from_url(url: &Url) -> Option<Self> {
-        const PATTERNS: &'static [&'static str] = &["/d/([0-9a-f]+)(/files)?", "/f/([0-9a-f]+)"];
-        let set = RegexSet::new(PATTERNS).unwrap();
-        let result = set.matches(url.path());
-        if let Some(idx) = result.iter().next() {
-            let pattern = Regex::new(PATTERNS[idx]).unwrap();
-            let captures = pattern.captures(url.path()).unwrap();
-            let token = captures.get(1).unwrap();
-            if idx == 0 {
-                let path = url
-                    .query_pairs()
-                    .find_map(|(k, v)| if k == "p" { Some(v) } else { None });
-                let share = ShareLink::Directory {
-                    token: token.as_str().to_string(),
-                    path: path.and_then(|s| PathBuf::from_str(s.as_ref()).ok()),
-                    file: captures.get(2).is_some(),
-                };
-                Some(share)
-            } else {
-                let share = ShareLink::SingleFile {
-                    token: token.as_str().to_string(),
-                };
-                Some(share)
-            }
-        } else {
-            None
+    let headers = common.headers().to_vec();
+    if !headers.is_empty() {
+        // like the API token, only for the server and not for the hosts that
+        // downloads are redirected to
+        let origin = url.origin();
+        config = config.middleware(
+            move |mut request: ureq::http::Request<ureq::SendBody>,
+                  next: ureq::middleware::MiddlewareNext| {
+                let same_origin = url::Url::parse(&request.uri().to_string())
+                    .is_ok_and(|url| url.origin() == origin);
+                if same_origin {
+                    for header in &headers {
+                        request
+                            .headers_mut()
+                            .append(header.name.clone(), header.value.clone());
+                    }
+                }
+                next.handle(request)
+            },
+        );
+    }
+    if let Some(token) = token {
+        config = config.middleware(auth::TokenAuth::new(url, token)?);
+    }
+    if common.http_cache() {
+        let mut cache = cache::HttpCache::new(&cache_dir()?);
+        if let Some(max_stale) = common.max_stale() {
+            cache = cache.with_max_stale(max_stale);
         }
+        config = config.middleware(cache);
     }
+    if let Some(path) = common.record() {
+        config = config.middleware(tape::Recorder::create(path)?);
+    } else if let Some(path) = common.replay() {
+        config = config.middleware(tape::Player::open(path)?);
+    }
+    Ok(stall::agent(config.build(), common.read_timeout()))
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(
-    tag = "type",
-    rename_all = "snake_case",
-    rename_all_fields = "snake_case"
-)]
-enum DirEntry {
-    Directory {
-        name: String,
-        path: PathBuf,
-        last_modified: DateTime<Utc>,
-        view_url: Url,
-    },
-    File {
-        name: String,
-        path: PathBuf,
-        size: u64,
-        last_modified: Option<DateTime<Utc>>,
-        download_url: Url,
-        view_url: Url,
-    },
+fn main() -> ExitCode {
+    match run() {
+        Ok(status) => {
+            healthcheck::finish(status == ExitCode::SUCCESS);
+            status
+        }
+        Err(e) => {
+            healthcheck::fail(&e);
+            eprintln!("Error: {}", redact::redact(&format!("{:?}", e)));
+            exit::of_error(&e)
+        }
+    }
 }
 
-impl DirEntry {
-    fn is_file(&self) -> bool {
-        match self {
-            Self::Directory { .. } => false,
-            Self::File { .. } => true,
-        }
+fn run() -> anyhow::Result<ExitCode> {
+    let config = Config::load(config::profile_arg().as_deref())?;
+    let cli = Cli::parse_with(&config);
+    redact::set_enabled(!cli.no_redact());
+    let command = cli.command();
+    if let Some(what) = command.writes().filter(|_| cli.no_write()) {
+        anyhow::bail!("--no-write forbids writing {}", what);
     }
-    fn is_dir(&self) -> bool {
-        match self {
-            Self::Directory { .. } => true,
-            Self::File { .. } => false,
-        }
+    if let Some(url) = command
+        .download_options()
+        .as_ref()
+        .and_then(|o| o.healthcheck_url())
+    {
+        healthcheck::start(url);
     }
-    fn name(&self) -> &str {
-        match self {
-            Self::Directory { name, .. } | Self::File { name, .. } => name,
+    let common = match command {
+        Command::InstallHandler(options) => {
+            return handler::install(options).map(|()| ExitCode::SUCCESS)
         }
-    }
-    fn path(&self) -> &Path {
-        match self {
-            Self::Directory { path, .. } | Self::File { path, .. } => path,
+        Command::Examples(options) => {
+            examples::print(options.command());
+            return Ok(ExitCode::SUCCESS);
         }
-    }
-    fn size(&self) -> Option<u64> {
-        match self {
-            Self::Directory { .. } => None,
-            Self::File { size, .. } => Some(*size),
+        Command::Config => {
+            config.print(&Cli::command_with(&config))?;
+            return Ok(ExitCode::SUCCESS);
         }
-    }
-    fn last_modified(&self) -> Option<&DateTime<Utc>> {
-        match self {
-            Self::Directory { last_modified, .. } => Some(last_modified),
-            Self::File { last_modified, .. } => last_modified.as_ref(),
+        Command::Join(options) => {
+            for manifest in options.manifests() {
+                let (joined, result) = volume::join(&manifest, options.into())?;
+                if options.remove_parts() {
+                    volume::remove_parts(&manifest)?;
+                }
+                println!("joined {}: {}", joined.display(), result);
+            }
+            return Ok(ExitCode::SUCCESS);
         }
+        command => command.common().expect("command accesses a share"),
+    };
+    // the cache is written as it is read
+    let readonly = cli.no_write().then(|| common.clone().without_http_cache());
+    let common = readonly.as_ref().unwrap_or(common);
+    let url = match common.url() {
+        Some(url) => url.clone(),
+        None => {
+            let url = clipboard::share_url()?;
+            eprintln!("Using {} from the clipboard", Redacted(&url));
+            url
+        }
+    };
+    if let Some(path) = common.record() {
+        eprintln!(
+            "{} keeps the share token and download URLs, so it gives access to the share like its link.",
+            path.display()
+        );
     }
-    fn download_url(&self) -> Option<&Url> {
-        match self {
-            Self::Directory { .. } => None,
-            Self::File { download_url, .. } => Some(download_url),
+    // configured credentials only go to the server they are configured for
+    let configured = common
+        .clone()
+        .with_credentials(config.password_for(&url), config.api_token_for(&url));
+    let common = &configured;
+    if let Command::Auth(options) = command {
+        match options.command() {
+            AuthCommand::Login(login) => {
+                let client = seafile::Client::with_agent(agent(common, &url, None)?, &url);
+                commands::login(&client, &url, login)?;
+            }
+            AuthCommand::Logout(_) => commands::logout(&url)?,
+            AuthCommand::Status(_) => {
+                let retry = Retry::new(common.max_retries(), common.retry_delay());
+                let connect = |token: &str| {
+                    let agent = agent(common, &url, Some(token))?;
+                    Ok(seafile::Client::with_agent(agent, &url).with_retry(retry))
+                };
+                commands::status(&url, connect, &mut std::io::stdout().lock())?;
+            }
         }
+        return Ok(ExitCode::SUCCESS);
     }
-    fn view_url(&self) -> &Url {
-        match self {
-            Self::Directory { view_url, .. } => view_url,
-            Self::File { download_url, .. } => download_url,
+    let token = api_token(common, &url);
+    for secret in [token.as_deref(), common.password()].into_iter().flatten() {
+        redact::secret(secret);
+    }
+    if let Command::Libraries(options) = command {
+        if token.is_none() {
+            anyhow::bail!("listing libraries requires --api-token or \"seaf-share auth login\"");
         }
+        let retry = Retry::new(common.max_retries(), common.retry_delay());
+        let agent = agent(common, &url, token.as_deref())?;
+        let client = seafile::Client::with_agent(agent, &url).with_retry(retry);
+        commands::libraries(&client, options, &mut std::io::stdout().lock())?;
+        return Ok(ExitCode::SUCCESS);
     }
-}
+    // read as the link to the file or directory of a library it names
+    let url = if ShareLink::is_smart_link(&url) {
+        seafile::resolve_smart_link(&agent(common, &url, token.as_deref())?, &url)?
+    } else {
+        url
+    };
+    let mut status = ExitCode::SUCCESS;
+    if let Some(link) = ShareLink::from_url(&url) {
+        // also outside of URLs, e.g. in the names of session files
+        if !link.token().is_library() {
+            redact::secret(link.token().as_str());
+        }
+        // The API client and the downloader share one agent (and its cookie
+        // jar), so a password session unlocked by one is valid for the other.
+        let agent = agent(common, &url, token.as_deref())?;
+        let seed = common.seed().map_or_else(Seed::from_clock, Seed::new);
+        let retry = Retry::new(common.max_retries(), common.retry_delay()).with_seed(seed);
+        let mut client = seafile::Client::with_agent(agent.clone(), &url)
+            .with_retry(retry)
+            .with_seed(seed)
+            .with_strict_json(common.strict_json())
+            .with_api(common.api());
+        let downloader = Downloader::with_client(agent).with_retry(retry);
+        if let Some(password) = common.password() {
+            client
+                .unlock(&link.page_url(&client), link.token(), password)
+                .with_context(|| "cannot unlock password-protected share")?;
+        }
+        let client = client;
+        // outlives the client, which the download takes
+        let parts = client.parts();
+        let path = link.resolve(common.path());
 
-fn main() -> anyhow::Result<()> {
-    let cli = Cli::parse();
-    let command = cli.command();
-    let common = command.common();
-    if let Some(link) = ShareLink::from_url(common.url()) {
-        let proxy = ureq::Proxy::try_from_env();
-        if proxy.is_some() {
-            eprintln!("{}", "Proxy environment variables are used.");
+        if link.is_upload() != matches!(command, Command::Upload(_)) {
+            if link.is_upload() {
+                anyhow::bail!("{} is an upload link, see \"seaf-share upload\"", url);
+            }
+            anyhow::bail!("{} is not an upload link", url);
         }
-        let config = ureq::config::Config::builder()
-            .proxy(proxy.clone())
-            .accept("application/json")
-            .build();
-        let client =
-            seafile::Client::with_agent(ureq::Agent::new_with_config(config), common.url());
-        let downloader = Downloader::with_client(ureq::Agent::new_with_config(
-            ureq::config::Config::builder().proxy(proxy.clone()).build(),
-        ));
-        let path = common
-            .path()
-            .as_ref()
-            .map(|p| {
-                let base = link.path().unwrap_or(Path::new("/"));
-                let mut buf = base.to_path_buf();
-                buf.push(p);
-                buf
-            })
-            .or(link.path().map(|p| p.to_path_buf()));
 
         match command {
             Command::List(options) => {
-                let mut result = Vec::new();
-                if link.is_single_file() {
-                    let file = client
-                        .single_file(common.url())
-                        .with_context(|| "cannot fetch single file info")?;
-                    result.push(file);
-                } else if link.is_file() {
-                    let parent = link.path().and_then(|p| p.parent());
-                    let entries = client.entries(link.token(), parent)?;
-                    let file = entries
-                        .iter()
-                        .find(|e| link.path().map(|p| p == e.path()).unwrap_or(false));
-                    if let Some(file) = file {
-                        result.push(file.clone());
+                let mut stdout = std::io::stdout().lock();
+                commands::list(&client, &link, path.as_deref(), options, &mut stdout)?;
+            }
+            Command::Download(_) | Command::Sync(_) => {
+                let options = command.download_options().unwrap();
+                let output = output::resolve(options.output(), &client, &link)?;
+                let options = options.with_output(output);
+                let staging = match options.stage_then_swap() {
+                    true => {
+                        let staging = stage::staging_dir(options.output())?;
+                        // files written in place would change the tree readers see
+                        let link = options.on_conflict() != ConflictAction::Continue
+                            && options.split_size().is_none();
+                        stage::seed(options.output(), &staging, link)?;
+                        Some(staging)
                     }
-                } else {
-                    let entries = client.entries(link.token(), path.as_ref())?;
-                    result.extend(entries);
+                    false => None,
+                };
+                let staged = staging.clone().map(|dir| options.clone().with_output(dir));
+                let report = commands::download(
+                    client,
+                    downloader,
+                    &link,
+                    &url,
+                    path.as_deref(),
+                    staged.as_ref().unwrap_or(&options),
+                    &mut std::io::stdout(),
+                )?;
+                healthcheck::summary(&report.summary);
+                if let Some(signal) = report.signal {
+                    healthcheck::finish(false);
+                    std::process::exit(128 + signal);
                 }
-                if options.json() {
-                    println!("{}", serde_json::to_string(&result)?);
-                } else {
-                    let table = result
-                        .iter()
-                        .map(|e| {
-                            let name = if e.is_dir() {
-                                format!("{}/", e.name())
-                            } else {
-                                e.name().to_string()
-                            };
-                            let na = "N/A".to_string();
-                            [
-                                name.cell(),
-                                e.size()
-                                    .map(|sz| human_bytes(sz as f64))
-                                    .unwrap_or(na.clone())
-                                    .cell(),
-                                e.last_modified()
-                                    .map(|dt| dt.to_rfc3339())
-                                    .unwrap_or(na.clone())
-                                    .cell(),
-                            ]
-                        })
-                        .table()
-                        .title(["Name", "Size", "Last Modified"])
-                        .display()?;
-                    println!("{}", table);
+                if let Some(staging) = &staging {
+                    if report.summary.is_complete() {
+                        stage::swap(staging, options.output())?;
+                    } else {
+                        eprintln!(
+                            "left {} as it was, as not every file was downloaded; \
+                             the next run continues in {}",
+                            options.output().display(),
+                            staging.display()
+                        );
+                    }
+                }
+                status = exit::of_summary(&report.summary);
+            }
+            Command::Watch(options) => {
+                let output = output::resolve(options.download_options().output(), &client, &link)?;
+                let options = options.clone().with_output(output);
+                commands::watch(
+                    client,
+                    downloader,
+                    &link,
+                    path.as_deref(),
+                    &options,
+                    &mut std::io::stdout(),
+                )?;
+                if let Some(signal) = interrupt::signal() {
+                    std::process::exit(128 + signal);
                 }
             }
-            Command::Download(options) => {
-                let mut queue = VecDeque::new();
-                if link.is_file() {
-                    let file = if link.is_single_file() {
-                        client.single_file(common.url())?
+            Command::Info(options) => {
+                commands::info(&client, &link, options, &mut std::io::stdout().lock())?;
+            }
+            Command::Find(options) => {
+                let found = match options.name() {
+                    Some(pattern) => find_named(
+                        &client,
+                        &link,
+                        path.as_deref(),
+                        pattern,
+                        options.server_search(),
+                    )?,
+                    None => walk(&client, &link, path.as_deref())?
+                        .collect::<anyhow::Result<Vec<_>>>()?,
+                };
+                if options.duplicates() {
+                    let files: Vec<_> = found.into_iter().filter(DirEntry::is_file).collect();
+                    let client = (!options.size_only()).then_some(&client);
+                    let sets = duplicates::find(files, client)?;
+                    if options.json() {
+                        println!("{}", serde_json::to_string(&sets)?);
                     } else {
-                        let parent = link.path().and_then(|p| p.parent());
-                        let entries = client.entries(link.token(), parent)?;
-                        let file = entries
-                            .iter()
-                            .find(|e| link.path().map(|p| p == e.path()).unwrap_or(false));
-                        file.expect("remote file should be found in its parent")
-                            .clone()
-                    };
-                    queue.push_back(file);
+                        for set in &sets {
+                            println!(
+                                "{} × {}{}, {} wasted",
+                                human_bytes(set.size as f64),
+                                set.paths.len(),
+                                set.id
+                                    .as_ref()
+                                    .map_or(String::new(), |id| format!(" ({})", id)),
+                                human_bytes(set.wasted() as f64),
+                            );
+                            for path in &set.paths {
+                                println!("  {}", path.display());
+                            }
+                        }
+                        let wasted: u64 = sets.iter().map(|s| s.wasted()).sum();
+                        println!(
+                            "{} duplicate sets, {} wasted",
+                            sets.len(),
+                            human_bytes(wasted as f64),
+                        );
+                    }
+                } else if options.json() {
+                    println!("{}", serde_json::to_string(&found)?);
                 } else {
-                    let entries = client.entries(link.token(), path.as_ref())?;
-                    if options.recursive() == Recursive::Dfs {
-                        queue.extend(entries.into_iter().rev());
-                    } else {
-                        queue.extend(entries);
+                    for entry in &found {
+                        println!("{}", entry.path().display());
                     }
                 }
-
-                while !queue.is_empty() {
-                    let entry = if options.recursive() == Recursive::Dfs {
-                        queue.pop_back().unwrap()
-                    } else {
-                        queue.pop_front().unwrap()
-                    };
-
-                    let mut dest = options.output().to_path_buf();
-                    if let Some(base) = path.as_ref() {
-                        dest.push(entry.path().strip_prefix(base)?);
+            }
+            Command::Du(options) => {
+                let base = walk_base(&link, path.as_deref());
+                if let Some(file) = options.export_ncdu() {
+                    let entries = walk(&client, &link, path.as_deref())?
+                        .collect::<anyhow::Result<Vec<_>>>()?;
+                    let tree = Tree::new(base, entries);
+                    let root_name = client.dir_url(link.token(), Some(base));
+                    if file == Path::new("-") {
+                        ncdu::export(&tree, root_name.as_str(), std::io::stdout().lock())?;
                     } else {
-                        dest.push(entry.path().strip_prefix("/")?);
+                        let out = std::io::BufWriter::new(std::fs::File::create(file)?);
+                        ncdu::export(&tree, root_name.as_str(), out)?;
                     }
-
-                    if options
-                        .excludes()
-                        .iter()
-                        .any(|p| p.matches_path(entry.path()))
-                    {
-                        continue;
+                    return Ok(ExitCode::SUCCESS);
+                }
+                let top = options.top().unwrap_or(0);
+                let mut usage = Usage::new(base, top);
+                for entry in walk(&client, &link, path.as_deref())? {
+                    usage.add(&entry?);
+                }
+                let total = usage.total();
+                let dirs = match top {
+                    0 => usage.dirs(),
+                    n => usage.largest_dirs(n),
+                };
+                if options.json() {
+                    let sized = |(path, bytes): (PathBuf, u64)| serde_json::json!({ "path": path, "bytes": bytes });
+                    let mut report = serde_json::json!({
+                        "path": base,
+                        "total": total,
+                        "directories": dirs.into_iter().map(sized).collect::<Vec<_>>(),
+                    });
+                    if top > 0 {
+                        report["largest_files"] =
+                            usage.largest_files().into_iter().map(sized).collect();
                     }
-                    if entry.is_file() {
-                        if options.dry_run() {
-                            eprintln!("{}", entry.download_url().unwrap());
-                        } else {
-                            match downloader.download_entry(&entry, options) {
-                                Err(e) => {
-                                    eprintln!(
-                                        "could not download {}: {}",
-                                        entry.path().to_string_lossy(),
-                                        e,
-                                    )
-                                }
-                                Ok(result) => {
-                                    println!(
-                                        "downloaded {}: {}",
-                                        entry.path().to_string_lossy(),
-                                        result
-                                    )
-                                }
-                            }
-                        }
-                    } else if options.recursive() != Recursive::None {
-                        if !options.dry_run() {
-                            std::fs::create_dir(dest)?;
-                        }
-                        let entries = client.entries(link.token(), Some(entry.path()))?;
-                        if options.recursive() == Recursive::Dfs {
-                            queue.extend(entries.into_iter().rev());
-                        } else {
-                            queue.extend(entries)
-                        }
+                    if options.extensions() {
+                        report["extensions"] = usage
+                            .extensions()
+                            .into_iter()
+                            .map(|(extension, used)| {
+                                serde_json::json!({
+                                    "extension": extension,
+                                    "files": used.files,
+                                    "bytes": used.bytes,
+                                })
+                            })
+                            .collect();
+                    }
+                    println!("{}", report);
+                    return Ok(ExitCode::SUCCESS);
+                }
+                if top > 0 {
+                    println!("Largest files:");
+                    for (path, size) in usage.largest_files() {
+                        println!("{:>12}  {}", human_bytes(size as f64), path.display());
+                    }
+                    println!("\nLargest directories:");
+                }
+                for (path, size) in dirs {
+                    println!("{:>12}  {}", human_bytes(size as f64), path.display());
+                }
+                println!(
+                    "{:>12}  {} ({} files, {} directories)",
+                    human_bytes(total.bytes as f64),
+                    base.display(),
+                    total.files,
+                    total.dirs,
+                );
+                if options.extensions() {
+                    println!("\nBy extension:");
+                    for (extension, used) in usage.extensions() {
+                        let extension = match extension.as_str() {
+                            "" => "(none)".to_string(),
+                            e => format!(".{}", e),
+                        };
+                        println!(
+                            "{:>12}  {:>8} files  {}",
+                            human_bytes(used.bytes as f64),
+                            used.files,
+                            extension,
+                        );
+                    }
+                }
+            }
+            Command::Tree(options) => {
+                let base = walk_base(&link, path.as_deref());
+                let entries =
+                    walk(&client, &link, path.as_deref())?.collect::<anyhow::Result<Vec<_>>>()?;
+                let tree = Tree::new(base, entries);
+                let total = tree.total(base);
+                println!("{}", client.dir_url(link.token(), Some(base)));
+                print_tree(&tree, base, "", options.level());
+                println!(
+                    "\n{} directories, {} files, {}",
+                    total.dirs,
+                    total.files,
+                    human_bytes(total.bytes as f64),
+                );
+            }
+            Command::Cat(options) => {
+                client.check_downloadable(&link)?;
+                let entry = client.file(&link, path.as_deref())?;
+                let url = entry.download_url().expect("files have a download URL");
+                let progress = Progress::default();
+                progress.set_rate_limit(options.limit_rate());
+                let downloader = downloader.with_progress(progress);
+                let mut stdout = std::io::stdout().lock();
+                match downloader.download(&mut stdout, url, entry.size()) {
+                    // the reader quit early, e.g. "head"
+                    Err(seaf_share_core::Error::Io(e))
+                        if e.kind() == std::io::ErrorKind::BrokenPipe => {}
+                    result => {
+                        result?;
                     }
                 }
             }
+            Command::Verify(options) => {
+                let base = walk_base(&link, path.as_deref());
+                let output = output::resolve(options.output(), &client, &link)?;
+                let ids = sync::FileIds::load(&output)?;
+                let mut verifier =
+                    Verifier::new(base, &output, options.size_only()).with_file_ids(ids);
+                let mut differences = Vec::new();
+                for entry in walk(&client, &link, path.as_deref())? {
+                    differences.extend(verifier.check(&entry?)?);
+                }
+                // a file link covers a single file of its directory
+                if link.is_dir() {
+                    differences.extend(verifier.extra()?);
+                }
+                differences.sort_by(|a, b| a.path.cmp(&b.path));
+                if options.json() {
+                    println!("{}", serde_json::to_string(&differences)?);
+                } else {
+                    for difference in &differences {
+                        println!("{:<8} {}", difference.status, difference.path.display());
+                    }
+                }
+                if !differences.is_empty() {
+                    anyhow::bail!("{} differs from the share", output.display());
+                }
+            }
+            Command::Upload(options) => {
+                let totals =
+                    upload::upload_all(&client, link.token(), options.files(), path.as_deref())?;
+                // failed uploads exit like failed downloads
+                let summary = Summary {
+                    downloaded: totals.uploaded,
+                    failed: totals.failed,
+                    ..Summary::default()
+                };
+                status = exit::of_summary(&summary);
+            }
+            Command::Libraries(_)
+            | Command::Auth(_)
+            | Command::Join(_)
+            | Command::InstallHandler(_)
+            | Command::Examples(_)
+            | Command::Config => {
+                unreachable!()
+            }
+        }
+        let deviations = parts.deviations();
+        if !deviations.is_empty() {
+            let count: u64 = deviations.iter().map(|d| d.count).sum();
+            eprintln!(
+                "{} listing entries differed from what the server is expected to send \
+                 (see the warnings above, or run with --strict-json to fail on them)",
+                count
+            );
         }
+    } else {
+        let e = anyhow::Error::new(seafile::Error::InvalidShare);
+        return Err(e.context(format!("{} is not a Seafile share link", url)));
     }
-    Ok(())
+    Ok(status)
 }
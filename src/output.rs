@@ -0,0 +1,66 @@
+//! Output destinations with tokens of the share, e.g.
+//! `~/shares/{repo_name}-{date}`, so that recurring jobs sort their copies
+//! without a wrapper script.
+
+use std::path::{Path, PathBuf};
+
+use seaf_share_core::{seafile, ShareLink};
+
+/// Tokens an output destination may contain
+const TOKENS: &str = "{token}, {repo_id}, {repo_name} and {date}";
+
+/// `output` with its tokens replaced by those of `link`; "{{" and "}}" stand
+/// for literal braces. The share page is only read for library tokens.
+pub fn resolve(
+    output: &Path,
+    client: &seafile::Client,
+    link: &ShareLink,
+) -> anyhow::Result<PathBuf> {
+    let Some(template) = output.to_str().filter(|s| s.contains(['{', '}'])) else {
+        return Ok(output.to_path_buf());
+    };
+    let mut info = None;
+    let mut resolved = String::new();
+    let mut rest = template;
+    while let Some(i) = rest.find(['{', '}']) {
+        resolved.push_str(&rest[..i]);
+        rest = &rest[i..];
+        if let Some(escaped) = rest.strip_prefix("{{").or(rest.strip_prefix("}}")) {
+            resolved.push_str(&rest[..1]);
+            rest = escaped;
+            continue;
+        }
+        let end = match rest.find('}') {
+            Some(end) if rest.starts_with('{') => end,
+            _ => anyhow::bail!("unmatched brace in output destination {}", template),
+        };
+        let value = match &rest[1..end] {
+            "token" => link.token().to_string(),
+            "date" => chrono::Local::now().format("%Y-%m-%d").to_string(),
+            name @ ("repo_id" | "repo_name") => {
+                if info.is_none() {
+                    info = Some(client.share_info(link)?);
+                }
+                let info = info.as_ref().unwrap();
+                let value = match name {
+                    "repo_id" => info.repo_id.as_ref(),
+                    _ => info.repo_name.as_ref(),
+                };
+                let Some(value) = value else {
+                    anyhow::bail!("the share page does not tell the {{{}}} of the share", name);
+                };
+                // a name must not add directories
+                value.replace(['/', '\\'], "_")
+            }
+            name => anyhow::bail!(
+                "unknown token {{{}}} in output destination, expected {}",
+                name,
+                TOKENS
+            ),
+        };
+        resolved.push_str(&value);
+        rest = &rest[end + 1..];
+    }
+    resolved.push_str(rest);
+    Ok(PathBuf::from(resolved))
+}
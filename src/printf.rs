@@ -0,0 +1,97 @@
+//! `--printf` templates for listed entries, in the spirit of `find -printf`.
+
+use std::str::FromStr;
+
+use seaf_share_core::DirEntry;
+
+#[derive(Debug, Clone)]
+enum Piece {
+    Literal(String),
+    Field(char),
+}
+
+/// Parsed `--printf` format, see [`crate::cli::ListOptions`]
+#[derive(Debug, Clone)]
+pub struct Template(Vec<Piece>);
+
+impl Template {
+    /// Expands the template for `entry`; no newline is added unless the
+    /// template ends with `\n`.
+    pub fn render(&self, entry: &DirEntry) -> String {
+        let mut out = String::new();
+        for piece in &self.0 {
+            match piece {
+                Piece::Literal(s) => out.push_str(s),
+                Piece::Field(c) => out.push_str(&field(entry, *c)),
+            }
+        }
+        out
+    }
+}
+
+fn field(entry: &DirEntry, directive: char) -> String {
+    match directive {
+        'n' => entry.name().to_string(),
+        'p' => entry.path().to_string_lossy().into_owned(),
+        'y' => if entry.is_dir() { "d" } else { "f" }.to_string(),
+        's' => entry.size().map(|s| s.to_string()).unwrap_or_default(),
+        'S' => entry
+            .size()
+            .map(|s| human_bytes::human_bytes(s as f64))
+            .unwrap_or_default(),
+        'm' => entry
+            .last_modified()
+            .map(|t| t.to_rfc3339())
+            .unwrap_or_default(),
+        't' => entry
+            .last_modified()
+            .map(|t| t.timestamp().to_string())
+            .unwrap_or_default(),
+        'c' => match entry {
+            DirEntry::File { content_type, .. } => content_type.clone().unwrap_or_default(),
+            DirEntry::Directory { .. } => String::new(),
+        },
+        'u' => entry
+            .download_url()
+            .map(|u| u.to_string())
+            .unwrap_or_default(),
+        'v' => entry.view_url().to_string(),
+        _ => unreachable!("directives are checked when parsing"),
+    }
+}
+
+impl FromStr for Template {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut pieces = Vec::new();
+        let mut literal = String::new();
+        let mut chars = s.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '%' => match chars.next() {
+                    Some('%') => literal.push('%'),
+                    Some(d @ ('n' | 'p' | 'y' | 's' | 'S' | 'm' | 't' | 'c' | 'u' | 'v')) => {
+                        pieces.push(Piece::Literal(std::mem::take(&mut literal)));
+                        pieces.push(Piece::Field(d));
+                    }
+                    Some(d) => return Err(format!("unknown directive \"%{}\"", d)),
+                    None => return Err("incomplete directive at the end".to_string()),
+                },
+                '\\' => match chars.next() {
+                    Some('n') => literal.push('\n'),
+                    Some('t') => literal.push('\t'),
+                    Some('r') => literal.push('\r'),
+                    Some('0') => literal.push('\0'),
+                    Some('\\') => literal.push('\\'),
+                    Some(e) => return Err(format!("unknown escape \"\\{}\"", e)),
+                    None => return Err("incomplete escape at the end".to_string()),
+                },
+                c => literal.push(c),
+            }
+        }
+        pieces.push(Piece::Literal(literal));
+        pieces.retain(|p| !matches!(p, Piece::Literal(s) if s.is_empty()));
+        Ok(Self(pieces))
+    }
+}